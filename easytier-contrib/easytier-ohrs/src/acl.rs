@@ -0,0 +1,244 @@
+use crate::instance_api::{self, block_on};
+use crate::{config_stage, parse_inst_id};
+use easytier::proto::acl::{Acl, Action, Chain, ChainType, Protocol, Rule};
+use easytier::proto::api::config::{AclPatch, InstanceConfigPatch, OutboundPolicyPatch};
+use easytier::proto::api::instance::GetAclRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+#[napi(object)]
+pub struct AclRule {
+    pub chain: String,
+    pub name: String,
+    pub priority: u32,
+    pub enabled: bool,
+    pub protocol: String,
+    pub ports: Vec<String>,
+    pub source_ips: Vec<String>,
+    pub destination_ips: Vec<String>,
+    pub source_ports: Vec<String>,
+    pub action: String,
+}
+
+impl AclRule {
+    fn from_rule(chain: ChainType, rule: Rule) -> Self {
+        Self {
+            chain: chain.as_str_name().to_string(),
+            protocol: rule.protocol().as_str_name().to_string(),
+            action: rule.action().as_str_name().to_string(),
+            name: rule.name,
+            priority: rule.priority,
+            enabled: rule.enabled,
+            ports: rule.ports,
+            source_ips: rule.source_ips,
+            destination_ips: rule.destination_ips,
+            source_ports: rule.source_ports,
+        }
+    }
+
+    // the core silently skips ips and ports it cannot parse, which would turn a typo into a
+    // broader rule than intended, so everything is checked before anything is applied.
+    fn validate(&self) -> Result<(ChainType, Protocol, Action), String> {
+        let chain = ChainType::from_str_name(&self.chain)
+            .filter(|c| *c != ChainType::UnspecifiedChain)
+            .ok_or_else(|| format!("invalid chain {}", self.chain))?;
+        let protocol = Protocol::from_str_name(&self.protocol)
+            .filter(|p| *p != Protocol::Unspecified)
+            .ok_or_else(|| format!("invalid protocol {}", self.protocol))?;
+        let action = Action::from_str_name(&self.action)
+            .filter(|a| *a != Action::Noop)
+            .ok_or_else(|| format!("invalid action {}", self.action))?;
+        if self.priority > u16::MAX as u32 {
+            return Err(format!("priority {} out of range", self.priority));
+        }
+        for ip in self.source_ips.iter().chain(self.destination_ips.iter()) {
+            if !is_valid_ip_or_cidr(ip) {
+                return Err(format!("invalid ip or cidr {}", ip));
+            }
+        }
+        for port in self.ports.iter().chain(self.source_ports.iter()) {
+            if !is_valid_port_range(port) {
+                return Err(format!("invalid port or port range {}", port));
+            }
+        }
+        Ok((chain, protocol, action))
+    }
+}
+
+fn is_valid_ip_or_cidr(s: &str) -> bool {
+    let (ip, prefix) = match s.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix)),
+        None => (s, None),
+    };
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return false;
+    };
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) => prefix.parse::<u8>().is_ok_and(|p| p <= max_prefix),
+        None => true,
+    }
+}
+
+fn is_valid_port_range(s: &str) -> bool {
+    match s.split_once('-') {
+        Some((start, end)) => match (start.trim().parse::<u16>(), end.trim().parse::<u16>()) {
+            (Ok(start), Ok(end)) => start <= end,
+            _ => false,
+        },
+        None => s.trim().parse::<u16>().is_ok(),
+    }
+}
+
+fn fetch_acl(uuid: &Uuid) -> Result<Acl, String> {
+    let service = instance_api::get_instance_service(uuid)
+        .ok_or_else(|| format!("instance {} not running", uuid))?;
+    block_on(
+        service
+            .get_acl_manage_service()
+            .get_acl(BaseController::default(), GetAclRequest::default()),
+    )
+    .map(|resp| resp.acl.unwrap_or_default())
+    .map_err(|e| e.to_string())
+}
+
+#[napi]
+pub fn get_acl_rules(inst_id: String) -> Vec<AclRule> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let acl = match fetch_acl(&uuid) {
+        Ok(acl) => acl,
+        Err(e) => {
+            hilog_error!("[Rust] get_acl_rules failed {}", e);
+            return vec![];
+        }
+    };
+    acl.acl_v1
+        .map(|v1| v1.chains)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|chain| {
+            let chain_type = chain.chain_type();
+            chain
+                .rules
+                .into_iter()
+                .map(move |rule| AclRule::from_rule(chain_type, rule))
+        })
+        .collect()
+}
+
+#[napi]
+pub fn set_acl_rules(inst_id: String, rules: Vec<AclRule>) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    // the rules are merged into the running acl, not into a staged one
+    if config_stage::is_staging(&uuid) {
+        hilog_error!("[Rust] set_acl_rules: cannot be staged, commit or discard first");
+        return false;
+    }
+    let mut acl = match fetch_acl(&uuid) {
+        Ok(acl) => acl,
+        Err(e) => {
+            hilog_error!("[Rust] set_acl_rules failed {}", e);
+            return false;
+        }
+    };
+
+    let mut validated = Vec::with_capacity(rules.len());
+    for rule in rules {
+        match rule.validate() {
+            Ok(types) => validated.push((rule, types)),
+            Err(e) => {
+                hilog_error!("[Rust] set_acl_rules: rule {} rejected, {}", rule.name, e);
+                return false;
+            }
+        }
+    }
+
+    let mut acl_v1 = acl.acl_v1.take().unwrap_or_default();
+    let old_chains = acl_v1.chains.clone();
+    for chain in acl_v1.chains.iter_mut() {
+        chain.rules.clear();
+    }
+    for (rule, (chain_type, protocol, action)) in validated {
+        // keep fields we do not expose (groups, rate limit, ...) of a rule with the same name
+        let mut new_rule = old_chains
+            .iter()
+            .filter(|c| c.chain_type == chain_type as i32)
+            .flat_map(|c| c.rules.iter())
+            .find(|r| r.name == rule.name)
+            .cloned()
+            .unwrap_or_default();
+        new_rule.name = rule.name;
+        new_rule.priority = rule.priority;
+        new_rule.enabled = rule.enabled;
+        new_rule.protocol = protocol as i32;
+        new_rule.ports = rule.ports;
+        new_rule.source_ips = rule.source_ips;
+        new_rule.destination_ips = rule.destination_ips;
+        new_rule.source_ports = rule.source_ports;
+        new_rule.action = action as i32;
+
+        match acl_v1
+            .chains
+            .iter_mut()
+            .find(|c| c.chain_type == chain_type as i32)
+        {
+            Some(chain) => chain.rules.push(new_rule),
+            None => acl_v1.chains.push(Chain {
+                name: chain_type.as_str_name().to_lowercase(),
+                chain_type: chain_type as i32,
+                enabled: true,
+                rules: vec![new_rule],
+                default_action: Action::Allow as i32,
+                ..Default::default()
+            }),
+        }
+    }
+    for chain in acl_v1.chains.iter_mut() {
+        chain.rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+    }
+    acl.acl_v1 = Some(acl_v1);
+
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            acl: Some(AclPatch {
+                acl: Some(acl),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+// which destinations the instance may send to through the overlay, a filter in front of
+// the routes, e.g. to keep a shared device away from parts of the network. empty
+// `allowed` allows everything not in `denied`, denied wins. cidrs are like 10.0.0.0/8,
+// plain ips match only themselves. both empty removes the filter.
+#[napi]
+pub fn set_outbound_policy(inst_id: String, allowed: Vec<String>, denied: Vec<String>) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if let Some(invalid) = allowed
+        .iter()
+        .chain(denied.iter())
+        .find(|x| x.parse::<cidr::IpCidr>().is_err())
+    {
+        hilog_error!("[Rust] set_outbound_policy: invalid cidr {}", invalid);
+        return false;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            outbound_policy: Some(OutboundPolicyPatch { allowed, denied }),
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,79 @@
+use easytier::peers::PeerConnApprover;
+use easytier::proto::api::instance::PeerConnInfo;
+use easytier::set_global_var;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::Promise;
+use napi_ohos::threadsafe_function::ThreadsafeFunction;
+use ohos_hilog_binding::{hilog_error, hilog_warn};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+// a peer is rejected when the app did not answer within this
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[napi(object)]
+pub struct PeerApprovalRequest {
+    pub inst_id: String,
+    pub peer_id: u32,
+    pub tunnel_type: Option<String>,
+    pub remote_addr: Option<String>,
+    // this instance made the conn
+    pub is_client: bool,
+}
+
+impl PeerApprovalRequest {
+    fn new(inst_id: uuid::Uuid, conn: PeerConnInfo) -> Self {
+        let tunnel = conn.tunnel.unwrap_or_default();
+        Self {
+            inst_id: inst_id.to_string(),
+            peer_id: conn.peer_id,
+            tunnel_type: Some(tunnel.tunnel_type).filter(|x| !x.is_empty()),
+            remote_addr: tunnel.remote_addr.map(|x| x.url),
+            is_client: conn.is_client,
+        }
+    }
+}
+
+type ApprovalCallback = ThreadsafeFunction<PeerApprovalRequest, Promise<bool>>;
+
+async fn ask(func: Arc<ApprovalCallback>, request: PeerApprovalRequest) -> bool {
+    let peer_id = request.peer_id;
+    let approval = async { func.call_async(Ok(request)).await?.await };
+    match tokio::time::timeout(APPROVAL_TIMEOUT, approval).await {
+        Ok(Ok(approved)) => approved,
+        Ok(Err(e)) => {
+            hilog_error!("[Rust] peer approval of {} failed {}", peer_id, e);
+            false
+        }
+        Err(_) => {
+            hilog_warn!("[Rust] peer approval of {} timed out, rejected", peer_id);
+            false
+        }
+    }
+}
+
+// asked before a peer of the own network that is not connected yet is let in through a
+// direct conn, in or out. the conn waits for the promise, each peer is only asked once
+// per run of the instance. nothing is exchanged with a rejected peer afterwards, also
+// not through relays. peers only ever reached through relays are not asked, this is no
+// replacement for the network secret. registering again replaces the callback.
+#[napi]
+pub fn register_peer_approval_callback(func: ApprovalCallback) {
+    let func = Arc::new(func);
+    let approver: PeerConnApprover = Arc::new(
+        move |inst_id: uuid::Uuid,
+              conn: PeerConnInfo|
+              -> Pin<Box<dyn Future<Output = bool> + Send>> {
+            Box::pin(ask(func.clone(), PeerApprovalRequest::new(inst_id, conn)))
+        },
+    );
+    set_global_var!(PEER_CONN_APPROVER, Some(approver));
+}
+
+// peers are let in without asking again, answers already given stay.
+#[napi]
+pub fn unregister_peer_approval_callback() {
+    set_global_var!(PEER_CONN_APPROVER, None);
+}
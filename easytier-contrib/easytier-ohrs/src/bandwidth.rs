@@ -0,0 +1,176 @@
+use crate::callback::check_call;
+use crate::instance_api;
+use crate::metrics::get_instance_stats;
+use crate::watches::Watches;
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::common::stats_manager::MetricName;
+use easytier::proto::api::instance::ListPeerRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const BANDWIDTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// above this loss the link is taken as saturated, what gets through is all it carries
+const CONGESTED_LOSS_RATE: f64 = 0.05;
+// a degraded link only counts as recovered above this multiple of the minimum
+const BANDWIDTH_RECOVER_RATIO: f64 = 1.25;
+
+#[napi(object)]
+pub struct BandwidthAlert {
+    pub inst_id: String,
+    pub estimated_mbps: f64,
+    pub min_mbps: f64,
+    // mean loss of the peer conns, 0 to 1
+    pub loss_rate: f64,
+    // true when the estimate went below the minimum, false when it recovered
+    pub degraded: bool,
+}
+
+struct BandwidthState {
+    min_mbps: f64,
+    func: ThreadsafeFunction<BandwidthAlert, ()>,
+}
+
+static BANDWIDTH_ALERTS: once_cell::sync::Lazy<Watches<Uuid, BandwidthState>> =
+    once_cell::sync::Lazy::new(Watches::new);
+
+// bytes the instance sent and received for itself, forwarded traffic is not counted
+async fn self_traffic_bytes(uuid: &Uuid) -> Option<u64> {
+    let stats = get_instance_stats(uuid).await?;
+    let names = [
+        MetricName::TrafficBytesSelfTx.to_string(),
+        MetricName::TrafficBytesSelfRx.to_string(),
+    ];
+    Some(
+        stats
+            .iter()
+            .filter(|m| names.contains(&m.name))
+            .map(|m| m.value)
+            .sum(),
+    )
+}
+
+async fn mean_loss_rate(uuid: &Uuid) -> Option<f64> {
+    let peers = INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_peer_manage_service()
+        .list_peer(BaseController::default(), ListPeerRequest::default())
+        .await
+        .ok()?
+        .peer_infos;
+    let losses: Vec<f64> = peers
+        .iter()
+        .flat_map(|p| p.conns.iter())
+        .map(|c| c.loss_rate as f64)
+        .collect();
+    if losses.is_empty() {
+        return Some(0.0);
+    }
+    Some(losses.iter().sum::<f64>() / losses.len() as f64)
+}
+
+// the throughput the link is known to carry. it rises to whatever went through without
+// much loss and falls to what still gets through once the loss shows the link is
+// saturated. an idle link gives no evidence either way and keeps the estimate.
+fn next_estimate(estimate: Option<f64>, rate_mbps: f64, loss_rate: f64) -> Option<f64> {
+    if loss_rate > CONGESTED_LOSS_RATE {
+        if rate_mbps == 0.0 {
+            return estimate;
+        }
+        return Some(rate_mbps * (1.0 - loss_rate));
+    }
+    match estimate {
+        Some(estimate) if estimate >= rate_mbps => Some(estimate),
+        _ if rate_mbps > 0.0 => Some(rate_mbps),
+        _ => estimate,
+    }
+}
+
+async fn watch_bandwidth(uuid: Uuid, state: Arc<BandwidthState>) {
+    let mut last: Option<(Instant, u64)> = None;
+    let mut estimate = None;
+    let mut degraded = false;
+    loop {
+        tokio::time::sleep(BANDWIDTH_CHECK_INTERVAL).await;
+        let (Some(bytes), Some(loss_rate)) =
+            (self_traffic_bytes(&uuid).await, mean_loss_rate(&uuid).await)
+        else {
+            hilog_debug!("[Rust] instance {} gone, stop watching bandwidth", uuid);
+            break;
+        };
+        let now = Instant::now();
+        let Some((last_at, last_bytes)) = last.replace((now, bytes)) else {
+            continue;
+        };
+        // counters go back after reset_instance_stats, that interval counts as idle
+        let rate_mbps = bytes.saturating_sub(last_bytes) as f64 * 8.0
+            / now.duration_since(last_at).as_micros().max(1) as f64;
+        estimate = next_estimate(estimate, rate_mbps, loss_rate);
+        let Some(estimate) = estimate else {
+            continue;
+        };
+
+        let crossed = if degraded {
+            estimate > state.min_mbps * BANDWIDTH_RECOVER_RATIO
+        } else {
+            estimate < state.min_mbps
+        };
+        if !crossed {
+            continue;
+        }
+        degraded = !degraded;
+        check_call(
+            "bandwidth alert",
+            state.func.call(
+                Ok(BandwidthAlert {
+                    inst_id: uuid.to_string(),
+                    estimated_mbps: estimate,
+                    min_mbps: state.min_mbps,
+                    loss_rate,
+                    degraded,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            ),
+        );
+    }
+}
+
+// calls `func` when the estimated bandwidth of the overlay drops below `min_mbps`, and
+// again once it is back above 125% of it. the estimate comes from the throughput of the
+// instance and the loss of its peer conns, checked every 2s: it only drops when the loss
+// shows the link cannot carry what is sent, so a link the app does not use stays as it
+// was. setting a new alert replaces the old one, 0 removes it.
+#[napi]
+pub fn set_bandwidth_alert(
+    inst_id: String,
+    min_mbps: f64,
+    func: ThreadsafeFunction<BandwidthAlert, ()>,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if min_mbps.is_nan() || min_mbps < 0.0 {
+        hilog_error!("[Rust] set_bandwidth_alert: invalid minimum {}", min_mbps);
+        return false;
+    }
+    if min_mbps == 0.0 {
+        BANDWIDTH_ALERTS.remove(&uuid);
+        return true;
+    }
+    if instance_api::get_instance_service(&uuid).is_none() {
+        return false;
+    }
+
+    BANDWIDTH_ALERTS.insert(uuid, BandwidthState { min_mbps, func }, |state| {
+        watch_bandwidth(uuid, state)
+    });
+    true
+}
+
+pub(crate) fn remove_bandwidth_alerts(uuids: &[Uuid]) {
+    BANDWIDTH_ALERTS.retain(|uuid| !uuids.contains(uuid));
+}
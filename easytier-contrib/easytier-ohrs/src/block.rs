@@ -0,0 +1,50 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use easytier::proto::api::instance::BlockPeerRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+
+fn send_block_request(inst_id: &str, peer_id: &str, duration_ms: u32) -> Option<bool> {
+    let uuid = parse_inst_id(inst_id)?;
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!("[Rust] block peer: invalid peer id {}", peer_id);
+        return None;
+    };
+    let service = instance_api::get_instance_service(&uuid)?;
+    let request = BlockPeerRequest {
+        instance: None,
+        peer_id,
+        duration_ms,
+    };
+    match block_on(
+        service
+            .get_peer_manage_service()
+            .block_peer(BaseController::default(), request),
+    ) {
+        Ok(resp) => Some(resp.was_blocked),
+        Err(e) => {
+            hilog_error!("[Rust] block peer {} failed {}", peer_id, e);
+            None
+        }
+    }
+}
+
+// drops the data to, from and relayed for the peer for `duration_ms`, then lets it through
+// again. the peer stays in the route table and can still be pinged. blocking a blocked
+// peer restarts the duration. the PeerBlocked and PeerUnblocked events of the instance
+// report both.
+#[napi]
+pub fn block_peer(inst_id: String, peer_id: String, duration_ms: u32) -> bool {
+    if duration_ms == 0 {
+        hilog_error!("[Rust] block_peer: duration must not be 0, use unblock_peer");
+        return false;
+    }
+    send_block_request(&inst_id, &peer_id, duration_ms).is_some()
+}
+
+// returns whether the peer was blocked.
+#[napi]
+pub fn unblock_peer(inst_id: String, peer_id: String) -> bool {
+    send_block_request(&inst_id, &peer_id, 0).unwrap_or(false)
+}
@@ -0,0 +1,192 @@
+use crate::instance_api::{self, block_on};
+use crate::route::cidrs_overlap;
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use cidr::{IpCidr, Ipv4Cidr, Ipv4Inet};
+use easytier::proto::api::config::{
+    ConfigPatchAction, GetConfigRequest, InstanceConfigPatch, ProxyNetworkPatch,
+};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+use uuid::Uuid;
+
+#[napi(object)]
+pub struct BridgeCheck {
+    pub can_bridge: bool,
+    // e.g. "10.144.144.0/24 (a) overlaps 10.144.0.0/16 (b)"
+    pub overlaps: Vec<String>,
+    pub reason: Option<String>,
+}
+
+impl BridgeCheck {
+    fn reject(reason: impl Into<String>) -> Self {
+        Self {
+            can_bridge: false,
+            overlaps: vec![],
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+// every cidr reachable through an instance: its virtual network, peers' virtual
+// networks and all advertised proxy cidrs.
+struct NetworkCidrs {
+    virtual_network: Ipv4Cidr,
+    cidrs: Vec<IpCidr>,
+}
+
+fn parse_advertised_cidr(s: &str) -> Option<IpCidr> {
+    // "real->mapped" advertises the mapped cidr
+    s.rsplit("->").next()?.trim().parse().ok()
+}
+
+async fn collect_cidrs(uuid: &Uuid) -> Result<NetworkCidrs, String> {
+    let info = INSTANCE_MANAGER
+        .get_network_info(uuid)
+        .await
+        .ok_or_else(|| format!("instance {} not found", uuid))?;
+    if !info.running {
+        return Err(format!("instance {} is not running", uuid));
+    }
+    let virtual_network: Ipv4Cidr = info
+        .my_node_info
+        .and_then(|x| x.virtual_ipv4)
+        .ok_or_else(|| format!("instance {} has no virtual ipv4 yet", uuid))?
+        .into();
+
+    let mut advertised = vec![];
+    for route in info.routes {
+        if let Some(ipv4) = route.ipv4_addr {
+            advertised.push(IpCidr::V4(ipv4.into()));
+        }
+        advertised.extend(
+            route
+                .proxy_cidrs
+                .iter()
+                .filter_map(|x| parse_advertised_cidr(x)),
+        );
+    }
+    if let Some(service) = INSTANCE_MANAGER.get_instance_service(uuid) {
+        if let Ok(resp) = service
+            .get_config_service()
+            .get_config(BaseController::default(), GetConfigRequest::default())
+            .await
+        {
+            let proxy_cidrs = resp.config.map(|x| x.proxy_cidrs).unwrap_or_default();
+            advertised.extend(proxy_cidrs.iter().filter_map(|x| parse_advertised_cidr(x)));
+        }
+    }
+    let mut cidrs = vec![IpCidr::V4(virtual_network)];
+    for cidr in advertised {
+        if !cidrs.contains(&cidr) {
+            cidrs.push(cidr);
+        }
+    }
+    Ok(NetworkCidrs {
+        virtual_network,
+        cidrs,
+    })
+}
+
+fn check(a: &Uuid, b: &Uuid) -> Result<(NetworkCidrs, NetworkCidrs), BridgeCheck> {
+    if a == b {
+        return Err(BridgeCheck::reject("cannot bridge an instance with itself"));
+    }
+    let (cidrs_a, cidrs_b) = block_on(async { (collect_cidrs(a).await, collect_cidrs(b).await) });
+    let cidrs_a = cidrs_a.map_err(BridgeCheck::reject)?;
+    let cidrs_b = cidrs_b.map_err(BridgeCheck::reject)?;
+
+    // the virtual networks advertised by an enabled bridge are not conflicts
+    let bridged_a = IpCidr::V4(cidrs_b.virtual_network);
+    let bridged_b = IpCidr::V4(cidrs_a.virtual_network);
+    let mut overlapped = vec![];
+    for x in cidrs_a.cidrs.iter().filter(|x| **x != bridged_a) {
+        for y in cidrs_b
+            .cidrs
+            .iter()
+            .filter(|y| **y != bridged_b && cidrs_overlap(x, y))
+        {
+            overlapped.push(format!("{} ({}) overlaps {} ({})", x, a, y, b));
+        }
+    }
+    if !overlapped.is_empty() {
+        return Err(BridgeCheck {
+            can_bridge: false,
+            overlaps: overlapped,
+            reason: Some("address ranges of the two networks overlap".to_string()),
+        });
+    }
+    Ok((cidrs_a, cidrs_b))
+}
+
+// whether traffic could be routed between the two networks without ambiguity,
+// i.e. no address reachable through one is also reachable through the other.
+#[napi]
+pub fn can_bridge(instance_a: String, instance_b: String) -> BridgeCheck {
+    let (Some(a), Some(b)) = (parse_inst_id(&instance_a), parse_inst_id(&instance_b)) else {
+        return BridgeCheck::reject("invalid instance id");
+    };
+    match check(&a, &b) {
+        Ok(_) => BridgeCheck {
+            can_bridge: true,
+            overlaps: vec![],
+            reason: None,
+        },
+        Err(e) => e,
+    }
+}
+
+fn proxy_network_patch(action: ConfigPatchAction, cidr: Ipv4Cidr) -> InstanceConfigPatch {
+    InstanceConfigPatch {
+        proxy_networks: vec![ProxyNetworkPatch {
+            action: action as i32,
+            cidr: Some(
+                Ipv4Inet::new(cidr.first_address(), cidr.network_length())
+                    .unwrap()
+                    .into(),
+            ),
+            mapped_cidr: None,
+        }],
+        ..Default::default()
+    }
+}
+
+// each instance advertises the virtual network of the other one as a proxy cidr, so
+// peers of one network reach the other through this device via the subnet proxy. only
+// one of them can use the tun device, the other one runs with no_tun.
+#[napi]
+pub fn enable_bridge(a: String, b: String) -> bool {
+    let (Some(uuid_a), Some(uuid_b)) = (parse_inst_id(&a), parse_inst_id(&b)) else {
+        return false;
+    };
+    let (cidrs_a, cidrs_b) = match check(&uuid_a, &uuid_b) {
+        Ok(x) => x,
+        Err(e) => {
+            hilog_error!(
+                "[Rust] enable_bridge {} {} rejected: {:?} {:?}",
+                a,
+                b,
+                e.reason,
+                e.overlaps
+            );
+            return false;
+        }
+    };
+    if !instance_api::patch_instance_config(
+        &uuid_a,
+        proxy_network_patch(ConfigPatchAction::Add, cidrs_b.virtual_network),
+    ) {
+        return false;
+    }
+    if !instance_api::patch_instance_config(
+        &uuid_b,
+        proxy_network_patch(ConfigPatchAction::Add, cidrs_a.virtual_network),
+    ) {
+        instance_api::patch_instance_config(
+            &uuid_a,
+            proxy_network_patch(ConfigPatchAction::Remove, cidrs_b.virtual_network),
+        );
+        return false;
+    }
+    true
+}
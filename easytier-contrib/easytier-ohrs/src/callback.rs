@@ -0,0 +1,26 @@
+use napi_derive_ohos::napi;
+use napi_ohos::Status;
+use ohos_hilog_binding::hilog_warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static DROPPED_CALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+// js callbacks are only ever called NonBlocking, so a js thread that cannot keep up
+// never stalls the networking threads. a call is dropped when the queue of a bounded
+// callback is full or the callback is being released, returns whether it was queued.
+pub(crate) fn check_call(name: &str, status: Status) -> bool {
+    if status == Status::Ok {
+        return true;
+    }
+    if DROPPED_CALLBACKS.fetch_add(1, Ordering::Relaxed) == 0 {
+        // only the first one, a backed up queue drops many in a row
+        hilog_warn!("[Rust] {} callback dropped {:?}", name, status);
+    }
+    false
+}
+
+// number of callback calls dropped since the module was loaded, across all callbacks.
+#[napi]
+pub fn get_dropped_callback_count() -> i64 {
+    DROPPED_CALLBACKS.load(Ordering::Relaxed) as i64
+}
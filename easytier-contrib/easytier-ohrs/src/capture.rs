@@ -0,0 +1,77 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use easytier::proto::api::instance::{StartPacketCaptureRequest, StopPacketCaptureRequest};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+
+// records the ip packets the instance exchanges with its peers through the tun device
+// in memory, up to `max_packets` packets or `max_bytes` bytes, at most 64MB whatever is
+// asked. only the first 128 bytes of each are kept unless `full_packets` is set. fails if
+// a capture of the instance runs already.
+#[napi]
+pub fn start_packet_capture(
+    inst_id: String,
+    max_packets: u32,
+    max_bytes: i64,
+    full_packets: Option<bool>,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if max_packets == 0 || max_bytes <= 0 {
+        hilog_error!("[Rust] start_packet_capture: limits must be above 0");
+        return false;
+    }
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return false;
+    };
+    let request = StartPacketCaptureRequest {
+        instance: None,
+        max_packets,
+        max_bytes: max_bytes as u64,
+        full_packets: full_packets.unwrap_or(false),
+    };
+    match block_on(
+        service
+            .get_peer_manage_service()
+            .start_packet_capture(BaseController::default(), request),
+    ) {
+        Ok(resp) => {
+            if !resp.started {
+                hilog_error!("[Rust] a packet capture of {} runs already", uuid);
+            }
+            resp.started
+        }
+        Err(e) => {
+            hilog_error!("[Rust] start packet capture of {} failed {}", uuid, e);
+            false
+        }
+    }
+}
+
+// ends the capture and returns the packets as a base64 encoded pcap file with raw ip
+// link type, e.g. for wireshark. empty if no capture of the instance runs.
+#[napi]
+pub fn stop_packet_capture(inst_id: String) -> String {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return String::new();
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return String::new();
+    };
+    match block_on(service.get_peer_manage_service().stop_packet_capture(
+        BaseController::default(),
+        StopPacketCaptureRequest { instance: None },
+    )) {
+        Ok(resp) => resp
+            .pcap
+            .map(|x| BASE64_STANDARD.encode(x))
+            .unwrap_or_default(),
+        Err(e) => {
+            hilog_error!("[Rust] stop packet capture of {} failed {}", uuid, e);
+            String::new()
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::{
+    INSTANCE_MANAGER, forget_ephemeral_instances, instance_state, lifecycle_write, packet_io,
+    quota, remove_instance_resources,
+};
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_debug;
+
+#[napi(object)]
+pub struct CleanupReport {
+    pub removed_instances: Vec<String>,
+    pub released_quota_watches: u32,
+    pub released_packet_pipes: u32,
+}
+
+// instances are started without a stop watcher, so one whose launcher died stays in the
+// manager until stop_network_instance is called, still holding its tun fd and blocking
+// a new start. if the ui crashed it never calls stop, drop those and the state kept for them.
+#[napi]
+pub fn cleanup_orphaned_resources() -> CleanupReport {
+    let removed = {
+        let _guard = lifecycle_write();
+        let dead: Vec<_> = INSTANCE_MANAGER
+            .iter()
+            .filter(|x| !x.is_easytier_running())
+            .map(|x| *x.key())
+            .collect();
+        if !dead.is_empty() {
+            let _ = INSTANCE_MANAGER.delete_network_instance(dead.clone());
+            instance_state::set_stopped(&dead);
+            remove_instance_resources(&dead);
+            forget_ephemeral_instances(&dead);
+        }
+        dead
+    };
+    let report = CleanupReport {
+        removed_instances: removed.iter().map(|x| x.to_string()).collect(),
+        released_quota_watches: quota::release_orphaned_quotas(),
+        released_packet_pipes: packet_io::release_orphaned_packet_pipes(),
+    };
+    hilog_debug!(
+        "[Rust] cleanup orphaned resources, instances {:?}, quota watches {}, packet pipes {}",
+        report.removed_instances,
+        report.released_quota_watches,
+        report.released_packet_pipes
+    );
+    report
+}
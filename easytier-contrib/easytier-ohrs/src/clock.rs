@@ -0,0 +1,70 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use easytier::proto::api::instance::{ListPeerRequest, ListRouteRequest};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use std::collections::HashMap;
+
+#[napi(object)]
+pub struct PeerSkew {
+    pub peer_id: u32,
+    pub hostname: String,
+    // clock of the peer minus ours, positive if the peer is ahead
+    pub offset_ms: i64,
+    // latency of the conn the estimate is taken from, the error is up to half of it
+    pub rtt_ms: f64,
+}
+
+// estimated from the timestamps exchanged in the handshake of each direct conn, so only
+// directly connected peers of versions sending them are listed. conns with the lowest
+// latency give the best estimate.
+#[napi]
+pub fn get_clock_skew(inst_id: String) -> Vec<PeerSkew> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    let (peers, routes) = block_on(async {
+        let peer_manage = service.get_peer_manage_service();
+        (
+            peer_manage
+                .list_peer(BaseController::default(), ListPeerRequest::default())
+                .await
+                .map(|x| x.peer_infos)
+                .unwrap_or_default(),
+            peer_manage
+                .list_route(BaseController::default(), ListRouteRequest::default())
+                .await
+                .map(|x| x.routes)
+                .unwrap_or_default(),
+        )
+    });
+    let hostnames: HashMap<u32, String> = routes
+        .into_iter()
+        .map(|r| (r.peer_id, r.hostname))
+        .collect();
+
+    let mut ret: Vec<PeerSkew> = peers
+        .into_iter()
+        .filter_map(|peer| {
+            let (offset_ms, latency_us) = peer
+                .conns
+                .iter()
+                .filter_map(|c| {
+                    let latency_us = c.stats.as_ref().map(|s| s.latency_us).unwrap_or_default();
+                    Some((c.clock_offset_ms?, latency_us))
+                })
+                .min_by_key(|(_, latency_us)| *latency_us)?;
+            Some(PeerSkew {
+                peer_id: peer.peer_id,
+                hostname: hostnames.get(&peer.peer_id).cloned().unwrap_or_default(),
+                offset_ms,
+                rtt_ms: latency_us as f64 / 1000.0,
+            })
+        })
+        .collect();
+    ret.sort_by_key(|x| x.peer_id);
+    ret
+}
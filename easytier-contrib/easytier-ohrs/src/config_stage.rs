@@ -0,0 +1,105 @@
+use crate::instance_api;
+use crate::parse_inst_id;
+use easytier::proto::api::config::InstanceConfigPatch;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_debug;
+use prost::Message;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// changes of the instances that stage their config, in the order the setters were called
+static PENDING_PATCHES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, Vec<InstanceConfigPatch>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+// keeps `patch` for commit_config while the instance stages its changes, else hands it
+// back to be applied now.
+pub(crate) fn stage_patch(uuid: &Uuid, patch: InstanceConfigPatch) -> Option<InstanceConfigPatch> {
+    match PENDING_PATCHES.lock().unwrap().get_mut(uuid) {
+        Some(pending) => {
+            pending.push(patch);
+            None
+        }
+        None => Some(patch),
+    }
+}
+
+// setters that derive their change from the running config, like replace_peers, cannot
+// be staged on top of the changes collected so far and refuse while this is true.
+pub(crate) fn is_staging(uuid: &Uuid) -> bool {
+    PENDING_PATCHES.lock().unwrap().contains_key(uuid)
+}
+
+// one patch doing what `patches` do one after another: later values replace earlier ones,
+// list changes are kept in order.
+fn merge_patches(patches: Vec<InstanceConfigPatch>) -> InstanceConfigPatch {
+    let mut merged = InstanceConfigPatch::default();
+    for patch in patches {
+        merged
+            .merge(patch.encode_to_vec().as_slice())
+            .expect("re-encoded patch is valid");
+    }
+    merged
+}
+
+pub(crate) fn remove_pending_configs(uuids: &[Uuid]) {
+    let mut pending = PENDING_PATCHES.lock().unwrap();
+    pending.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// from now on the live setters of the instance, like set_max_peers or set_dns_resolver,
+// only collect their changes until commit_config or discard_pending_config. they still
+// check their arguments, but errors of the changes themselves only show up on commit.
+// setters that build on the running config, replace_peers and set_acl_rules, fail while
+// staging. staging again keeps what is collected already.
+#[napi]
+pub fn stage_config(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if instance_api::get_instance_service(&uuid).is_none() {
+        return false;
+    }
+    PENDING_PATCHES.lock().unwrap().entry(uuid).or_default();
+    true
+}
+
+// applies the collected changes as one patch and ends the staging. its values are all
+// checked before any is applied, so an invalid one fails the commit without changing
+// anything. only a list change the instance cannot carry out, e.g. a port forward
+// without a running socks5 server, can leave the changes before it applied. true if
+// nothing was staged.
+#[napi]
+pub fn commit_config(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let pending = PENDING_PATCHES
+        .lock()
+        .unwrap()
+        .remove(&uuid)
+        .unwrap_or_default();
+    if pending.is_empty() {
+        return true;
+    }
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return false;
+    };
+    hilog_debug!("[Rust] commit {} config changes of {}", pending.len(), uuid);
+    instance_api::apply_patch(service.as_ref(), &uuid, merge_patches(pending))
+}
+
+// drops the collected changes and ends the staging, the running config stays as it was.
+#[napi]
+pub fn discard_pending_config(inst_id: String) {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return;
+    };
+    if let Some(pending) = PENDING_PATCHES.lock().unwrap().remove(&uuid) {
+        hilog_debug!(
+            "[Rust] discard {} config changes of {}",
+            pending.len(),
+            uuid
+        );
+    }
+}
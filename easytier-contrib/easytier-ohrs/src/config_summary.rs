@@ -0,0 +1,122 @@
+use easytier::common::config::{self, ConfigLoader, TomlConfigLoader};
+use easytier::proto::common::CompressionAlgoPb;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+
+#[napi(object)]
+pub struct ConfigSummary {
+    pub network_name: String,
+    pub dhcp: bool,
+    pub no_tun: bool,
+    pub encryption: bool,
+    pub compression: bool,
+    pub proxy_networks: bool,
+    // this node lets peers use it as exit node
+    pub exit_node: bool,
+    // this node sends traffic out through exit nodes
+    pub uses_exit_nodes: bool,
+    pub socks5: bool,
+    pub wireguard: bool,
+    pub port_forwards: bool,
+    pub magic_dns: bool,
+    pub kcp_proxy: bool,
+    pub quic_proxy: bool,
+    pub private_mode: bool,
+    pub p2p_disabled: bool,
+    pub peer_count: u32,
+    pub listener_count: u32,
+    pub proxy_network_count: u32,
+    pub port_forward_count: u32,
+}
+
+// only parses `cfg_str`, nothing is started. keys this version does not know are
+// ignored, as when running the config. returns undefined if the config is invalid.
+#[napi]
+pub fn summarize_config(cfg_str: String) -> Option<ConfigSummary> {
+    let cfg = match TomlConfigLoader::new_from_str(&cfg_str) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            hilog_error!("[Rust] summarize_config failed {}", e);
+            return None;
+        }
+    };
+    let flags = cfg.get_flags();
+    let proxy_networks = cfg.get_proxy_cidrs();
+    let port_forwards = cfg.get_port_forwards();
+    Some(ConfigSummary {
+        network_name: cfg.get_network_identity().network_name,
+        dhcp: cfg.get_dhcp(),
+        no_tun: flags.no_tun,
+        encryption: flags.enable_encryption,
+        compression: flags.data_compress_algo > CompressionAlgoPb::None as i32,
+        proxy_networks: !proxy_networks.is_empty(),
+        exit_node: flags.enable_exit_node,
+        uses_exit_nodes: !cfg.get_exit_nodes().is_empty(),
+        socks5: cfg.get_socks5_portal().is_some(),
+        wireguard: cfg.get_vpn_portal_config().is_some(),
+        port_forwards: !port_forwards.is_empty(),
+        magic_dns: flags.accept_dns,
+        kcp_proxy: flags.enable_kcp_proxy,
+        quic_proxy: flags.enable_quic_proxy,
+        private_mode: flags.private_mode,
+        p2p_disabled: flags.disable_p2p,
+        peer_count: cfg.get_peers().len() as u32,
+        listener_count: cfg.get_listeners().unwrap_or_default().len() as u32,
+        proxy_network_count: proxy_networks.len() as u32,
+        port_forward_count: port_forwards.len() as u32,
+    })
+}
+
+// the toml config with every flag written out and unordered lists sorted, so configs
+// that mean the same give the same string and two of them can be diffed line by line.
+// returns "ERROR ..." if the config is invalid.
+#[napi]
+pub fn normalize_config(cfg_str: String) -> String {
+    match TomlConfigLoader::new_from_str(&cfg_str) {
+        Ok(cfg) => cfg.dump_normalized(),
+        Err(e) => {
+            hilog_error!("[Rust] normalize_config failed {}", e);
+            format!("ERROR {}", e)
+        }
+    }
+}
+
+// a hex sha256 of normalize_config, the same for configs that mean the same, so a
+// cached config can be compared without keeping it. returns "ERROR ..." if the config
+// is invalid.
+#[napi]
+pub fn config_fingerprint(cfg_str: String) -> String {
+    match TomlConfigLoader::new_from_str(&cfg_str) {
+        Ok(cfg) => cfg.fingerprint(),
+        Err(e) => {
+            hilog_error!("[Rust] config_fingerprint failed {}", e);
+            format!("ERROR {}", e)
+        }
+    }
+}
+
+#[napi(object)]
+pub struct ConfigKeyInfo {
+    // keys inside tables are joined with a dot, e.g. "flags.mtu" or "peer.uri"
+    pub name: String,
+    // string, bool, integer, string_array, table or table_array
+    pub value_type: String,
+    pub default: Option<String>,
+    // must be given whenever its table or array entry is
+    pub required: bool,
+}
+
+// the keys the toml config loader accepts, taken from the loader itself. the acl table
+// is only listed as a whole.
+#[napi]
+pub fn get_config_schema() -> Vec<ConfigKeyInfo> {
+    config::config_schema()
+        .into_iter()
+        .map(|x| ConfigKeyInfo {
+            name: x.name,
+            value_type: x.value_type.to_string(),
+            default: x.default,
+            required: x.required,
+        })
+        .collect()
+}
@@ -0,0 +1,226 @@
+use crate::error_event::{self, ErrorCategory};
+use crate::{INSTANCE_MANAGER, first_peer, instance_api, instance_state, parse_inst_id, subnets};
+use easytier::common::global_ctx::GlobalCtxEvent;
+use easytier::proto::api::instance::ConnectorStatus;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_debug;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectErrorCategory {
+    DnsFailure,
+    AllPeersUnreachable,
+    AuthRejected,
+    TunSetupFailed,
+    // all peers were given up after max reconnect attempts, kept until the restart
+    GaveUp,
+}
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct ConnectError {
+    pub category: ConnectErrorCategory,
+    pub detail: String,
+    // unix timestamp in seconds
+    pub time: i64,
+}
+
+impl ConnectError {
+    fn new(category: ConnectErrorCategory, detail: String) -> Self {
+        Self {
+            category,
+            detail,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+static CONNECT_ERRORS: once_cell::sync::Lazy<Mutex<HashMap<Uuid, ConnectError>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+// kept apart from the peer errors, so connecting to a peer does not hide a tun device that
+// still does not work
+static TUN_ERRORS: once_cell::sync::Lazy<Mutex<HashMap<Uuid, ConnectError>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn errors_of(category: ConnectErrorCategory) -> &'static Mutex<HashMap<Uuid, ConnectError>> {
+    match category {
+        ConnectErrorCategory::TunSetupFailed => &*TUN_ERRORS,
+        _ => &*CONNECT_ERRORS,
+    }
+}
+
+// the error is the debug output of the connector result, see ManualConnectorManager.
+pub(crate) fn classify_connect_error(err: &str) -> ConnectErrorCategory {
+    if err.contains("SecretKeyError") {
+        ConnectErrorCategory::AuthRejected
+    } else if err.contains("get ip from url failed") {
+        ConnectErrorCategory::DnsFailure
+    } else {
+        ConnectErrorCategory::AllPeersUnreachable
+    }
+}
+
+fn record(uuid: Uuid, error: ConnectError) {
+    hilog_debug!(
+        "[Rust] instance {} connect error {:?} {}",
+        uuid,
+        error.category,
+        error.detail
+    );
+    errors_of(error.category)
+        .lock()
+        .unwrap()
+        .insert(uuid, error);
+    instance_state::set_degraded(&uuid, true);
+}
+
+fn clear(uuid: &Uuid, categories: &[ConnectErrorCategory]) {
+    let mut cleared = false;
+    for category in categories {
+        let mut errors = errors_of(*category).lock().unwrap();
+        if errors.get(uuid).is_some_and(|e| e.category == *category) {
+            errors.remove(uuid);
+            cleared = true;
+        }
+    }
+    if cleared && !has_connect_error(uuid) {
+        instance_state::set_degraded(uuid, false);
+    }
+}
+
+// also true while the tun device does not work
+pub(crate) fn has_connect_error(uuid: &Uuid) -> bool {
+    CONNECT_ERRORS.lock().unwrap().contains_key(uuid)
+        || TUN_ERRORS.lock().unwrap().contains_key(uuid)
+}
+
+// no connector is retried anymore, see set_max_reconnect_attempts
+async fn all_connectors_gave_up(uuid: &Uuid) -> bool {
+    let Some(service) = INSTANCE_MANAGER.get_instance_service(uuid) else {
+        return false;
+    };
+    instance_api::list_connectors(service.as_ref())
+        .await
+        .is_some_and(|x| {
+            !x.is_empty()
+                && x.iter()
+                    .all(|(_, status)| *status == ConnectorStatus::GaveUp as i32)
+        })
+}
+
+// keep the latest connect failure of an instance until it connects to a peer. failures
+// are only recorded while no peer is connected, one unreachable peer among many is not
+// a failure to connect.
+pub(crate) fn watch_connect_errors(uuid: Uuid) {
+    CONNECT_ERRORS.lock().unwrap().remove(&uuid);
+    TUN_ERRORS.lock().unwrap().remove(&uuid);
+    let Some(mut receiver) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| *x.key() == uuid)
+        .and_then(|x| x.subscribe_event())
+    else {
+        return;
+    };
+    instance_api::spawn(async move {
+        let mut connected_peers = HashSet::new();
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            error_event::report_error_event(&uuid, &event);
+            first_peer::report_first_peer_event(&uuid, &event);
+            subnets::report_subnets_event(&uuid, &event);
+            match event {
+                GlobalCtxEvent::PeerAdded(peer_id) => {
+                    connected_peers.insert(peer_id);
+                    clear(
+                        &uuid,
+                        &[
+                            ConnectErrorCategory::DnsFailure,
+                            ConnectErrorCategory::AllPeersUnreachable,
+                            ConnectErrorCategory::AuthRejected,
+                        ],
+                    );
+                }
+                GlobalCtxEvent::PeerRemoved(peer_id) => {
+                    connected_peers.remove(&peer_id);
+                }
+                GlobalCtxEvent::ConnectError(dst, _, err) if connected_peers.is_empty() => {
+                    record(
+                        uuid,
+                        ConnectError::new(
+                            classify_connect_error(&err),
+                            format!("connect to {} failed: {}", dst, err),
+                        ),
+                    );
+                }
+                GlobalCtxEvent::ConnectorGaveUp(_)
+                    if connected_peers.is_empty() && all_connectors_gave_up(&uuid).await =>
+                {
+                    let detail = "gave up reconnecting to all peers".to_string();
+                    error_event::report_error(&uuid, ErrorCategory::GaveUp, detail.clone());
+                    record(
+                        uuid,
+                        ConnectError::new(ConnectErrorCategory::GaveUp, detail),
+                    );
+                }
+                GlobalCtxEvent::TunDeviceError(err) => {
+                    record(
+                        uuid,
+                        ConnectError::new(ConnectErrorCategory::TunSetupFailed, err),
+                    );
+                }
+                GlobalCtxEvent::TunDeviceReady(_) => {
+                    clear(&uuid, &[ConnectErrorCategory::TunSetupFailed]);
+                }
+                GlobalCtxEvent::WokenOnDemand(_) => {
+                    instance_state::set_woken(&uuid);
+                }
+                _ => {}
+            }
+        }
+    });
+}
+
+pub(crate) fn remove_connect_errors(uuids: &[Uuid]) {
+    for errors in [&CONNECT_ERRORS, &TUN_ERRORS] {
+        errors
+            .lock()
+            .unwrap()
+            .retain(|uuid, _| !uuids.contains(uuid));
+    }
+}
+
+// why the instance could not connect, none if it is connected or still trying its
+// first peers. a failed tun device is returned while no peer error is kept. kept after
+// the instance stopped until stop_network_instance is called.
+#[napi]
+pub fn get_last_connect_error(inst_id: String) -> Option<ConnectError> {
+    let uuid = parse_inst_id(&inst_id)?;
+    for errors in [&CONNECT_ERRORS, &TUN_ERRORS] {
+        if let Some(error) = errors.lock().unwrap().get(&uuid) {
+            return Some(error.clone());
+        }
+    }
+
+    // errors that stop the launcher before any event is issued, e.g. creating the tun
+    // device on startup
+    let error_msg = INSTANCE_MANAGER
+        .iter()
+        .find(|x| *x.key() == uuid)
+        .and_then(|x| x.get_latest_error_msg())?;
+    error_msg
+        .to_ascii_lowercase()
+        .contains("tun")
+        .then(|| ConnectError::new(ConnectErrorCategory::TunSetupFailed, error_msg))
+}
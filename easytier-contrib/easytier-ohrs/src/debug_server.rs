@@ -0,0 +1,235 @@
+use crate::diagnostics::redact;
+use crate::instance_api::block_on;
+use crate::metrics::{get_instance_stats, get_metrics_prometheus};
+use crate::{INSTANCE_MANAGER, lifecycle_read, parse_inst_id};
+use easytier::proto::api::instance::{ListPeerRequest, ListRouteRequest};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error, hilog_warn};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_DEBUG_ADDR: &str = "127.0.0.1:0";
+// how long stop_debug_server may wait for the accept loop to notice
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REQUEST_SIZE: usize = 8192;
+
+const INDEX: &str = "GET /instances                 running infos of all instances
+GET /instances/<id>/peers      peers and their conns
+GET /instances/<id>/routes     route table
+GET /instances/<id>/stats      counters
+GET /metrics                   all counters in prometheus text format
+";
+
+struct DebugServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+static DEBUG_SERVER: Mutex<Option<DebugServer>> = Mutex::new(None);
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    // secrets and credentials are redacted, the server has no authentication
+    fn json(value: Option<serde_json::Value>) -> Self {
+        match value {
+            Some(mut value) => {
+                redact(&mut value);
+                Self {
+                    status: "200 OK",
+                    content_type: "application/json",
+                    body: value.to_string(),
+                }
+            }
+            None => Self::not_found(),
+        }
+    }
+
+    fn text(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    fn not_found() -> Self {
+        Self {
+            status: "404 Not Found",
+            content_type: "text/plain; charset=utf-8",
+            body: INDEX.to_string(),
+        }
+    }
+}
+
+fn instance_json(id: &str, resource: &str) -> Option<serde_json::Value> {
+    let uuid = parse_inst_id(id)?;
+    let service = INSTANCE_MANAGER.get_instance_service(&uuid)?;
+    let peer_manage = service.get_peer_manage_service();
+    block_on(async {
+        let value = match resource {
+            "peers" => serde_json::to_value(
+                peer_manage
+                    .list_peer(BaseController::default(), ListPeerRequest::default())
+                    .await
+                    .ok()?
+                    .peer_infos,
+            ),
+            "routes" => serde_json::to_value(
+                peer_manage
+                    .list_route(BaseController::default(), ListRouteRequest::default())
+                    .await
+                    .ok()?
+                    .routes,
+            ),
+            "stats" => serde_json::to_value(get_instance_stats(&uuid).await?),
+            _ => return None,
+        };
+        value.ok()
+    })
+}
+
+fn route(path: &str) -> Response {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        [""] => Response::text(INDEX.to_string()),
+        ["metrics"] => Response::text(get_metrics_prometheus()),
+        ["instances"] => {
+            let _guard = lifecycle_read();
+            Response::json(
+                INSTANCE_MANAGER
+                    .collect_network_infos_sync()
+                    .ok()
+                    .and_then(|x| serde_json::to_value(x).ok()),
+            )
+        }
+        ["instances", id, resource] => Response::json(instance_json(id, resource)),
+        _ => Response::not_found(),
+    }
+}
+
+fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = vec![];
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|x| x == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => route(target.split('?').next().unwrap_or_default()),
+        _ => Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain; charset=utf-8",
+            body: "only GET is supported\n".to_string(),
+        },
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )
+}
+
+fn serve(listener: TcpListener, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if let Err(e) = handle(stream) {
+                    hilog_debug!("[Rust] debug server request from {} failed {}", peer, e);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(e) => {
+                hilog_error!("[Rust] debug server accept failed {}", e);
+                std::thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+// serves the infos, peers, routes and stats of the instances as json over plain http, for
+// debugging on the device. empty `bind_addr` listens on a free port of 127.0.0.1, other
+// addresses expose all of it to whoever can reach them, there is no auth. returns the
+// bound address, none if it cannot be bound or a debug server runs already.
+#[napi]
+pub fn start_debug_server(bind_addr: String) -> Option<String> {
+    let bind_addr = if bind_addr.is_empty() {
+        DEFAULT_DEBUG_ADDR.to_string()
+    } else {
+        bind_addr
+    };
+    let addr: SocketAddr = match bind_addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            hilog_error!(
+                "[Rust] start_debug_server: invalid address {} {}",
+                bind_addr,
+                e
+            );
+            return None;
+        }
+    };
+    let mut server = DEBUG_SERVER.lock().unwrap();
+    if let Some(server) = server.as_ref() {
+        hilog_error!("[Rust] debug server already runs on {}", server.addr);
+        return None;
+    }
+    if !addr.ip().is_loopback() {
+        hilog_warn!(
+            "[Rust] debug server on {} is reachable from other hosts",
+            addr
+        );
+    }
+    let listener = match TcpListener::bind(addr).and_then(|x| {
+        x.set_nonblocking(true)?;
+        Ok(x)
+    }) {
+        Ok(listener) => listener,
+        Err(e) => {
+            hilog_error!("[Rust] debug server bind {} failed {}", addr, e);
+            return None;
+        }
+    };
+    let addr = listener.local_addr().ok()?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = stop.clone();
+    let thread = std::thread::spawn(move || serve(listener, stop_clone));
+    hilog_debug!("[Rust] debug server listening on {}", addr);
+    *server = Some(DebugServer { addr, stop, thread });
+    Some(addr.to_string())
+}
+
+#[napi]
+pub fn stop_debug_server() {
+    let Some(server) = DEBUG_SERVER.lock().unwrap().take() else {
+        return;
+    };
+    server.stop.store(true, Ordering::Relaxed);
+    let _ = server.thread.join();
+}
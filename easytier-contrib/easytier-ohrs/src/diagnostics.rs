@@ -0,0 +1,445 @@
+use crate::instance_api::{self, block_on, list_connectors};
+use crate::nat::{NatSuggestion, NatWarningKind, detect_nat_warnings, instance_stun_info};
+use crate::route::{RouteTable, list_routes, same_table};
+use crate::{INSTANCE_MANAGER, get_version_info, lifecycle_read, parse_inst_id, tun};
+use easytier::proto::api::config::GetConfigRequest;
+use easytier::proto::api::instance::{ConnectorStatus, ShowNodeInfoRequest};
+use easytier::proto::api::manage::NetworkConfig;
+use easytier::proto::common::NatType;
+use easytier::proto::rpc_types::controller::BaseController;
+use easytier::rpc_service::InstanceRpcService;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::AsyncTask;
+use napi_ohos::{Env, Task};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use serde_json::{Map, Value, json};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const REDACTED: &str = "<redacted>";
+// the route table has to stay the same this long to count as converged
+const CONVERGENCE_WINDOW: Duration = Duration::from_secs(2);
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    // the last two are the keys of a wireguard config, as in vpn_portal_cfg
+    [
+        "secret",
+        "password",
+        "private_key",
+        "token",
+        "privatekey",
+        "presharedkey",
+    ]
+    .iter()
+    .any(|s| key.contains(s))
+}
+
+// `key = value` lines of a config text like the wireguard config of the vpn portal
+fn redact_config_lines(text: &str) -> String {
+    text.lines()
+        .map(|line| match line.split_once('=') {
+            Some((key, _)) if is_secret_key(key.trim()) => {
+                format!("{}= {}", key, REDACTED)
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// blank out secret fields, credentials embedded in urls and secret lines of config
+// texts, anywhere in the tree.
+pub(crate) fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if is_secret_key(k) && !v.is_null() {
+                    *v = Value::String(REDACTED.to_string());
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(arr) => arr.iter_mut().for_each(redact),
+        Value::String(s) => {
+            if let Ok(mut url) = url::Url::parse(s) {
+                if url.password().is_some() && url.set_password(Some(REDACTED)).is_ok() {
+                    *s = url.to_string();
+                }
+            } else if s.contains('\n') {
+                *s = redact_config_lines(s);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn instance_config(uuid: &Uuid) -> Option<Result<NetworkConfig, String>> {
+    let service = instance_api::get_instance_service(uuid)?;
+    Some(
+        block_on(
+            service
+                .get_config_service()
+                .get_config(BaseController::default(), GetConfigRequest::default()),
+        )
+        .map(|resp| resp.config.unwrap_or_default())
+        .map_err(|e| e.to_string()),
+    )
+}
+
+// easytier has no elected coordinator for ip assignment. dhcp nodes pick a free address
+// in the subnet of the addresses already used by peers, so nodes with a static address
+// are the ones that decide the subnet.
+fn is_ip_authority_of(config: &NetworkConfig) -> bool {
+    !config.dhcp.unwrap_or(false) && config.virtual_ipv4.as_ref().is_some_and(|x| !x.is_empty())
+}
+
+#[napi]
+pub fn is_ip_authority(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    match instance_config(&uuid) {
+        Some(Ok(config)) => is_ip_authority_of(&config),
+        Some(Err(e)) => {
+            hilog_error!("[Rust] is_ip_authority get config of {} failed {}", uuid, e);
+            false
+        }
+        None => false,
+    }
+}
+
+// everything support usually asks for in one json blob: version, per instance config,
+// running info with recent events, peers and routes. secrets are redacted.
+#[napi]
+pub fn export_diagnostics_bundle() -> String {
+    let version = get_version_info();
+    let mut instances = Map::new();
+    {
+        let _guard = lifecycle_read();
+        match INSTANCE_MANAGER.collect_network_infos_sync() {
+            Ok(infos) => {
+                for (uuid, info) in infos {
+                    let (config, ip_authority) = match instance_config(&uuid) {
+                        Some(Ok(config)) => (
+                            serde_json::to_value(&config).unwrap_or(Value::Null),
+                            Value::Bool(is_ip_authority_of(&config)),
+                        ),
+                        Some(Err(e)) => (json!({ "error": e }), Value::Null),
+                        None => (Value::Null, Value::Null),
+                    };
+                    instances.insert(
+                        uuid.to_string(),
+                        json!({
+                            "config": config,
+                            "ip_authority": ip_authority,
+                            "running_info": serde_json::to_value(&info).unwrap_or(Value::Null),
+                        }),
+                    );
+                }
+            }
+            Err(e) => {
+                hilog_error!(
+                    "[Rust] export_diagnostics_bundle collect infos failed {}",
+                    e
+                );
+            }
+        }
+    }
+
+    let mut bundle = json!({
+        "generated_at": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        "version": {
+            "version": version.version,
+            "git_hash": version.git_hash,
+            "enabled_features": version.enabled_features,
+        },
+        "instances": instances,
+    });
+    redact(&mut bundle);
+    serde_json::to_string_pretty(&bundle).unwrap_or_else(|e| format!("ERROR {}", e))
+}
+
+struct Check {
+    name: &'static str,
+    passed: bool,
+    detail: String,
+    fix: Option<&'static str>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Self {
+            name,
+            passed: true,
+            detail,
+            fix: None,
+        }
+    }
+
+    fn fail(name: &'static str, detail: String, fix: &'static str) -> Self {
+        Self {
+            name,
+            passed: false,
+            detail,
+            fix: Some(fix),
+        }
+    }
+}
+
+async fn check_nat(uuid: &Uuid) -> Check {
+    let nat_type = instance_stun_info(uuid)
+        .await
+        .map(|x| x.udp_nat_type())
+        .unwrap_or(NatType::Unknown);
+    if nat_type == NatType::Unknown {
+        return Check::fail(
+            "nat type",
+            "not detected yet".to_string(),
+            "wait for the detection to finish or call refreshNatDetection, udp to the stun servers may be blocked",
+        );
+    }
+    let warnings = detect_nat_warnings(uuid).await;
+    let Some(first) = warnings.first() else {
+        return Check::pass("nat type", format!("udp {}", nat_type.as_str_name()));
+    };
+    let kinds: Vec<&str> = warnings
+        .iter()
+        .map(|x| match x.kind {
+            NatWarningKind::SymmetricNat => "symmetric nat, direct connections mostly fail",
+            NatWarningKind::Cgnat => "carrier grade nat, nothing can connect to the device",
+        })
+        .collect();
+    Check::fail(
+        "nat type",
+        format!("udp {}, {}", nat_type.as_str_name(), kinds.join(", ")),
+        match first.suggestion {
+            NatSuggestion::UseIpv6 => {
+                "the device has public ipv6, peers with ipv6 connect directly"
+            }
+            NatSuggestion::AddRelayPeer => "add a public peer close to the device to relay through",
+        },
+    )
+}
+
+fn check_peers(connectors: &[(url::Url, i32)], routes: &RouteTable) -> Check {
+    let disconnected: Vec<String> = connectors
+        .iter()
+        .filter(|(_, status)| *status != ConnectorStatus::Connected as i32)
+        .map(|(url, _)| url.to_string())
+        .collect();
+    let connected = format!(
+        "{} of {} peer urls connected",
+        connectors.len() - disconnected.len(),
+        connectors.len()
+    );
+    if routes.is_empty() {
+        return Check::fail(
+            "peer reachability",
+            format!("no peer reachable, {}", connected),
+            if connectors.is_empty() {
+                "add the url of a peer of the network"
+            } else {
+                "check the peer urls, the network name and secret and whether the device is online"
+            },
+        );
+    }
+    if !disconnected.is_empty() {
+        return Check::fail(
+            "peer reachability",
+            format!(
+                "{} peers reachable, {}, not connected: {}",
+                routes.len(),
+                connected,
+                disconnected.join(" ")
+            ),
+            "check the urls that are not connected, the peers may be down",
+        );
+    }
+    Check::pass(
+        "peer reachability",
+        format!("{} peers reachable, {}", routes.len(), connected),
+    )
+}
+
+// with the resolver of the system, set_dns_resolver only changes the one of the instance
+async fn check_dns(connectors: &[(url::Url, i32)]) -> Check {
+    let hosts: Vec<(String, u16)> = connectors
+        .iter()
+        .filter_map(|(url, _)| match url.host()? {
+            url::Host::Domain(host) => Some((host.to_string(), url.port_or_known_default()?)),
+            _ => None,
+        })
+        .collect();
+    if hosts.is_empty() {
+        return Check::pass("dns", "no peer urls with hostnames".to_string());
+    }
+    let mut failed = vec![];
+    for (host, port) in &hosts {
+        match tokio::time::timeout(
+            RESOLVE_TIMEOUT,
+            tokio::net::lookup_host((host.as_str(), *port)),
+        )
+        .await
+        {
+            Ok(Ok(mut addrs)) if addrs.next().is_some() => {}
+            Ok(Ok(_)) => failed.push(format!("{} (no address)", host)),
+            Ok(Err(e)) => failed.push(format!("{} ({})", host, e)),
+            Err(_) => failed.push(format!("{} (timeout)", host)),
+        }
+    }
+    if failed.is_empty() {
+        return Check::pass("dns", format!("{} peer hostnames resolved", hosts.len()));
+    }
+    Check::fail(
+        "dns",
+        format!("cannot resolve {}", failed.join(", ")),
+        "check the dns servers of the device or use ip addresses in the peer urls",
+    )
+}
+
+fn check_tun(uuid: &Uuid, config: Option<&NetworkConfig>) -> Check {
+    if config.and_then(|x| x.no_tun).unwrap_or(false) {
+        return Check::pass("tun device", "disabled by no_tun".to_string());
+    }
+    if tun::has_tun_device(uuid) {
+        return Check::pass(
+            "tun device",
+            tun::get_tun_interface_name(uuid.to_string()).unwrap_or_else(|| "ready".to_string()),
+        );
+    }
+    Check::fail(
+        "tun device",
+        "not ready".to_string(),
+        "pass the fd of the vpn extension with setTunFd",
+    )
+}
+
+// not a problem either way, shown since a leaf node changes which routes other peers get
+async fn check_relay(service: &dyn InstanceRpcService) -> Check {
+    let relay_enabled = service
+        .get_peer_manage_service()
+        .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+        .await
+        .ok()
+        .and_then(|x| x.node_info)
+        .map(|x| x.relay_enabled);
+    match relay_enabled {
+        Some(true) => Check::pass("relay", "forwards traffic between other peers".to_string()),
+        Some(false) => Check::pass(
+            "relay",
+            "disabled, other peers do not route through this node".to_string(),
+        ),
+        None => Check::fail(
+            "relay",
+            "node info not available".to_string(),
+            "the instance may be stopping, run the diagnostics again",
+        ),
+    }
+}
+
+async fn check_convergence(uuid: &Uuid, routes: &RouteTable) -> Check {
+    if routes.is_empty() {
+        return Check::fail(
+            "route convergence",
+            "no routes".to_string(),
+            "connect to a peer first",
+        );
+    }
+    tokio::time::sleep(CONVERGENCE_WINDOW).await;
+    match list_routes(uuid).await {
+        Some(now) if same_table(routes, &now) => Check::pass(
+            "route convergence",
+            format!(
+                "{} routes stable for {}s",
+                now.len(),
+                CONVERGENCE_WINDOW.as_secs()
+            ),
+        ),
+        _ => Check::fail(
+            "route convergence",
+            "routes still changing".to_string(),
+            "wait until the network settles, a peer may be reconnecting over and over",
+        ),
+    }
+}
+
+fn format_report(uuid: &Uuid, checks: &[Check]) -> String {
+    let mut report = format!("diagnostics of instance {}\n", uuid);
+    for check in checks {
+        report += &format!(
+            "[{}] {}: {}\n",
+            if check.passed { "PASS" } else { "FAIL" },
+            check.name,
+            check.detail
+        );
+        if let Some(fix) = check.fix {
+            report += &format!("       fix: {}\n", fix);
+        }
+    }
+    report += &format!(
+        "{} of {} checks passed\n",
+        checks.iter().filter(|x| x.passed).count(),
+        checks.len()
+    );
+    report
+}
+
+pub struct DiagnosticsTask {
+    inst_id: String,
+}
+
+impl DiagnosticsTask {
+    async fn run(
+        uuid: &Uuid,
+        service: &dyn InstanceRpcService,
+        config: Option<&NetworkConfig>,
+    ) -> String {
+        let connectors = list_connectors(service).await.unwrap_or_default();
+        let routes = list_routes(uuid).await.unwrap_or_default();
+        let checks = vec![
+            check_nat(uuid).await,
+            check_peers(&connectors, &routes),
+            check_dns(&connectors).await,
+            check_tun(uuid, config),
+            check_relay(service).await,
+            check_convergence(uuid, &routes).await,
+        ];
+        format_report(uuid, &checks)
+    }
+}
+
+#[napi]
+impl Task for DiagnosticsTask {
+    type Output = String;
+    type JsValue = String;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        let not_running =
+            || napi_ohos::Error::from_reason(format!("instance {} is not running", self.inst_id));
+        let uuid = parse_inst_id(&self.inst_id).ok_or_else(not_running)?;
+        let service = instance_api::get_instance_service(&uuid).ok_or_else(not_running)?;
+        let config = instance_config(&uuid).and_then(Result::ok);
+        let report = block_on(Self::run(&uuid, service.as_ref(), config.as_ref()));
+        hilog_debug!("[Rust] diagnostics of {}\n{}", uuid, report);
+        Ok(report)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+// checks nat type, peer reachability, dns of the peer hostnames, the tun device, relaying
+// and route convergence of the running instance, resolves with a report of one line per check and
+// a fix for the failed ones. takes a few seconds, the routes are watched for a moment.
+// rejects if the instance is not running.
+#[napi]
+pub fn run_diagnostics(inst_id: String) -> AsyncTask<DiagnosticsTask> {
+    AsyncTask::new(DiagnosticsTask { inst_id })
+}
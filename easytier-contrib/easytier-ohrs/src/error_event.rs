@@ -0,0 +1,106 @@
+use crate::callback::check_call;
+use crate::connect_error::{ConnectErrorCategory, classify_connect_error};
+use easytier::common::global_ctx::GlobalCtxEvent;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+#[napi(string_enum)]
+pub enum ErrorCategory {
+    // connecting to a peer failed, it is retried
+    ConnectFailed,
+    DnsFailure,
+    // the peer or an incoming conn used another network secret
+    AuthRejected,
+    TunError,
+    ListenerFailed,
+    // the dhcp assigned ipv4 is used by another peer
+    IpConflict,
+    // no peer is connected and all of them were given up, see set_max_reconnect_attempts
+    GaveUp,
+}
+
+#[napi(object)]
+pub struct ErrorEvent {
+    pub inst_id: String,
+    pub category: ErrorCategory,
+    pub message: String,
+    // unix timestamp in seconds
+    pub time: i64,
+}
+
+static ERROR_CALLBACK: Mutex<Option<ThreadsafeFunction<ErrorEvent, ()>>> = Mutex::new(None);
+
+fn classify(event: &GlobalCtxEvent) -> Option<(ErrorCategory, String)> {
+    Some(match event {
+        GlobalCtxEvent::ConnectError(dst, _, err) => {
+            let category = match classify_connect_error(err) {
+                ConnectErrorCategory::AuthRejected => ErrorCategory::AuthRejected,
+                ConnectErrorCategory::DnsFailure => ErrorCategory::DnsFailure,
+                _ => ErrorCategory::ConnectFailed,
+            };
+            (category, format!("connect to {} failed: {}", dst, err))
+        }
+        // other failed incoming conns are mostly scanners, not worth a callback
+        GlobalCtxEvent::ConnectionError(_, remote, err)
+            if classify_connect_error(err) == ConnectErrorCategory::AuthRejected =>
+        {
+            (
+                ErrorCategory::AuthRejected,
+                format!("conn from {} rejected: {}", remote, err),
+            )
+        }
+        GlobalCtxEvent::TunDeviceError(err) => (ErrorCategory::TunError, err.clone()),
+        GlobalCtxEvent::ListenerAddFailed(url, err) => (
+            ErrorCategory::ListenerFailed,
+            format!("listen on {} failed: {}", url, err),
+        ),
+        GlobalCtxEvent::DhcpIpv4Conflicted(ip) => (
+            ErrorCategory::IpConflict,
+            format!("dhcp ip {:?} conflicts with another peer", ip),
+        ),
+        _ => return None,
+    })
+}
+
+pub(crate) fn report_error(uuid: &Uuid, category: ErrorCategory, message: String) {
+    let callback = ERROR_CALLBACK.lock().unwrap();
+    let Some(func) = callback.as_ref() else {
+        return;
+    };
+    let event = ErrorEvent {
+        inst_id: uuid.to_string(),
+        category,
+        message,
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default(),
+    };
+    check_call(
+        "error",
+        func.call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking),
+    );
+}
+
+// called with every event of every running instance
+pub(crate) fn report_error_event(uuid: &Uuid, event: &GlobalCtxEvent) {
+    if let Some((category, message)) = classify(event) {
+        report_error(uuid, category, message);
+    }
+}
+
+// only the error events of all instances, a low volume alternative to filtering the
+// log callback. failed reconnects are reported each time. registering again replaces
+// the callback.
+#[napi]
+pub fn register_error_callback(func: ThreadsafeFunction<ErrorEvent, ()>) {
+    *ERROR_CALLBACK.lock().unwrap() = Some(func);
+}
+
+#[napi]
+pub fn unregister_error_callback() {
+    ERROR_CALLBACK.lock().unwrap().take();
+}
@@ -0,0 +1,52 @@
+use crate::callback::check_call;
+use easytier::common::global_ctx::GlobalCtxEvent;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[napi(object)]
+pub struct FirstPeerConnectedEvent {
+    pub inst_id: String,
+    pub peer_id: String,
+    // from the start of the instance to the first peer connected
+    pub elapsed_ms: i64,
+}
+
+static FIRST_PEER_CALLBACK: Mutex<Option<ThreadsafeFunction<FirstPeerConnectedEvent, ()>>> =
+    Mutex::new(None);
+
+// called with every event of every running instance
+pub(crate) fn report_first_peer_event(uuid: &Uuid, event: &GlobalCtxEvent) {
+    let GlobalCtxEvent::FirstPeerConnected(peer_id, elapsed_ms) = event else {
+        return;
+    };
+    let callback = FIRST_PEER_CALLBACK.lock().unwrap();
+    let Some(func) = callback.as_ref() else {
+        return;
+    };
+    check_call(
+        "first peer",
+        func.call(
+            Ok(FirstPeerConnectedEvent {
+                inst_id: uuid.to_string(),
+                peer_id: peer_id.to_string(),
+                elapsed_ms: *elapsed_ms as i64,
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+        ),
+    );
+}
+
+// called once per start of an instance, when it connected to its first peer, e.g. to
+// measure how long onboarding takes and which peer made it. a slow first peer is often
+// a slow relay. registering again replaces the callback.
+#[napi]
+pub fn register_first_peer_callback(func: ThreadsafeFunction<FirstPeerConnectedEvent, ()>) {
+    *FIRST_PEER_CALLBACK.lock().unwrap() = Some(func);
+}
+
+#[napi]
+pub fn unregister_first_peer_callback() {
+    FIRST_PEER_CALLBACK.lock().unwrap().take();
+}
@@ -0,0 +1,91 @@
+use crate::{INSTANCE_MANAGER, instance_api, parse_inst_id};
+use easytier::common::global_ctx::GlobalCtxEvent;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HolePunchStatus {
+    // no punching needed or tried yet, e.g. the peer is connected directly already
+    NotStarted,
+    InProgress,
+    Succeeded,
+    // the last round failed, it is retried after a backoff
+    Failed,
+}
+
+// instance id -> peer id -> last punch status
+static HOLE_PUNCH_STATUS: once_cell::sync::Lazy<
+    Mutex<HashMap<Uuid, HashMap<u32, HolePunchStatus>>>,
+> = once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn update(uuid: Uuid, peer_id: u32, status: Option<HolePunchStatus>) {
+    let mut all = HOLE_PUNCH_STATUS.lock().unwrap();
+    let peers = all.entry(uuid).or_default();
+    match status {
+        Some(status) => peers.insert(peer_id, status),
+        None => peers.remove(&peer_id),
+    };
+}
+
+pub(crate) fn watch_hole_punch(uuid: Uuid) {
+    HOLE_PUNCH_STATUS.lock().unwrap().remove(&uuid);
+    let Some(mut receiver) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| *x.key() == uuid)
+        .and_then(|x| x.subscribe_event())
+    else {
+        return;
+    };
+    instance_api::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            match event {
+                GlobalCtxEvent::HolePunchStarted(peer_id) => {
+                    update(uuid, peer_id, Some(HolePunchStatus::InProgress))
+                }
+                GlobalCtxEvent::HolePunchSucceeded(peer_id) => {
+                    update(uuid, peer_id, Some(HolePunchStatus::Succeeded))
+                }
+                GlobalCtxEvent::HolePunchFailed(peer_id) => {
+                    update(uuid, peer_id, Some(HolePunchStatus::Failed))
+                }
+                // punching starts over if the direct connection is lost
+                GlobalCtxEvent::PeerRemoved(peer_id) => update(uuid, peer_id, None),
+                _ => {}
+            }
+        }
+    });
+}
+
+pub(crate) fn remove_hole_punch_status(uuids: &[Uuid]) {
+    let mut all = HOLE_PUNCH_STATUS.lock().unwrap();
+    all.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// state of the udp hole punching to a peer reached over a relay, e.g. for a
+// "connecting directly" hint. peers connected through a listener or connector never punch.
+#[napi]
+pub fn get_hole_punch_status(inst_id: String, peer_id: String) -> HolePunchStatus {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return HolePunchStatus::NotStarted;
+    };
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!("[Rust] get_hole_punch_status: invalid peer id {}", peer_id);
+        return HolePunchStatus::NotStarted;
+    };
+    HOLE_PUNCH_STATUS
+        .lock()
+        .unwrap()
+        .get(&uuid)
+        .and_then(|x| x.get(&peer_id).copied())
+        .unwrap_or(HolePunchStatus::NotStarted)
+}
@@ -0,0 +1,111 @@
+use crate::instance_api;
+use easytier::common::set_default_machine_id;
+use easytier::tunnel::generate_digest_from_str;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::Promise;
+use napi_ohos::threadsafe_function::ThreadsafeFunction;
+use ohos_hilog_binding::{hilog_debug, hilog_error, hilog_warn};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+// the configured or generated id is kept when the provider did not answer within this
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+type IdentityProvider = ThreadsafeFunction<(), Promise<String>>;
+
+struct Identity {
+    configured: Option<String>,
+    provider: Option<Arc<IdentityProvider>>,
+    provided: Option<String>,
+}
+
+static IDENTITY: Mutex<Identity> = Mutex::new(Identity {
+    configured: None,
+    provider: None,
+    provided: None,
+});
+
+impl Identity {
+    fn machine_id(&self) -> Option<String> {
+        self.provided.clone().or(self.configured.clone())
+    }
+}
+
+fn apply(identity: &Identity) {
+    set_default_machine_id(identity.machine_id());
+}
+
+// the instance id of a config without instance_id, derived from the machine id and the
+// network name so peers see the same node after a restart or reinstall. none without a
+// machine id, the instance gets a random id then.
+pub(crate) fn default_instance_id(network_name: &str) -> Option<Uuid> {
+    let machine_id = IDENTITY.lock().unwrap().machine_id()?;
+    let mut digest = [0u8; 16];
+    generate_digest_from_str(network_name, &machine_id, &mut digest);
+    Some(Uuid::from_bytes(digest))
+}
+
+// machine id of the device. configs without instance_id run under an id derived from it
+// and their network name, the same on every start. empty goes back to random instance
+// ids. an id from the identity provider takes precedence.
+#[napi]
+pub fn set_machine_id(machine_id: String) {
+    let mut identity = IDENTITY.lock().unwrap();
+    identity.configured = Some(machine_id).filter(|x| !x.is_empty());
+    apply(&identity);
+}
+
+async fn ask(func: Arc<IdentityProvider>) {
+    let id = async { func.call_async(Ok(())).await?.await };
+    let id = match tokio::time::timeout(PROVIDER_TIMEOUT, id).await {
+        Ok(Ok(id)) if !id.is_empty() => id,
+        Ok(Ok(_)) => {
+            hilog_warn!("[Rust] identity provider returned an empty id, keep the current one");
+            return;
+        }
+        Ok(Err(e)) => {
+            hilog_error!(
+                "[Rust] identity provider failed {}, keep the current one",
+                e
+            );
+            return;
+        }
+        Err(_) => {
+            hilog_warn!("[Rust] identity provider timed out, keep the current one");
+            return;
+        }
+    };
+    let mut identity = IDENTITY.lock().unwrap();
+    // replaced or unregistered meanwhile
+    if !identity
+        .provider
+        .as_ref()
+        .is_some_and(|x| Arc::ptr_eq(x, &func))
+    {
+        return;
+    }
+    hilog_debug!("[Rust] machine id from identity provider");
+    identity.provided = Some(id);
+    apply(&identity);
+}
+
+// asks `func` for the machine id, e.g. one kept in the hardware backed keystore, instead
+// of storing it in a file. it is called once when registered, until the promise resolves
+// the id of set_machine_id is used, and kept if the promise fails. instances started
+// before it resolved keep their id, so register it before starting any.
+#[napi]
+pub fn register_identity_provider(func: IdentityProvider) {
+    let func = Arc::new(func);
+    IDENTITY.lock().unwrap().provider = Some(func.clone());
+    instance_api::spawn(ask(func));
+}
+
+// goes back to the id of set_machine_id, or random instance ids without one.
+#[napi]
+pub fn unregister_identity_provider() {
+    let mut identity = IDENTITY.lock().unwrap();
+    identity.provider = None;
+    identity.provided = None;
+    apply(&identity);
+}
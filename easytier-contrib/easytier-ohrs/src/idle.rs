@@ -0,0 +1,104 @@
+use crate::callback::check_call;
+use crate::instance_api::block_on;
+use crate::watches::Watches;
+use crate::{INSTANCE_MANAGER, parse_inst_id, stop_network_instance};
+use easytier::proto::api::instance::GetStatsRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::hilog_debug;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+// the longest the stop may come after the timeout
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[napi(object)]
+pub struct IdleTimeoutEvent {
+    pub inst_id: String,
+    pub idle_ms: i64,
+}
+
+struct IdleState {
+    timeout: Duration,
+    func: Option<ThreadsafeFunction<IdleTimeoutEvent, ()>>,
+}
+
+static IDLE_WATCHES: once_cell::sync::Lazy<Watches<Uuid, IdleState>> =
+    once_cell::sync::Lazy::new(Watches::new);
+
+async fn tun_idle_time(uuid: &Uuid) -> Option<Duration> {
+    let resp = INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_stats_service()
+        .get_stats(BaseController::default(), GetStatsRequest::default())
+        .await
+        .ok()?;
+    Some(Duration::from_millis(resp.tun_idle_ms))
+}
+
+async fn watch_idle(uuid: Uuid, state: Arc<IdleState>) {
+    loop {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+        let Some(idle) = tun_idle_time(&uuid).await else {
+            hilog_debug!("[Rust] instance {} gone, stop watching idle time", uuid);
+            break;
+        };
+        if idle < state.timeout {
+            continue;
+        }
+
+        hilog_debug!("[Rust] instance {} idle for {:?}, stopping", uuid, idle);
+        // stopping drops the instance and this watch with it, so it is not awaited. also
+        // keeps it off the runtime threads.
+        tokio::task::spawn_blocking(move || {
+            stop_network_instance(vec![uuid.to_string()]);
+            if let Some(func) = &state.func {
+                check_call(
+                    "idle timeout",
+                    func.call(
+                        Ok(IdleTimeoutEvent {
+                            inst_id: uuid.to_string(),
+                            idle_ms: idle.as_millis() as i64,
+                        }),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    ),
+                );
+            }
+        });
+        return;
+    }
+}
+
+// stops the instance once no packet went through its tun device for `seconds`, then
+// calls `func`. traffic of the instance itself, like keepalives and route sync, does not
+// count. setting a new timeout replaces the old one, 0 disables it.
+#[napi]
+pub fn set_idle_timeout(
+    inst_id: String,
+    seconds: u32,
+    func: Option<ThreadsafeFunction<IdleTimeoutEvent, ()>>,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if seconds == 0 {
+        IDLE_WATCHES.remove(&uuid);
+        return true;
+    }
+    if block_on(tun_idle_time(&uuid)).is_none() {
+        return false;
+    }
+
+    let state = IdleState {
+        timeout: Duration::from_secs(seconds as u64),
+        func,
+    };
+    IDLE_WATCHES.insert(uuid, state, |state| watch_idle(uuid, state));
+    true
+}
+
+pub(crate) fn remove_idle_timeouts(uuids: &[Uuid]) {
+    IDLE_WATCHES.retain(|uuid| !uuids.contains(uuid));
+}
@@ -0,0 +1,105 @@
+use crate::{INSTANCE_MANAGER, config_stage};
+use easytier::proto::api::config::{
+    ConfigPatchAction, InstanceConfigPatch, PatchConfigRequest, UrlPatch,
+};
+use easytier::proto::api::instance::ListConnectorRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use easytier::rpc_service::InstanceRpcService;
+use ohos_hilog_binding::hilog_error;
+use std::future::Future;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// instance rpc services are async, the napi exports are sync, so a small shared runtime
+// drives the calls instead of building a runtime per call.
+static RUNTIME: once_cell::sync::Lazy<tokio::runtime::Runtime> = once_cell::sync::Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap()
+});
+
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+pub(crate) fn spawn<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    RUNTIME.spawn(future)
+}
+
+pub(crate) fn get_instance_service(uuid: &Uuid) -> Option<Arc<dyn InstanceRpcService>> {
+    let service = INSTANCE_MANAGER.get_instance_service(uuid);
+    if service.is_none() {
+        hilog_error!(
+            "[Rust] instance {} not running or api service not ready",
+            uuid
+        );
+    }
+    service
+}
+
+pub(crate) fn patch_instance_config(uuid: &Uuid, patch: InstanceConfigPatch) -> bool {
+    let Some(service) = get_instance_service(uuid) else {
+        return false;
+    };
+    match config_stage::stage_patch(uuid, patch) {
+        Some(patch) => apply_patch(service.as_ref(), uuid, patch),
+        None => true,
+    }
+}
+
+pub(crate) fn apply_patch(
+    service: &dyn InstanceRpcService,
+    uuid: &Uuid,
+    patch: InstanceConfigPatch,
+) -> bool {
+    let request = PatchConfigRequest {
+        patch: Some(patch),
+        instance: None,
+    };
+    match block_on(
+        service
+            .get_config_service()
+            .patch_config(BaseController::default(), request),
+    ) {
+        Ok(_) => true,
+        Err(e) => {
+            hilog_error!("[Rust] patch config of {} failed {}", uuid, e);
+            false
+        }
+    }
+}
+
+pub(crate) async fn list_connectors(
+    service: &dyn InstanceRpcService,
+) -> Option<Vec<(url::Url, i32)>> {
+    let resp = service
+        .get_connector_manage_service()
+        .list_connector(BaseController::default(), ListConnectorRequest::default())
+        .await
+        .ok()?;
+    Some(
+        resp.connectors
+            .into_iter()
+            .filter_map(|x| Some((x.url?.into(), x.status)))
+            .collect(),
+    )
+}
+
+pub(crate) fn connector_patch(action: ConfigPatchAction, urls: &[url::Url]) -> InstanceConfigPatch {
+    InstanceConfigPatch {
+        connectors: urls
+            .iter()
+            .map(|x| UrlPatch {
+                action: action as i32,
+                url: Some(x.clone().into()),
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,348 @@
+use crate::callback::check_call;
+use crate::{INSTANCE_MANAGER, instance_api, parse_inst_id};
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::hilog_debug;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+// how often a starting instance is checked for having finished its start
+const START_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleState {
+    // registered, waiting for its tun fd before it is started
+    Created,
+    Starting,
+    Running,
+    // runs with connect_on_demand, the peers are connected once the first packet is sent
+    // or force_connect is called, then it is Running
+    Dormant,
+    // running, but not connected to any peer or without a working tun device, see
+    // get_last_connect_error
+    Degraded,
+    Stopping,
+    Stopped,
+    // the instance ended by itself or could not be started, until it is stopped
+    Failed,
+}
+
+#[napi(object)]
+pub struct LifecycleChange {
+    pub inst_id: String,
+    pub from: LifecycleState,
+    pub to: LifecycleState,
+    // why it failed, only set for Failed
+    pub error: Option<String>,
+}
+
+struct InstanceState {
+    state: LifecycleState,
+    // counts the starts, a watch of an earlier start leaves the state alone
+    generation: u64,
+    // started on demand and not woken yet, running is dormant then
+    dormant: bool,
+}
+
+static INSTANCE_STATES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, InstanceState>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+static LIFECYCLE_CALLBACK: Mutex<Option<ThreadsafeFunction<LifecycleChange, ()>>> =
+    Mutex::new(None);
+
+fn report_change(uuid: &Uuid, from: LifecycleState, to: LifecycleState, error: Option<String>) {
+    hilog_debug!("[Rust] instance {} lifecycle {:?} -> {:?}", uuid, from, to);
+    let callback = LIFECYCLE_CALLBACK.lock().unwrap();
+    let Some(func) = callback.as_ref() else {
+        return;
+    };
+    check_call(
+        "lifecycle",
+        func.call(
+            Ok(LifecycleChange {
+                inst_id: uuid.to_string(),
+                from,
+                to,
+                error,
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+        ),
+    );
+}
+
+// moves the instance to `to` if it is in one of `from`, none of `from` matches any state
+fn transition(
+    uuid: &Uuid,
+    from: Option<&[LifecycleState]>,
+    to: LifecycleState,
+    error: Option<String>,
+) {
+    let (old, to) = {
+        let mut states = INSTANCE_STATES.lock().unwrap();
+        let current = states.get(uuid);
+        let old = current.map_or(LifecycleState::Stopped, |x| x.state);
+        let to = if current.is_some_and(|x| x.dormant)
+            && matches!(to, LifecycleState::Running | LifecycleState::Degraded)
+        {
+            LifecycleState::Dormant
+        } else {
+            to
+        };
+        if old == to || from.is_some_and(|x| !x.contains(&old)) {
+            return;
+        }
+        let entry = states.entry(*uuid).or_insert(InstanceState {
+            state: old,
+            generation: 0,
+            dormant: false,
+        });
+        if to == LifecycleState::Starting {
+            entry.generation += 1;
+        }
+        entry.state = to;
+        (old, to)
+    };
+    report_change(uuid, old, to, error);
+}
+
+fn current_generation(uuid: &Uuid) -> Option<u64> {
+    INSTANCE_STATES
+        .lock()
+        .unwrap()
+        .get(uuid)
+        .map(|x| x.generation)
+}
+
+pub(crate) fn set_created(uuid: &Uuid) {
+    transition(
+        uuid,
+        Some(&[LifecycleState::Stopped, LifecycleState::Failed]),
+        LifecycleState::Created,
+        None,
+    );
+}
+
+// before set_starting, whether the instance starts with connect_on_demand
+pub(crate) fn set_on_demand(uuid: &Uuid, on_demand: bool) {
+    let mut states = INSTANCE_STATES.lock().unwrap();
+    match states.get_mut(uuid) {
+        Some(x) => x.dormant = on_demand,
+        None if on_demand => {
+            states.insert(
+                *uuid,
+                InstanceState {
+                    state: LifecycleState::Stopped,
+                    generation: 0,
+                    dormant: true,
+                },
+            );
+        }
+        None => {}
+    }
+}
+
+// the instance left its dormancy, by traffic or force_connect
+pub(crate) fn set_woken(uuid: &Uuid) {
+    if let Some(x) = INSTANCE_STATES.lock().unwrap().get_mut(uuid) {
+        x.dormant = false;
+    }
+    transition(
+        uuid,
+        Some(&[LifecycleState::Dormant]),
+        LifecycleState::Running,
+        None,
+    );
+}
+
+pub(crate) fn set_starting(uuid: &Uuid) {
+    transition(uuid, None, LifecycleState::Starting, None);
+}
+
+pub(crate) fn set_failed(uuid: &Uuid, error: String) {
+    transition(uuid, None, LifecycleState::Failed, Some(error));
+}
+
+// between running and degraded only, a connect error while starting is picked up once
+// the instance runs
+pub(crate) fn set_degraded(uuid: &Uuid, degraded: bool) {
+    let (from, to) = if degraded {
+        (LifecycleState::Running, LifecycleState::Degraded)
+    } else {
+        (LifecycleState::Degraded, LifecycleState::Running)
+    };
+    transition(uuid, Some(&[from]), to, None);
+}
+
+pub(crate) fn set_stopping(uuids: &[Uuid]) {
+    for uuid in uuids {
+        transition(
+            uuid,
+            Some(&[
+                LifecycleState::Created,
+                LifecycleState::Starting,
+                LifecycleState::Running,
+                LifecycleState::Dormant,
+                LifecycleState::Degraded,
+                LifecycleState::Failed,
+            ]),
+            LifecycleState::Stopping,
+            None,
+        );
+    }
+}
+
+pub(crate) fn set_stopped(uuids: &[Uuid]) {
+    for uuid in uuids {
+        transition(
+            uuid,
+            Some(&[
+                LifecycleState::Created,
+                LifecycleState::Stopping,
+                LifecycleState::Failed,
+            ]),
+            LifecycleState::Stopped,
+            None,
+        );
+    }
+}
+
+// for the ephemeral instances, nothing is kept of them after the stop
+pub(crate) fn remove_lifecycle_states(uuids: &[Uuid]) {
+    let mut states = INSTANCE_STATES.lock().unwrap();
+    states.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// follows a started instance until it runs, and notices when its launcher ends without
+// being stopped.
+pub(crate) fn watch_lifecycle(uuid: Uuid) {
+    let Some(generation) = current_generation(&uuid) else {
+        return;
+    };
+    let Some(stop_notifier) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| *x.key() == uuid)
+        .and_then(|x| x.get_stop_notifier())
+    else {
+        return;
+    };
+    let still_current = move || current_generation(&uuid) == Some(generation);
+    instance_api::spawn(async move {
+        // the api service is only set once the instance finished its start
+        while INSTANCE_MANAGER.get_instance_service(&uuid).is_none() {
+            if tokio::time::timeout(START_POLL_INTERVAL, stop_notifier.notified())
+                .await
+                .is_ok()
+            {
+                break;
+            }
+        }
+        if INSTANCE_MANAGER.get_instance_service(&uuid).is_some() && still_current() {
+            let to = if crate::connect_error::has_connect_error(&uuid) {
+                LifecycleState::Degraded
+            } else {
+                LifecycleState::Running
+            };
+            transition(&uuid, Some(&[LifecycleState::Starting]), to, None);
+            stop_notifier.notified().await;
+        }
+        if !still_current() {
+            return;
+        }
+        let error = INSTANCE_MANAGER
+            .iter()
+            .find(|x| *x.key() == uuid)
+            .and_then(|x| x.get_latest_error_msg())
+            .unwrap_or_else(|| "instance stopped unexpectedly".to_string());
+        transition(
+            &uuid,
+            Some(&[
+                LifecycleState::Starting,
+                LifecycleState::Running,
+                LifecycleState::Dormant,
+                LifecycleState::Degraded,
+            ]),
+            LifecycleState::Failed,
+            Some(error),
+        );
+    });
+}
+
+// more precise than is_running_network: Created while run_network_instance waits for
+// the tun fd, Starting until the instance is up, Running or Degraded while it runs,
+// Dormant while an instance of run_network_instance_on_demand waits for traffic,
+// Stopping and Stopped for stop_network_instance, Failed when it could not start or
+// ended with an error. instances never started are Stopped.
+#[napi]
+pub fn get_lifecycle_state(inst_id: String) -> LifecycleState {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return LifecycleState::Stopped;
+    };
+    INSTANCE_STATES
+        .lock()
+        .unwrap()
+        .get(&uuid)
+        .map(|x| x.state)
+        .unwrap_or(LifecycleState::Stopped)
+}
+
+// called with every change of the lifecycle state of every instance. registering again
+// replaces the callback.
+#[napi]
+pub fn register_lifecycle_callback(func: ThreadsafeFunction<LifecycleChange, ()>) {
+    *LIFECYCLE_CALLBACK.lock().unwrap() = Some(func);
+}
+
+#[napi]
+pub fn unregister_lifecycle_callback() {
+    LIFECYCLE_CALLBACK.lock().unwrap().take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(uuid: &Uuid) -> LifecycleState {
+        get_lifecycle_state(uuid.to_string())
+    }
+
+    // what watch_lifecycle does once the started instance runs
+    fn set_running(uuid: &Uuid) {
+        transition(
+            uuid,
+            Some(&[LifecycleState::Starting]),
+            LifecycleState::Running,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_dormant_lifecycle() {
+        let uuid = Uuid::new_v4();
+        set_on_demand(&uuid, true);
+        set_starting(&uuid);
+        set_running(&uuid);
+        assert_eq!(state(&uuid), LifecycleState::Dormant);
+        // no peer is expected to be connected while dormant
+        set_degraded(&uuid, true);
+        assert_eq!(state(&uuid), LifecycleState::Dormant);
+
+        set_woken(&uuid);
+        assert_eq!(state(&uuid), LifecycleState::Running);
+        set_woken(&uuid);
+        assert_eq!(state(&uuid), LifecycleState::Running);
+
+        set_stopping(&[uuid]);
+        set_stopped(&[uuid]);
+        assert_eq!(state(&uuid), LifecycleState::Stopped);
+
+        // the next start without connect_on_demand is not dormant
+        set_on_demand(&uuid, false);
+        set_starting(&uuid);
+        set_running(&uuid);
+        assert_eq!(state(&uuid), LifecycleState::Running);
+        remove_lifecycle_states(&[uuid]);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::KeyValuePair;
+use napi_derive_ohos::napi;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// labels given when starting each instance, only kept to look instances up
+static LABELS: once_cell::sync::Lazy<Mutex<HashMap<Uuid, Vec<(String, String)>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) fn set_labels(uuid: Uuid, labels: Vec<KeyValuePair>) {
+    let labels: Vec<_> = labels.into_iter().map(|x| (x.key, x.value)).collect();
+    let mut all = LABELS.lock().unwrap();
+    if labels.is_empty() {
+        all.remove(&uuid);
+    } else {
+        all.insert(uuid, labels);
+    }
+}
+
+pub(crate) fn get_labels(uuid: &Uuid) -> Vec<KeyValuePair> {
+    LABELS
+        .lock()
+        .unwrap()
+        .get(uuid)
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| KeyValuePair {
+            key: key.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+pub(crate) fn remove_labels(uuids: &[Uuid]) {
+    let mut labels = LABELS.lock().unwrap();
+    labels.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// ids of the instances started with the label `key` = `value`, a key may be given more
+// than once with different values.
+#[napi]
+pub fn get_instances_by_label(key: String, value: String) -> Vec<String> {
+    LABELS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, labels)| labels.iter().any(|(k, v)| *k == key && *v == value))
+        .map(|(uuid, _)| uuid.to_string())
+        .collect()
+}
@@ -0,0 +1,129 @@
+use crate::callback::check_call;
+use crate::instance_api;
+use crate::watches::Watches;
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::proto::api::instance::ListRouteRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const LATENCY_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// a degraded peer only counts as recovered below this share of the threshold, so a
+// latency jittering around the threshold does not fire on every check
+const LATENCY_RECOVER_RATIO: f64 = 0.8;
+
+#[napi(object)]
+pub struct LatencyAlert {
+    pub inst_id: String,
+    pub peer_id: u32,
+    pub latency_ms: i32,
+    pub threshold_ms: u32,
+    // true when the latency went above the threshold, false when it recovered
+    pub degraded: bool,
+}
+
+struct LatencyState {
+    threshold_ms: u32,
+    func: ThreadsafeFunction<LatencyAlert, ()>,
+}
+
+static LATENCY_ALERTS: once_cell::sync::Lazy<Watches<(Uuid, u32), LatencyState>> =
+    once_cell::sync::Lazy::new(Watches::new);
+
+// the latency of the path to the peer the route table uses, averaged over the recent
+// pings of each conn on it. none if the instance is gone, the inner none if the peer
+// has no route now.
+async fn path_latency(uuid: &Uuid, peer_id: u32) -> Option<Option<i32>> {
+    let routes = INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_peer_manage_service()
+        .list_route(BaseController::default(), ListRouteRequest::default())
+        .await
+        .ok()?
+        .routes;
+    Some(
+        routes
+            .into_iter()
+            .find(|r| r.peer_id == peer_id)
+            .map(|r| r.path_latency),
+    )
+}
+
+async fn watch_latency(uuid: Uuid, peer_id: u32, state: Arc<LatencyState>) {
+    let threshold = state.threshold_ms as f64;
+    let mut degraded = false;
+    loop {
+        tokio::time::sleep(LATENCY_CHECK_INTERVAL).await;
+        let Some(latency) = path_latency(&uuid, peer_id).await else {
+            hilog_debug!("[Rust] instance {} gone, stop watching latency", uuid);
+            break;
+        };
+        // an unreachable peer keeps its state until it is measured again
+        let Some(latency) = latency else {
+            continue;
+        };
+        let crossed = if degraded {
+            (latency as f64) < threshold * LATENCY_RECOVER_RATIO
+        } else {
+            latency as f64 > threshold
+        };
+        if !crossed {
+            continue;
+        }
+        degraded = !degraded;
+        check_call(
+            "latency alert",
+            state.func.call(
+                Ok(LatencyAlert {
+                    inst_id: uuid.to_string(),
+                    peer_id,
+                    latency_ms: latency,
+                    threshold_ms: state.threshold_ms,
+                    degraded,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            ),
+        );
+    }
+}
+
+// calls `func` when the latency to the peer goes above `threshold_ms`, and again once it
+// dropped back below 80% of it. checked every 2s. setting a new alert for the peer
+// replaces the old one, 0 removes it.
+#[napi]
+pub fn set_latency_alert(
+    inst_id: String,
+    peer_id: String,
+    threshold_ms: u32,
+    func: ThreadsafeFunction<LatencyAlert, ()>,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!("[Rust] set_latency_alert: invalid peer id {}", peer_id);
+        return false;
+    };
+    if threshold_ms == 0 {
+        LATENCY_ALERTS.remove(&(uuid, peer_id));
+        return true;
+    }
+    if instance_api::get_instance_service(&uuid).is_none() {
+        return false;
+    }
+
+    LATENCY_ALERTS.insert(
+        (uuid, peer_id),
+        LatencyState { threshold_ms, func },
+        |state| watch_latency(uuid, peer_id, state),
+    );
+    true
+}
+
+pub(crate) fn remove_latency_alerts(uuids: &[Uuid]) {
+    LATENCY_ALERTS.retain(|(uuid, _)| !uuids.contains(uuid));
+}
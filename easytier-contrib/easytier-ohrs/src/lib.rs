@@ -1,33 +1,156 @@
+mod acl;
+mod approval;
+mod bandwidth;
+mod block;
+mod bridge;
+mod callback;
+mod capture;
+mod cleanup;
+mod clock;
+mod config_stage;
+mod config_summary;
+mod connect_error;
+mod debug_server;
+mod diagnostics;
+mod error_event;
+mod first_peer;
+mod hole_punch;
+mod identity;
+mod idle;
+mod instance_api;
+mod instance_state;
+mod labels;
+mod latency;
+mod listener_ports;
+mod manager_state;
+mod metrics;
+mod nat;
 mod native_log;
+mod packet_io;
+mod ping;
+mod quota;
+mod relay;
+mod resource;
+mod route;
+mod security;
+mod session;
+mod shutdown;
+mod snapshot;
+mod stats_history;
+mod subnets;
+mod throughput;
+mod tun;
+mod verify;
+mod watches;
 
+use base64::prelude::{BASE64_STANDARD, Engine as _};
 use easytier::common::config::{ConfigFileControl, ConfigLoader, TomlConfigLoader};
-use easytier::common::constants::EASYTIER_VERSION;
+use easytier::common::constants::{EASYTIER_GIT_HASH, EASYTIER_VERSION, get_enabled_features};
+use easytier::common::global_ctx::{AddressFamilyPreference, TransportProfile};
+use easytier::common::stun::StunInfoCollector;
+use easytier::instance::instance::Instance;
 use easytier::instance_manager::NetworkInstanceManager;
-use easytier::proto::api::manage::NetworkConfig;
+use easytier::proto::api::config::{InstanceConfigPatch, PeerMetadata, ReconnectBufferPatch};
+use easytier::proto::api::manage::{NetworkConfig, NetworkingMethod};
 use napi_derive_ohos::napi;
-use ohos_hilog_binding::{hilog_debug, hilog_error};
+use napi_ohos::bindgen_prelude::AsyncTask;
+use napi_ohos::{Env, Task};
+use ohos_hilog_binding::{hilog_debug, hilog_error, hilog_warn};
+use prost::Message;
+use std::collections::HashSet;
 use std::format;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 use uuid::Uuid;
 
 static INSTANCE_MANAGER: once_cell::sync::Lazy<NetworkInstanceManager> =
     once_cell::sync::Lazy::new(NetworkInstanceManager::new);
 
+// starting/stopping instances takes the write side, collecting infos takes the read side,
+// so a collect never observes an instance that is only half created or half torn down.
+static INSTANCE_LIFECYCLE_LOCK: RwLock<()> = RwLock::new(());
+
+fn lifecycle_read() -> RwLockReadGuard<'static, ()> {
+    INSTANCE_LIFECYCLE_LOCK
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+fn lifecycle_write() -> RwLockWriteGuard<'static, ()> {
+    INSTANCE_LIFECYCLE_LOCK
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
 #[napi(object)]
+#[derive(Clone)]
 pub struct KeyValuePair {
     pub key: String,
     pub value: String,
 }
 
+#[napi(object)]
+pub struct VersionInfo {
+    pub version: String,
+    pub git_hash: String,
+    pub enabled_features: Vec<String>,
+}
+
 #[napi]
 pub fn easytier_version() -> String {
     EASYTIER_VERSION.to_string()
 }
 
+#[napi]
+pub fn get_version_info() -> VersionInfo {
+    VersionInfo {
+        version: EASYTIER_VERSION.to_string(),
+        git_hash: EASYTIER_GIT_HASH.to_string(),
+        enabled_features: get_enabled_features()
+            .into_iter()
+            .map(|f| f.to_string())
+            .collect(),
+    }
+}
+
+// whether the library was built with the cargo feature `name` of easytier, the names are
+// the ones in enabled_features of get_version_info, e.g. socks5, wireguard or quic.
+#[napi]
+pub fn has_feature(name: String) -> bool {
+    get_enabled_features().contains(&name.as_str())
+}
+
+// -1 detaches the tun device of the instance. other fds below 1 are refused, 0 would
+// be stdin and usually means the app lost the fd it meant to pass. for an instance
+// waiting in run_network_instance_when_tun_ready the fd is kept and handed to it when it
+// starts, other instances have to run.
 #[napi]
 pub fn set_tun_fd(inst_id: String, fd: i32) -> bool {
+    if fd <= 0 && fd != Instance::NO_TUN_FD {
+        hilog_error!(
+            "[Rust] invalid tun fd {} for {}, use -1 for no tun",
+            fd,
+            inst_id
+        );
+        return false;
+    }
     match Uuid::try_parse(&inst_id) {
+        Ok(uuid) if !INSTANCE_MANAGER.list_network_instance_ids().contains(&uuid) => {
+            let pending = Some(fd).filter(|x| *x != Instance::NO_TUN_FD);
+            if !tun::set_pending_tun_fd(&uuid, pending) {
+                hilog_error!("[Rust] cant set tun fd {}, {} is not running", fd, inst_id);
+                return false;
+            }
+            hilog_debug!("[Rust] keep tun fd {} until {} starts.", fd, inst_id);
+            true
+        }
         Ok(uuid) => match INSTANCE_MANAGER.set_tun_fd(&uuid, fd) {
             Ok(_) => {
+                if fd == Instance::NO_TUN_FD {
+                    tun::forget_tun_names(&[uuid]);
+                }
                 hilog_debug!("[Rust] set tun fd {} to {}.", fd, inst_id);
                 true
             }
@@ -43,6 +166,38 @@ pub fn set_tun_fd(inst_id: String, fd: i32) -> bool {
     }
 }
 
+// for platforms handing out one tun fd per address family. the ipv4 fd is the one of
+// set_tun_fd and is needed in any case, with an ipv6 fd set ipv6 packets are read from
+// and written to that one instead. -1 as ipv6 fd goes back to a single fd.
+#[napi]
+pub fn set_tun_fd_family(inst_id: String, fd: i32, family: String) -> bool {
+    match family.as_str() {
+        "ipv4" => return set_tun_fd(inst_id, fd),
+        "ipv6" => {}
+        _ => {
+            hilog_error!("[Rust] unknown address family {}, use ipv4 or ipv6", family);
+            return false;
+        }
+    }
+    if fd <= 0 && fd != Instance::NO_TUN_FD {
+        hilog_error!("[Rust] invalid ipv6 tun fd {} for {}", fd, inst_id);
+        return false;
+    }
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    match INSTANCE_MANAGER.set_ipv6_tun_fd(&uuid, fd) {
+        Ok(_) => {
+            hilog_debug!("[Rust] set ipv6 tun fd {} to {}.", fd, inst_id);
+            true
+        }
+        Err(e) => {
+            hilog_error!("[Rust] cant set ipv6 tun fd {} to {}. {}", fd, inst_id, e);
+            false
+        }
+    }
+}
+
 #[napi]
 pub fn default_network_config() -> String {
     match NetworkConfig::new_from_config(TomlConfigLoader::default()) {
@@ -71,6 +226,68 @@ pub fn convert_toml_to_network_config(cfg_str: String) -> String {
     }
 }
 
+fn is_tracker_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+// a tracker is an http(s) peer url answering with the current relays, as a list of
+// connector urls or a redirect to one, see HttpTunnelConnector. it is queried again on
+// every reconnect, while the configured peers are connected independently of it.
+// replaces the trackers already in `cfg_json`, an empty url removes them. returns the
+// new network config json, or "ERROR ..." when the url or config is invalid.
+#[napi]
+pub fn set_tracker_url(cfg_json: String, url: String) -> String {
+    let mut cfg = match serde_json::from_str::<NetworkConfig>(&cfg_json) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            hilog_error!("[Rust] set_tracker_url: parse config failed {}", e);
+            return format!("ERROR {}", e);
+        }
+    };
+    if !url.is_empty() {
+        if let Err(e) = url::Url::parse(&url) {
+            return format!("ERROR invalid tracker url {}: {}", url, e);
+        }
+        if !is_tracker_url(&url) {
+            return format!("ERROR tracker url must be http or https: {}", url);
+        }
+    }
+
+    let mut peers = match NetworkingMethod::try_from(cfg.networking_method.unwrap_or_default()) {
+        Ok(NetworkingMethod::PublicServer) => cfg.public_server_url.take().into_iter().collect(),
+        Ok(NetworkingMethod::Manual) => std::mem::take(&mut cfg.peer_urls),
+        _ => vec![],
+    };
+    peers.retain(|x| !x.is_empty() && !is_tracker_url(x));
+    if !url.is_empty() {
+        peers.push(url);
+    }
+    let method = if peers.is_empty() {
+        NetworkingMethod::Standalone
+    } else {
+        NetworkingMethod::Manual
+    };
+    cfg.peer_urls = peers;
+    cfg.networking_method = Some(method as i32);
+    serde_json::to_string(&cfg).unwrap_or_else(|e| format!("ERROR {}", e))
+}
+
+// with ipv6 disabled the instance neither listens on ipv6 nor connects to ipv6 addresses
+// of peers, only the virtual ipv6 of the instance is kept. takes effect when the
+// instance is (re)started. returns the new network config json, or "ERROR ...".
+#[napi]
+pub fn set_ipv6_enabled(cfg_json: String, enabled: bool) -> String {
+    let mut cfg = match serde_json::from_str::<NetworkConfig>(&cfg_json) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            hilog_error!("[Rust] set_ipv6_enabled: parse config failed {}", e);
+            return format!("ERROR {}", e);
+        }
+    };
+    cfg.disable_ipv6 = Some(!enabled);
+    serde_json::to_string(&cfg).unwrap_or_else(|e| format!("ERROR {}", e))
+}
+
 #[napi]
 pub fn parse_network_config(cfg_json: String) -> bool {
     match serde_json::from_str::<NetworkConfig>(&cfg_json) {
@@ -91,65 +308,591 @@ pub fn parse_network_config(cfg_json: String) -> bool {
     }
 }
 
+#[napi(string_enum)]
+pub enum RunInstanceError {
+    InvalidConfig,
+    AlreadyStarting,
+    AlreadyRunning,
+    DuplicateNetworkName,
+    StartFailed,
+    // no tun fd was passed to set_tun_fd within wait_for_tun_ms of
+    // run_network_instance_when_tun_ready
+    TunNotReady,
+}
+
+#[napi(object)]
+pub struct RunInstanceResult {
+    pub success: bool,
+    pub inst_id: Option<String>,
+    pub error: Option<RunInstanceError>,
+    pub error_msg: Option<String>,
+}
+
+impl RunInstanceResult {
+    fn ok(inst_id: Uuid) -> Self {
+        Self {
+            success: true,
+            inst_id: Some(inst_id.to_string()),
+            error: None,
+            error_msg: None,
+        }
+    }
+
+    pub(crate) fn err(error: RunInstanceError, error_msg: String) -> Self {
+        hilog_error!("[Rust] run network instance failed {}", error_msg);
+        Self {
+            success: false,
+            inst_id: None,
+            error: Some(error),
+            error_msg: Some(error_msg),
+        }
+    }
+}
+
+// ids of instances whose start is in flight, so a double-tap on connect is rejected
+// instead of racing the first call.
+static STARTING_INSTANCES: once_cell::sync::Lazy<Mutex<HashSet<Uuid>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashSet::new()));
+
+struct StartingGuard(Uuid);
+
+impl StartingGuard {
+    fn try_new(inst_id: Uuid) -> Option<Self> {
+        let mut starting = STARTING_INSTANCES.lock().unwrap();
+        starting.insert(inst_id).then_some(Self(inst_id))
+    }
+}
+
+impl Drop for StartingGuard {
+    fn drop(&mut self) {
+        STARTING_INSTANCES.lock().unwrap().remove(&self.0);
+    }
+}
+
+// whether starting an instance whose network name is already used by a running one is
+// rejected, or only logged. only one instance uses the tun device, the others run with
+// no_tun.
+static REJECT_DUPLICATE_NETWORK_NAME: AtomicBool = AtomicBool::new(true);
+
 #[napi]
-pub fn run_network_instance(cfg_json: String) -> bool {
-    let cfg = match serde_json::from_str::<NetworkConfig>(&cfg_json) {
-        Ok(cfg) => match cfg.gen_config() {
-            Ok(toml) => toml,
-            Err(e) => {
-                hilog_error!("[Rust] parse config failed {}", e);
-                return false;
+pub fn set_reject_duplicate_network_name(reject: bool) {
+    REJECT_DUPLICATE_NETWORK_NAME.store(reject, Ordering::Relaxed);
+}
+
+// stun servers of the instances whose config has none, none for the built in ones.
+static STUN_SERVERS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+fn apply_stun_servers(cfg: &TomlConfigLoader) {
+    let Some(servers) = STUN_SERVERS.lock().unwrap().clone() else {
+        return;
+    };
+    // only ipv6 literals can be told apart, domains are looked up for ipv4
+    let (v6, v4): (Vec<String>, Vec<String>) = servers
+        .into_iter()
+        .partition(|x| x.parse::<SocketAddr>().is_ok_and(|x| x.is_ipv6()));
+    if cfg.get_stun_servers().is_none() && !v4.is_empty() {
+        cfg.set_stun_servers(Some(v4));
+    }
+    if cfg.get_stun_servers_v6().is_none() && !v6.is_empty() {
+        cfg.set_stun_servers_v6(Some(v6));
+    }
+}
+
+// replaces the built in stun servers of the nat detection for the instances started
+// afterwards, e.g. where those are blocked. entries are `host[:port]`, 3478 by default,
+// `[ipv6]:port` or `txt:domain` whose txt record lists servers. stun_servers in the
+// config of an instance take precedence. empty goes back to the built in ones. false
+// without applying any if one is invalid.
+#[napi]
+pub fn set_stun_servers(servers: Vec<String>) -> bool {
+    if let Some(invalid) = servers
+        .iter()
+        .find(|x| !StunInfoCollector::is_valid_server(x))
+    {
+        hilog_error!("[Rust] set_stun_servers: invalid server {}", invalid);
+        return false;
+    }
+    *STUN_SERVERS.lock().unwrap() = Some(servers).filter(|x| !x.is_empty());
+    true
+}
+
+const MAX_WORKER_THREADS: u32 = 16;
+
+// worker threads of the runtime of each instance, 0 keeps what the config says.
+static WORKER_THREADS: AtomicU32 = AtomicU32::new(0);
+
+fn apply_worker_threads(cfg: &TomlConfigLoader) {
+    let n = WORKER_THREADS.load(Ordering::Relaxed);
+    if n == 0 {
+        return;
+    }
+    let mut flags = cfg.get_flags();
+    // a multi thread runtime has at least 2 workers, one thread means a current thread one
+    flags.multi_thread = n > 1;
+    flags.multi_thread_count = n;
+    cfg.set_flags(flags);
+}
+
+// caps the threads the runtime of an instance runs on, overriding multi_thread and
+// multi_thread_count of its config. 1 runs everything on the instance thread, 0 goes back
+// to the config. must be called while no instance runs, fails otherwise and above 16.
+#[napi]
+pub fn set_worker_threads(n: u32) -> bool {
+    if n > MAX_WORKER_THREADS {
+        hilog_error!(
+            "[Rust] set_worker_threads: {} is more than {}",
+            n,
+            MAX_WORKER_THREADS
+        );
+        return false;
+    }
+    let _guard = lifecycle_write();
+    if !INSTANCE_MANAGER.list_network_instance_ids().is_empty() {
+        hilog_error!("[Rust] set_worker_threads must be called before instances start");
+        return false;
+    }
+    WORKER_THREADS.store(n, Ordering::Relaxed);
+    true
+}
+
+// instances whose state is wiped on stop, see run_ephemeral_instance.
+static EPHEMERAL_INSTANCES: once_cell::sync::Lazy<Mutex<HashSet<Uuid>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashSet::new()));
+
+// drop what is kept after stop for the ephemeral ones among `ids`.
+pub(crate) fn forget_ephemeral_instances(ids: &[Uuid]) {
+    let ephemeral: Vec<Uuid> = {
+        let mut instances = EPHEMERAL_INSTANCES.lock().unwrap();
+        ids.iter()
+            .filter(|x| instances.remove(x))
+            .copied()
+            .collect()
+    };
+    quota::remove_quotas(&ephemeral);
+    instance_state::remove_lifecycle_states(&ephemeral);
+}
+
+// drop the state kept per instance for `ids`, on stop and for the instances found dead
+// by cleanup_orphaned_resources.
+pub(crate) fn remove_instance_resources(ids: &[Uuid]) {
+    connect_error::remove_connect_errors(ids);
+    config_stage::remove_pending_configs(ids);
+    hole_punch::remove_hole_punch_status(ids);
+    tun::forget_tun_names(ids);
+    tun::forget_pending_tun_fds(ids);
+    metrics::remove_stats_baselines(ids);
+    resource::remove_resource_samples(ids);
+    stats_history::remove_stats_history(ids);
+    idle::remove_idle_timeouts(ids);
+    labels::remove_labels(ids);
+    latency::remove_latency_alerts(ids);
+    bandwidth::remove_bandwidth_alerts(ids);
+}
+
+// `labels` are only kept for get_instances_by_label, they do not change the instance.
+#[napi]
+pub fn run_network_instance(
+    cfg_json: String,
+    labels: Option<Vec<KeyValuePair>>,
+) -> RunInstanceResult {
+    let result = match load_network_config(&cfg_json) {
+        Ok(cfg) => start_network_instance(cfg, false),
+        Err(e) => return e,
+    };
+    if let Some(uuid) = result.inst_id.as_deref().and_then(parse_inst_id) {
+        labels::set_labels(uuid, labels.unwrap_or_default());
+    }
+    result
+}
+
+pub struct RunWhenTunReadyTask {
+    // an error found before waiting, returned as the result
+    cfg: Option<Result<TomlConfigLoader, RunInstanceResult>>,
+    labels: Vec<KeyValuePair>,
+    timeout: Duration,
+}
+
+#[napi]
+impl Task for RunWhenTunReadyTask {
+    type Output = RunInstanceResult;
+    type JsValue = RunInstanceResult;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        let cfg = match self.cfg.take() {
+            Some(Ok(cfg)) => cfg,
+            Some(Err(e)) => return Ok(e),
+            None => return Err(napi_ohos::Error::from_reason("the task already ran")),
+        };
+        let uuid = cfg.get_id();
+        if !cfg.get_flags().no_tun {
+            match tun::wait_pending_tun_fd(&uuid, self.timeout) {
+                Some(true) => {}
+                Some(false) => {
+                    let msg = format!(
+                        "no tun fd for instance {} within {}ms",
+                        uuid,
+                        self.timeout.as_millis()
+                    );
+                    instance_state::set_failed(&uuid, msg.clone());
+                    return Ok(RunInstanceResult::err(RunInstanceError::TunNotReady, msg));
+                }
+                None => {
+                    return Ok(RunInstanceResult::err(
+                        RunInstanceError::TunNotReady,
+                        format!("instance {} was stopped while waiting for its tun fd", uuid),
+                    ));
+                }
             }
-        },
-        Err(e) => {
-            hilog_error!("[Rust] parse config failed {}", e);
-            return false;
         }
+        let result = start_network_instance(cfg, false);
+        // a failed start leaves the fd to the app
+        tun::forget_pending_tun_fds(&[uuid]);
+        if result.success {
+            labels::set_labels(uuid, std::mem::take(&mut self.labels));
+        }
+        Ok(result)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+// like run_network_instance, but an instance using a tun device is only started once its
+// fd was passed to set_tun_fd, waiting up to `wait_for_tun_ms` for it and failing with
+// TunNotReady after. the wait starts when this returns, so set_tun_fd can follow right
+// after the call on the same thread. the config needs an instance_id, set_tun_fd has to
+// know it before the instance runs.
+#[napi]
+pub fn run_network_instance_when_tun_ready(
+    cfg_json: String,
+    labels: Option<Vec<KeyValuePair>>,
+    wait_for_tun_ms: u32,
+) -> AsyncTask<RunWhenTunReadyTask> {
+    let cfg = load_network_config(&cfg_json).and_then(|cfg| {
+        let has_id =
+            serde_json::from_str::<NetworkConfig>(&cfg_json).is_ok_and(|x| x.instance_id.is_some());
+        if !has_id {
+            return Err(RunInstanceResult::err(
+                RunInstanceError::InvalidConfig,
+                "the config has no instance_id to pass the tun fd for".to_string(),
+            ));
+        }
+        let uuid = cfg.get_id();
+        if !cfg.get_flags().no_tun && !tun::expect_pending_tun_fd(uuid) {
+            return Err(RunInstanceResult::err(
+                RunInstanceError::AlreadyStarting,
+                format!("instance {} already waits for its tun fd", uuid),
+            ));
+        }
+        instance_state::set_created(&uuid);
+        Ok(cfg)
+    });
+    AsyncTask::new(RunWhenTunReadyTask {
+        cfg: Some(cfg),
+        labels: labels.unwrap_or_default(),
+        timeout: Duration::from_millis(wait_for_tun_ms as u64),
+    })
+}
+
+// nothing of the instance is written to disk, its config is never saved and it does not
+// use the machine id. it runs under a freshly generated instance id so sessions cannot
+// be linked, and all state kept for it is dropped on stop.
+#[napi]
+pub fn run_ephemeral_instance(cfg_json: String) -> RunInstanceResult {
+    match load_network_config(&cfg_json) {
+        Ok(cfg) => start_network_instance(cfg, true),
+        Err(e) => e,
+    }
+}
+
+const LISTENER_SCHEMES: &[&str] = &["tcp", "udp", "ws", "wss", "quic", "wg", "faketcp"];
+const PEER_SCHEMES: &[&str] = &[
+    "tcp", "udp", "ws", "wss", "quic", "wg", "faketcp", "http", "https", "txt", "srv",
+];
+
+// what parsing lets through but makes the start fail or leaves a listener or peer
+// silently unused.
+fn validate_network_config(cfg: &TomlConfigLoader) -> Result<(), String> {
+    if cfg.get_network_identity().network_name.is_empty() {
+        return Err("network name is empty".to_string());
+    }
+    for listener in cfg.get_listeners().unwrap_or_default() {
+        if !LISTENER_SCHEMES.contains(&listener.scheme()) {
+            return Err(format!("unsupported listener {}", listener));
+        }
+    }
+    for peer in cfg.get_peers() {
+        if !PEER_SCHEMES.contains(&peer.uri.scheme()) {
+            return Err(format!("unsupported peer {}", peer.uri));
+        }
+        if peer.uri.host_str().is_none_or(|x| x.is_empty()) {
+            return Err(format!("peer {} has no host", peer.uri));
+        }
+    }
+    // overlapping proxy networks of one node are ambiguous when mapped, redundant otherwise
+    let proxy_cidrs: Vec<cidr::IpCidr> = cfg
+        .get_proxy_cidrs()
+        .iter()
+        .map(|x| x.mapped_cidr.unwrap_or(x.cidr).into())
+        .collect();
+    for (i, a) in proxy_cidrs.iter().enumerate() {
+        if let Some(b) = proxy_cidrs[i + 1..]
+            .iter()
+            .find(|b| route::cidrs_overlap(a, b))
+        {
+            return Err(format!("proxy networks {} and {} overlap", a, b));
+        }
+    }
+    Ok(())
+}
+
+// parse_network_config and run_network_instance in one call, `cfg_str` is a network
+// config json or a toml config. the instance is only started if the config is valid,
+// otherwise InvalidConfig is returned and nothing is changed.
+#[napi]
+pub fn validate_and_run(cfg_str: String) -> RunInstanceResult {
+    let cfg = match load_config_str(&cfg_str) {
+        Ok(cfg) => cfg,
+        Err(e) => return e,
     };
+    if let Err(e) = validate_network_config(&cfg) {
+        return RunInstanceResult::err(
+            RunInstanceError::InvalidConfig,
+            format!("invalid config: {}", e),
+        );
+    }
+    start_network_instance(cfg, false)
+}
+
+// a network config json or a toml config
+pub(crate) fn load_config_str(cfg_str: &str) -> Result<TomlConfigLoader, RunInstanceResult> {
+    if cfg_str.trim_start().starts_with('{') {
+        return load_network_config(cfg_str);
+    }
+    TomlConfigLoader::new_from_str(cfg_str).map_err(|e| {
+        RunInstanceResult::err(
+            RunInstanceError::InvalidConfig,
+            format!("parse config failed {}", e),
+        )
+    })
+}
+
+// starts the instance without connecting to its peers. listeners run and peers may
+// connect to it, but its own peers are connected only once the first unicast packet is
+// sent through the tun to the virtual network, the manual routes or, with exit nodes, to
+// anywhere, or force_connect is called. multicast, broadcast and link local packets of
+// the system do not wake it. the lifecycle state is Dormant until then, and Running with
+// the lifecycle callback when it is woken. the packet that wakes it is dropped, no peer
+// owns its address yet so the reconnect buffer does not hold it either, tcp sends it
+// again. `cfg_str` is a network config json or a toml config.
+#[napi]
+pub fn run_network_instance_on_demand(cfg_str: String) -> RunInstanceResult {
+    let cfg = match load_config_str(&cfg_str) {
+        Ok(cfg) => cfg,
+        Err(e) => return e,
+    };
+    let mut flags = cfg.get_flags();
+    flags.connect_on_demand = true;
+    cfg.set_flags(flags);
+    start_network_instance(cfg, false)
+}
 
-    if INSTANCE_MANAGER.list_network_instance_ids().len() > 0 {
-        hilog_error!("[Rust] there is a running instance!");
+// wakes an instance of run_network_instance_on_demand and connects to its peers now,
+// nothing happens if it is awake already. false if it is not running.
+#[napi]
+pub fn force_connect(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
         return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            connect_now: Some(true),
+            ..Default::default()
+        },
+    )
+}
+
+pub(crate) fn load_network_config(cfg_json: &str) -> Result<TomlConfigLoader, RunInstanceResult> {
+    let config_err = |e: String| {
+        RunInstanceResult::err(
+            RunInstanceError::InvalidConfig,
+            format!("parse config failed {}", e),
+        )
+    };
+    let mut cfg =
+        serde_json::from_str::<NetworkConfig>(cfg_json).map_err(|e| config_err(e.to_string()))?;
+    if cfg.instance_id.is_none() {
+        let network_name = cfg.network_name.clone().unwrap_or_default();
+        cfg.instance_id = identity::default_instance_id(&network_name).map(|x| x.to_string());
     }
+    cfg.gen_config().map_err(|e| config_err(e.to_string()))
+}
 
+pub(crate) fn start_network_instance(cfg: TomlConfigLoader, ephemeral: bool) -> RunInstanceResult {
+    if ephemeral {
+        cfg.set_id(Uuid::new_v4());
+    }
     let inst_id = cfg.get_id();
+    let Some(_starting) = StartingGuard::try_new(inst_id) else {
+        return RunInstanceResult::err(
+            RunInstanceError::AlreadyStarting,
+            format!("instance {} is already starting", inst_id),
+        );
+    };
+
+    let _guard = lifecycle_write();
+    let network_name = cfg.get_network_identity().network_name;
+    if let Some(dup) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| *x.key() != inst_id && x.get_network_name() == network_name)
+    {
+        let msg = format!(
+            "network name {} is already used by instance {}",
+            network_name,
+            dup.key()
+        );
+        if REJECT_DUPLICATE_NETWORK_NAME.load(Ordering::Relaxed) {
+            return RunInstanceResult::err(RunInstanceError::DuplicateNetworkName, msg);
+        }
+        hilog_error!("[Rust] {}", msg);
+    }
     if INSTANCE_MANAGER
         .list_network_instance_ids()
         .contains(&inst_id)
     {
-        return false;
+        return RunInstanceResult::err(
+            RunInstanceError::AlreadyRunning,
+            format!("instance {} is already running", inst_id),
+        );
+    }
+    // the app has one vpn tun device, instances without tun run beside the one using it,
+    // e.g. for bridging
+    if let Some(running) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| x.uses_tun())
+        .filter(|_| !cfg.get_flags().no_tun)
+    {
+        return RunInstanceResult::err(
+            RunInstanceError::AlreadyRunning,
+            format!(
+                "instance {} uses the tun device, run this one with no_tun",
+                running.key()
+            ),
+        );
+    }
+
+    apply_worker_threads(&cfg);
+    apply_stun_servers(&cfg);
+    let keep_listener_ports = !ephemeral && listener_ports::restore_listener_ports(&cfg);
+    instance_state::set_on_demand(&inst_id, cfg.get_flags().connect_on_demand);
+    instance_state::set_starting(&inst_id);
+    match INSTANCE_MANAGER.run_network_instance(cfg, false, ConfigFileControl::STATIC_CONFIG) {
+        Ok(inst_id) => {
+            instance_state::watch_lifecycle(inst_id);
+            if ephemeral {
+                EPHEMERAL_INSTANCES.lock().unwrap().insert(inst_id);
+            }
+            if keep_listener_ports {
+                listener_ports::watch_listener_ports(inst_id);
+            }
+            connect_error::watch_connect_errors(inst_id);
+            hole_punch::watch_hole_punch(inst_id);
+            stats_history::watch_stats_history(inst_id, !ephemeral);
+            tun::watch_tun_device(inst_id);
+            if let Some(fd) = tun::take_pending_tun_fd(&inst_id) {
+                if let Err(e) = INSTANCE_MANAGER.set_tun_fd(&inst_id, fd) {
+                    hilog_error!("[Rust] cant set tun fd {} to {}. {}", fd, inst_id, e);
+                }
+            }
+            RunInstanceResult::ok(inst_id)
+        }
+        Err(e) => {
+            instance_state::set_failed(&inst_id, e.to_string());
+            RunInstanceResult::err(RunInstanceError::StartFailed, e.to_string())
+        }
     }
-    INSTANCE_MANAGER
-        .run_network_instance(cfg, false, ConfigFileControl::STATIC_CONFIG)
-        .unwrap();
-    true
 }
 
 #[napi]
 pub fn stop_network_instance(inst_names: Vec<String>) {
-    INSTANCE_MANAGER
-        .delete_network_instance(
-            inst_names
-                .into_iter()
-                .filter_map(|s| Uuid::parse_str(&s).ok())
-                .collect(),
-        )
-        .unwrap();
+    let _guard = lifecycle_write();
+    let ids: Vec<Uuid> = inst_names
+        .into_iter()
+        .filter_map(|s| Uuid::parse_str(&s).ok())
+        .collect();
+    instance_state::set_stopping(&ids);
+    remove_instance_resources(&ids);
+    if let Err(e) = INSTANCE_MANAGER.delete_network_instance(ids.clone()) {
+        hilog_error!("[Rust] delete network instances {:?} failed {}", ids, e);
+    }
+    instance_state::set_stopped(&ids);
+    forget_ephemeral_instances(&ids);
+    // the dscp is shared by the process, the next instance starts without it
+    if INSTANCE_MANAGER.list_network_instance_ids().is_empty() {
+        let _ = easytier::tunnel::common::set_socket_dscp(None);
+    }
+    // instances are joined on delete, nothing uses their packet pipes any more
+    packet_io::release_orphaned_packet_pipes();
     hilog_debug!("[Rust] stop_network_instance");
 }
 
+// stops every instance, after the callback of register_shutdown_callback finished. async
+// so the js thread stays free to run that callback.
+#[napi]
+pub async fn stop_all_network_instances() {
+    shutdown::run_shutdown_callback().await;
+    let ids: Vec<String> = INSTANCE_MANAGER
+        .list_network_instance_ids()
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    // stopping joins the instances, keep it off the runtime threads.
+    let _ = tokio::task::spawn_blocking(move || stop_network_instance(ids)).await;
+}
+
+// format of the values returned by collect_network_infos. protobuf is the
+// NetworkInstanceRunningInfo message of api_manage.proto, base64 encoded.
+static INFO_FORMAT_PROTOBUF: AtomicBool = AtomicBool::new(false);
+
+#[napi]
+pub fn set_info_format(format: String) -> bool {
+    let protobuf = match format.as_str() {
+        "json" => false,
+        "protobuf" => true,
+        _ => {
+            hilog_error!("[Rust] set_info_format: unknown format {}", format);
+            return false;
+        }
+    };
+    INFO_FORMAT_PROTOBUF.store(protobuf, Ordering::Relaxed);
+    true
+}
+
 #[napi]
 pub fn collect_network_infos() -> Vec<KeyValuePair> {
     let mut result = Vec::new();
+    let _guard = lifecycle_read();
+    let protobuf = INFO_FORMAT_PROTOBUF.load(Ordering::Relaxed);
     match INSTANCE_MANAGER.collect_network_infos_sync() {
         Ok(map) => {
+            let start = std::time::Instant::now();
             for (uuid, info) in map.iter() {
-                // convert value to json string
-                let value = match serde_json::to_string(&info) {
-                    Ok(value) => value,
-                    Err(e) => {
-                        hilog_error!("[Rust] failed to serialize instance {} info: {}", uuid, e);
-                        continue;
+                let value = if protobuf {
+                    BASE64_STANDARD.encode(info.encode_to_vec())
+                } else {
+                    // convert value to json string
+                    match serde_json::to_string(&info) {
+                        Ok(value) => value,
+                        Err(e) => {
+                            hilog_error!(
+                                "[Rust] failed to serialize instance {} info: {}",
+                                uuid,
+                                e
+                            );
+                            continue;
+                        }
                     }
                 };
                 result.push(KeyValuePair {
@@ -157,6 +900,12 @@ pub fn collect_network_infos() -> Vec<KeyValuePair> {
                     value: value.clone(),
                 });
             }
+            hilog_debug!(
+                "[Rust] collect_network_infos serialized {} bytes as {} in {:?}",
+                result.iter().map(|x| x.value.len()).sum::<usize>(),
+                if protobuf { "protobuf" } else { "json" },
+                start.elapsed()
+            );
         }
         Err(_) => {}
     }
@@ -183,3 +932,450 @@ pub fn is_running_network(inst_id: String) -> bool {
         }
     }
 }
+
+fn parse_inst_id(inst_id: &str) -> Option<Uuid> {
+    match Uuid::try_parse(inst_id) {
+        Ok(uuid) => Some(uuid),
+        Err(e) => {
+            hilog_error!("[Rust] cant covert {} to uuid. {}", inst_id, e);
+            None
+        }
+    }
+}
+
+#[napi]
+pub fn rotate_network_secret(inst_id: String, new_secret: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if !INSTANCE_MANAGER.list_network_instance_ids().contains(&uuid) {
+        hilog_error!(
+            "[Rust] rotate_network_secret: instance {} not running",
+            inst_id
+        );
+        return false;
+    }
+    if new_secret.is_empty() {
+        hilog_error!("[Rust] rotate_network_secret: new secret is empty");
+        return false;
+    }
+    // the network secret is baked into the instance's global context when it starts and the
+    // peer handshake has no re-key step, so there is nothing we can swap in place.
+    hilog_error!(
+        "[Rust] rotate_network_secret: live secret rotation is not supported by easytier {}, restart instance {} with the new secret instead",
+        EASYTIER_VERSION,
+        inst_id
+    );
+    false
+}
+
+#[napi]
+pub fn set_bind_source_ip(inst_id: String, ip: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if !ip.is_empty() {
+        let addr = match ip.parse::<IpAddr>() {
+            Ok(addr) => addr,
+            Err(e) => {
+                hilog_error!("[Rust] set_bind_source_ip: invalid ip {}. {}", ip, e);
+                return false;
+            }
+        };
+        // a throwaway bind fails with AddrNotAvailable if the address is not assigned to this device
+        if let Err(e) = std::net::UdpSocket::bind((addr, 0)) {
+            hilog_error!(
+                "[Rust] set_bind_source_ip: {} is not usable on this device. {}",
+                ip,
+                e
+            );
+            return false;
+        }
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            bind_source_ip: Some(ip),
+            ..Default::default()
+        },
+    )
+}
+
+#[napi]
+pub fn set_observer_mode(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            observer_mode: Some(enabled),
+            ..Default::default()
+        },
+    )
+}
+
+// disabled, traffic between other peers is no longer forwarded through the instance, e.g.
+// to save mobile data. the instance still reaches and is reached by all peers.
+#[napi]
+pub fn set_relay_enabled(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            relay_enabled: Some(enabled),
+            ..Default::default()
+        },
+    )
+}
+
+// announces the instance on the local network by mdns and connects to the peers of the
+// same network found there without going through a relay. they are marked with
+// lan_discovered in the peer list. off by default.
+#[napi]
+pub fn set_lan_discovery(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            lan_discovery: Some(enabled),
+            ..Default::default()
+        },
+    )
+}
+
+// lets the other peers run run_throughput_test against this instance. it answers one
+// test per peer at a time, at up to 16 MiB/s for about a minute, and refuses while its
+// own tun traffic is busy. off by default.
+#[napi]
+pub fn set_throughput_test_server_enabled(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            serve_throughput_tests: Some(enabled),
+            ..Default::default()
+        },
+    )
+}
+
+// holds up to `max_packets` outbound packets for up to `max_ms` while no peer of their
+// destination is reachable, e.g. during a reconnect after a network change, and sends
+// them once it is back. when full the oldest are dropped, counted as
+// reconnect_buffer_dropped in the stats. 0 for either disables it, the default.
+#[napi]
+pub fn set_reconnect_buffer(inst_id: String, max_packets: u32, max_ms: u32) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            reconnect_buffer: Some(ReconnectBufferPatch {
+                max_packets,
+                max_ms,
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+// 0 removes the limit.
+#[napi]
+pub fn set_max_peers(inst_id: String, max: u32) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            max_peers: Some(max),
+            ..Default::default()
+        },
+    )
+}
+
+// low_latency, throughput or reliable. kcp streams opened before keep their tuning. the
+// profile overrides latency_first of the config, only low_latency routes by latency.
+#[napi]
+pub fn set_transport_profile(inst_id: String, profile: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if let Err(e) = profile.parse::<TransportProfile>() {
+        hilog_error!("[Rust] set_transport_profile: {}", e);
+        return false;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            transport_profile: Some(profile),
+            ..Default::default()
+        },
+    )
+}
+
+// ipv4_only, ipv6_only, prefer_ipv4 (the default) or prefer_ipv6, used on the next
+// connection attempt to each peer.
+#[napi]
+pub fn set_address_family_preference(inst_id: String, pref: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if let Err(e) = pref.parse::<AddressFamilyPreference>() {
+        hilog_error!("[Rust] set_address_family_preference: {}", e);
+        return false;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            address_family_preference: Some(pref),
+            ..Default::default()
+        },
+    )
+}
+
+// for measuring the cost of encryption, all peers of the network have to be switched
+// the same way. disabling it sends the traffic in plain text.
+#[napi]
+pub fn set_encryption_enabled(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if !enabled {
+        hilog_warn!(
+            "[Rust] encryption of {} disabled, traffic is not protected",
+            uuid
+        );
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            encryption_enabled: Some(enabled),
+            ..Default::default()
+        },
+    )
+}
+
+// name servers peer hostnames are resolved with on the next connect attempts, instead
+// of the system resolver. entries are ips with an optional port, 53 by default. an
+// empty list goes back to the system resolver.
+#[napi]
+pub fn set_dns_resolver(inst_id: String, servers: Vec<String>) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            dns_servers: Some(servers.join(",")),
+            ..Default::default()
+        },
+    )
+}
+
+// the hostname also becomes a magic dns label, so it has to be a single valid label.
+const MAX_HOSTNAME_LEN: usize = 63;
+
+fn is_valid_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().count() <= MAX_HOSTNAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+// peers see the new name after the next route sync, usually within a second.
+#[napi]
+pub fn set_advertised_hostname(inst_id: String, name: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if !is_valid_hostname(&name) {
+        hilog_error!(
+            "[Rust] set_advertised_hostname: invalid hostname {:?}",
+            name
+        );
+        return false;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            hostname: Some(name),
+            ..Default::default()
+        },
+    )
+}
+
+// the key-values other peers of the network see in the metadata of this one in their
+// routes, replacing those set before. fails when keys and values add up to more than
+// 1024 bytes. like the hostname they arrive with the next route sync.
+#[napi]
+pub fn set_peer_metadata(inst_id: String, metadata: Vec<KeyValuePair>) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            peer_metadata: Some(PeerMetadata {
+                entries: metadata.into_iter().map(|x| (x.key, x.value)).collect(),
+            }),
+            ..Default::default()
+        },
+    )
+}
+
+// after `max` failed reconnects in a row a peer is not retried anymore. once all peers
+// are given up while none is connected, get_last_connect_error reports GaveUp and the
+// error callback is called with it. 0 means no limit, the default.
+#[napi]
+pub fn set_max_reconnect_attempts(inst_id: String, max: u32) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            max_reconnect_attempts: Some(max),
+            ..Default::default()
+        },
+    )
+}
+
+// marks the packets of the sockets all instances of the process open from now on with the
+// dscp `value`, e.g. 46 for expedited forwarding. it is not per instance, the sockets are
+// created without knowing it. connections that exist already keep theirs, 0 restores the
+// default for new ones, and so does stopping the last instance. it can be set before
+// any instance starts. only routers that honor dscp prioritize by it.
+#[napi]
+pub fn set_global_dscp(value: u8) -> bool {
+    match easytier::tunnel::common::set_socket_dscp((value != 0).then_some(value)) {
+        Ok(()) => true,
+        Err(e) => {
+            hilog_error!("[Rust] set_global_dscp: {}", e);
+            false
+        }
+    }
+}
+
+// the ping scheduler ticks once per second, shorter intervals cannot be honored.
+const MIN_KEEPALIVE_INTERVAL_MS: u32 = 1000;
+
+#[napi]
+pub fn set_keepalive_interval(inst_id: String, interval_ms: u32) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let mut interval_ms = interval_ms;
+    if interval_ms != 0 && interval_ms < MIN_KEEPALIVE_INTERVAL_MS {
+        hilog_warn!(
+            "[Rust] set_keepalive_interval: {}ms is too low, clamp to {}ms",
+            interval_ms,
+            MIN_KEEPALIVE_INTERVAL_MS
+        );
+        interval_ms = MIN_KEEPALIVE_INTERVAL_MS;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            keepalive_interval_ms: Some(interval_ms),
+            ..Default::default()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use easytier::common::config::NetworkIdentity;
+    use std::sync::{Arc, Barrier};
+
+    fn test_config(inst_id: Uuid) -> TomlConfigLoader {
+        let cfg = TomlConfigLoader::default();
+        cfg.set_id(inst_id);
+        cfg.set_network_identity(NetworkIdentity::new(
+            format!("net-{}", inst_id),
+            "sec".to_string(),
+        ));
+        cfg.set_listeners(vec![]);
+        let mut flags = cfg.get_flags();
+        flags.no_tun = true;
+        cfg.set_flags(flags);
+        cfg
+    }
+
+    #[test]
+    fn test_start_same_config_concurrently() {
+        let inst_id = Uuid::new_v4();
+        let barrier = Arc::new(Barrier::new(2));
+        let starts: Vec<_> = (0..2)
+            .map(|_| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let cfg = test_config(inst_id);
+                    barrier.wait();
+                    start_network_instance(cfg, false)
+                })
+            })
+            .collect();
+        let results: Vec<_> = starts.into_iter().map(|x| x.join().unwrap()).collect();
+
+        // one of them starts it, the other is told it is starting or already running
+        assert_eq!(results.iter().filter(|x| x.success).count(), 1);
+        let rejected = results.iter().find(|x| !x.success).unwrap();
+        assert!(matches!(
+            rejected.error,
+            Some(RunInstanceError::AlreadyStarting | RunInstanceError::AlreadyRunning)
+        ));
+        assert!(!STARTING_INSTANCES.lock().unwrap().contains(&inst_id));
+
+        stop_network_instance(vec![inst_id.to_string()]);
+        assert!(
+            !INSTANCE_MANAGER
+                .list_network_instance_ids()
+                .contains(&inst_id)
+        );
+    }
+
+    #[test]
+    fn test_collect_network_infos_while_start_stop() {
+        let ids = [Uuid::new_v4(), Uuid::new_v4()];
+        let stop = Arc::new(AtomicBool::new(false));
+        let collector = {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut rounds = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    // only whole infos of the instances running while collecting
+                    for info in collect_network_infos() {
+                        assert!(!info.value.is_empty());
+                    }
+                    rounds += 1;
+                }
+                rounds
+            })
+        };
+
+        for _ in 0..10 {
+            for id in ids {
+                assert!(start_network_instance(test_config(id), false).success);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+            stop_network_instance(ids.iter().map(|x| x.to_string()).collect());
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        assert!(collector.join().unwrap() > 0);
+        let keys: Vec<String> = collect_network_infos().into_iter().map(|x| x.key).collect();
+        assert!(ids.iter().all(|x| !keys.contains(&x.to_string())));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::instance_api::{self, block_on};
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::common::config::{ConfigLoader, TomlConfigLoader};
+use easytier::proto::api::instance::ShowNodeInfoRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const PORTS_FILE: &str = "listener_ports.json";
+// how long to wait for the listeners of a started instance before recording their ports
+const LISTENERS_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// instance id -> running listener urls, for the instances whose ports are kept across
+// restarts.
+type StoredPorts = HashMap<String, Vec<String>>;
+
+// the lock also serializes the read-modify-write of the ports file
+static DATA_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn read_stored_ports(path: &Path) -> StoredPorts {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|x| serde_json::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    DATA_DIR.lock().unwrap().clone()
+}
+
+fn stored_listeners(uuid: &Uuid) -> Option<Vec<String>> {
+    let dir = DATA_DIR.lock().unwrap();
+    read_stored_ports(&dir.as_ref()?.join(PORTS_FILE)).remove(&uuid.to_string())
+}
+
+fn update_stored_ports(f: impl FnOnce(&mut StoredPorts)) -> bool {
+    let dir = DATA_DIR.lock().unwrap();
+    let Some(path) = dir.as_ref().map(|x| x.join(PORTS_FILE)) else {
+        hilog_error!("[Rust] no data dir set for listener ports");
+        return false;
+    };
+    let mut ports = read_stored_ports(&path);
+    f(&mut ports);
+    if let Err(e) = std::fs::write(&path, serde_json::to_string(&ports).unwrap()) {
+        hilog_error!("[Rust] write {} failed {}", path.display(), e);
+        return false;
+    }
+    true
+}
+
+fn bind_addr(url: &url::Url) -> Option<SocketAddr> {
+    let ip = match url.host()? {
+        url::Host::Ipv4(ip) => IpAddr::V4(ip),
+        url::Host::Ipv6(ip) => IpAddr::V6(ip),
+        url::Host::Domain(_) => return None,
+    };
+    Some(SocketAddr::new(ip, url.port()?))
+}
+
+// whether the port of the listener can be bound right now, false for unknown schemes
+fn is_port_free(url: &url::Url) -> bool {
+    let Some(addr) = bind_addr(url) else {
+        return false;
+    };
+    match url.scheme() {
+        "tcp" | "ws" | "wss" | "faketcp" => TcpListener::bind(addr).is_ok(),
+        "udp" | "wg" | "quic" => UdpSocket::bind(addr).is_ok(),
+        _ => false,
+    }
+}
+
+#[napi(string_enum)]
+#[derive(PartialEq)]
+pub enum PortStatus {
+    Available,
+    InUse,
+    // a privileged port, below 1024 without the permission to bind those
+    PermissionDenied,
+    // port 0, an unknown protocol or another bind error
+    Invalid,
+}
+
+// binds a throwaway socket on all ipv4 interfaces, it is closed again right away.
+#[napi]
+pub fn get_port_status(port: u16, proto: String) -> PortStatus {
+    if port == 0 {
+        return PortStatus::Invalid;
+    }
+    let addr = SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED), port);
+    let ret = match proto.as_str() {
+        "tcp" => TcpListener::bind(addr).map(drop),
+        "udp" => UdpSocket::bind(addr).map(drop),
+        _ => {
+            hilog_error!("[Rust] unknown protocol {}, use tcp or udp", proto);
+            return PortStatus::Invalid;
+        }
+    };
+    match ret {
+        Ok(()) => PortStatus::Available,
+        Err(e) => match e.kind() {
+            ErrorKind::AddrInUse => PortStatus::InUse,
+            ErrorKind::PermissionDenied => PortStatus::PermissionDenied,
+            _ => {
+                hilog_debug!("[Rust] bind {} port {} failed {}", proto, port, e);
+                PortStatus::Invalid
+            }
+        },
+    }
+}
+
+// whether a listener of `proto` could bind `port` now, see get_port_status for why not.
+#[napi]
+pub fn is_port_available(port: u16, proto: String) -> bool {
+    get_port_status(port, proto) == PortStatus::Available
+}
+
+// gives the listeners of `cfg` configured with port 0 the ports they had last time, if
+// those are still free. returns whether port persistence is enabled for the instance.
+pub(crate) fn restore_listener_ports(cfg: &TomlConfigLoader) -> bool {
+    let Some(saved) = stored_listeners(&cfg.get_id()) else {
+        return false;
+    };
+    let mut listeners = cfg.get_listeners().unwrap_or_default();
+    let fixed_ports: Vec<u16> = listeners
+        .iter()
+        .filter_map(|x| x.port())
+        .filter(|x| *x != 0)
+        .collect();
+    let mut saved: Vec<url::Url> = saved
+        .iter()
+        .filter_map(|x| x.parse().ok())
+        .filter(|x: &url::Url| x.port().is_some_and(|p| !fixed_ports.contains(&p)))
+        .collect();
+
+    for listener in listeners.iter_mut().filter(|x| x.port() == Some(0)) {
+        let Some(pos) = saved
+            .iter()
+            .position(|x| x.scheme() == listener.scheme() && x.host() == listener.host())
+        else {
+            continue;
+        };
+        let url = saved.remove(pos);
+        if is_port_free(&url) {
+            hilog_debug!("[Rust] reuse listener port {}", url);
+            *listener = url;
+        } else {
+            hilog_debug!("[Rust] listener port of {} is taken, use a new one", url);
+        }
+    }
+    cfg.set_listeners(listeners);
+    true
+}
+
+async fn running_listeners(uuid: &Uuid) -> Option<Vec<String>> {
+    let service = INSTANCE_MANAGER.get_instance_service(uuid)?;
+    service
+        .get_peer_manage_service()
+        .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+        .await
+        .ok()?
+        .node_info
+        .map(|x| x.listeners)
+}
+
+fn store_listener_ports(uuid: &Uuid, listeners: Vec<String>) -> bool {
+    update_stored_ports(|x| {
+        x.insert(uuid.to_string(), listeners);
+    })
+}
+
+// records the ports the started instance got, they differ from the saved ones when
+// those were taken.
+pub(crate) fn watch_listener_ports(uuid: Uuid) {
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        while start.elapsed() < LISTENERS_READY_TIMEOUT {
+            match block_on(running_listeners(&uuid)) {
+                Some(listeners) if !listeners.is_empty() => {
+                    store_listener_ports(&uuid, listeners);
+                    return;
+                }
+                _ => std::thread::sleep(Duration::from_millis(200)),
+            }
+        }
+    });
+}
+
+// directory of the state kept across app restarts, e.g. the files dir of the app.
+#[napi]
+pub fn set_data_dir(dir: String) -> bool {
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        hilog_error!("[Rust] set_data_dir: {} is not a directory", dir.display());
+        return false;
+    }
+    *DATA_DIR.lock().unwrap() = Some(dir);
+    true
+}
+
+// when enabled, listeners configured with port 0 get the ports they have now again on
+// the next start of the instance, as long as those are free. the ports are saved in
+// the dir given to set_data_dir, fails if none is set or the instance is not running.
+#[napi]
+pub fn set_listener_port_persistence(inst_id: String, enabled: bool) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if !enabled {
+        return update_stored_ports(|x| {
+            x.remove(&uuid.to_string());
+        });
+    }
+    if instance_api::get_instance_service(&uuid).is_none() {
+        return false;
+    }
+    match block_on(running_listeners(&uuid)) {
+        Some(listeners) => store_listener_ports(&uuid, listeners),
+        None => false,
+    }
+}
+
+// the urls the instance listens on, with the ports actually assigned.
+#[napi]
+pub fn get_listeners(inst_id: String) -> Vec<String> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    block_on(running_listeners(&uuid)).unwrap_or_default()
+}
+
+#[napi(object)]
+pub struct ListenerStatus {
+    // the url in the config, e.g. with port 0
+    pub requested: String,
+    // the url it listens on, with the port actually assigned
+    pub bound: Option<String>,
+    // why binding failed the last time, none once it is bound
+    pub error: Option<String>,
+}
+
+// one entry per configured listener, bound or not, e.g. to tell that the tcp listener
+// is not up while udp is. a failed listener is retried a few times, its entry follows.
+// empty if the instance is not running.
+#[napi]
+pub fn get_listener_status(inst_id: String) -> Vec<ListenerStatus> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = INSTANCE_MANAGER.get_instance_service(&uuid) else {
+        return vec![];
+    };
+    block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()
+    .and_then(|x| x.node_info)
+    .map(|x| x.listener_statuses)
+    .unwrap_or_default()
+    .into_iter()
+    .map(|x| ListenerStatus {
+        requested: x.requested,
+        bound: Some(x.bound).filter(|x| !x.is_empty()),
+        error: Some(x.error).filter(|x| !x.is_empty()),
+    })
+    .collect()
+}
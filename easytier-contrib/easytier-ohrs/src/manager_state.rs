@@ -0,0 +1,125 @@
+use crate::instance_api::block_on;
+use crate::snapshot::{start_from_snapshot, take_snapshot};
+use crate::{
+    EPHEMERAL_INSTANCES, INSTANCE_MANAGER, KeyValuePair, RunInstanceError, RunInstanceResult,
+    labels, parse_inst_id, start_network_instance,
+};
+use easytier::common::config::TomlConfigLoader;
+use easytier::proto::api::instance::ShowNodeInfoRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use uuid::Uuid;
+
+const MANAGER_STATE_VERSION: u64 = 1;
+
+// the config as running now, with the changes made since the start
+fn running_config(uuid: &Uuid) -> Option<String> {
+    let service = INSTANCE_MANAGER.get_instance_service(uuid)?;
+    block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()?
+    .node_info
+    .map(|x| x.config)
+}
+
+// the configs of all running instances with a snapshot of each, see snapshot_instance,
+// to start them again with restore_manager_state after the app was killed. ephemeral
+// instances are left out. the state contains the network secrets, keep it like the
+// configs.
+#[napi]
+pub fn serialize_manager_state() -> String {
+    let ephemeral = EPHEMERAL_INSTANCES.lock().unwrap().clone();
+    let mut instances = vec![];
+    for uuid in INSTANCE_MANAGER.list_network_instance_ids() {
+        if ephemeral.contains(&uuid) {
+            continue;
+        }
+        let Some(config) = running_config(&uuid) else {
+            hilog_debug!("[Rust] instance {} is not running, not saved", uuid);
+            continue;
+        };
+        let labels: Vec<_> = labels::get_labels(&uuid)
+            .into_iter()
+            .map(|x| serde_json::json!({"key": x.key, "value": x.value}))
+            .collect();
+        instances.push(serde_json::json!({
+            "config": config,
+            "snapshot": take_snapshot(&uuid).ok(),
+            "labels": labels,
+        }));
+    }
+    serde_json::json!({
+        "version": MANAGER_STATE_VERSION,
+        "instances": instances,
+    })
+    .to_string()
+}
+
+fn restore_instance(value: &serde_json::Value) -> RunInstanceResult {
+    let invalid = |e: String| {
+        RunInstanceResult::err(
+            RunInstanceError::InvalidConfig,
+            format!("invalid manager state: {}", e),
+        )
+    };
+    let Some(config) = value["config"].as_str() else {
+        return invalid("instance without config".to_string());
+    };
+    let cfg = match TomlConfigLoader::new_from_str(config) {
+        Ok(cfg) => cfg,
+        Err(e) => return invalid(e.to_string()),
+    };
+    let result = match &value["snapshot"] {
+        serde_json::Value::Null => start_network_instance(cfg, false),
+        snapshot => start_from_snapshot(cfg, snapshot),
+    };
+    let labels: Vec<KeyValuePair> = value["labels"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| {
+            Some(KeyValuePair {
+                key: x["key"].as_str()?.to_string(),
+                value: x["value"].as_str()?.to_string(),
+            })
+        })
+        .collect();
+    if let Some(uuid) = result.inst_id.as_deref().and_then(parse_inst_id) {
+        labels::set_labels(uuid, labels);
+    }
+    result
+}
+
+// starts the instances saved by serialize_manager_state under their old ids, so the tun
+// fds can be given to them with set_tun_fd as before. one result per saved instance, in
+// order. a state of another version gives a single InvalidConfig result.
+#[napi]
+pub fn restore_manager_state(state: String) -> Vec<RunInstanceResult> {
+    let value: serde_json::Value = match serde_json::from_str(&state) {
+        Ok(value) => value,
+        Err(e) => {
+            return vec![RunInstanceResult::err(
+                RunInstanceError::InvalidConfig,
+                format!("invalid manager state: {}", e),
+            )];
+        }
+    };
+    let version = value["version"].as_u64().unwrap_or_default();
+    if version != MANAGER_STATE_VERSION {
+        hilog_error!("[Rust] restore_manager_state: version {}", version);
+        return vec![RunInstanceResult::err(
+            RunInstanceError::InvalidConfig,
+            format!("unsupported manager state version {}", version),
+        )];
+    }
+    value["instances"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(restore_instance)
+        .collect()
+}
@@ -0,0 +1,243 @@
+use crate::instance_api::{self, block_on};
+use crate::{INSTANCE_MANAGER, lifecycle_read, parse_inst_id};
+use easytier::common::stats_manager::MetricName;
+use easytier::proto::api::instance::{GetStatsRequest, ListRouteRequest, MetricSnapshot};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// counter values when reset_instance_stats was called, per series of each instance.
+// the counters themselves are written lock free on the data path and cannot be reset.
+static STATS_BASELINES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, HashMap<String, u64>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn series_key(metric: &MetricSnapshot) -> String {
+    let mut labels: Vec<_> = metric.labels.iter().collect();
+    labels.sort();
+    format!("{}{:?}", metric.name, labels)
+}
+
+// make counters count from the last reset. series created after it count from zero, and
+// so does one that dropped below its baseline, it was evicted while idle and recreated.
+fn apply_baselines(uuid: &Uuid, metrics: &mut [MetricSnapshot]) {
+    let mut baselines = STATS_BASELINES.lock().unwrap();
+    let Some(baseline) = baselines.get_mut(uuid) else {
+        return;
+    };
+    let mut seen = HashMap::new();
+    for metric in metrics.iter_mut() {
+        let key = series_key(metric);
+        if let Some(base) = baseline.get(&key).copied() {
+            if metric.value >= base {
+                metric.value -= base;
+                seen.insert(key, base);
+            }
+        }
+    }
+    *baseline = seen;
+}
+
+pub(crate) async fn get_instance_stats(uuid: &Uuid) -> Option<Vec<MetricSnapshot>> {
+    let mut metrics = INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_stats_service()
+        .get_stats(BaseController::default(), GetStatsRequest::default())
+        .await
+        .ok()?
+        .metrics;
+    apply_baselines(uuid, &mut metrics);
+    Some(metrics)
+}
+
+pub(crate) fn remove_stats_baselines(uuids: &[Uuid]) {
+    let mut baselines = STATS_BASELINES.lock().unwrap();
+    baselines.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// zero the traffic and reconnect counters reported by get_metrics_prometheus, e.g. to
+// measure usage over a window. conns and data quotas are not affected, scrapers see the
+// drop as a counter reset so rates do not spike.
+#[napi]
+pub fn reset_instance_stats(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return false;
+    };
+    let Ok(resp) = block_on(
+        service
+            .get_stats_service()
+            .get_stats(BaseController::default(), GetStatsRequest::default()),
+    ) else {
+        return false;
+    };
+    let baseline = resp
+        .metrics
+        .iter()
+        .map(|m| (series_key(m), m.value))
+        .collect();
+    STATS_BASELINES.lock().unwrap().insert(uuid, baseline);
+    true
+}
+
+// only instance level labels, so the number of series grows with instances and not
+// with peers, conns or destinations.
+struct InstanceMetrics {
+    labels: String,
+    tx_bytes: u64,
+    rx_bytes: u64,
+    reconnects: u64,
+    peers: usize,
+    direct_peers: usize,
+    // latency of directly connected peers
+    avg_latency_ms: Option<f64>,
+    max_latency_ms: Option<i32>,
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+async fn collect_instance_metrics(uuid: &Uuid) -> Option<InstanceMetrics> {
+    let network_name = INSTANCE_MANAGER.get_network_instance_name(uuid)?;
+    let stats = get_instance_stats(uuid).await?;
+    let routes = INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_peer_manage_service()
+        .list_route(BaseController::default(), ListRouteRequest::default())
+        .await
+        .ok()?
+        .routes;
+
+    let sum_of = |name: MetricName| {
+        let name = name.to_string();
+        stats
+            .iter()
+            .filter(|m| m.name == name)
+            .map(|m| m.value)
+            .sum::<u64>()
+    };
+    let direct_latencies: Vec<i32> = routes
+        .iter()
+        .filter(|r| r.cost == 1)
+        .map(|r| r.path_latency)
+        .collect();
+
+    Some(InstanceMetrics {
+        labels: format!(
+            "inst_id=\"{}\",network_name=\"{}\"",
+            uuid,
+            escape_label_value(&network_name)
+        ),
+        tx_bytes: sum_of(MetricName::TrafficBytesTx),
+        rx_bytes: sum_of(MetricName::TrafficBytesRx),
+        reconnects: sum_of(MetricName::ConnectorReconnect),
+        peers: routes.len(),
+        direct_peers: direct_latencies.len(),
+        avg_latency_ms: (!direct_latencies.is_empty()).then(|| {
+            direct_latencies.iter().map(|x| *x as f64).sum::<f64>() / direct_latencies.len() as f64
+        }),
+        max_latency_ms: direct_latencies.iter().max().copied(),
+    })
+}
+
+fn write_family<T: std::fmt::Display>(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    instances: &[InstanceMetrics],
+    value: impl Fn(&InstanceMetrics) -> Option<T>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for inst in instances {
+        if let Some(v) = value(inst) {
+            let _ = writeln!(out, "{}{{{}}} {}", name, inst.labels, v);
+        }
+    }
+}
+
+// per instance counters in prometheus text exposition format. counters of idle conns
+// are evicted by the stats manager, scrapers see that as a counter reset.
+#[napi]
+pub fn get_metrics_prometheus() -> String {
+    let instances: Vec<InstanceMetrics> = {
+        let _guard = lifecycle_read();
+        block_on(async {
+            let mut ret = Vec::new();
+            for uuid in INSTANCE_MANAGER.list_network_instance_ids() {
+                if let Some(m) = collect_instance_metrics(&uuid).await {
+                    ret.push(m);
+                }
+            }
+            ret
+        })
+    };
+
+    let mut out = String::new();
+    write_family(
+        &mut out,
+        "easytier_traffic_bytes_tx_total",
+        "counter",
+        "Bytes sent on peer conns.",
+        &instances,
+        |m| Some(m.tx_bytes),
+    );
+    write_family(
+        &mut out,
+        "easytier_traffic_bytes_rx_total",
+        "counter",
+        "Bytes received on peer conns.",
+        &instances,
+        |m| Some(m.rx_bytes),
+    );
+    write_family(
+        &mut out,
+        "easytier_reconnects_total",
+        "counter",
+        "Reconnect attempts of configured peers.",
+        &instances,
+        |m| Some(m.reconnects),
+    );
+    write_family(
+        &mut out,
+        "easytier_peers",
+        "gauge",
+        "Reachable peers.",
+        &instances,
+        |m| Some(m.peers),
+    );
+    write_family(
+        &mut out,
+        "easytier_direct_peers",
+        "gauge",
+        "Directly connected peers.",
+        &instances,
+        |m| Some(m.direct_peers),
+    );
+    write_family(
+        &mut out,
+        "easytier_peer_latency_avg_ms",
+        "gauge",
+        "Average latency of directly connected peers in milliseconds.",
+        &instances,
+        |m| m.avg_latency_ms,
+    );
+    write_family(
+        &mut out,
+        "easytier_peer_latency_max_ms",
+        "gauge",
+        "Max latency of directly connected peers in milliseconds.",
+        &instances,
+        |m| m.max_latency_ms,
+    );
+    out
+}
@@ -0,0 +1,350 @@
+use crate::callback::check_call;
+use crate::instance_api::{self, block_on};
+use crate::route::subscribe_route_snapshots;
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::common::stun::{StunInfoCollector, StunInfoCollectorTrait};
+use easytier::proto::api::config::InstanceConfigPatch;
+use easytier::proto::api::instance::ShowNodeInfoRequest;
+use easytier::proto::common::{NatType, StunInfo};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::AsyncTask;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_ohos::{Env, Task};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const PREWARM_TIMEOUT: Duration = Duration::from_secs(10);
+// the warnings are checked with the route snapshots, at most this often
+const NAT_WARNING_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[napi(object)]
+pub struct NatInfo {
+    pub udp_nat_type: String,
+    pub tcp_nat_type: String,
+    pub public_ips: Vec<String>,
+    pub min_port: u32,
+    pub max_port: u32,
+    pub last_update_time: i64,
+}
+
+impl From<StunInfo> for NatInfo {
+    fn from(info: StunInfo) -> Self {
+        Self {
+            udp_nat_type: info.udp_nat_type().as_str_name().to_string(),
+            tcp_nat_type: info.tcp_nat_type().as_str_name().to_string(),
+            public_ips: info.public_ip,
+            min_port: info.min_port,
+            max_port: info.max_port,
+            last_update_time: info.last_update_time,
+        }
+    }
+}
+
+impl NatInfo {
+    fn to_stun_info(&self) -> Option<StunInfo> {
+        Some(StunInfo {
+            udp_nat_type: NatType::from_str_name(&self.udp_nat_type)? as i32,
+            tcp_nat_type: NatType::from_str_name(&self.tcp_nat_type)? as i32,
+            last_update_time: self.last_update_time,
+            public_ip: self.public_ips.clone(),
+            min_port: self.min_port,
+            max_port: self.max_port,
+        })
+    }
+}
+
+pub struct PrewarmNatTask;
+
+#[napi]
+impl Task for PrewarmNatTask {
+    type Output = NatInfo;
+    type JsValue = NatInfo;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        // a standalone collector, detection tasks are aborted when it is dropped.
+        let info = block_on(async {
+            let collector = StunInfoCollector::new_with_default_servers();
+            let start = Instant::now();
+            loop {
+                let info = collector.get_stun_info();
+                let done = info.udp_nat_type != NatType::Unknown as i32
+                    && info.tcp_nat_type != NatType::Unknown as i32;
+                if done || start.elapsed() > PREWARM_TIMEOUT {
+                    break info;
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        });
+        hilog_debug!("[Rust] prewarm nat detection done {:?}", info);
+        Ok(info.into())
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+#[napi]
+pub fn prewarm_nat_detection() -> AsyncTask<PrewarmNatTask> {
+    AsyncTask::new(PrewarmNatTask)
+}
+
+pub struct RefreshNatTask {
+    inst_id: String,
+}
+
+pub(crate) async fn instance_stun_info(uuid: &Uuid) -> Option<StunInfo> {
+    INSTANCE_MANAGER
+        .get_instance_service(uuid)?
+        .get_peer_manage_service()
+        .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+        .await
+        .ok()?
+        .node_info?
+        .stun_info
+}
+
+#[napi]
+impl Task for RefreshNatTask {
+    type Output = NatInfo;
+    type JsValue = NatInfo;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        let not_running =
+            || napi_ohos::Error::from_reason(format!("instance {} is not running", self.inst_id));
+        let uuid = parse_inst_id(&self.inst_id).ok_or_else(not_running)?;
+        // last_update_time is in seconds
+        let requested_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let patch = InstanceConfigPatch {
+            redetect_nat: Some(true),
+            ..Default::default()
+        };
+        if !instance_api::patch_instance_config(&uuid, patch) {
+            return Err(not_running());
+        }
+
+        let info = block_on(async {
+            let start = Instant::now();
+            loop {
+                let info = instance_stun_info(&uuid).await?;
+                let done = info.last_update_time >= requested_at
+                    && info.udp_nat_type != NatType::Unknown as i32
+                    && info.tcp_nat_type != NatType::Unknown as i32;
+                if done || start.elapsed() > PREWARM_TIMEOUT {
+                    break Some(info);
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        })
+        .ok_or_else(not_running)?;
+        hilog_debug!("[Rust] nat detection of {} refreshed {:?}", uuid, info);
+        Ok(info.into())
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+// detects the nat type of the running instance again, e.g. when the os reports a
+// connectivity change, and resolves with the new result. gives up waiting after 10s and
+// resolves with what is known then, rejects if the instance is not running.
+#[napi]
+pub fn refresh_nat_detection(inst_id: String) -> AsyncTask<RefreshNatTask> {
+    AsyncTask::new(RefreshNatTask { inst_id })
+}
+
+// inject nat info detected by prewarm_nat_detection, the instance reports it to peers
+// until its own detection finishes instead of reporting an unknown nat type.
+#[napi]
+pub fn set_nat_info_hint(inst_id: String, info: NatInfo) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Some(stun_info) = info.to_stun_info() else {
+        hilog_error!(
+            "[Rust] set_nat_info_hint: invalid nat type {} {}",
+            info.udp_nat_type,
+            info.tcp_nat_type
+        );
+        return false;
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            stun_info_hint: Some(stun_info),
+            ..Default::default()
+        },
+    )
+}
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NatWarningKind {
+    // the udp mapping changes per destination in an unpredictable way, hole punching
+    // mostly fails and peers are reached through relays
+    SymmetricNat,
+    // the device only has a carrier grade nat address, nothing can connect to it
+    Cgnat,
+}
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NatSuggestion {
+    // the device has a public ipv6 address, peers with ipv6 connect directly
+    UseIpv6,
+    // add a public peer close to the device to relay through
+    AddRelayPeer,
+}
+
+#[napi(object)]
+pub struct NatWarning {
+    pub inst_id: String,
+    pub kind: NatWarningKind,
+    pub suggestion: NatSuggestion,
+    pub udp_nat_type: String,
+}
+
+// 100.64.0.0/10, rfc 6598
+fn is_cgnat_addr(addr: &Ipv4Addr) -> bool {
+    let octets = addr.octets();
+    octets[0] == 100 && (octets[1] & 0xc0) == 64
+}
+
+pub(crate) async fn detect_nat_warnings(uuid: &Uuid) -> Vec<NatWarning> {
+    let Some(service) = INSTANCE_MANAGER.get_instance_service(uuid) else {
+        return vec![];
+    };
+    let Some(node_info) = service
+        .get_peer_manage_service()
+        .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+        .await
+        .ok()
+        .and_then(|x| x.node_info)
+    else {
+        return vec![];
+    };
+    let stun_info = node_info.stun_info.unwrap_or_default();
+    let ip_list = node_info.ip_list.unwrap_or_default();
+
+    let mut kinds = vec![];
+    if matches!(
+        stun_info.udp_nat_type(),
+        NatType::Symmetric | NatType::SymUdpFirewall
+    ) {
+        kinds.push(NatWarningKind::SymmetricNat);
+    }
+    if ip_list
+        .interface_ipv4s
+        .iter()
+        .chain(ip_list.public_ipv4.iter())
+        .any(|x| is_cgnat_addr(&Ipv4Addr::from(x.addr)))
+    {
+        kinds.push(NatWarningKind::Cgnat);
+    }
+
+    let suggestion = if ip_list.public_ipv6.is_some() {
+        NatSuggestion::UseIpv6
+    } else {
+        NatSuggestion::AddRelayPeer
+    };
+    kinds
+        .into_iter()
+        .map(|kind| NatWarning {
+            inst_id: uuid.to_string(),
+            kind,
+            suggestion,
+            udp_nat_type: stun_info.udp_nat_type().as_str_name().to_string(),
+        })
+        .collect()
+}
+
+async fn watch_nat_warnings(func: ThreadsafeFunction<NatWarning, ()>) {
+    let mut warned: HashSet<(Uuid, NatWarningKind)> = HashSet::new();
+    let mut last_check: Option<Instant> = None;
+    let mut snapshots = subscribe_route_snapshots();
+    while snapshots.changed().await.is_ok() {
+        let snapshot = snapshots.borrow_and_update().clone();
+        if last_check.is_some_and(|x| x.elapsed() < NAT_WARNING_CHECK_INTERVAL) {
+            continue;
+        }
+        last_check = Some(Instant::now());
+        warned.retain(|(id, _)| snapshot.instances.contains(id));
+        for id in snapshot.instances.iter() {
+            for warning in detect_nat_warnings(id).await {
+                if warned.insert((*id, warning.kind)) {
+                    hilog_debug!("[Rust] instance {} nat warning {:?}", id, warning.kind);
+                    check_call(
+                        "nat warning",
+                        func.call(Ok(warning), ThreadsafeFunctionCallMode::NonBlocking),
+                    );
+                }
+            }
+        }
+    }
+}
+
+static NAT_WARNING_WATCH: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+
+// fired once per instance and kind as soon as the nat detection of the instance finds it
+// behind a hard symmetric nat or a carrier grade nat. registering again reports the
+// current warnings once more.
+#[napi]
+pub fn register_nat_warning_callback(func: ThreadsafeFunction<NatWarning, ()>) {
+    let task = instance_api::spawn(watch_nat_warnings(func));
+    if let Some(old) = NAT_WARNING_WATCH.lock().unwrap().replace(task) {
+        old.abort();
+    }
+}
+
+#[napi]
+pub fn unregister_nat_warning_callback() {
+    if let Some(old) = NAT_WARNING_WATCH.lock().unwrap().take() {
+        old.abort();
+    }
+}
+
+// idle timeouts of udp mappings seen in practice, rfc 4787 asks for at least 2 minutes but
+// many home routers and most carrier grade nats drop them much earlier.
+const CONE_MAPPING_TTL_SECS: u32 = 60;
+const STRICT_MAPPING_TTL_SECS: u32 = 30;
+
+// estimated seconds an idle udp mapping of the nat the instance is behind stays open,
+// keepalives sent more often than this hold the mappings of the peer conns. it is an
+// estimate from the detected nat type, carrier grade nat and symmetric nats get the
+// shorter one. undefined when not behind a nat, while the nat type is unknown or the
+// instance is not running.
+#[napi]
+pub fn get_nat_mapping_ttl(inst_id: String) -> Option<u32> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let node_info = block_on(async {
+        INSTANCE_MANAGER
+            .get_instance_service(&uuid)?
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+            .await
+            .ok()?
+            .node_info
+    })?;
+    let cgnat = node_info.ip_list.is_some_and(|x| {
+        x.interface_ipv4s
+            .iter()
+            .chain(x.public_ipv4.iter())
+            .any(|x| is_cgnat_addr(&Ipv4Addr::from(x.addr)))
+    });
+    match node_info.stun_info?.udp_nat_type() {
+        NatType::Unknown | NatType::OpenInternet | NatType::NoPat => None,
+        NatType::FullCone | NatType::Restricted | NatType::PortRestricted if !cgnat => {
+            Some(CONE_MAPPING_TTL_SECS)
+        }
+        _ => Some(STRICT_MAPPING_TTL_SECS),
+    }
+}
@@ -4,10 +4,13 @@ use ohos_hilog_binding::{
 };
 use std::collections::HashMap;
 use std::panic;
+use std::sync::{Mutex, OnceLock};
 use tracing::{Event, Subscriber};
-use tracing_core::Level;
+use tracing_core::{Level, LevelFilter};
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::layer::{Context, Layer};
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Registry, reload};
 
 static INITIALIZED: std::sync::Once = std::sync::Once::new();
 fn panic_hook(info: &panic::PanicHookInfo) {
@@ -30,13 +33,152 @@ pub fn hilog_global_options(domain: u32, tag: String) {
     })
 }
 
+static LOG_TARGETS: OnceLock<reload::Handle<Targets, Registry>> = OnceLock::new();
+
+// what set_log_level and set_log_targets set, applied again by each of them and by
+// init_tracing_subscriber, so neither undoes the other and nothing set before the init
+// is lost
+#[derive(Clone)]
+struct LogState {
+    level: Option<LevelFilter>,
+    filters: Vec<String>,
+}
+
+static LOG_STATE: Mutex<LogState> = Mutex::new(LogState {
+    level: None,
+    filters: vec![],
+});
+
+fn all_targets() -> Targets {
+    Targets::new().with_default(LevelFilter::TRACE)
+}
+
+impl LogState {
+    fn targets(&self) -> Result<Targets, String> {
+        if self.filters.is_empty() && self.level.is_none() {
+            return Ok(all_targets());
+        }
+        let targets = if self.filters.is_empty() {
+            Targets::new()
+        } else {
+            self.filters
+                .join(",")
+                .parse::<Targets>()
+                .map_err(|e| format!("invalid filter {:?} {}", self.filters, e))?
+        };
+        let default = self.level.or(targets.default_level()).unwrap_or(
+            // without filters everything is logged, with them only the given modules
+            if self.filters.is_empty() {
+                LevelFilter::TRACE
+            } else {
+                LevelFilter::OFF
+            },
+        );
+        Ok(targets.with_default(default))
+    }
+}
+
+// reloading the filter rebuilds the interest cache of all callsites, events that were
+// disabled before are emitted from the next one on
+fn update_log_state(
+    handle: Option<&reload::Handle<Targets, Registry>>,
+    update: impl FnOnce(&mut LogState),
+) -> Result<(), String> {
+    let mut state = LOG_STATE.lock().unwrap();
+    let mut new_state = state.clone();
+    update(&mut new_state);
+    let targets = new_state.targets()?;
+    if let Some(handle) = handle {
+        handle.reload(targets).map_err(|e| e.to_string())?;
+    }
+    *state = new_state;
+    Ok(())
+}
+
 #[napi]
 pub fn init_tracing_subscriber() {
-    tracing_subscriber::registry()
-        .with(CallbackLayer {
-            callback: Box::new(tracing_callback),
-        })
-        .init();
+    // held until the handle is set, so no setter in between is missed
+    let state = LOG_STATE.lock().unwrap();
+    let targets = state.targets().unwrap_or_else(|_| all_targets());
+    let (filter, handle) = reload::Layer::new(targets);
+    if LOG_TARGETS.set(handle).is_err() {
+        return;
+    }
+    drop(state);
+    if let Err(e) = tracing_subscriber::registry()
+        .with(
+            CallbackLayer {
+                callback: Box::new(tracing_callback),
+            }
+            .with_filter(filter),
+        )
+        .try_init()
+    {
+        hilog_error!("[Rust] init_tracing_subscriber failed {}", e);
+    }
+}
+
+// level of the modules without a filter of set_log_targets: off, error, warn, info,
+// debug or trace. empty resets it to what set_log_targets gives. takes effect at once,
+// also when called before init_tracing_subscriber. false if the level is invalid.
+#[napi]
+pub fn set_log_level(level: String) -> bool {
+    let level = if level.is_empty() {
+        None
+    } else {
+        match level.parse::<LevelFilter>() {
+            Ok(level) => Some(level),
+            Err(e) => {
+                hilog_error!("[Rust] set_log_level: invalid level {} {}", level, e);
+                return false;
+            }
+        }
+    };
+    match update_log_state(LOG_TARGETS.get(), |state| state.level = level) {
+        Ok(()) => true,
+        Err(e) => {
+            hilog_error!("[Rust] set_log_level failed {}", e);
+            false
+        }
+    }
+}
+
+// only events of the given modules reach hilog, e.g. ["easytier::peers=debug",
+// "easytier::tunnel=info"]. a filter without module is the level of all other modules,
+// which are dropped otherwise, unless set_log_level gives their level. empty logs
+// everything again. takes effect at once, also when called before
+// init_tracing_subscriber. false if a filter is invalid.
+#[napi]
+pub fn set_log_targets(filters: Vec<String>) -> bool {
+    match update_log_state(LOG_TARGETS.get(), |state| state.filters = filters) {
+        Ok(()) => true,
+        Err(e) => {
+            hilog_error!("[Rust] set_log_targets: {}", e);
+            false
+        }
+    }
+}
+
+#[napi(object)]
+pub struct LogConfig {
+    // level of the modules without a filter, as set_log_level takes it
+    pub level: String,
+    // as given to set_log_targets
+    pub targets: Vec<String>,
+}
+
+#[napi]
+pub fn get_log_config() -> LogConfig {
+    let state = LOG_STATE.lock().unwrap().clone();
+    let level = state
+        .targets()
+        .ok()
+        .and_then(|x| x.default_level())
+        .unwrap_or(LevelFilter::TRACE);
+    LogConfig {
+        level: level.to_string().to_lowercase(),
+        targets: state.filters,
+    }
 }
 
 fn tracing_callback(event: &Event, fields: HashMap<String, String>) {
@@ -94,3 +236,60 @@ impl<'a> tracing::field::Visit for FieldCollector<'a> {
             .insert(field.name().to_string(), format!("{:?}", value));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_reload_log_config() {
+        let emitted = Arc::new(AtomicUsize::new(0));
+        let counter = emitted.clone();
+        let (filter, handle) = reload::Layer::new(all_targets());
+        let subscriber = tracing_subscriber::registry().with(
+            CallbackLayer {
+                callback: Box::new(move |_, _| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }),
+            }
+            .with_filter(filter),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("logged");
+            assert_eq!(emitted.load(Ordering::Relaxed), 1);
+
+            update_log_state(Some(&handle), |x| x.level = Some(LevelFilter::WARN)).unwrap();
+            tracing::debug!("dropped");
+            tracing::warn!("logged");
+            assert_eq!(emitted.load(Ordering::Relaxed), 2);
+            assert_eq!(get_log_config().level, "warn");
+
+            let filters = vec!["easytier_ohrs::native_log=debug".to_string()];
+            update_log_state(Some(&handle), |x| x.filters = filters.clone()).unwrap();
+            tracing::debug!("logged");
+            assert_eq!(emitted.load(Ordering::Relaxed), 3);
+            assert_eq!(get_log_config().targets, filters);
+
+            // an invalid filter changes nothing
+            assert!(
+                update_log_state(Some(&handle), |x| x.filters = vec!["a=loud".to_string()])
+                    .is_err()
+            );
+            tracing::debug!("logged");
+            assert_eq!(emitted.load(Ordering::Relaxed), 4);
+            assert_eq!(get_log_config().targets, filters);
+
+            update_log_state(Some(&handle), |x| x.filters.clear()).unwrap();
+            tracing::debug!("dropped");
+            assert_eq!(emitted.load(Ordering::Relaxed), 4);
+
+            update_log_state(Some(&handle), |x| x.level = None).unwrap();
+            tracing::trace!("logged");
+            assert_eq!(emitted.load(Ordering::Relaxed), 5);
+            assert_eq!(get_log_config().level, "trace");
+        });
+    }
+}
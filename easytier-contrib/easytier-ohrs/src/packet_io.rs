@@ -0,0 +1,178 @@
+use crate::callback::check_call;
+use crate::instance_api::{self, block_on};
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::proto::api::instance::ShowNodeInfoRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::Status;
+use napi_ohos::bindgen_prelude::Buffer;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const MAX_PACKET_SIZE: usize = 65535;
+// how often the reader thread checks whether the pipe is released
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+// drop the packet instead of blocking the js thread when the instance stops reading
+const WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+// packets waiting for the js thread, further ones are dropped like on a full nic queue
+const READER_QUEUE_SIZE: usize = 1024;
+
+type PacketReader = ThreadsafeFunction<Buffer, (), Buffer, Status, true, false, READER_QUEUE_SIZE>;
+
+// a datagram socket pair keeps packet boundaries, so one end can be handed to the
+// instance in place of a tun fd while the app shuttles packets through the other.
+struct PacketPipe {
+    app_end: UnixDatagram,
+    // the fd the instance uses as its tun device, has to stay open as long as it runs
+    _tun_end: UnixDatagram,
+    reader: Mutex<Option<PacketReader>>,
+    released: AtomicBool,
+}
+
+static PIPES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, Arc<PacketPipe>>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn read_loop(uuid: Uuid, pipe: Arc<PacketPipe>) {
+    let mut buf = vec![0u8; MAX_PACKET_SIZE];
+    while !pipe.released.load(Ordering::Relaxed) {
+        let len = match pipe.app_end.recv(&mut buf) {
+            Ok(len) => len,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                hilog_error!("[Rust] read packet of {} failed {}", uuid, e);
+                break;
+            }
+        };
+        if let Some(func) = pipe.reader.lock().unwrap().as_ref() {
+            check_call(
+                "packet reader",
+                func.call(
+                    Ok(buf[..len].to_vec().into()),
+                    ThreadsafeFunctionCallMode::NonBlocking,
+                ),
+            );
+        }
+    }
+    hilog_debug!("[Rust] packet reader of {} stopped", uuid);
+}
+
+fn create_pipe(uuid: &Uuid) -> Option<Arc<PacketPipe>> {
+    let (app_end, tun_end) = match UnixDatagram::pair() {
+        Ok(pair) => pair,
+        Err(e) => {
+            hilog_error!("[Rust] create packet pipe failed {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = app_end
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .and_then(|_| app_end.set_write_timeout(Some(WRITE_TIMEOUT)))
+    {
+        hilog_error!("[Rust] set packet pipe timeout failed {}", e);
+        return None;
+    }
+    if let Err(e) = INSTANCE_MANAGER.set_tun_fd(uuid, tun_end.as_raw_fd()) {
+        hilog_error!("[Rust] cant attach packet pipe to {}. {}", uuid, e);
+        return None;
+    }
+
+    let pipe = Arc::new(PacketPipe {
+        app_end,
+        _tun_end: tun_end,
+        reader: Mutex::new(None),
+        released: AtomicBool::new(false),
+    });
+    let pipe_c = pipe.clone();
+    let uuid = *uuid;
+    std::thread::spawn(move || read_loop(uuid, pipe_c));
+    Some(pipe)
+}
+
+// use the app as the tun device of the instance: packets the instance sends to the
+// virtual nic are passed to `func`, and the app feeds received ones with write_packet.
+// an alternative to set_tun_fd, registering again replaces the callback.
+#[napi]
+pub fn register_packet_reader(inst_id: String, func: PacketReader) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let mut pipes = PIPES.lock().unwrap();
+    let pipe = match pipes.get(&uuid) {
+        Some(pipe) => pipe.clone(),
+        None => {
+            let Some(pipe) = create_pipe(&uuid) else {
+                return false;
+            };
+            pipes.insert(uuid, pipe.clone());
+            pipe
+        }
+    };
+    *pipe.reader.lock().unwrap() = Some(func);
+    true
+}
+
+// feed one raw ip packet to the instance as if read from its tun device.
+#[napi]
+pub fn write_packet(inst_id: String, data: Buffer) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Some(pipe) = PIPES.lock().unwrap().get(&uuid).cloned() else {
+        hilog_error!(
+            "[Rust] write_packet: no packet reader registered for {}",
+            uuid
+        );
+        return false;
+    };
+    match pipe.app_end.send(&data) {
+        Ok(_) => true,
+        Err(e) => {
+            hilog_debug!("[Rust] write_packet to {} dropped {}", uuid, e);
+            false
+        }
+    }
+}
+
+// close pipes whose instance is no longer managed, returns how many were closed.
+pub(crate) fn release_orphaned_packet_pipes() -> u32 {
+    let ids = INSTANCE_MANAGER.list_network_instance_ids();
+    let mut pipes = PIPES.lock().unwrap();
+    let before = pipes.len();
+    pipes.retain(|uuid, pipe| {
+        let alive = ids.contains(uuid);
+        if !alive {
+            pipe.released.store(true, Ordering::Relaxed);
+        }
+        alive
+    });
+    (before - pipes.len()) as u32
+}
+
+// the largest ip packet to pass to write_packet, or to set as mtu of a tun device the
+// app creates itself. lower when encryption is on, 0 if the instance is not running.
+#[napi]
+pub fn get_effective_overlay_mtu(inst_id: String) -> u32 {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return 0;
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return 0;
+    };
+    block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()
+    .and_then(|x| x.node_info)
+    .map(|x| x.overlay_mtu)
+    .unwrap_or_default()
+}
@@ -0,0 +1,124 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use easytier::proto::api::instance::{ListRouteRequest, PingPeerRequest, Route};
+use easytier::proto::rpc_types::controller::BaseController;
+use easytier::rpc_service::InstanceRpcService;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+const PING_COUNT: u32 = 4;
+const PING_TIMEOUT_MS: u32 = 1000;
+// peers pinged at the same time during a sweep
+const SWEEP_CONCURRENCY: usize = 8;
+
+#[napi(object)]
+pub struct PingResult {
+    pub peer_id: u32,
+    pub hostname: String,
+    pub ipv4_addr: Option<String>,
+    pub sent: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    // none if no probe was answered
+    pub avg_rtt_ms: Option<f64>,
+    pub min_rtt_ms: Option<f64>,
+    pub max_rtt_ms: Option<f64>,
+}
+
+async fn ping(service: &dyn InstanceRpcService, route: Route) -> Option<PingResult> {
+    let resp = match service
+        .get_peer_manage_service()
+        .ping_peer(
+            BaseController::default(),
+            PingPeerRequest {
+                instance: None,
+                peer_id: route.peer_id,
+                count: PING_COUNT,
+                timeout_ms: PING_TIMEOUT_MS,
+            },
+        )
+        .await
+    {
+        Ok(resp) => resp,
+        Err(e) => {
+            hilog_error!("[Rust] ping peer {} failed {}", route.peer_id, e);
+            return None;
+        }
+    };
+
+    let rtts: Vec<f64> = resp.rtt_us.iter().map(|x| *x as f64 / 1000.0).collect();
+    Some(PingResult {
+        peer_id: route.peer_id,
+        hostname: route.hostname,
+        ipv4_addr: route.ipv4_addr.map(|x| x.to_string()),
+        sent: resp.sent,
+        received: resp.received,
+        loss_percent: if resp.sent == 0 {
+            0.0
+        } else {
+            (resp.sent - resp.received) as f64 * 100.0 / resp.sent as f64
+        },
+        avg_rtt_ms: (!rtts.is_empty()).then(|| rtts.iter().sum::<f64>() / rtts.len() as f64),
+        min_rtt_ms: rtts.iter().copied().reduce(f64::min),
+        max_rtt_ms: rtts.iter().copied().reduce(f64::max),
+    })
+}
+
+async fn list_routes(service: &dyn InstanceRpcService) -> Vec<Route> {
+    service
+        .get_peer_manage_service()
+        .list_route(BaseController::default(), ListRouteRequest::default())
+        .await
+        .map(|x| x.routes)
+        .unwrap_or_default()
+}
+
+// probes go over the current route, so relayed peers are measured end to end.
+#[napi]
+pub fn ping_peer(inst_id: String, peer_id: u32) -> Option<PingResult> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let service = instance_api::get_instance_service(&uuid)?;
+    block_on(async {
+        let route = list_routes(service.as_ref())
+            .await
+            .into_iter()
+            .find(|r| r.peer_id == peer_id)?;
+        ping(service.as_ref(), route).await
+    })
+}
+
+// pings every peer in the route table, results are sorted by peer id. takes about
+// PING_COUNT * PING_TIMEOUT_MS per SWEEP_CONCURRENCY unreachable peers.
+#[napi]
+pub fn run_ping_sweep(inst_id: String) -> Vec<PingResult> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    block_on(async {
+        let semaphore = Arc::new(Semaphore::new(SWEEP_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+        for route in list_routes(service.as_ref()).await {
+            let service = service.clone();
+            let semaphore = semaphore.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire().await.ok()?;
+                ping(service.as_ref(), route).await
+            });
+        }
+
+        let mut results = Vec::new();
+        while let Some(ret) = tasks.join_next().await {
+            if let Ok(Some(result)) = ret {
+                results.push(result);
+            }
+        }
+        results.sort_by_key(|r| r.peer_id);
+        results
+    })
+}
@@ -0,0 +1,177 @@
+use crate::callback::check_call;
+use crate::instance_api::{self, block_on};
+use crate::watches::Watches;
+use crate::{INSTANCE_MANAGER, parse_inst_id, stop_network_instance};
+use easytier::common::stats_manager::MetricName;
+use easytier::proto::api::instance::GetStatsRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const QUOTA_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[napi(object)]
+pub struct QuotaEvent {
+    pub inst_id: String,
+    pub used_bytes: i64,
+    pub quota_bytes: i64,
+    pub paused: bool,
+}
+
+struct QuotaState {
+    quota_bytes: u64,
+    auto_pause: bool,
+    used_bytes: AtomicU64,
+    // value of each counter seen by the last check
+    last_counters: Mutex<HashMap<CounterKey, u64>>,
+    exceeded: AtomicBool,
+    func: ThreadsafeFunction<QuotaEvent, ()>,
+}
+
+static QUOTAS: once_cell::sync::Lazy<Watches<Uuid, QuotaState>> =
+    once_cell::sync::Lazy::new(Watches::new);
+
+// metric name and labels, one per peer conn counter
+type CounterKey = (String, BTreeMap<String, String>);
+
+// cumulative bytes sent and received by each peer conn counter of the instance.
+async fn traffic_counters(uuid: &Uuid) -> Option<HashMap<CounterKey, u64>> {
+    let service = INSTANCE_MANAGER.get_instance_service(uuid)?;
+    let metrics = service
+        .get_stats_service()
+        .get_stats(BaseController::default(), GetStatsRequest::default())
+        .await
+        .ok()?
+        .metrics;
+    let names = [
+        MetricName::TrafficBytesTx.to_string(),
+        MetricName::TrafficBytesRx.to_string(),
+    ];
+    Some(
+        metrics
+            .into_iter()
+            .filter(|m| names.contains(&m.name))
+            .map(|m| ((m.name, m.labels), m.value))
+            .collect(),
+    )
+}
+
+// idle counters are evicted by the stats manager and restart from zero, so sum the
+// growth of each counter instead of trusting the total. a counter that is new or has
+// shrunk since the last check counts with its whole value.
+fn traffic_delta(last: &HashMap<CounterKey, u64>, counters: &HashMap<CounterKey, u64>) -> u64 {
+    counters
+        .iter()
+        .map(|(key, &value)| match last.get(key) {
+            Some(&prev) if value >= prev => value - prev,
+            _ => value,
+        })
+        .sum()
+}
+
+async fn watch_quota(uuid: Uuid, state: Arc<QuotaState>) {
+    loop {
+        tokio::time::sleep(QUOTA_CHECK_INTERVAL).await;
+        let Some(counters) = traffic_counters(&uuid).await else {
+            hilog_debug!("[Rust] instance {} gone, stop watching data quota", uuid);
+            break;
+        };
+        let delta = {
+            let mut last = state.last_counters.lock().unwrap();
+            let delta = traffic_delta(&last, &counters);
+            *last = counters;
+            delta
+        };
+        let used = state.used_bytes.fetch_add(delta, Ordering::Relaxed) + delta;
+        if used < state.quota_bytes || state.exceeded.swap(true, Ordering::Relaxed) {
+            continue;
+        }
+
+        if state.auto_pause {
+            // stopping drops the instance, keep it off the runtime threads.
+            let inst_id = uuid.to_string();
+            let _ = tokio::task::spawn_blocking(move || stop_network_instance(vec![inst_id])).await;
+        }
+        check_call(
+            "quota",
+            state.func.call(
+                Ok(QuotaEvent {
+                    inst_id: uuid.to_string(),
+                    used_bytes: used as i64,
+                    quota_bytes: state.quota_bytes as i64,
+                    paused: state.auto_pause,
+                }),
+                ThreadsafeFunctionCallMode::NonBlocking,
+            ),
+        );
+        if state.auto_pause {
+            break;
+        }
+    }
+}
+
+// fires `func` once the traffic of the instance since now crosses `bytes`, optionally
+// stopping the instance. setting a new quota replaces the old one, `bytes` <= 0 removes it.
+#[napi]
+pub fn set_data_quota(
+    inst_id: String,
+    bytes: i64,
+    auto_pause: bool,
+    func: ThreadsafeFunction<QuotaEvent, ()>,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if bytes <= 0 {
+        QUOTAS.remove(&uuid);
+        return true;
+    }
+    if instance_api::get_instance_service(&uuid).is_none() {
+        return false;
+    }
+    let Some(counters) = block_on(traffic_counters(&uuid)) else {
+        hilog_error!("[Rust] set_data_quota: get stats of {} failed", uuid);
+        return false;
+    };
+
+    let state = QuotaState {
+        quota_bytes: bytes as u64,
+        auto_pause,
+        used_bytes: AtomicU64::new(0),
+        last_counters: Mutex::new(counters),
+        exceeded: AtomicBool::new(false),
+        func,
+    };
+    QUOTAS.insert(uuid, state, |state| watch_quota(uuid, state));
+    true
+}
+
+// start counting the quota of the instance from zero again, e.g. for a new billing period.
+#[napi]
+pub fn reset_data_quota(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Some(state) = QUOTAS.get(&uuid) else {
+        hilog_error!("[Rust] reset_data_quota: no data quota set for {}", uuid);
+        return false;
+    };
+    state.used_bytes.store(0, Ordering::Relaxed);
+    state.exceeded.store(false, Ordering::Relaxed);
+    true
+}
+
+pub(crate) fn remove_quotas(uuids: &[Uuid]) {
+    QUOTAS.retain(|uuid| !uuids.contains(uuid));
+}
+
+// drop quota watches whose instance is no longer managed, returns how many were dropped.
+pub(crate) fn release_orphaned_quotas() -> u32 {
+    QUOTAS.retain(|uuid| INSTANCE_MANAGER.get_instance_service(uuid).is_some()) as u32
+}
@@ -0,0 +1,163 @@
+use crate::instance_api::{self, block_on, connector_patch, list_connectors};
+use crate::{config_stage, parse_inst_id};
+use easytier::proto::api::config::{ConfigPatchAction, InstanceConfigPatch};
+use easytier::proto::api::instance::{ConnectorStatus, ListRouteRequest};
+use easytier::proto::rpc_types::controller::BaseController;
+use easytier::rpc_service::InstanceRpcService;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// how long the old peers are kept while none of the new ones is connected yet
+const PEER_SWAP_TIMEOUT: Duration = Duration::from_secs(10);
+const PEER_SWAP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// peer id of the relay most relayed routes currently go through, none if every
+// reachable peer is directly connected.
+#[napi]
+pub fn get_active_relay(inst_id: String) -> Option<String> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let service = instance_api::get_instance_service(&uuid)?;
+    let routes = match block_on(
+        service
+            .get_peer_manage_service()
+            .list_route(BaseController::default(), ListRouteRequest::default()),
+    ) {
+        Ok(resp) => resp.routes,
+        Err(e) => {
+            hilog_error!("[Rust] get_active_relay list route failed {}", e);
+            return None;
+        }
+    };
+
+    let mut relay_count: HashMap<u32, usize> = HashMap::new();
+    for route in routes.iter().filter(|r| r.cost > 1) {
+        *relay_count.entry(route.next_hop_peer_id).or_default() += 1;
+    }
+    relay_count
+        .into_iter()
+        .max_by_key(|(peer_id, count)| (*count, *peer_id))
+        .map(|(peer_id, _)| peer_id.to_string())
+}
+
+// pin relayed traffic to go through `peer_id` when a path through it exists,
+// empty string restores automatic selection.
+#[napi]
+pub fn set_preferred_relay(inst_id: String, peer_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let preferred_relay = if peer_id.is_empty() {
+        0
+    } else {
+        match peer_id.parse::<u32>() {
+            Ok(id) if id != 0 => id,
+            _ => {
+                hilog_error!("[Rust] set_preferred_relay: invalid peer id {}", peer_id);
+                return false;
+            }
+        }
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            preferred_relay: Some(preferred_relay),
+            ..Default::default()
+        },
+    )
+}
+
+fn remove_old_peers(
+    uuid: Uuid,
+    service: Arc<dyn InstanceRpcService>,
+    added: Vec<url::Url>,
+    removed: Vec<url::Url>,
+) {
+    let start = Instant::now();
+    while start.elapsed() < PEER_SWAP_TIMEOUT {
+        let connected = block_on(list_connectors(service.as_ref()))
+            .unwrap_or_default()
+            .into_iter()
+            .any(|(url, status)| {
+                status == ConnectorStatus::Connected as i32 && added.contains(&url)
+            });
+        if connected {
+            break;
+        }
+        std::thread::sleep(PEER_SWAP_POLL_INTERVAL);
+    }
+    hilog_debug!("[Rust] replace peers of {}: removing {:?}", uuid, removed);
+    // finishes a replace applied before, not staged even if stage_config was called since
+    instance_api::apply_patch(
+        service.as_ref(),
+        &uuid,
+        connector_patch(ConfigPatchAction::Remove, &removed),
+    );
+}
+
+// makes `peer_urls` the whole set of peers to connect to. the new peers are added right
+// away and the ones no longer listed are removed once a new one connected, or after
+// PEER_SWAP_TIMEOUT, so a relay migration does not leave the instance without peers.
+// returns false without changing anything if any url is invalid or the instance stages
+// its config, see stage_config.
+#[napi]
+pub fn replace_peers(inst_id: String, peer_urls: Vec<String>) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let mut urls: Vec<url::Url> = vec![];
+    for peer_url in peer_urls {
+        match url::Url::parse(&peer_url) {
+            Ok(url) if url.host_str().is_some() => {
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+            _ => {
+                hilog_error!("[Rust] replace_peers: invalid peer url {}", peer_url);
+                return false;
+            }
+        }
+    }
+    if config_stage::is_staging(&uuid) {
+        hilog_error!("[Rust] replace_peers: cannot be staged, commit or discard first");
+        return false;
+    }
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return false;
+    };
+    let Some(current) = block_on(list_connectors(service.as_ref())) else {
+        hilog_error!("[Rust] replace_peers: list connectors of {} failed", uuid);
+        return false;
+    };
+    let current: Vec<url::Url> = current.into_iter().map(|(url, _)| url).collect();
+    let added: Vec<url::Url> = urls
+        .iter()
+        .filter(|x| !current.contains(x))
+        .cloned()
+        .collect();
+    let removed: Vec<url::Url> = current.into_iter().filter(|x| !urls.contains(x)).collect();
+
+    if !added.is_empty()
+        && !instance_api::patch_instance_config(
+            &uuid,
+            connector_patch(ConfigPatchAction::Add, &added),
+        )
+    {
+        return false;
+    }
+    if removed.is_empty() {
+        return true;
+    }
+    if added.is_empty() {
+        return instance_api::patch_instance_config(
+            &uuid,
+            connector_patch(ConfigPatchAction::Remove, &removed),
+        );
+    }
+    std::thread::spawn(move || remove_old_peers(uuid, service, added, removed));
+    true
+}
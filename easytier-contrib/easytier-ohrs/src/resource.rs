@@ -0,0 +1,128 @@
+use crate::instance_api::block_on;
+use crate::metrics::get_instance_stats;
+use crate::parse_inst_id;
+use easytier::common::stats_manager::MetricName;
+use easytier::launcher::instance_thread_name;
+use napi_derive_ohos::napi;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use uuid::Uuid;
+
+// unit of the cpu times in /proc, fixed by the kernel abi
+const USER_HZ: u64 = 100;
+
+#[napi(object)]
+pub struct ResourceUsage {
+    // cpu time of the threads of the instance since it started
+    pub cpu_time_ms: f64,
+    // share of one core used since the previous call, undefined on the first one
+    pub cpu_percent: Option<f64>,
+    pub packets_per_sec: Option<f64>,
+    // times the threads of the instance went to sleep and were woken up again
+    pub wakeups: f64,
+    pub wakeups_per_sec: Option<f64>,
+    pub threads: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    cpu_ticks: u64,
+    packets: u64,
+    wakeups: u64,
+}
+
+// the previous sample of each instance, rates are over the time between two calls
+static LAST_SAMPLES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, Sample>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct ThreadTimes {
+    cpu_ticks: u64,
+    wakeups: u64,
+    threads: u32,
+}
+
+fn read_thread_times(thread_name: &str) -> ThreadTimes {
+    let mut times = ThreadTimes {
+        cpu_ticks: 0,
+        wakeups: 0,
+        threads: 0,
+    };
+    let Ok(tasks) = std::fs::read_dir("/proc/self/task") else {
+        return times;
+    };
+    for task in tasks.flatten() {
+        let path = task.path();
+        let comm = std::fs::read_to_string(path.join("comm")).unwrap_or_default();
+        if comm.trim_end() != thread_name {
+            continue;
+        }
+        // the fields after the name, which is in parens and may contain spaces
+        let stat = std::fs::read_to_string(path.join("stat")).unwrap_or_default();
+        let fields: Vec<&str> = stat
+            .rsplit_once(')')
+            .map(|x| x.1.split_whitespace().collect())
+            .unwrap_or_default();
+        let field = |i: usize| fields.get(i).and_then(|x| x.parse::<u64>().ok());
+        // utime and stime
+        times.cpu_ticks += field(11).unwrap_or(0) + field(12).unwrap_or(0);
+
+        let status = std::fs::read_to_string(path.join("status")).unwrap_or_default();
+        times.wakeups += status
+            .lines()
+            .find_map(|x| x.strip_prefix("voluntary_ctxt_switches:"))
+            .and_then(|x| x.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        times.threads += 1;
+    }
+    times
+}
+
+pub(crate) fn remove_resource_samples(uuids: &[Uuid]) {
+    let mut samples = LAST_SAMPLES.lock().unwrap();
+    samples.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// estimate of the battery cost of the instance: cpu time and wakeups of its threads and
+// the packets it handles. the cpu spent in the tun driver and the radio is not included.
+// undefined if the instance is not running.
+#[napi]
+pub fn get_resource_usage(inst_id: String) -> Option<ResourceUsage> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let stats = block_on(get_instance_stats(&uuid))?;
+    let sum_of = |name: MetricName| {
+        let name = name.to_string();
+        stats
+            .iter()
+            .filter(|m| m.name == name)
+            .map(|m| m.value)
+            .sum::<u64>()
+    };
+    let times = read_thread_times(&instance_thread_name(&uuid));
+    let sample = Sample {
+        at: Instant::now(),
+        cpu_ticks: times.cpu_ticks,
+        packets: sum_of(MetricName::TrafficPacketsTx) + sum_of(MetricName::TrafficPacketsRx),
+        wakeups: times.wakeups,
+    };
+
+    let last = LAST_SAMPLES.lock().unwrap().insert(uuid, sample);
+    let elapsed = last
+        .map(|x| sample.at.duration_since(x.at).as_secs_f64())
+        .filter(|x| *x > 0.0);
+    // counters go back after reset_instance_stats, that interval counts as idle
+    let rate = |f: fn(&Sample) -> u64| {
+        let last = last?;
+        Some(f(&sample).saturating_sub(f(&last)) as f64 / elapsed?)
+    };
+
+    Some(ResourceUsage {
+        cpu_time_ms: (sample.cpu_ticks * 1000 / USER_HZ) as f64,
+        cpu_percent: rate(|x| x.cpu_ticks).map(|x| x * 100.0 / USER_HZ as f64),
+        packets_per_sec: rate(|x| x.packets),
+        wakeups: sample.wakeups as f64,
+        wakeups_per_sec: rate(|x| x.wakeups),
+        threads: times.threads,
+    })
+}
@@ -0,0 +1,1047 @@
+use crate::callback::check_call;
+use crate::instance_api::{self, block_on};
+use crate::{INSTANCE_MANAGER, KeyValuePair, parse_inst_id};
+use easytier::proto::api::config::{
+    ConfigPatchAction, GetConfigRequest, InstanceConfigPatch, RouteFailoverPolicyPatch,
+};
+use easytier::proto::api::instance::{
+    ListPeerRequest, ListRouteRequest, NodeInfo, Route, ShowNodeInfoRequest,
+};
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, watch};
+use uuid::Uuid;
+
+// at most one snapshot per interval while the routes change
+const ROUTE_MIN_INTERVAL: Duration = Duration::from_millis(500);
+// catches the instances started or stopped without a route change
+const ROUTE_FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+// report at least this often while the table keeps changing, e.g. during convergence
+const ROUTE_MAX_COALESCE: Duration = Duration::from_secs(3);
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct RouteInfo {
+    pub peer_id: u32,
+    pub hostname: String,
+    pub ipv4_addr: Option<String>,
+    pub ipv6_addr: Option<String>,
+    pub next_hop_peer_id: u32,
+    pub cost: i32,
+    pub path_latency: i32,
+    pub proxy_cidrs: Vec<String>,
+    // proxy cidrs whose traffic currently goes to this peer
+    pub active_proxy_cidrs: Vec<String>,
+    // set by the peer with set_peer_metadata
+    pub metadata: Vec<KeyValuePair>,
+}
+
+impl From<Route> for RouteInfo {
+    fn from(route: Route) -> Self {
+        Self {
+            peer_id: route.peer_id,
+            hostname: route.hostname,
+            ipv4_addr: route.ipv4_addr.map(|x| x.to_string()),
+            ipv6_addr: route.ipv6_addr.map(|x| x.to_string()),
+            next_hop_peer_id: route.next_hop_peer_id,
+            cost: route.cost,
+            path_latency: route.path_latency,
+            proxy_cidrs: route.proxy_cidrs,
+            active_proxy_cidrs: route.active_proxy_cidrs,
+            metadata: route
+                .metadata
+                .into_iter()
+                .map(|(key, value)| KeyValuePair { key, value })
+                .collect(),
+        }
+    }
+}
+
+impl RouteInfo {
+    // latency jitters all the time, a route only counts as modified when its path changes.
+    fn same_path(&self, other: &Self) -> bool {
+        self.hostname == other.hostname
+            && self.ipv4_addr == other.ipv4_addr
+            && self.ipv6_addr == other.ipv6_addr
+            && self.next_hop_peer_id == other.next_hop_peer_id
+            && self.cost == other.cost
+            && self.proxy_cidrs == other.proxy_cidrs
+            && self.active_proxy_cidrs == other.active_proxy_cidrs
+    }
+}
+
+#[napi(object)]
+pub struct RouteChange {
+    pub inst_id: String,
+    pub added: Vec<RouteInfo>,
+    pub removed: Vec<RouteInfo>,
+    pub modified: Vec<RouteInfo>,
+}
+
+pub(crate) type RouteTable = BTreeMap<u32, RouteInfo>;
+
+pub(crate) fn same_table(a: &RouteTable, b: &RouteTable) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .all(|((ka, va), (kb, vb))| ka == kb && va.same_path(vb))
+}
+
+fn diff_table(inst_id: &Uuid, old: &RouteTable, new: &RouteTable) -> RouteChange {
+    RouteChange {
+        inst_id: inst_id.to_string(),
+        added: new
+            .iter()
+            .filter(|(k, _)| !old.contains_key(k))
+            .map(|(_, v)| v.clone())
+            .collect(),
+        removed: old
+            .iter()
+            .filter(|(k, _)| !new.contains_key(k))
+            .map(|(_, v)| v.clone())
+            .collect(),
+        modified: new
+            .iter()
+            .filter(|(k, v)| old.get(k).is_some_and(|o| !o.same_path(v)))
+            .map(|(_, v)| v.clone())
+            .collect(),
+    }
+}
+
+pub(crate) async fn list_routes(uuid: &Uuid) -> Option<RouteTable> {
+    let service = INSTANCE_MANAGER.get_instance_service(uuid)?;
+    let routes = service
+        .get_peer_manage_service()
+        .list_route(BaseController::default(), ListRouteRequest::default())
+        .await
+        .ok()?
+        .routes;
+    Some(
+        routes
+            .into_iter()
+            .map(|r| (r.peer_id, RouteInfo::from(r)))
+            .collect(),
+    )
+}
+
+#[napi]
+pub fn get_routes(inst_id: String) -> Vec<RouteInfo> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    block_on(list_routes(&uuid))
+        .map(|routes| routes.into_values().collect())
+        .unwrap_or_default()
+}
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeerSortKey {
+    // lowest path latency first
+    Latency,
+    // by hostname, then peer id
+    Name,
+    // most traffic over the direct conns first
+    Bytes,
+}
+
+#[napi(object)]
+pub struct PeerEntry {
+    pub peer_id: u32,
+    pub hostname: String,
+    pub ipv4_addr: Option<String>,
+    pub latency_ms: i32,
+    pub cost: i32,
+    // has a direct conn, otherwise it is reached through relays and the bytes are 0
+    pub direct: bool,
+    pub tx_bytes: i64,
+    pub rx_bytes: i64,
+}
+
+#[napi(object)]
+pub struct PeerPage {
+    // of all peers, not only of this page
+    pub total: u32,
+    pub peers: Vec<PeerEntry>,
+}
+
+// one page of the peers of get_routes, sorted by `sort_by`, for lazily loading the list
+// of a large network. pages can shift while peers come and go. an empty page with total
+// 0 if the instance is not running.
+#[napi]
+pub fn list_peers_paged(
+    inst_id: String,
+    offset: u32,
+    limit: u32,
+    sort_by: PeerSortKey,
+) -> PeerPage {
+    let empty = PeerPage {
+        total: 0,
+        peers: vec![],
+    };
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return empty;
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return empty;
+    };
+    let Some((routes, peers)) = block_on(async {
+        let routes = list_routes(&uuid).await?;
+        let peers = service
+            .get_peer_manage_service()
+            .list_peer(BaseController::default(), ListPeerRequest::default())
+            .await
+            .map(|x| x.peer_infos)
+            .unwrap_or_default();
+        Some((routes, peers))
+    }) else {
+        return empty;
+    };
+
+    let mut traffic: HashMap<u32, (i64, i64)> = HashMap::new();
+    for conn in peers.into_iter().flat_map(|p| p.conns) {
+        let stats = conn.stats.unwrap_or_default();
+        let entry = traffic.entry(conn.peer_id).or_default();
+        entry.0 += stats.tx_bytes as i64;
+        entry.1 += stats.rx_bytes as i64;
+    }
+    let mut entries: Vec<PeerEntry> = routes
+        .into_values()
+        .map(|route| {
+            let bytes = traffic.get(&route.peer_id).copied();
+            let (tx_bytes, rx_bytes) = bytes.unwrap_or_default();
+            PeerEntry {
+                peer_id: route.peer_id,
+                hostname: route.hostname,
+                ipv4_addr: route.ipv4_addr,
+                latency_ms: route.path_latency,
+                cost: route.cost,
+                direct: bytes.is_some(),
+                tx_bytes,
+                rx_bytes,
+            }
+        })
+        .collect();
+    // routes come by peer id, which breaks the ties
+    match sort_by {
+        PeerSortKey::Latency => entries.sort_by_key(|x| x.latency_ms),
+        PeerSortKey::Name => entries.sort_by(|a, b| a.hostname.cmp(&b.hostname)),
+        PeerSortKey::Bytes => entries.sort_by_key(|x| std::cmp::Reverse(x.tx_bytes + x.rx_bytes)),
+    }
+    PeerPage {
+        total: entries.len() as u32,
+        peers: entries
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+    }
+}
+
+// the cidrs other peers route to this instance: its virtual ips as host routes and
+// the proxy networks it announces, mapped cidrs instead of the real ones.
+#[napi]
+pub fn get_advertised_routes(inst_id: String) -> Vec<String> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()
+    .and_then(|x| x.node_info)
+    .map(|x| x.advertised_cidrs)
+    .unwrap_or_default()
+}
+
+#[napi(object)]
+pub struct DefaultRouteStatus {
+    // exit nodes are configured, none of them may have a route though
+    pub exit_nodes_configured: bool,
+    pub via_exit_node: bool,
+    pub exit_peer_id: Option<u32>,
+    pub exit_hostname: Option<String>,
+    pub latency_ms: Option<i32>,
+}
+
+const DEFAULT_ROUTE: &str = "0.0.0.0/0";
+
+fn has_addr(route: &RouteInfo, addr: &IpAddr) -> bool {
+    let parse = |x: &Option<String>| x.as_deref().and_then(|x| x.parse::<cidr::IpInet>().ok());
+    [&route.ipv4_addr, &route.ipv6_addr]
+        .into_iter()
+        .filter_map(parse)
+        .any(|x| x.address() == *addr)
+}
+
+// picks the peer like the data path does: a peer announcing 0.0.0.0/0 as proxy network,
+// otherwise the first configured exit node that has a route.
+#[napi]
+pub fn get_default_route_status(inst_id: String) -> Option<DefaultRouteStatus> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let service = instance_api::get_instance_service(&uuid)?;
+    let (routes, exit_nodes) = block_on(async {
+        let routes = list_routes(&uuid).await?;
+        let config = service
+            .get_config_service()
+            .get_config(BaseController::default(), GetConfigRequest::default())
+            .await
+            .ok()?
+            .config?;
+        Some((routes, config.exit_nodes))
+    })?;
+    let exit_nodes: Vec<IpAddr> = exit_nodes.iter().filter_map(|x| x.parse().ok()).collect();
+
+    let exit = routes
+        .values()
+        .find(|r| r.proxy_cidrs.iter().any(|x| x == DEFAULT_ROUTE))
+        .or_else(|| {
+            exit_nodes
+                .iter()
+                .find_map(|addr| routes.values().find(|r| has_addr(r, addr)))
+        });
+    Some(DefaultRouteStatus {
+        exit_nodes_configured: !exit_nodes.is_empty(),
+        via_exit_node: exit.is_some(),
+        exit_peer_id: exit.map(|x| x.peer_id),
+        exit_hostname: exit.map(|x| x.hostname.clone()),
+        latency_ms: exit.map(|x| x.path_latency),
+    })
+}
+
+#[napi(object)]
+pub struct RouteFailoverPolicy {
+    // 0 means no limit
+    pub max_latency_ms: u32,
+    pub max_loss_percent: u32,
+}
+
+// when several peers advertise `cidr`, prefer the one whose path stays within the
+// thresholds and switch away once it exceeds them. both limits 0 removes the policy.
+#[napi]
+pub fn set_route_failover_policy(
+    inst_id: String,
+    cidr: String,
+    policy: RouteFailoverPolicy,
+) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if policy.max_loss_percent > 100 {
+        hilog_error!(
+            "[Rust] set_route_failover_policy: invalid loss percent {}",
+            policy.max_loss_percent
+        );
+        return false;
+    }
+    let action = if policy.max_latency_ms == 0 && policy.max_loss_percent == 0 {
+        ConfigPatchAction::Remove
+    } else {
+        ConfigPatchAction::Add
+    };
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            route_failover_policies: vec![RouteFailoverPolicyPatch {
+                action: action as i32,
+                cidr,
+                max_latency_ms: policy.max_latency_ms,
+                max_loss_percent: policy.max_loss_percent,
+            }],
+            ..Default::default()
+        },
+    )
+}
+
+// the route tables of the running instances, listed once for all the route watches
+#[derive(Default)]
+pub(crate) struct RouteSnapshot {
+    pub(crate) instances: Vec<Uuid>,
+    // missing for the instances whose routes could not be listed
+    pub(crate) tables: HashMap<Uuid, RouteTable>,
+}
+
+static ROUTE_POLLER: Mutex<Option<watch::Sender<Arc<RouteSnapshot>>>> = Mutex::new(None);
+
+// wakes the route poll on every route change of the instance and once it stopped
+fn forward_route_changes(
+    mut changes: watch::Receiver<()>,
+    notify: Arc<Notify>,
+) -> tokio::task::JoinHandle<()> {
+    instance_api::spawn(async move {
+        while changes.changed().await.is_ok() {
+            notify.notify_one();
+        }
+        notify.notify_one();
+    })
+}
+
+async fn poll_routes(tx: watch::Sender<Arc<RouteSnapshot>>) {
+    let notify = Arc::new(Notify::new());
+    let mut forwards: HashMap<Uuid, tokio::task::JoinHandle<()>> = HashMap::new();
+    // a change is followed by one more snapshot, so the watches see the table settle
+    let mut follow_up = false;
+    // stops once no watch is subscribed any more
+    while !tx.is_closed() {
+        tokio::time::sleep(ROUTE_MIN_INTERVAL).await;
+        let wait = if follow_up {
+            Duration::ZERO
+        } else {
+            ROUTE_FALLBACK_POLL_INTERVAL - ROUTE_MIN_INTERVAL
+        };
+        follow_up = tokio::time::timeout(wait, notify.notified()).await.is_ok();
+
+        let instances = INSTANCE_MANAGER.list_network_instance_ids();
+        forwards.retain(|id, forward| {
+            let keep = instances.contains(id) && !forward.is_finished();
+            if !keep {
+                forward.abort();
+            }
+            keep
+        });
+        let mut tables = HashMap::new();
+        for id in instances.iter() {
+            if !forwards.contains_key(id) {
+                if let Some(changes) = INSTANCE_MANAGER.subscribe_route_changes(id) {
+                    forwards.insert(*id, forward_route_changes(changes, notify.clone()));
+                }
+            }
+            if let Some(routes) = list_routes(id).await {
+                tables.insert(*id, routes);
+            }
+        }
+        tx.send_replace(Arc::new(RouteSnapshot { instances, tables }));
+    }
+    forwards.values().for_each(|forward| forward.abort());
+}
+
+// a new snapshot is sent once the routes of an instance changed, at most every 500ms,
+// and every 2s otherwise. the snapshots start with the first subscriber
+pub(crate) fn subscribe_route_snapshots() -> watch::Receiver<Arc<RouteSnapshot>> {
+    let mut poller = ROUTE_POLLER.lock().unwrap();
+    if let Some(tx) = poller.as_ref().filter(|x| !x.is_closed()) {
+        return tx.subscribe();
+    }
+    let (tx, rx) = watch::channel(Arc::new(RouteSnapshot::default()));
+    instance_api::spawn(poll_routes(tx.clone()));
+    *poller = Some(tx);
+    rx
+}
+
+#[derive(Default)]
+struct InstanceRoutes {
+    reported: RouteTable,
+    polled: RouteTable,
+    pending_since: Option<Instant>,
+}
+
+async fn watch_routes(func: ThreadsafeFunction<RouteChange, ()>) {
+    let emit = |change: RouteChange| {
+        check_call(
+            "route change",
+            func.call(Ok(change), ThreadsafeFunctionCallMode::NonBlocking),
+        );
+    };
+    let mut instances: HashMap<Uuid, InstanceRoutes> = HashMap::new();
+    let mut snapshots = subscribe_route_snapshots();
+    while snapshots.changed().await.is_ok() {
+        let snapshot = snapshots.borrow_and_update().clone();
+        instances.retain(|id, state| {
+            let alive = snapshot.instances.contains(id);
+            if !alive && !state.reported.is_empty() {
+                emit(diff_table(id, &state.reported, &RouteTable::new()));
+            }
+            alive
+        });
+
+        for (&id, routes) in snapshot.tables.iter() {
+            let state = instances.entry(id).or_default();
+            let settled = same_table(routes, &state.polled);
+            state.polled = routes.clone();
+            if same_table(&state.polled, &state.reported) {
+                state.pending_since = None;
+                continue;
+            }
+
+            // wait for the table to stop changing so a burst is reported once.
+            let pending_since = *state.pending_since.get_or_insert_with(Instant::now);
+            if !settled && pending_since.elapsed() < ROUTE_MAX_COALESCE {
+                continue;
+            }
+            emit(diff_table(&id, &state.reported, &state.polled));
+            state.reported = state.polled.clone();
+            state.pending_since = None;
+        }
+    }
+}
+
+static ROUTE_WATCH: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+
+// the first callback after registering carries every current route as added.
+#[napi]
+pub fn register_route_change_callback(func: ThreadsafeFunction<RouteChange, ()>) {
+    let task = instance_api::spawn(watch_routes(func));
+    if let Some(old) = ROUTE_WATCH.lock().unwrap().replace(task) {
+        old.abort();
+    }
+}
+
+#[napi]
+pub fn unregister_route_change_callback() {
+    if let Some(old) = ROUTE_WATCH.lock().unwrap().take() {
+        old.abort();
+    }
+}
+
+// used when no settle window is given
+const DEFAULT_SETTLE_WINDOW: Duration = Duration::from_secs(5);
+
+#[napi(object)]
+pub struct ConvergedEvent {
+    pub inst_id: String,
+    pub route_count: u32,
+}
+
+struct InstanceConvergence {
+    polled: RouteTable,
+    changed_at: Instant,
+    converged: bool,
+}
+
+async fn watch_convergence(func: ThreadsafeFunction<ConvergedEvent, ()>, settle: Duration) {
+    let mut instances: HashMap<Uuid, InstanceConvergence> = HashMap::new();
+    let mut snapshots = subscribe_route_snapshots();
+    while snapshots.changed().await.is_ok() {
+        let snapshot = snapshots.borrow_and_update().clone();
+        instances.retain(|id, _| snapshot.instances.contains(id));
+
+        for (&id, routes) in snapshot.tables.iter() {
+            let state = instances.entry(id).or_insert_with(|| InstanceConvergence {
+                polled: RouteTable::new(),
+                changed_at: Instant::now(),
+                converged: false,
+            });
+            if !same_table(routes, &state.polled) {
+                state.polled = routes.clone();
+                state.changed_at = Instant::now();
+                state.converged = false;
+                continue;
+            }
+            if state.converged || state.polled.is_empty() || state.changed_at.elapsed() < settle {
+                continue;
+            }
+            state.converged = true;
+            check_call(
+                "converged",
+                func.call(
+                    Ok(ConvergedEvent {
+                        inst_id: id.to_string(),
+                        route_count: state.polled.len() as u32,
+                    }),
+                    ThreadsafeFunctionCallMode::NonBlocking,
+                ),
+            );
+        }
+    }
+}
+
+static CONVERGENCE_WATCH: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+
+// fired once an instance has routes and its route table did not change for `settle_ms`
+// (0 for the default of 5s), and again each time it settles after later changes. an
+// instance that is already stable when registering is reported after one window.
+#[napi]
+pub fn register_convergence_callback(func: ThreadsafeFunction<ConvergedEvent, ()>, settle_ms: u32) {
+    let settle = match settle_ms {
+        0 => DEFAULT_SETTLE_WINDOW,
+        ms => Duration::from_millis(ms as u64),
+    };
+    let task = instance_api::spawn(watch_convergence(func, settle));
+    if let Some(old) = CONVERGENCE_WATCH.lock().unwrap().replace(task) {
+        old.abort();
+    }
+}
+
+#[napi]
+pub fn unregister_convergence_callback() {
+    if let Some(old) = CONVERGENCE_WATCH.lock().unwrap().take() {
+        old.abort();
+    }
+}
+
+#[napi(string_enum)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathKind {
+    Direct,
+    Relay,
+}
+
+impl PathKind {
+    fn of(route: &RouteInfo) -> Self {
+        if route.cost <= 1 {
+            PathKind::Direct
+        } else {
+            PathKind::Relay
+        }
+    }
+}
+
+#[napi(object)]
+pub struct PathChange {
+    pub inst_id: String,
+    pub peer_id: u32,
+    pub from: PathKind,
+    pub to: PathKind,
+    // next hop of the new path when it is relayed
+    pub relay_peer_id: Option<u32>,
+}
+
+async fn watch_path_changes(func: ThreadsafeFunction<PathChange, ()>) {
+    let mut instances: HashMap<Uuid, HashMap<u32, PathKind>> = HashMap::new();
+    let mut snapshots = subscribe_route_snapshots();
+    while snapshots.changed().await.is_ok() {
+        let snapshot = snapshots.borrow_and_update().clone();
+        instances.retain(|id, _| snapshot.instances.contains(id));
+
+        for (&id, routes) in snapshot.tables.iter() {
+            let paths: HashMap<u32, PathKind> = routes
+                .values()
+                .map(|r| (r.peer_id, PathKind::of(r)))
+                .collect();
+            let Some(old) = instances.insert(id, paths.clone()) else {
+                // the first poll only learns the current paths
+                continue;
+            };
+            for (peer_id, to) in paths {
+                let Some(from) = old.get(&peer_id).copied().filter(|x| *x != to) else {
+                    continue;
+                };
+                hilog_debug!("[Rust] path to {} changed {:?} -> {:?}", peer_id, from, to);
+                check_call(
+                    "path change",
+                    func.call(
+                        Ok(PathChange {
+                            inst_id: id.to_string(),
+                            peer_id,
+                            from,
+                            to,
+                            relay_peer_id: (to == PathKind::Relay)
+                                .then(|| routes[&peer_id].next_hop_peer_id),
+                        }),
+                        ThreadsafeFunctionCallMode::NonBlocking,
+                    ),
+                );
+            }
+        }
+    }
+}
+
+static PATH_CHANGE_WATCH: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+
+// fired when the path to a peer goes from relayed to direct, e.g. once hole punching
+// succeeds, or back. peers appearing or disappearing are reported by the route change
+// callback instead.
+#[napi]
+pub fn register_path_change_callback(func: ThreadsafeFunction<PathChange, ()>) {
+    let task = instance_api::spawn(watch_path_changes(func));
+    if let Some(old) = PATH_CHANGE_WATCH.lock().unwrap().replace(task) {
+        old.abort();
+    }
+}
+
+#[napi]
+pub fn unregister_path_change_callback() {
+    if let Some(old) = PATH_CHANGE_WATCH.lock().unwrap().take() {
+        old.abort();
+    }
+}
+
+struct TopologyEdge {
+    from: u32,
+    to: u32,
+    latency_ms: Option<i32>,
+    // the peers between the two ends, unknown here
+    hidden_hops: i32,
+}
+
+// edges from this node to the direct peers, and from the next hop to each relayed peer.
+// only the next hop of a relayed path is known, so longer paths skip the hops between.
+fn topology_edges(my_peer_id: u32, routes: &RouteTable) -> Vec<TopologyEdge> {
+    routes
+        .values()
+        .map(|r| {
+            if r.cost <= 1 {
+                return TopologyEdge {
+                    from: my_peer_id,
+                    to: r.peer_id,
+                    latency_ms: Some(r.path_latency),
+                    hidden_hops: 0,
+                };
+            }
+            let hop_latency = routes.get(&r.next_hop_peer_id).map(|x| x.path_latency);
+            TopologyEdge {
+                from: r.next_hop_peer_id,
+                to: r.peer_id,
+                latency_ms: hop_latency.map(|x| (r.path_latency - x).max(0)),
+                hidden_hops: r.cost - 2,
+            }
+        })
+        .collect()
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// the mesh as seen by the instance, as graphviz dot or a json graph
+// (https://jsongraphformat.info). nodes are the peers, edges are weighted by latency in
+// ms. returns "ERROR ..." when the format is unknown or the instance is not running.
+#[napi]
+pub fn export_topology(inst_id: String, format: String) -> String {
+    if format != "dot" && format != "json" {
+        return format!("ERROR unknown format {}, use dot or json", format);
+    }
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return format!("ERROR invalid instance id {}", inst_id);
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return format!("ERROR instance {} not found", uuid);
+    };
+    let Some(node) = block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()
+    .and_then(|x| x.node_info) else {
+        return format!("ERROR instance {} is not running", uuid);
+    };
+    let routes = block_on(list_routes(&uuid)).unwrap_or_default();
+    let edges = topology_edges(node.peer_id, &routes);
+
+    if format == "dot" {
+        let mut dot = String::from("graph easytier {\n");
+        dot += &format!(
+            "  {} [label=\"{}\", shape=box];\n",
+            node.peer_id,
+            escape_dot(&node.hostname)
+        );
+        for r in routes.values() {
+            dot += &format!("  {} [label=\"{}\"];\n", r.peer_id, escape_dot(&r.hostname));
+        }
+        for e in edges {
+            let mut attrs = vec![];
+            if let Some(latency) = e.latency_ms {
+                attrs.push(format!(
+                    "label=\"{}ms\", weight={}",
+                    latency,
+                    latency.max(1)
+                ));
+            }
+            if e.hidden_hops > 0 {
+                attrs.push("style=dashed".to_string());
+            }
+            dot += &format!("  {} -- {} [{}];\n", e.from, e.to, attrs.join(", "));
+        }
+        dot += "}\n";
+        return dot;
+    }
+
+    let mut nodes = serde_json::Map::new();
+    nodes.insert(
+        node.peer_id.to_string(),
+        serde_json::json!({
+            "label": node.hostname,
+            "metadata": {"self": true, "ipv4": node.ipv4_addr},
+        }),
+    );
+    for r in routes.values() {
+        nodes.insert(
+            r.peer_id.to_string(),
+            serde_json::json!({
+                "label": r.hostname,
+                "metadata": {"self": false, "ipv4": r.ipv4_addr, "cost": r.cost},
+            }),
+        );
+    }
+    let edges: Vec<_> = edges
+        .into_iter()
+        .map(|e| {
+            serde_json::json!({
+                "source": e.from.to_string(),
+                "target": e.to.to_string(),
+                "metadata": {"latency_ms": e.latency_ms, "hidden_hops": e.hidden_hops},
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "graph": {"directed": false, "nodes": nodes, "edges": edges},
+    })
+    .to_string()
+}
+
+pub(crate) fn cidrs_overlap(a: &cidr::IpCidr, b: &cidr::IpCidr) -> bool {
+    a.contains(&b.first_address()) || b.contains(&a.first_address())
+}
+
+#[napi(string_enum)]
+pub enum OverlapKind {
+    // several peers advertise the same cidr, one of them gets all of its traffic
+    Duplicate,
+    // one cidr contains the other, the more specific one wins for the addresses in it
+    Nested,
+}
+
+#[napi(object)]
+pub struct OverlapWarning {
+    pub kind: OverlapKind,
+    pub cidr: String,
+    pub peer_id: u32,
+    pub other_cidr: String,
+    pub other_peer_id: u32,
+    // the peer the traffic to the overlapping range goes to, undefined if neither is
+    // used, e.g. while the routes converge
+    pub winner_peer_id: Option<u32>,
+}
+
+struct ProxyAdvert {
+    peer_id: u32,
+    cidr: cidr::IpCidr,
+    // this peer gets the traffic to the cidr
+    active: bool,
+}
+
+async fn node_info(uuid: &Uuid) -> Option<NodeInfo> {
+    instance_api::get_instance_service(uuid)?
+        .get_peer_manage_service()
+        .show_node_info(BaseController::default(), ShowNodeInfoRequest::default())
+        .await
+        .ok()?
+        .node_info
+}
+
+fn proxy_adverts(node: &NodeInfo, routes: &RouteTable) -> Vec<ProxyAdvert> {
+    // own proxy cidrs are "real->mapped" when mapped, the mapped one is announced. they
+    // always win over the same cidr of a peer.
+    let own = node
+        .proxy_cidrs
+        .iter()
+        .map(|x| x.rsplit("->").next().unwrap_or(x))
+        .map(|x| (node.peer_id, x, true));
+    let peers = routes.values().flat_map(|r| {
+        r.proxy_cidrs
+            .iter()
+            .map(|x| (r.peer_id, x.as_str(), r.active_proxy_cidrs.contains(x)))
+    });
+    own.chain(peers)
+        .filter_map(|(peer_id, cidr, active)| {
+            Some(ProxyAdvert {
+                peer_id,
+                cidr: cidr.parse().ok()?,
+                active,
+            })
+        })
+        .collect()
+}
+
+// the proxy networks announced in the network that overlap, including the ones of this
+// instance. set_route_failover_policy decides between the peers of a duplicate cidr.
+#[napi]
+pub fn resolve_proxy_overlaps(inst_id: String) -> Vec<OverlapWarning> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some((node, routes)) = block_on(async {
+        let node = node_info(&uuid).await?;
+        Some((node, list_routes(&uuid).await.unwrap_or_default()))
+    }) else {
+        return vec![];
+    };
+    let adverts = proxy_adverts(&node, &routes);
+
+    let mut warnings = vec![];
+    for (i, a) in adverts.iter().enumerate() {
+        for b in adverts[i + 1..].iter() {
+            if a.peer_id == b.peer_id || !cidrs_overlap(&a.cidr, &b.cidr) {
+                continue;
+            }
+            // the more specific cidr wins, whichever peer got it when it is announced twice
+            let narrow = if a.cidr.network_length() >= b.cidr.network_length() {
+                a.cidr
+            } else {
+                b.cidr
+            };
+            let kind = if a.cidr == b.cidr {
+                OverlapKind::Duplicate
+            } else {
+                OverlapKind::Nested
+            };
+            let winner = adverts
+                .iter()
+                .find(|x| x.cidr == narrow && x.active)
+                .map(|x| x.peer_id);
+            warnings.push(OverlapWarning {
+                kind,
+                cidr: a.cidr.to_string(),
+                peer_id: a.peer_id,
+                other_cidr: b.cidr.to_string(),
+                other_peer_id: b.peer_id,
+                winner_peer_id: winner,
+            });
+        }
+    }
+    warnings
+}
+
+#[napi(string_enum)]
+pub enum LoopKind {
+    // following the next hops from a peer comes back to a peer passed before
+    NextHopCycle,
+    // a proxy cidr lies in the virtual network, packets to those virtual ips may be sent
+    // into the subnet and come back through it
+    VirtualNetworkProxied,
+    // a proxy cidr contains the address a conn to a peer goes to, the conn would be sent
+    // through the network it carries
+    UnderlayRouted,
+}
+
+#[napi(object)]
+pub struct LoopWarning {
+    pub kind: LoopKind,
+    // the cycle in order, or the peer announcing the cidr and for UnderlayRouted the peer
+    // of the conn
+    pub peer_ids: Vec<u32>,
+    pub cidr: Option<String>,
+    pub detail: String,
+}
+
+// the peers from `start` on until a next hop is one of them again, none if the path ends
+// at a direct peer or at a peer without route.
+fn next_hop_cycle(my_peer_id: u32, routes: &RouteTable, start: u32) -> Option<Vec<u32>> {
+    let mut path = vec![start];
+    let mut cur = start;
+    loop {
+        let next = routes.get(&cur)?.next_hop_peer_id;
+        if next == cur || next == my_peer_id {
+            return None;
+        }
+        if let Some(pos) = path.iter().position(|x| *x == next) {
+            let mut cycle = path.split_off(pos);
+            // the same cycle is found from each of its peers
+            let min = cycle.iter().enumerate().min_by_key(|x| x.1).map(|x| x.0)?;
+            cycle.rotate_left(min);
+            return Some(cycle);
+        }
+        path.push(next);
+        cur = next;
+    }
+}
+
+fn url_ip(url: &url::Url) -> Option<IpAddr> {
+    match url.host()? {
+        url::Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        url::Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        url::Host::Domain(_) => None,
+    }
+}
+
+// routes of the instance that send packets in circles, from the route table and the
+// announced proxy networks. empty if there are none or the instance is not running.
+#[napi]
+pub fn detect_routing_loops(inst_id: String) -> Vec<LoopWarning> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    let Some((node, routes, peers)) = block_on(async {
+        let node = node_info(&uuid).await?;
+        let routes = list_routes(&uuid).await.unwrap_or_default();
+        let peers = service
+            .get_peer_manage_service()
+            .list_peer(BaseController::default(), ListPeerRequest::default())
+            .await
+            .map(|x| x.peer_infos)
+            .unwrap_or_default();
+        Some((node, routes, peers))
+    }) else {
+        return vec![];
+    };
+
+    let mut warnings = vec![];
+    let mut cycles = HashSet::new();
+    for peer_id in routes.keys() {
+        let Some(cycle) = next_hop_cycle(node.peer_id, &routes, *peer_id) else {
+            continue;
+        };
+        if cycles.insert(cycle.clone()) {
+            warnings.push(LoopWarning {
+                kind: LoopKind::NextHopCycle,
+                detail: format!("next hops {:?} point back to {}", cycle, cycle[0]),
+                peer_ids: cycle,
+                cidr: None,
+            });
+        }
+    }
+
+    let adverts = proxy_adverts(&node, &routes);
+    let virtual_net = node
+        .ipv4_addr
+        .parse::<cidr::IpInet>()
+        .ok()
+        .map(|x| x.network());
+    for advert in adverts.iter() {
+        // broader cidrs are fine, the virtual network is the more specific route
+        if let Some(net) = virtual_net.filter(|x| x.contains(&advert.cidr.first_address())) {
+            warnings.push(LoopWarning {
+                kind: LoopKind::VirtualNetworkProxied,
+                peer_ids: vec![advert.peer_id],
+                cidr: Some(advert.cidr.to_string()),
+                detail: format!(
+                    "proxy cidr {} is in the virtual network {}",
+                    advert.cidr, net
+                ),
+            });
+        }
+    }
+
+    for peer in peers.iter() {
+        let remotes = peer
+            .conns
+            .iter()
+            .filter(|c| !c.is_closed)
+            .filter_map(|c| c.tunnel.as_ref()?.remote_addr.clone())
+            .filter_map(|x| url_ip(&url::Url::from(x)))
+            .collect::<HashSet<_>>();
+        for ip in remotes {
+            // the default route of exit nodes keeps the conns to the peers out of it
+            let Some(advert) = adverts.iter().find(|x| {
+                x.peer_id != node.peer_id && x.cidr.network_length() > 0 && x.cidr.contains(&ip)
+            }) else {
+                continue;
+            };
+            warnings.push(LoopWarning {
+                kind: LoopKind::UnderlayRouted,
+                peer_ids: vec![advert.peer_id, peer.peer_id],
+                cidr: Some(advert.cidr.to_string()),
+                detail: format!(
+                    "conn to peer {} at {} goes into proxy cidr {}",
+                    peer.peer_id, ip, advert.cidr
+                ),
+            });
+        }
+    }
+    warnings
+}
@@ -0,0 +1,47 @@
+use crate::instance_api::block_on;
+use crate::{INSTANCE_MANAGER, parse_inst_id};
+use easytier::proto::api::instance::ShowNodeInfoRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+
+// ciphers that hide the traffic but give no real protection
+const WEAK_CIPHERS: &[&str] = &["xor"];
+
+#[napi(object)]
+pub struct SecurityStatus {
+    pub encryption_enabled: bool,
+    // undefined when encryption is disabled
+    pub cipher: Option<String>,
+    pub weak_cipher: bool,
+    // false when encryption is disabled or any peer sent plaintext data recently
+    pub all_links_encrypted: bool,
+    // peers that sent unencrypted data in the last minute
+    pub plaintext_peer_ids: Vec<u32>,
+}
+
+// whether the traffic of the instance with its peers is encrypted, for a lock icon.
+// undefined if the instance is not running.
+#[napi]
+pub fn get_security_status(inst_id: String) -> Option<SecurityStatus> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let service = INSTANCE_MANAGER.get_instance_service(&uuid)?;
+    let node_info = block_on(
+        service
+            .get_peer_manage_service()
+            .show_node_info(BaseController::default(), ShowNodeInfoRequest::default()),
+    )
+    .ok()?
+    .node_info?;
+
+    let enabled = node_info.encryption_enabled;
+    let cipher = Some(node_info.encryption_algorithm).filter(|_| enabled);
+    Some(SecurityStatus {
+        encryption_enabled: enabled,
+        weak_cipher: cipher
+            .as_ref()
+            .is_some_and(|x| WEAK_CIPHERS.contains(&x.as_str())),
+        cipher,
+        all_links_encrypted: enabled && node_info.plaintext_peer_ids.is_empty(),
+        plaintext_peer_ids: node_info.plaintext_peer_ids,
+    })
+}
@@ -0,0 +1,331 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use easytier::common::global_ctx::TransportPreference;
+use easytier::common::network::get_path_mtu as get_path_mtu_to;
+use easytier::proto::api::config::{InstanceConfigPatch, PeerTransportPreferencePatch};
+use easytier::proto::api::instance::{
+    ClosePeerConnRequest, ListPeerRequest, ListRouteRequest, PeerInfo,
+};
+use easytier::proto::rpc_types::controller::BaseController;
+use easytier::rpc_service::InstanceRpcService;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::net::{IpAddr, SocketAddr};
+
+#[napi(object)]
+pub struct SessionInfo {
+    // none for the relayed path
+    pub conn_id: Option<String>,
+    // tunnel type of a direct conn, e.g. "tcp", or "relay"
+    pub protocol: String,
+    pub local_addr: Option<String>,
+    pub remote_addr: Option<String>,
+    // unix timestamp in seconds, none for the relayed path
+    pub established_at: Option<i64>,
+    // only counted on direct conns, 0 for the relayed path
+    pub tx_bytes: i64,
+    pub rx_bytes: i64,
+    pub latency_ms: f64,
+    // whether data to the peer currently goes through this session
+    pub active: bool,
+    pub relay_peer_id: Option<u32>,
+    // negotiated in the handshake of a direct conn, none for the relayed path
+    pub protocol_version: Option<u32>,
+}
+
+// the direct conns to the peer and, when it is reached through other peers, the relayed
+// path. direct conns are preferred as long as one is up, the default conn carries data.
+#[napi]
+pub fn get_peer_sessions(inst_id: String, peer_id: String) -> Vec<SessionInfo> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!("[Rust] get_peer_sessions: invalid peer id {}", peer_id);
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    let peer = list_peer_infos(service.as_ref())
+        .and_then(|peers| peers.into_iter().find(|p| p.peer_id == peer_id));
+    let route = block_on(
+        service
+            .get_peer_manage_service()
+            .list_route(BaseController::default(), ListRouteRequest::default()),
+    )
+    .ok()
+    .and_then(|x| x.routes.into_iter().find(|r| r.peer_id == peer_id));
+
+    let mut sessions = vec![];
+    let mut has_direct = false;
+    if let Some(peer) = peer {
+        let default_conn_id = peer.default_conn_id.map(|x| x.to_string());
+        for conn in peer.conns.into_iter().filter(|c| !c.is_closed) {
+            has_direct = true;
+            let tunnel = conn.tunnel.unwrap_or_default();
+            let stats = conn.stats.unwrap_or_default();
+            sessions.push(SessionInfo {
+                active: default_conn_id.as_ref() == Some(&conn.conn_id),
+                conn_id: Some(conn.conn_id),
+                protocol: tunnel.tunnel_type,
+                local_addr: tunnel.local_addr.map(|x| x.to_string()),
+                remote_addr: tunnel.remote_addr.map(|x| x.to_string()),
+                established_at: Some(conn.established_at),
+                tx_bytes: stats.tx_bytes as i64,
+                rx_bytes: stats.rx_bytes as i64,
+                latency_ms: stats.latency_us as f64 / 1000.0,
+                relay_peer_id: None,
+                protocol_version: Some(conn.protocol_version),
+            });
+        }
+    }
+    if let Some(route) = route.filter(|r| r.next_hop_peer_id != peer_id) {
+        sessions.push(SessionInfo {
+            conn_id: None,
+            protocol: "relay".to_string(),
+            local_addr: None,
+            remote_addr: None,
+            established_at: None,
+            tx_bytes: 0,
+            rx_bytes: 0,
+            latency_ms: route.path_latency as f64,
+            active: !has_direct,
+            relay_peer_id: Some(route.next_hop_peer_id),
+            protocol_version: None,
+        });
+    }
+    sessions
+}
+
+// version of the peer protocol spoken with the peer, the lower one of the handshakes of
+// both ends, so an old peer shows up with its old version. the lowest one if the conns
+// differ, none when the peer is not directly connected.
+#[napi]
+pub fn get_peer_protocol_version(inst_id: String, peer_id: String) -> Option<u32> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!(
+            "[Rust] get_peer_protocol_version: invalid peer id {}",
+            peer_id
+        );
+        return None;
+    };
+    let service = instance_api::get_instance_service(&uuid)?;
+    list_peer_infos(service.as_ref())?
+        .into_iter()
+        .find(|p| p.peer_id == peer_id)?
+        .conns
+        .into_iter()
+        .filter(|c| !c.is_closed)
+        .map(|c| c.protocol_version)
+        .min()
+}
+
+// mtu of the network path to the peer as the system knows it from path mtu discovery,
+// the smallest one over the direct ipv4 conns. see get_effective_overlay_mtu for the
+// largest packet to the whole overlay, that plus the tunnel overhead has to fit into it.
+// none when the peer is only reached through relays or the path is unknown.
+#[napi]
+pub fn get_path_mtu(inst_id: String, peer_id: String) -> Option<u32> {
+    let uuid = parse_inst_id(&inst_id)?;
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!("[Rust] get_path_mtu: invalid peer id {}", peer_id);
+        return None;
+    };
+    let service = instance_api::get_instance_service(&uuid)?;
+    list_peer_infos(service.as_ref())?
+        .into_iter()
+        .find(|p| p.peer_id == peer_id)?
+        .conns
+        .into_iter()
+        .filter(|c| !c.is_closed)
+        .filter_map(|c| {
+            let url: url::Url = c.tunnel?.remote_addr?.url.parse().ok()?;
+            let ip = match url.host()? {
+                url::Host::Ipv4(ip) => IpAddr::V4(ip),
+                url::Host::Ipv6(ip) => IpAddr::V6(ip),
+                url::Host::Domain(_) => return None,
+            };
+            get_path_mtu_to(&SocketAddr::new(ip, url.port()?))
+        })
+        .min()
+}
+
+// "ip:port" of a tunnel url, brackets around ipv6
+fn url_endpoint(url: &url::Url) -> Option<String> {
+    Some(format!("{}:{}", url.host()?, url.port()?))
+}
+
+// the local ip:port each open direct conn to the peer uses, for outbound firewall rules.
+// conns the peer made to a listener report the listen address, 0.0.0.0 when it listens
+// on all interfaces. empty when the peer is only reached through relays.
+#[napi]
+pub fn get_peer_local_endpoints(inst_id: String, peer_id: String) -> Vec<String> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!(
+            "[Rust] get_peer_local_endpoints: invalid peer id {}",
+            peer_id
+        );
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    let Some(peer) = list_peer_infos(service.as_ref())
+        .and_then(|peers| peers.into_iter().find(|p| p.peer_id == peer_id))
+    else {
+        return vec![];
+    };
+    let mut endpoints: Vec<String> = peer
+        .conns
+        .into_iter()
+        .filter(|c| !c.is_closed)
+        .filter_map(|c| c.tunnel?.local_addr)
+        .filter_map(|x| url_endpoint(&url::Url::from(x)))
+        .collect();
+    endpoints.sort();
+    endpoints.dedup();
+    endpoints
+}
+
+#[napi(object)]
+pub struct ConnectionInfo {
+    pub conn_id: String,
+    pub peer_id: String,
+    // tunnel type, e.g. "tcp"
+    pub protocol: String,
+    pub local_addr: Option<String>,
+    pub remote_addr: Option<String>,
+    // whether this end dialed the conn
+    pub is_client: bool,
+    // unix timestamp in seconds
+    pub established_at: i64,
+    pub tx_bytes: i64,
+    pub rx_bytes: i64,
+    pub latency_ms: f64,
+    pub loss_rate: f64,
+}
+
+fn list_peer_infos(service: &dyn InstanceRpcService) -> Option<Vec<PeerInfo>> {
+    match block_on(
+        service
+            .get_peer_manage_service()
+            .list_peer(BaseController::default(), ListPeerRequest::default()),
+    ) {
+        Ok(resp) => Some(resp.peer_infos),
+        Err(e) => {
+            hilog_error!("[Rust] list peers failed {}", e);
+            None
+        }
+    }
+}
+
+// every open direct conn of the instance, over all peers.
+#[napi]
+pub fn list_connections(inst_id: String) -> Vec<ConnectionInfo> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return vec![];
+    };
+    let Some(peers) = list_peer_infos(service.as_ref()) else {
+        return vec![];
+    };
+    peers
+        .into_iter()
+        .flat_map(|p| p.conns)
+        .filter(|c| !c.is_closed)
+        .map(|conn| {
+            let tunnel = conn.tunnel.unwrap_or_default();
+            let stats = conn.stats.unwrap_or_default();
+            ConnectionInfo {
+                conn_id: conn.conn_id,
+                peer_id: conn.peer_id.to_string(),
+                protocol: tunnel.tunnel_type,
+                local_addr: tunnel.local_addr.map(|x| x.to_string()),
+                remote_addr: tunnel.remote_addr.map(|x| x.to_string()),
+                is_client: conn.is_client,
+                established_at: conn.established_at,
+                tx_bytes: stats.tx_bytes as i64,
+                rx_bytes: stats.rx_bytes as i64,
+                latency_ms: stats.latency_us as f64 / 1000.0,
+                loss_rate: conn.loss_rate as f64,
+            }
+        })
+        .collect()
+}
+
+// drops one conn of list_connections, e.g. a wedged one, the other conns to the peer
+// stay. conns this end dialed are dialed again by their connector, a fresh conn has a
+// new id. false if no such conn is open.
+#[napi]
+pub fn kill_connection(inst_id: String, conn_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Some(service) = instance_api::get_instance_service(&uuid) else {
+        return false;
+    };
+    let Some(peer_id) = list_peer_infos(service.as_ref()).and_then(|peers| {
+        peers
+            .into_iter()
+            .flat_map(|p| p.conns)
+            .find(|c| c.conn_id == conn_id && !c.is_closed)
+            .map(|c| c.peer_id)
+    }) else {
+        hilog_error!("[Rust] kill_connection: no conn {} in {}", conn_id, uuid);
+        return false;
+    };
+    match block_on(service.get_peer_manage_service().close_peer_conn(
+        BaseController::default(),
+        ClosePeerConnRequest {
+            instance: None,
+            peer_id,
+            conn_id: conn_id.clone(),
+        },
+    )) {
+        Ok(_) => {
+            hilog_debug!("[Rust] killed conn {} to {} of {}", conn_id, peer_id, uuid);
+            true
+        }
+        Err(e) => {
+            hilog_error!("[Rust] kill conn {} of {} failed {}", conn_id, uuid, e);
+            false
+        }
+    }
+}
+
+// `prefer` is udp, tcp or auto. for udp or tcp the data to the peer goes over a direct
+// conn of that transport while there is one, e.g. tcp where udp is throttled, the conn
+// with the lowest latency otherwise. ws and faketcp count as tcp, wg and quic as udp.
+// kept while the instance runs, also across reconnects. connection_type in the peer
+// list is the transport in use.
+#[napi]
+pub fn set_peer_transport_preference(inst_id: String, peer_id: String, prefer: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    let Ok(peer_id) = peer_id.parse::<u32>() else {
+        hilog_error!(
+            "[Rust] set_peer_transport_preference: invalid peer id {}",
+            peer_id
+        );
+        return false;
+    };
+    if let Err(e) = prefer.parse::<TransportPreference>() {
+        hilog_error!("[Rust] set_peer_transport_preference: {}", e);
+        return false;
+    }
+    instance_api::patch_instance_config(
+        &uuid,
+        InstanceConfigPatch {
+            peer_transport_preferences: vec![PeerTransportPreferencePatch { peer_id, prefer }],
+            ..Default::default()
+        },
+    )
+}
@@ -0,0 +1,39 @@
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::Promise;
+use napi_ohos::threadsafe_function::ThreadsafeFunction;
+use ohos_hilog_binding::{hilog_debug, hilog_error, hilog_warn};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// the teardown goes on when the app did not finish within this
+const SHUTDOWN_CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+type ShutdownCallback = ThreadsafeFunction<(), Promise<()>>;
+
+static SHUTDOWN_CALLBACK: Mutex<Option<ShutdownCallback>> = Mutex::new(None);
+
+// taken out before it is called, so it fires once even if shutdowns race
+pub(crate) async fn run_shutdown_callback() {
+    let Some(func) = SHUTDOWN_CALLBACK.lock().unwrap().take() else {
+        return;
+    };
+    let done = async { func.call_async(Ok(())).await?.await };
+    match tokio::time::timeout(SHUTDOWN_CALLBACK_TIMEOUT, done).await {
+        Ok(Ok(())) => hilog_debug!("[Rust] shutdown callback done"),
+        Ok(Err(e)) => hilog_error!("[Rust] shutdown callback failed {}", e),
+        Err(_) => hilog_warn!("[Rust] shutdown callback timed out, shut down anyway"),
+    }
+}
+
+// called once by stop_all_network_instances before the instances are stopped, e.g. to
+// persist the state of the app. the stop waits for the returned promise, at most 5s.
+// the callback is dropped once it fired, registering again replaces it.
+#[napi]
+pub fn register_shutdown_callback(func: ShutdownCallback) {
+    *SHUTDOWN_CALLBACK.lock().unwrap() = Some(func);
+}
+
+#[napi]
+pub fn unregister_shutdown_callback() {
+    SHUTDOWN_CALLBACK.lock().unwrap().take();
+}
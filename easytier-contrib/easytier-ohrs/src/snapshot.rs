@@ -0,0 +1,205 @@
+use crate::instance_api::{self, block_on, connector_patch};
+use crate::{
+    INSTANCE_MANAGER, RunInstanceError, RunInstanceResult, load_network_config, parse_inst_id,
+    start_network_instance,
+};
+use easytier::common::config::{ConfigLoader, TomlConfigLoader};
+use easytier::proto::api::config::{ConfigPatchAction, InstanceConfigPatch};
+use easytier::proto::common::StunInfo;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const SNAPSHOT_VERSION: u64 = 1;
+// how long to wait for the api service of the restored instance
+const SERVICE_READY_TIMEOUT: Duration = Duration::from_secs(5);
+// connectors added from a snapshot are removed after this, conns made through them stay
+// up but are not reconnected
+const SNAPSHOT_ENDPOINT_TTL: Duration = Duration::from_secs(30);
+
+struct InstanceSnapshot {
+    virtual_ipv4: Option<cidr::Ipv4Inet>,
+    stun_info: Option<StunInfo>,
+    peer_endpoints: Vec<url::Url>,
+}
+
+// the peer endpoints are the remote urls of conns this instance made, the ones of
+// incoming conns are ephemeral ports of the peers.
+pub(crate) fn take_snapshot(uuid: &Uuid) -> Result<serde_json::Value, String> {
+    let Some(network_name) = INSTANCE_MANAGER
+        .iter()
+        .find(|x| x.key() == uuid)
+        .map(|x| x.get_network_name())
+    else {
+        return Err(format!("instance {} not found", uuid));
+    };
+    let info = match block_on(INSTANCE_MANAGER.get_network_info(uuid)) {
+        Some(info) if info.running => info,
+        _ => return Err(format!("instance {} is not running", uuid)),
+    };
+
+    let node = info.my_node_info.unwrap_or_default();
+    let mut peer_endpoints: Vec<String> = vec![];
+    for conn in info.peers.iter().flat_map(|x| x.conns.iter()) {
+        if !conn.is_client || conn.is_closed {
+            continue;
+        }
+        let Some(url) = conn.tunnel.as_ref().and_then(|x| x.remote_addr.as_ref()) else {
+            continue;
+        };
+        if !peer_endpoints.contains(&url.url) {
+            peer_endpoints.push(url.url.clone());
+        }
+    }
+    Ok(serde_json::json!({
+        "version": SNAPSHOT_VERSION,
+        "network_name": network_name,
+        "virtual_ipv4": node.virtual_ipv4.map(|x| cidr::Ipv4Inet::from(x).to_string()),
+        "stun_info": node.stun_info,
+        "peer_endpoints": peer_endpoints,
+    }))
+}
+
+#[napi]
+pub fn snapshot_instance(inst_id: String) -> String {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return format!("ERROR invalid instance id {}", inst_id);
+    };
+    match take_snapshot(&uuid) {
+        Ok(snapshot) => snapshot.to_string(),
+        Err(e) => format!("ERROR {}", e),
+    }
+}
+
+fn parse_snapshot(
+    value: &serde_json::Value,
+    cfg: &TomlConfigLoader,
+) -> Result<InstanceSnapshot, String> {
+    let version = value["version"].as_u64().unwrap_or_default();
+    if version != SNAPSHOT_VERSION {
+        return Err(format!("unsupported snapshot version {}", version));
+    }
+    let network_name = cfg.get_network_identity().network_name;
+    if value["network_name"].as_str() != Some(network_name.as_str()) {
+        return Err(format!(
+            "snapshot is of network {}, not {}",
+            value["network_name"], network_name
+        ));
+    }
+
+    let virtual_ipv4 = match value["virtual_ipv4"].as_str() {
+        Some(ip) => Some(
+            ip.parse()
+                .map_err(|e| format!("invalid virtual ipv4 {}", e))?,
+        ),
+        None => None,
+    };
+    let stun_info = match &value["stun_info"] {
+        serde_json::Value::Null => None,
+        info => Some(serde_json::from_value(info.clone()).map_err(|e| e.to_string())?),
+    };
+    let peer_endpoints = value["peer_endpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str()?.parse().ok())
+        .collect();
+    Ok(InstanceSnapshot {
+        virtual_ipv4,
+        stun_info,
+        peer_endpoints,
+    })
+}
+
+fn restore(uuid: Uuid, snapshot: InstanceSnapshot) {
+    let start = Instant::now();
+    while INSTANCE_MANAGER.get_instance_service(&uuid).is_none() {
+        if start.elapsed() > SERVICE_READY_TIMEOUT {
+            hilog_error!("[Rust] restore snapshot of {}: instance not ready", uuid);
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    if let Some(stun_info) = snapshot.stun_info {
+        instance_api::patch_instance_config(
+            &uuid,
+            InstanceConfigPatch {
+                stun_info_hint: Some(stun_info),
+                ..Default::default()
+            },
+        );
+    }
+    if snapshot.peer_endpoints.is_empty()
+        || !instance_api::patch_instance_config(
+            &uuid,
+            connector_patch(ConfigPatchAction::Add, &snapshot.peer_endpoints),
+        )
+    {
+        return;
+    }
+    hilog_debug!(
+        "[Rust] restored {} peer endpoints of {}",
+        snapshot.peer_endpoints.len(),
+        uuid
+    );
+    std::thread::sleep(SNAPSHOT_ENDPOINT_TTL);
+    if INSTANCE_MANAGER.get_instance_service(&uuid).is_some() {
+        instance_api::patch_instance_config(
+            &uuid,
+            connector_patch(ConfigPatchAction::Remove, &snapshot.peer_endpoints),
+        );
+    }
+}
+
+// starts the instance like run_network_instance, then connects to the peers in the
+// snapshot right away and reports the nat info of it until detection finishes. with
+// dhcp the virtual ipv4 of the snapshot is taken again if no peer uses it now. fails
+// with InvalidConfig if the snapshot is not of the network in `cfg_json`.
+#[napi]
+pub fn run_network_instance_from_snapshot(cfg_json: String, snapshot: String) -> RunInstanceResult {
+    let cfg = match load_network_config(&cfg_json) {
+        Ok(cfg) => cfg,
+        Err(e) => return e,
+    };
+    let snapshot: serde_json::Value = match serde_json::from_str(&snapshot) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return RunInstanceResult::err(
+                RunInstanceError::InvalidConfig,
+                format!("incompatible snapshot: {}", e),
+            );
+        }
+    };
+    start_from_snapshot(cfg, &snapshot)
+}
+
+pub(crate) fn start_from_snapshot(
+    cfg: TomlConfigLoader,
+    snapshot: &serde_json::Value,
+) -> RunInstanceResult {
+    let mut snapshot = match parse_snapshot(snapshot, &cfg) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            return RunInstanceResult::err(
+                RunInstanceError::InvalidConfig,
+                format!("incompatible snapshot: {}", e),
+            );
+        }
+    };
+    if cfg.get_dhcp() && snapshot.virtual_ipv4.is_some() {
+        cfg.set_ipv4(snapshot.virtual_ipv4);
+    }
+    // configured peers are connected anyway
+    let peers = cfg.get_peers();
+    snapshot
+        .peer_endpoints
+        .retain(|x| !peers.iter().any(|p| p.uri == *x));
+
+    let result = start_network_instance(cfg, false);
+    if let Some(uuid) = result.inst_id.as_deref().and_then(parse_inst_id) {
+        std::thread::spawn(move || restore(uuid, snapshot));
+    }
+    result
+}
@@ -0,0 +1,302 @@
+use crate::instance_api;
+use crate::listener_ports::data_dir;
+use crate::metrics::get_instance_stats;
+use crate::parse_inst_id;
+use crate::route::list_routes;
+use easytier::common::stats_manager::MetricName;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::hilog_error;
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+const HISTORY_DIR: &str = "stats_history";
+const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_RETENTION: Duration = Duration::from_secs(60 * 60);
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+// bounds the memory whatever the retention, a day at the default interval
+const MAX_SAMPLES: usize = 8640;
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct StatsSample {
+    // unix timestamp in milliseconds
+    pub time_ms: i64,
+    // totals since the start of the instance, a drop marks a restart
+    pub tx_bytes: i64,
+    pub rx_bytes: i64,
+    // mean path latency to the reachable peers, none without peers
+    pub latency_ms: Option<f64>,
+}
+
+impl StatsSample {
+    // one line per sample in the history files, "<time> <tx> <rx> <latency or ->"
+    fn encode(&self) -> String {
+        let latency = self
+            .latency_ms
+            .map_or_else(|| "-".to_string(), |x| x.to_string());
+        format!(
+            "{} {} {} {}\n",
+            self.time_ms, self.tx_bytes, self.rx_bytes, latency
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let sample = Self {
+            time_ms: parts.next()?.parse().ok()?,
+            tx_bytes: parts.next()?.parse().ok()?,
+            rx_bytes: parts.next()?.parse().ok()?,
+            latency_ms: match parts.next()? {
+                "-" => None,
+                x => Some(x.parse().ok()?),
+            },
+        };
+        Some(sample)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HistoryConfig {
+    interval: Duration,
+    retention: Duration,
+    // keep the histories in the data dir too, so they survive app restarts
+    persist: bool,
+}
+
+static HISTORY_CONFIG: Mutex<HistoryConfig> = Mutex::new(HistoryConfig {
+    interval: DEFAULT_SAMPLE_INTERVAL,
+    retention: DEFAULT_RETENTION,
+    persist: false,
+});
+
+struct History {
+    samples: VecDeque<StatsSample>,
+    // the samples are appended to it, it is rewritten with the retained ones once it
+    // holds twice as many lines
+    file: Option<PathBuf>,
+    file_lines: usize,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for History {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+static HISTORIES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, History>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn history_file(uuid: &Uuid) -> Option<PathBuf> {
+    Some(data_dir()?.join(HISTORY_DIR).join(uuid.to_string()))
+}
+
+// drops what is older than the retention or beyond MAX_SAMPLES, oldest first
+fn trim(samples: &mut VecDeque<StatsSample>, config: HistoryConfig, now_ms: i64) {
+    let oldest = now_ms - config.retention.as_millis() as i64;
+    while samples.front().is_some_and(|x| x.time_ms < oldest) || samples.len() > MAX_SAMPLES {
+        samples.pop_front();
+    }
+}
+
+fn write_samples(path: &Path, samples: &VecDeque<StatsSample>) {
+    let content: String = samples.iter().map(|x| x.encode()).collect();
+    let ret =
+        std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| std::fs::write(path, content));
+    if let Err(e) = ret {
+        hilog_error!("[Rust] write {} failed {}", path.display(), e);
+    }
+}
+
+fn append_sample(path: &Path, sample: &StatsSample) {
+    let ret = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(sample.encode().as_bytes()));
+    if let Err(e) = ret {
+        hilog_error!("[Rust] append to {} failed {}", path.display(), e);
+    }
+}
+
+// the files of instances that are not started again, e.g. with a new random instance id
+// each time, are never continued. drop those not written to within the retention, their
+// samples would all be trimmed on a start anyway.
+fn remove_stale_history_files(config: HistoryConfig) {
+    let Some(dir) = data_dir().map(|x| x.join(HISTORY_DIR)) else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|x| x.modified())
+            .is_ok_and(|x| now.duration_since(x).unwrap_or_default() > config.retention);
+        if !stale {
+            continue;
+        }
+        if let Err(e) = std::fs::remove_file(entry.path()) {
+            hilog_error!("[Rust] remove {} failed {}", entry.path().display(), e);
+        }
+    }
+}
+
+async fn take_sample(uuid: &Uuid) -> Option<StatsSample> {
+    let stats = get_instance_stats(uuid).await?;
+    let sum_of = |name: MetricName| {
+        let name = name.to_string();
+        stats
+            .iter()
+            .filter(|m| m.name == name)
+            .map(|m| m.value)
+            .sum::<u64>() as i64
+    };
+    let latencies: Vec<f64> = list_routes(uuid)
+        .await
+        .unwrap_or_default()
+        .values()
+        .map(|x| x.path_latency as f64)
+        .collect();
+    Some(StatsSample {
+        time_ms: now_ms(),
+        tx_bytes: sum_of(MetricName::TrafficBytesTx),
+        rx_bytes: sum_of(MetricName::TrafficBytesRx),
+        latency_ms: (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+    })
+}
+
+fn push_sample(uuid: &Uuid, sample: StatsSample, config: HistoryConfig) {
+    let mut histories = HISTORIES.lock().unwrap();
+    let Some(history) = histories.get_mut(uuid) else {
+        return;
+    };
+    history.samples.push_back(sample.clone());
+    trim(&mut history.samples, config, sample.time_ms);
+    let Some(path) = history.file.as_ref() else {
+        return;
+    };
+    if history.file_lines >= 2 * history.samples.len() {
+        write_samples(path, &history.samples);
+        history.file_lines = history.samples.len();
+    } else {
+        append_sample(path, &sample);
+        history.file_lines += 1;
+    }
+}
+
+async fn sample_stats(uuid: Uuid) {
+    loop {
+        let config = *HISTORY_CONFIG.lock().unwrap();
+        tokio::time::sleep(config.interval).await;
+        // none while the instance is starting or after it failed
+        if let Some(sample) = take_sample(&uuid).await {
+            push_sample(&uuid, sample, config);
+        }
+    }
+}
+
+// sampled in the background from the start of the instance on, so the app does not
+// have to poll while it is in the background. a persisted history is picked up again
+// unless `persistable` is false, for the ephemeral instances.
+pub(crate) fn watch_stats_history(uuid: Uuid, persistable: bool) {
+    let config = *HISTORY_CONFIG.lock().unwrap();
+    let file = (config.persist && persistable)
+        .then(|| history_file(&uuid))
+        .flatten();
+    let mut samples: VecDeque<StatsSample> = file
+        .as_ref()
+        .and_then(|x| std::fs::read_to_string(x).ok())
+        .unwrap_or_default()
+        .lines()
+        .filter_map(StatsSample::decode)
+        .collect();
+    trim(&mut samples, config, now_ms());
+    if let Some(path) = file.as_ref() {
+        remove_stale_history_files(config);
+        write_samples(path, &samples);
+    }
+    let history = History {
+        file_lines: samples.len(),
+        samples,
+        file,
+        task: instance_api::spawn(sample_stats(uuid)),
+    };
+    HISTORIES.lock().unwrap().insert(uuid, history);
+}
+
+// the persisted history stays for the next start, until it is older than the retention
+pub(crate) fn remove_stats_history(uuids: &[Uuid]) {
+    let mut histories = HISTORIES.lock().unwrap();
+    histories.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+// the samples taken after `since_ms`, a unix timestamp in milliseconds, oldest first. 0
+// for all of them. the history begins with the start of the instance, or earlier with
+// set_stats_history_persistence, and is dropped from memory on stop.
+#[napi]
+pub fn get_stats_history(inst_id: String, since_ms: i64) -> Vec<StatsSample> {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return vec![];
+    };
+    HISTORIES
+        .lock()
+        .unwrap()
+        .get(&uuid)
+        .map(|x| {
+            x.samples
+                .iter()
+                .filter(|s| s.time_ms > since_ms)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// a sample is taken every `interval_ms` and kept for `retention_ms`, for all instances,
+// 10s and an hour by default. the interval is at least 1s, and at most 8640 samples are
+// kept whatever the retention. false if a value is out of range.
+#[napi]
+pub fn set_stats_history_retention(interval_ms: u32, retention_ms: u32) -> bool {
+    let interval = Duration::from_millis(interval_ms as u64);
+    let retention = Duration::from_millis(retention_ms as u64);
+    if interval < MIN_SAMPLE_INTERVAL || retention < interval {
+        hilog_error!(
+            "[Rust] set_stats_history_retention: invalid interval {}ms or retention {}ms",
+            interval_ms,
+            retention_ms
+        );
+        return false;
+    }
+    let mut config = HISTORY_CONFIG.lock().unwrap();
+    config.interval = interval;
+    config.retention = retention;
+    true
+}
+
+// when enabled, the histories of the instances started afterwards are also written to
+// the dir given to set_data_dir and continued on their next start, across app restarts.
+// fails if no data dir is set.
+#[napi]
+pub fn set_stats_history_persistence(enabled: bool) -> bool {
+    if enabled && data_dir().is_none() {
+        hilog_error!("[Rust] no data dir set for the stats history");
+        return false;
+    }
+    HISTORY_CONFIG.lock().unwrap().persist = enabled;
+    true
+}
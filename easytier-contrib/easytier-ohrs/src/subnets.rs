@@ -0,0 +1,53 @@
+use crate::callback::check_call;
+use easytier::common::global_ctx::GlobalCtxEvent;
+use napi_derive_ohos::napi;
+use napi_ohos::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[napi(object)]
+pub struct ReachableSubnetsChanged {
+    pub inst_id: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+static SUBNETS_CALLBACK: Mutex<Option<ThreadsafeFunction<ReachableSubnetsChanged, ()>>> =
+    Mutex::new(None);
+
+// called with every event of every running instance
+pub(crate) fn report_subnets_event(uuid: &Uuid, event: &GlobalCtxEvent) {
+    let GlobalCtxEvent::ProxyCidrsUpdated(added, removed) = event else {
+        return;
+    };
+    let callback = SUBNETS_CALLBACK.lock().unwrap();
+    let Some(func) = callback.as_ref() else {
+        return;
+    };
+    check_call(
+        "reachable subnets",
+        func.call(
+            Ok(ReachableSubnetsChanged {
+                inst_id: uuid.to_string(),
+                added: added.iter().map(|x| x.to_string()).collect(),
+                removed: removed.iter().map(|x| x.to_string()).collect(),
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+        ),
+    );
+}
+
+// called when the set of ipv4 subnets an instance can reach through its peers changes,
+// e.g. a gateway peer joined announcing its lan. the subnets are the proxy networks of
+// all peers and the vpn portal, or only the manual routes when they are configured.
+// the first call after the start carries all of them as added, nothing is reported when
+// the instance stops. registering again replaces the callback.
+#[napi]
+pub fn register_reachable_subnets_callback(func: ThreadsafeFunction<ReachableSubnetsChanged, ()>) {
+    *SUBNETS_CALLBACK.lock().unwrap() = Some(func);
+}
+
+#[napi]
+pub fn unregister_reachable_subnets_callback() {
+    SUBNETS_CALLBACK.lock().unwrap().take();
+}
@@ -0,0 +1,157 @@
+use crate::instance_api::{self, block_on};
+use crate::parse_inst_id;
+use easytier::proto::api::instance::ThroughputTestRequest;
+use easytier::proto::rpc_types::controller::BaseController;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::AsyncTask;
+use napi_ohos::{Env, Task};
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Mutex;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+#[napi(object)]
+pub struct ThroughputResult {
+    pub peer_id: u32,
+    pub up_mbps: f64,
+    pub down_mbps: f64,
+    pub cancelled: bool,
+    pub error: Option<String>,
+}
+
+impl ThroughputResult {
+    fn failed(peer_id: u32, error: impl Into<String>) -> Self {
+        Self {
+            peer_id,
+            up_mbps: 0.0,
+            down_mbps: 0.0,
+            cancelled: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+// the running test of each instance, aborted to cancel it
+static RUNNING_TESTS: once_cell::sync::Lazy<Mutex<HashMap<Uuid, AbortHandle>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn mbps(bytes: u64, us: u64) -> f64 {
+    if us == 0 {
+        0.0
+    } else {
+        bytes as f64 * 8.0 / us as f64
+    }
+}
+
+pub struct ThroughputTestTask {
+    inst_id: String,
+    peer_id: String,
+    duration_ms: u32,
+}
+
+impl ThroughputTestTask {
+    fn run(&self, uuid: Uuid, peer_id: u32) -> ThroughputResult {
+        let Some(service) = instance_api::get_instance_service(&uuid) else {
+            return ThroughputResult::failed(peer_id, format!("instance {} not found", uuid));
+        };
+        let request = ThroughputTestRequest {
+            instance: None,
+            peer_id,
+            duration_ms: self.duration_ms,
+        };
+        let handle = {
+            let mut tests = RUNNING_TESTS.lock().unwrap();
+            let Entry::Vacant(entry) = tests.entry(uuid) else {
+                return ThroughputResult::failed(peer_id, "a throughput test is already running");
+            };
+            let handle = instance_api::spawn(async move {
+                service
+                    .get_peer_manage_service()
+                    .throughput_test(BaseController::default(), request)
+                    .await
+            });
+            entry.insert(handle.abort_handle());
+            handle
+        };
+        // aborting the task drops the rpc future, which stops sending chunks right away
+        let ret = block_on(handle).ok();
+        RUNNING_TESTS.lock().unwrap().remove(&uuid);
+
+        match ret {
+            Some(Ok(resp)) => ThroughputResult {
+                peer_id,
+                up_mbps: mbps(resp.upload_bytes, resp.upload_us),
+                down_mbps: mbps(resp.download_bytes, resp.download_us),
+                cancelled: false,
+                error: None,
+            },
+            Some(Err(e)) => {
+                hilog_error!("[Rust] throughput test to {} failed {}", peer_id, e);
+                ThroughputResult::failed(peer_id, e.to_string())
+            }
+            None => {
+                hilog_debug!("[Rust] throughput test to {} cancelled", peer_id);
+                ThroughputResult {
+                    cancelled: true,
+                    ..ThroughputResult::failed(peer_id, "cancelled")
+                }
+            }
+        }
+    }
+}
+
+#[napi]
+impl Task for ThroughputTestTask {
+    type Output = ThroughputResult;
+    type JsValue = ThroughputResult;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        let Ok(peer_id) = self.peer_id.parse::<u32>() else {
+            return Ok(ThroughputResult::failed(
+                0,
+                format!("invalid peer id {}", self.peer_id),
+            ));
+        };
+        let Some(uuid) = parse_inst_id(&self.inst_id) else {
+            return Ok(ThroughputResult::failed(peer_id, "invalid instance id"));
+        };
+        Ok(self.run(uuid, peer_id))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+// uploads to the peer for the first half of `duration_ms` and downloads for the second
+// half, over the route currently used for it. one test per instance at a time, chunks
+// are sent a few at a time so traffic of other peers keeps flowing.
+#[napi]
+pub fn run_throughput_test(
+    inst_id: String,
+    peer_id: String,
+    duration_ms: u32,
+) -> AsyncTask<ThroughputTestTask> {
+    AsyncTask::new(ThroughputTestTask {
+        inst_id,
+        peer_id,
+        duration_ms,
+    })
+}
+
+// returns false if no test is running for the instance.
+#[napi]
+pub fn cancel_throughput_test(inst_id: String) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    match RUNNING_TESTS.lock().unwrap().get(&uuid) {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,291 @@
+use crate::instance_api::{self, block_on};
+use crate::{INSTANCE_MANAGER, load_config_str, parse_inst_id};
+use easytier::common::config::ConfigLoader;
+use easytier::common::global_ctx::{EventBusSubscriber, GlobalCtxEvent};
+use easytier::common::network::get_tun_fd_info;
+use easytier::instance::instance::Instance;
+use napi_derive_ohos::napi;
+use ohos_hilog_binding::{hilog_debug, hilog_error};
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+// the name core falls back to when the kernel name of the fd is unknown
+const FD_IFNAME_PREFIX: &str = "tunfd_";
+// how long swap_tun_fd waits for the instance to take the new fd
+const TUN_SWAP_TIMEOUT: Duration = Duration::from_secs(3);
+
+static TUN_NAMES: once_cell::sync::Lazy<Mutex<HashMap<Uuid, String>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashMap::new()));
+
+// instances waiting in run_network_instance_when_tun_ready and the fd passed to
+// set_tun_fd for them meanwhile, taken by their start. only those get a pending fd.
+static PENDING_TUN_FDS: once_cell::sync::Lazy<(Mutex<HashMap<Uuid, Option<i32>>>, Condvar)> =
+    once_cell::sync::Lazy::new(|| (Mutex::new(HashMap::new()), Condvar::new()));
+
+// false if the instance already waits for its fd
+pub(crate) fn expect_pending_tun_fd(uuid: Uuid) -> bool {
+    let mut fds = PENDING_TUN_FDS.0.lock().unwrap();
+    if fds.contains_key(&uuid) {
+        return false;
+    }
+    fds.insert(uuid, None);
+    true
+}
+
+// false if the instance does not wait for a fd
+pub(crate) fn set_pending_tun_fd(uuid: &Uuid, fd: Option<i32>) -> bool {
+    let (fds, ready) = &*PENDING_TUN_FDS;
+    let mut fds = fds.lock().unwrap();
+    let Some(pending) = fds.get_mut(uuid) else {
+        return false;
+    };
+    *pending = fd;
+    ready.notify_all();
+    true
+}
+
+pub(crate) fn take_pending_tun_fd(uuid: &Uuid) -> Option<i32> {
+    PENDING_TUN_FDS.0.lock().unwrap().remove(uuid).flatten()
+}
+
+// cancels the waits of the instances, their fds are dropped
+pub(crate) fn forget_pending_tun_fds(uuids: &[Uuid]) {
+    let (fds, ready) = &*PENDING_TUN_FDS;
+    fds.lock().unwrap().retain(|uuid, _| !uuids.contains(uuid));
+    ready.notify_all();
+}
+
+// whether a fd for the instance arrives within `timeout`, it stays pending for the start.
+// none if the wait was cancelled by forget_pending_tun_fds. on timeout the instance no
+// longer waits.
+pub(crate) fn wait_pending_tun_fd(uuid: &Uuid, timeout: Duration) -> Option<bool> {
+    let (fds, ready) = &*PENDING_TUN_FDS;
+    let (mut fds, _) = ready
+        .wait_timeout_while(fds.lock().unwrap(), timeout, |fds| {
+            fds.get(uuid).is_some_and(|x| x.is_none())
+        })
+        .unwrap();
+    match fds.get(uuid) {
+        None => None,
+        Some(Some(_)) => Some(true),
+        Some(None) => {
+            fds.remove(uuid);
+            Some(false)
+        }
+    }
+}
+
+fn subscribe_event(uuid: &Uuid) -> Option<EventBusSubscriber> {
+    INSTANCE_MANAGER
+        .iter()
+        .find(|x| x.key() == uuid)
+        .and_then(|x| x.subscribe_event())
+}
+
+// a failing fd does not take down the device in use, so errors keep the name
+pub(crate) fn watch_tun_device(uuid: Uuid) {
+    forget_tun_names(&[uuid]);
+    let Some(mut receiver) = subscribe_event(&uuid) else {
+        return;
+    };
+    instance_api::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(GlobalCtxEvent::TunDeviceReady(name)) => {
+                    TUN_NAMES.lock().unwrap().insert(uuid, name);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+pub(crate) fn forget_tun_names(uuids: &[Uuid]) {
+    let mut names = TUN_NAMES.lock().unwrap();
+    names.retain(|uuid, _| !uuids.contains(uuid));
+}
+
+pub(crate) fn has_tun_device(uuid: &Uuid) -> bool {
+    TUN_NAMES.lock().unwrap().contains_key(uuid)
+}
+
+// name of the interface the system created for the fd of set_tun_fd, e.g. "tun0".
+// undefined until the fd is set, for instances without tun and when the system does not
+// tell the name.
+#[napi]
+pub fn get_tun_interface_name(inst_id: String) -> Option<String> {
+    let uuid = parse_inst_id(&inst_id)?;
+    TUN_NAMES
+        .lock()
+        .unwrap()
+        .get(&uuid)
+        .filter(|x| !x.starts_with(FD_IFNAME_PREFIX))
+        .cloned()
+}
+
+// whether `fd` is open and a tun device, the kernel names the interface of those
+fn is_tun_fd(fd: i32) -> bool {
+    std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd))
+        .is_ok_and(|x| x.lines().any(|l| l.starts_with("iff:")))
+}
+
+async fn wait_tun_device(receiver: &mut EventBusSubscriber) -> bool {
+    loop {
+        match receiver.recv().await {
+            Ok(GlobalCtxEvent::TunDeviceReady(_)) => return true,
+            Ok(GlobalCtxEvent::TunDeviceError(_)) | Err(RecvError::Closed) => return false,
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+        }
+    }
+}
+
+// replaces the tun fd of a running instance, e.g. with the one the vpn extension got
+// after a network change. peer conns and routes stay, the old fd is used until the new
+// one is up and kept if that fails, the app closes it once this returns true.
+#[napi]
+pub fn swap_tun_fd(inst_id: String, new_fd: i32) -> bool {
+    let Some(uuid) = parse_inst_id(&inst_id) else {
+        return false;
+    };
+    if new_fd <= 0 || !is_tun_fd(new_fd) {
+        hilog_error!("[Rust] swap_tun_fd: {} is not an open tun fd", new_fd);
+        return false;
+    }
+    let Some(old_fd) = INSTANCE_MANAGER
+        .get_tun_fd(&uuid)
+        .filter(|x| *x != Instance::NO_TUN_FD)
+    else {
+        hilog_error!("[Rust] swap_tun_fd: {} has no tun fd, use set_tun_fd", uuid);
+        return false;
+    };
+    if old_fd == new_fd {
+        return true;
+    }
+    let Some(mut receiver) = subscribe_event(&uuid) else {
+        return false;
+    };
+    if let Err(e) = INSTANCE_MANAGER.set_tun_fd(&uuid, new_fd) {
+        hilog_error!("[Rust] swap_tun_fd of {} failed {}", uuid, e);
+        return false;
+    }
+    let swapped = block_on(tokio::time::timeout(
+        TUN_SWAP_TIMEOUT,
+        wait_tun_device(&mut receiver),
+    ))
+    .unwrap_or(false);
+    if swapped {
+        hilog_debug!(
+            "[Rust] swapped tun fd of {} from {} to {}",
+            uuid,
+            old_fd,
+            new_fd
+        );
+    } else {
+        hilog_error!(
+            "[Rust] tun fd {} not taken by {}, keep {}",
+            new_fd,
+            uuid,
+            old_fd
+        );
+        // the old fd is still in use, setting it back stops the retries of the new one
+        let _ = INSTANCE_MANAGER.set_tun_fd(&uuid, old_fd);
+    }
+    swapped
+}
+
+#[napi(object)]
+pub struct CompatResult {
+    pub compatible: bool,
+    // one per mismatch, empty when compatible
+    pub reasons: Vec<String>,
+    // what the kernel reports for the fd, none if it is no tun fd
+    pub ifname: Option<String>,
+    pub mtu: Option<u32>,
+    pub addrs: Vec<String>,
+}
+
+fn check_addr(addrs: &[cidr::IpInet], expected: cidr::IpInet, reasons: &mut Vec<String>) {
+    let family = if expected.is_ipv4() { "ipv4" } else { "ipv6" };
+    let same_family: Vec<_> = addrs
+        .iter()
+        .filter(|x| x.is_ipv4() == expected.is_ipv4())
+        .collect();
+    match same_family
+        .iter()
+        .find(|x| x.address() == expected.address())
+    {
+        Some(addr) if addr.network_length() != expected.network_length() => reasons.push(format!(
+            "the tun has {} but the config expects {}",
+            addr, expected
+        )),
+        Some(_) => {}
+        None if same_family.is_empty() => reasons.push(format!(
+            "the config expects {} {} but the tun has no {} address",
+            family, expected, family
+        )),
+        None => reasons.push(format!(
+            "the config expects {} {} but the tun has {}",
+            family,
+            expected,
+            same_family
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+// whether the tun fd fits the config before set_tun_fd is called with it: the virtual
+// addresses of the config are the ones of the tun, and the tun mtu is not above the
+// mtu of the config, larger packets would be dropped in the overlay. with dhcp the
+// ipv4 is not known before the start and not checked. `cfg_str` is a network config
+// json or a toml config.
+#[napi]
+pub fn check_tun_compatibility(cfg_str: String, fd: i32) -> CompatResult {
+    let mut result = CompatResult {
+        compatible: false,
+        reasons: vec![],
+        ifname: None,
+        mtu: None,
+        addrs: vec![],
+    };
+    let cfg = match load_config_str(&cfg_str) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            result.reasons.push(e.error_msg.unwrap_or_default());
+            return result;
+        }
+    };
+    let Some(info) = (fd > 0).then(|| get_tun_fd_info(fd)).flatten() else {
+        result.reasons.push(format!("{} is not an open tun fd", fd));
+        return result;
+    };
+    let flags = cfg.get_flags();
+    if flags.no_tun {
+        result
+            .reasons
+            .push("the config runs without tun, no_tun is set".to_string());
+    }
+    if let Some(ipv4) = cfg.get_ipv4().filter(|_| !cfg.get_dhcp()) {
+        check_addr(&info.addrs, ipv4.into(), &mut result.reasons);
+    }
+    if let Some(ipv6) = cfg.get_ipv6() {
+        check_addr(&info.addrs, ipv6.into(), &mut result.reasons);
+    }
+    if let Some(mtu) = info.mtu.filter(|x| *x > flags.mtu) {
+        result.reasons.push(format!(
+            "the tun mtu {} is larger than the mtu {} of the config",
+            mtu, flags.mtu
+        ));
+    }
+    result.compatible = result.reasons.is_empty();
+    result.ifname = Some(info.ifname);
+    result.mtu = info.mtu;
+    result.addrs = info.addrs.iter().map(|x| x.to_string()).collect();
+    result
+}
@@ -0,0 +1,131 @@
+use crate::instance_api::block_on;
+use easytier::common::config::{ConfigLoader, NetworkIdentity, TomlConfigLoader};
+use easytier::common::error::Error;
+use easytier::common::global_ctx::GlobalCtx;
+use easytier::connector::create_connector_by_url;
+use easytier::peers::peer_conn::PeerConn;
+use easytier::tunnel::IpVersion;
+use napi_derive_ohos::napi;
+use napi_ohos::bindgen_prelude::AsyncTask;
+use napi_ohos::{Env, Task};
+use ohos_hilog_binding::hilog_debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// how long a relay is given to drop the conn of a rejected network
+const RELAY_REJECT_WAIT: Duration = Duration::from_secs(2);
+
+#[napi(string_enum)]
+#[derive(Debug)]
+pub enum VerifyError {
+    InvalidUrl,
+    RelayUnreachable,
+    AuthRejected,
+}
+
+#[napi(object)]
+pub struct VerifyResult {
+    pub success: bool,
+    pub error: Option<VerifyError>,
+    pub detail: Option<String>,
+}
+
+impl VerifyResult {
+    fn failed(error: VerifyError, detail: String) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            detail: Some(detail),
+        }
+    }
+}
+
+pub struct VerifySecretTask {
+    peer_url: String,
+    network_name: String,
+    secret: String,
+}
+
+impl VerifySecretTask {
+    async fn verify(&self) -> VerifyResult {
+        let config = TomlConfigLoader::default();
+        config.set_network_identity(NetworkIdentity::new(
+            self.network_name.clone(),
+            self.secret.clone(),
+        ));
+        let global_ctx = Arc::new(GlobalCtx::new(config));
+
+        let mut connector =
+            match create_connector_by_url(&self.peer_url, &global_ctx, IpVersion::Both).await {
+                Ok(connector) => connector,
+                Err(e @ Error::InvalidUrl(_)) => {
+                    return VerifyResult::failed(VerifyError::InvalidUrl, e.to_string());
+                }
+                Err(e) => {
+                    return VerifyResult::failed(VerifyError::RelayUnreachable, e.to_string());
+                }
+            };
+        let tunnel = match tokio::time::timeout(CONNECT_TIMEOUT, connector.connect()).await {
+            Ok(Ok(tunnel)) => tunnel,
+            Ok(Err(e)) => {
+                return VerifyResult::failed(VerifyError::RelayUnreachable, e.to_string());
+            }
+            Err(_) => {
+                return VerifyResult::failed(
+                    VerifyError::RelayUnreachable,
+                    "connect timeout".to_string(),
+                );
+            }
+        };
+
+        match PeerConn::verify_network_identity(global_ctx, tunnel, RELAY_REJECT_WAIT).await {
+            Ok(()) => VerifyResult {
+                success: true,
+                error: None,
+                detail: None,
+            },
+            Err(e @ Error::SecretKeyError(_)) => {
+                VerifyResult::failed(VerifyError::AuthRejected, e.to_string())
+            }
+            Err(e) => VerifyResult::failed(VerifyError::RelayUnreachable, e.to_string()),
+        }
+    }
+}
+
+#[napi]
+impl Task for VerifySecretTask {
+    type Output = VerifyResult;
+    type JsValue = VerifyResult;
+
+    fn compute(&mut self) -> napi_ohos::Result<Self::Output> {
+        let result = block_on(self.verify());
+        hilog_debug!(
+            "[Rust] verify secret of {} at {}: {:?} {:?}",
+            self.network_name,
+            self.peer_url,
+            result.error,
+            result.detail
+        );
+        Ok(result)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi_ohos::Result<Self::JsValue> {
+        Ok(output)
+    }
+}
+
+// only the handshake is done, the network is not joined. a relay that does not host the
+// network yet accepts any secret, as it would on a real join.
+#[napi]
+pub fn verify_network_secret(
+    peer_url: String,
+    network_name: String,
+    secret: String,
+) -> AsyncTask<VerifySecretTask> {
+    AsyncTask::new(VerifySecretTask {
+        peer_url,
+        network_name,
+        secret,
+    })
+}
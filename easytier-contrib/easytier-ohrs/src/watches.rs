@@ -0,0 +1,82 @@
+use crate::instance_api;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+struct Watch<S> {
+    state: Arc<S>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl<S> Drop for Watch<S> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// background tasks set per instance, like the alerts. removing or replacing one aborts
+// its task, and a task that ends on its own removes itself unless it was replaced.
+pub(crate) struct Watches<K, S> {
+    watches: Mutex<HashMap<K, Watch<S>>>,
+}
+
+impl<K, S> Watches<K, S>
+where
+    K: Hash + Eq + Copy + Send + Sync + 'static,
+    S: Send + Sync + 'static,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            watches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // runs `watch` with the state until it returns, replacing the watch set for `key`
+    pub(crate) fn insert<F, Fut>(&'static self, key: K, state: S, watch: F)
+    where
+        F: FnOnce(Arc<S>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let state = Arc::new(state);
+        let fut = watch(state.clone());
+        let ours = state.clone();
+        // locked before the spawn, so a task ending at once finds its own entry
+        let mut watches = self.watches.lock().unwrap();
+        let task = instance_api::spawn(async move {
+            fut.await;
+            self.remove_if_same(&key, &ours);
+        });
+        watches.insert(key, Watch { state, task });
+    }
+
+    fn remove_if_same(&self, key: &K, state: &Arc<S>) {
+        let mut watches = self.watches.lock().unwrap();
+        if watches
+            .get(key)
+            .is_some_and(|w| Arc::ptr_eq(&w.state, state))
+        {
+            watches.remove(key);
+        }
+    }
+
+    pub(crate) fn remove(&self, key: &K) {
+        self.watches.lock().unwrap().remove(key);
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<Arc<S>> {
+        self.watches
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|w| w.state.clone())
+    }
+
+    // keeps the watches `f` returns true for, returns how many were removed
+    pub(crate) fn retain(&self, mut f: impl FnMut(&K) -> bool) -> usize {
+        let mut watches = self.watches.lock().unwrap();
+        let before = watches.len();
+        watches.retain(|key, _| f(key));
+        before - watches.len()
+    }
+}
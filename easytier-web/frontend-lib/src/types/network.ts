@@ -295,6 +295,9 @@ export enum EventType {
   PeerRemoved = 'PeerRemoved', // number
   PeerConnAdded = 'PeerConnAdded', // PeerConnInfo
   PeerConnRemoved = 'PeerConnRemoved', // PeerConnInfo
+  PeerBlocked = 'PeerBlocked', // number, number
+  PeerUnblocked = 'PeerUnblocked', // number
+  FirstPeerConnected = 'FirstPeerConnected', // number, number
 
   ListenerAdded = 'ListenerAdded', // any
   ListenerAddFailed = 'ListenerAddFailed', // any, string
@@ -304,6 +307,11 @@ export enum EventType {
 
   Connecting = 'Connecting', // any
   ConnectError = 'ConnectError', // string, string, string
+  ConnectorGaveUp = 'ConnectorGaveUp', // string
+
+  HolePunchStarted = 'HolePunchStarted', // number
+  HolePunchSucceeded = 'HolePunchSucceeded', // number
+  HolePunchFailed = 'HolePunchFailed', // number
 
   VpnPortalStarted = 'VpnPortalStarted', // string
   VpnPortalClientConnected = 'VpnPortalClientConnected', // string, string
@@ -315,4 +323,7 @@ export enum EventType {
   PortForwardAdded = 'PortForwardAdded', // PortForwardConfigPb
 
   ProxyCidrsUpdated = 'ProxyCidrsUpdated', // string[], string[]
+
+  RelayEnabledChanged = 'RelayEnabledChanged', // boolean
+  WokenOnDemand = 'WokenOnDemand', // string
 }
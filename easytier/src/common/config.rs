@@ -58,6 +58,7 @@ pub fn gen_default_flags() -> Flags {
         encryption_algorithm: "aes-gcm".to_string(),
         disable_sym_hole_punching: false,
         tld_dns_zone: DEFAULT_ET_DNS_ZONE.to_string(),
+        connect_on_demand: false,
     }
 }
 
@@ -459,6 +460,65 @@ impl TomlConfigLoader {
         Ok(ret)
     }
 
+    /// Like dump, but every flag and the default stun servers are written out and the
+    /// lists whose order does not matter are sorted, so configs that mean the same give
+    /// the same string. Exit nodes keep their order, it is their priority.
+    pub fn dump_normalized(&self) -> String {
+        let flags = serde_json::to_value(self.get_flags()).unwrap();
+        let mut config = self.config.lock().unwrap().clone();
+        if let serde_json::Value::Object(flags) = flags {
+            // toml integers are i64, u64::MAX means no limit and is left out like in dump
+            let mut flags: Vec<_> = flags
+                .into_iter()
+                .filter(|(_, v)| !v.as_u64().is_some_and(|x| x > i64::MAX as u64))
+                .collect();
+            flags.sort_by(|a, b| a.0.cmp(&b.0));
+            config.flags = Some(flags.into_iter().collect());
+        }
+        config.dhcp = Some(config.dhcp.unwrap_or_default());
+        config
+            .stun_servers
+            .get_or_insert_with(StunInfoCollector::get_default_servers);
+        config
+            .stun_servers_v6
+            .get_or_insert_with(StunInfoCollector::get_default_servers_v6);
+
+        if let Some(x) = config.listeners.as_mut() {
+            x.sort();
+        }
+        if let Some(x) = config.mapped_listeners.as_mut() {
+            x.sort();
+        }
+        if let Some(x) = config.peer.as_mut() {
+            x.sort_by(|a, b| a.uri.cmp(&b.uri));
+        }
+        if let Some(x) = config.proxy_network.as_mut() {
+            x.sort_by_key(|x| x.cidr.to_string());
+        }
+        if let Some(x) = config.routes.as_mut() {
+            x.sort_by_key(|x| x.to_string());
+        }
+        if let Some(x) = config.port_forward.as_mut() {
+            x.sort_by(|a, b| (&a.proto, a.bind_addr).cmp(&(&b.proto, b.bind_addr)));
+        }
+        if let Some(x) = config.tcp_whitelist.as_mut() {
+            x.sort();
+        }
+        if let Some(x) = config.udp_whitelist.as_mut() {
+            x.sort();
+        }
+        toml::to_string_pretty(&config).unwrap()
+    }
+
+    /// Hex sha256 of dump_normalized, equal for configs that mean the same.
+    pub fn fingerprint(&self) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(self.dump_normalized().as_bytes())
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect()
+    }
+
     fn gen_flags(mut flags_hashmap: serde_json::Map<String, serde_json::Value>) -> Flags {
         let default_flags_json = serde_json::to_string(&gen_default_flags()).unwrap();
         let default_flags_hashmap =
@@ -1020,6 +1080,95 @@ pub async fn load_config_from_file(
     Ok((config, control))
 }
 
+/// A key `TomlConfigLoader` reads, keys inside tables are joined with a dot. `required`
+/// keys must be given whenever their table or array entry is, `default` is what the
+/// loader uses when the key is left out, none if it stays unset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigKeyInfo {
+    pub name: String,
+    /// string, bool, integer, string_array, table or table_array
+    pub value_type: &'static str,
+    pub default: Option<String>,
+    pub required: bool,
+}
+
+// (name, value_type, default, required) of the fields of `Config` and their sub keys,
+// test_config_schema_matches_loader checks it against the struct. flags come from
+// `gen_default_flags` instead.
+const CONFIG_KEYS: &[(&str, &str, Option<&str>, bool)] = &[
+    ("netns", "string", None, false),
+    ("hostname", "string", None, false),
+    ("instance_name", "string", Some("default"), false),
+    ("instance_id", "string", None, false),
+    ("ipv4", "string", None, false),
+    ("ipv6", "string", None, false),
+    ("dhcp", "bool", Some("false"), false),
+    ("network_identity", "table", None, false),
+    (
+        "network_identity.network_name",
+        "string",
+        Some("default"),
+        true,
+    ),
+    ("network_identity.network_secret", "string", Some(""), false),
+    ("listeners", "string_array", None, false),
+    ("mapped_listeners", "string_array", None, false),
+    ("exit_nodes", "string_array", None, false),
+    ("peer", "table_array", None, false),
+    ("peer.uri", "string", None, true),
+    ("proxy_network", "table_array", None, false),
+    ("proxy_network.cidr", "string", None, true),
+    ("proxy_network.mapped_cidr", "string", None, false),
+    ("proxy_network.allow", "string_array", None, false),
+    ("vpn_portal_config", "table", None, false),
+    ("vpn_portal_config.client_cidr", "string", None, true),
+    ("vpn_portal_config.wireguard_listen", "string", None, true),
+    ("routes", "string_array", None, false),
+    ("socks5_proxy", "string", None, false),
+    ("port_forward", "table_array", None, false),
+    ("port_forward.bind_addr", "string", None, true),
+    ("port_forward.dst_addr", "string", None, true),
+    ("port_forward.proto", "string", None, true),
+    ("flags", "table", None, false),
+    ("acl", "table", None, false),
+    ("tcp_whitelist", "string_array", None, false),
+    ("udp_whitelist", "string_array", None, false),
+    ("stun_servers", "string_array", None, false),
+    ("stun_servers_v6", "string_array", None, false),
+];
+
+/// All keys `TomlConfigLoader` accepts with their types and defaults, e.g. to build a
+/// config editor. The acl table is only listed as a whole.
+pub fn config_schema() -> Vec<ConfigKeyInfo> {
+    let mut keys: Vec<ConfigKeyInfo> = CONFIG_KEYS
+        .iter()
+        .map(|(name, value_type, default, required)| ConfigKeyInfo {
+            name: name.to_string(),
+            value_type,
+            default: default.map(str::to_string),
+            required: *required,
+        })
+        .collect();
+    let serde_json::Value::Object(flags) = serde_json::to_value(gen_default_flags()).unwrap()
+    else {
+        unreachable!("flags serialize to a map");
+    };
+    keys.extend(flags.into_iter().map(|(name, value)| ConfigKeyInfo {
+        name: format!("flags.{}", name),
+        value_type: match &value {
+            serde_json::Value::Bool(_) => "bool",
+            serde_json::Value::Number(_) => "integer",
+            _ => "string",
+        },
+        default: Some(match value {
+            serde_json::Value::String(s) => s,
+            value => value.to_string(),
+        }),
+        required: false,
+    }));
+    keys
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -1060,6 +1209,56 @@ stun_servers = [
         assert_eq!(stun_servers[2], "txt:stun.easytier.cn");
     }
 
+    #[test]
+    fn test_unknown_keys_ignored() {
+        let config_str = r#"
+instance_name = "test"
+some_future_key = "value"
+
+[flags]
+enable_exit_node = true
+some_future_flag = 1
+
+[future_section]
+key = true
+"#;
+
+        let config = TomlConfigLoader::new_from_str(config_str).unwrap();
+        assert_eq!(config.get_inst_name(), "test");
+        assert!(config.get_flags().enable_exit_node);
+    }
+
+    #[test]
+    fn test_dump_normalized() {
+        let a = TomlConfigLoader::new_from_str(
+            r#"
+instance_name = "test"
+listeners = ["udp://0.0.0.0:11010", "tcp://0.0.0.0:11010"]
+
+[flags]
+mtu = 1380
+enable_encryption = true
+"#,
+        )
+        .unwrap();
+        let b = TomlConfigLoader::new_from_str(
+            r#"
+listeners = ["tcp://0.0.0.0:11010", "udp://0.0.0.0:11010"]
+instance_name = "test"
+dhcp = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(a.dump_normalized(), b.dump_normalized());
+        assert!(a.dump_normalized().contains("enable_exit_node = false"));
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint().len(), 64);
+
+        b.set_dhcp(true);
+        assert_ne!(a.dump_normalized(), b.dump_normalized());
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
     #[tokio::test]
     async fn full_example_test() {
         let config_str = r#"
@@ -1689,4 +1888,84 @@ enable_encryption = ${MIXED_ENCRYPTION}
         std::env::remove_var("MIXED_ENCRYPTION");
         std::env::remove_var("MIXED_LISTEN_PORT");
     }
+
+    #[test]
+    fn test_config_schema_matches_loader() {
+        // every field set, a new field of Config has to be added here and to CONFIG_KEYS
+        let config = Config {
+            netns: Some("ns".to_string()),
+            hostname: Some("host".to_string()),
+            instance_name: Some("inst".to_string()),
+            instance_id: Some(uuid::Uuid::new_v4()),
+            ipv4: Some("10.0.0.1/24".to_string()),
+            ipv6: Some("fd00::1/64".to_string()),
+            dhcp: Some(true),
+            network_identity: Some(NetworkIdentity::new(
+                "net".to_string(),
+                "secret".to_string(),
+            )),
+            listeners: Some(vec!["tcp://0.0.0.0:11010".parse().unwrap()]),
+            mapped_listeners: Some(vec!["tcp://1.2.3.4:11010".parse().unwrap()]),
+            exit_nodes: Some(vec!["10.0.0.2".parse().unwrap()]),
+            peer: Some(vec![PeerConfig {
+                uri: "tcp://1.2.3.4:11010".parse().unwrap(),
+            }]),
+            proxy_network: Some(vec![ProxyNetworkConfig {
+                cidr: "192.168.0.0/24".parse().unwrap(),
+                mapped_cidr: Some("10.1.0.0/24".parse().unwrap()),
+                allow: Some(vec!["tcp".to_string()]),
+            }]),
+            vpn_portal_config: Some(VpnPortalConfig {
+                client_cidr: "10.14.0.0/24".parse().unwrap(),
+                wireguard_listen: "0.0.0.0:11013".parse().unwrap(),
+            }),
+            routes: Some(vec!["192.168.1.0/24".parse().unwrap()]),
+            socks5_proxy: Some("socks5://0.0.0.0:1080".parse().unwrap()),
+            port_forward: Some(vec![PortForwardConfig {
+                bind_addr: "0.0.0.0:80".parse().unwrap(),
+                dst_addr: "10.0.0.2:80".parse().unwrap(),
+                proto: "tcp".to_string(),
+            }]),
+            flags: Some(serde_json::Map::new()),
+            flags_struct: None,
+            acl: Some(Acl::default()),
+            tcp_whitelist: Some(vec!["80".to_string()]),
+            udp_whitelist: Some(vec!["53".to_string()]),
+            stun_servers: Some(vec!["stun.l.google.com:19302".to_string()]),
+            stun_servers_v6: Some(vec!["stun.l.google.com:19302".to_string()]),
+        };
+        let value = toml::Value::try_from(&config).unwrap();
+        let mut names = std::collections::BTreeSet::new();
+        for (key, value) in value.as_table().unwrap() {
+            names.insert(key.clone());
+            if key == "flags" || key == "acl" {
+                continue;
+            }
+            let table = match value {
+                toml::Value::Table(table) => Some(table),
+                toml::Value::Array(array) => array.first().and_then(|x| x.as_table()),
+                _ => None,
+            };
+            for sub in table.into_iter().flat_map(|x| x.keys()) {
+                names.insert(format!("{}.{}", key, sub));
+            }
+        }
+
+        let schema = config_schema();
+        let listed: std::collections::BTreeSet<_> = schema
+            .iter()
+            .map(|x| x.name.clone())
+            .filter(|x| !x.starts_with("flags."))
+            .collect();
+        assert_eq!(names, listed);
+
+        let mtu = schema.iter().find(|x| x.name == "flags.mtu").unwrap();
+        assert_eq!(mtu.value_type, "integer");
+        assert_eq!(mtu.default.as_deref(), Some("1380"));
+        let protocol = schema
+            .iter()
+            .find(|x| x.name == "flags.default_protocol")
+            .unwrap();
+        assert_eq!(protocol.default.as_deref(), Some("tcp"));
+    }
 }
@@ -29,6 +29,12 @@ define_global_var!(MAX_DIRECT_CONNS_PER_PEER_IN_FOREIGN_NETWORK, u32, 3);
 
 define_global_var!(DIRECT_CONNECT_TO_PUBLIC_SERVER, bool, true);
 
+define_global_var!(
+    PEER_CONN_APPROVER,
+    Option<crate::peers::PeerConnApprover>,
+    None
+);
+
 pub const UDP_HOLE_PUNCH_CONNECTOR_SERVICE_ID: u32 = 2;
 
 pub const WIN_SERVICE_WORK_DIR_REG_KEY: &str = "SOFTWARE\\EasyTier\\Service\\WorkDir";
@@ -39,3 +45,43 @@ pub const EASYTIER_VERSION: &str = git_version::git_version!(
     suffix = "",
     fallback = env!("CARGO_PKG_VERSION")
 );
+
+pub const EASYTIER_GIT_HASH: &str = git_version::git_version!(
+    args = ["--abbrev=8", "--always", "--dirty=~"],
+    fallback = "unknown"
+);
+
+pub fn get_enabled_features() -> Vec<&'static str> {
+    let mut r = vec![];
+    if cfg!(feature = "tun") {
+        r.push("tun");
+    }
+    if cfg!(feature = "websocket") {
+        r.push("websocket");
+    }
+    if cfg!(feature = "wireguard") {
+        r.push("wireguard");
+    }
+    if cfg!(feature = "quic") {
+        r.push("quic");
+    }
+    if cfg!(feature = "smoltcp") {
+        r.push("smoltcp");
+    }
+    if cfg!(feature = "socks5") {
+        r.push("socks5");
+    }
+    if cfg!(feature = "aes-gcm") {
+        r.push("aes-gcm");
+    }
+    if cfg!(feature = "openssl-crypto") {
+        r.push("openssl-crypto");
+    }
+    if cfg!(feature = "mimalloc") {
+        r.push("mimalloc");
+    }
+    if cfg!(feature = "jemalloc") {
+        r.push("jemalloc");
+    }
+    r
+}
@@ -1,3 +1,4 @@
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
@@ -46,6 +47,32 @@ pub static RESOLVER: Lazy<Arc<Resolver<GenericConnector<TokioRuntimeProvider>>>>
         Arc::new(builder.build())
     });
 
+tokio::task_local! {
+    static SCOPED_RESOLVER: Arc<TokioResolver>;
+}
+
+/// resolver using only the given name servers, e.g. when the system dns is broken.
+pub fn build_resolver(name_servers: &[SocketAddr]) -> Arc<TokioResolver> {
+    let mut cfg = ResolverConfig::new();
+    for addr in name_servers {
+        cfg.add_name_server(NameServerConfig::new(*addr, Protocol::Udp));
+    }
+    let mut opt = ResolverOpts::default();
+    opt.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+    let builder = TokioResolver::builder_with_config(cfg, TokioConnectionProvider::default())
+        .with_options(opt);
+    Arc::new(builder.build())
+}
+
+/// hostnames looked up by `socket_addrs` within `f` only go to `resolver`, neither the
+/// system resolver nor the default name servers are asked.
+pub async fn with_resolver<F: Future>(resolver: Option<Arc<TokioResolver>>, f: F) -> F::Output {
+    match resolver {
+        Some(resolver) => SCOPED_RESOLVER.scope(resolver, f).await,
+        None => f.await,
+    }
+}
+
 pub async fn resolve_txt_record(domain_name: &str) -> Result<String, Error> {
     let r = RESOLVER.clone();
     let response = r
@@ -92,6 +119,14 @@ pub async fn socket_addrs(
     }
     let host = host.to_string();
 
+    if let Ok(resolver) = SCOPED_RESOLVER.try_with(|x| x.clone()) {
+        let ret = resolver
+            .lookup_ip(&host)
+            .await
+            .with_context(|| format!("dns lookup_ip failed, host: {}, port: {}", host, port))?;
+        return Ok(ret.iter().map(|ip| SocketAddr::new(ip, port)).collect());
+    }
+
     if ALLOW_USE_SYSTEM_DNS_RESOLVER.load(std::sync::atomic::Ordering::Relaxed) {
         let socket_addr = format!("{}:{}", host, port);
         match lookup_host(socket_addr).await {
@@ -140,4 +175,19 @@ mod tests {
         assert_eq!(2, addrs.len(), "addrs: {:?}", addrs);
         println!("addrs2: {:?}", addrs);
     }
+
+    #[tokio::test]
+    async fn test_scoped_resolver() {
+        let url = url::Url::parse("tcp://github-ci-test.easytier.cn:80").unwrap();
+        let resolver = build_resolver(&["223.5.5.5:53".parse().unwrap()]);
+        let addrs = with_resolver(Some(resolver), socket_addrs(&url, || Some(80)))
+            .await
+            .unwrap();
+        assert_eq!(2, addrs.len(), "addrs: {:?}", addrs);
+
+        // no name server answers, the system resolver must not be used instead
+        let resolver = build_resolver(&["127.0.0.1:9".parse().unwrap()]);
+        let ret = with_resolver(Some(resolver), socket_addrs(&url, || Some(80))).await;
+        assert!(ret.is_err(), "ret: {:?}", ret);
+    }
 }
@@ -1,5 +1,7 @@
 use std::collections::hash_map::DefaultHasher;
-use std::net::IpAddr;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 use std::{
     hash::Hasher,
     sync::{Arc, Mutex},
@@ -11,10 +13,11 @@ use crate::common::token_bucket::TokenBucketManager;
 use crate::peers::acl_filter::AclFilter;
 use crate::proto::acl::GroupIdentity;
 use crate::proto::api::config::InstanceConfigPatch;
-use crate::proto::api::instance::PeerConnInfo;
+use crate::proto::api::instance::{ListenerStatus, PeerConnInfo};
 use crate::proto::common::{PeerFeatureFlag, PortForwardConfigPb};
 use crate::proto::peer_rpc::PeerGroupInfo;
 use crossbeam::atomic::AtomicCell;
+use hickory_resolver::TokioResolver;
 
 use super::{
     config::{ConfigLoader, Flags},
@@ -26,6 +29,153 @@ use super::{
 
 pub type NetworkIdentity = crate::common::config::NetworkIdentity;
 
+// room for the cipher tail, subtracted from the configured mtu when encryption is on
+const ENCRYPTION_MTU_OVERHEAD: u32 = 20;
+
+/// max summed length of the keys and values a peer advertises as metadata, it is sent
+/// with every route sync.
+pub const MAX_PEER_METADATA_SIZE: usize = 1024;
+
+pub fn peer_metadata_size(metadata: &BTreeMap<String, String>) -> usize {
+    metadata.iter().map(|(k, v)| k.len() + v.len()).sum()
+}
+
+/// thresholds for a proxy cidr advertised by more than one peer. a path exceeding them
+/// is only used when no path to another peer advertising the cidr stays within them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteFailoverPolicy {
+    pub max_latency_ms: Option<u32>,
+    pub max_loss_percent: Option<u32>,
+}
+
+/// destinations this node may send to through the overlay, a filter applied before the
+/// route table is looked up. denied cidrs win over allowed ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutboundPolicy {
+    /// empty allows every destination that is not denied
+    pub allowed: Vec<cidr::IpCidr>,
+    pub denied: Vec<cidr::IpCidr>,
+}
+
+impl OutboundPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.allowed.is_empty() && self.denied.is_empty()
+    }
+
+    pub fn allows(&self, ip: &IpAddr) -> bool {
+        !self.denied.iter().any(|x| x.contains(ip))
+            && (self.allowed.is_empty() || self.allowed.iter().any(|x| x.contains(ip)))
+    }
+}
+
+/// latency vs throughput tuning of routing and kcp streams, see `kcp_proxy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportProfile {
+    /// route by latency, flush kcp streams every 5ms
+    LowLatency,
+    /// route by hops, flush kcp streams every 20ms so segments are fuller
+    Throughput,
+    /// route by hops so paths do not flap with latency, flush kcp streams every 10ms
+    Reliable,
+}
+
+impl TransportProfile {
+    pub fn latency_first(&self) -> bool {
+        matches!(self, TransportProfile::LowLatency)
+    }
+}
+
+impl std::str::FromStr for TransportProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "low_latency" => Ok(TransportProfile::LowLatency),
+            "throughput" => Ok(TransportProfile::Throughput),
+            "reliable" => Ok(TransportProfile::Reliable),
+            _ => Err(anyhow::anyhow!("unknown transport profile: {}", s)),
+        }
+    }
+}
+
+/// which address families of a peer endpoint are tried, and in which order, when it
+/// resolves to both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressFamilyPreference {
+    Ipv4Only,
+    Ipv6Only,
+    #[default]
+    PreferIpv4,
+    PreferIpv6,
+}
+
+impl AddressFamilyPreference {
+    pub fn allow_ipv4(&self) -> bool {
+        !matches!(self, AddressFamilyPreference::Ipv6Only)
+    }
+
+    pub fn allow_ipv6(&self) -> bool {
+        !matches!(self, AddressFamilyPreference::Ipv4Only)
+    }
+
+    pub fn prefer_ipv6(&self) -> bool {
+        matches!(
+            self,
+            AddressFamilyPreference::Ipv6Only | AddressFamilyPreference::PreferIpv6
+        )
+    }
+}
+
+impl std::str::FromStr for AddressFamilyPreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ipv4_only" => Ok(AddressFamilyPreference::Ipv4Only),
+            "ipv6_only" => Ok(AddressFamilyPreference::Ipv6Only),
+            "prefer_ipv4" => Ok(AddressFamilyPreference::PreferIpv4),
+            "prefer_ipv6" => Ok(AddressFamilyPreference::PreferIpv6),
+            _ => Err(anyhow::anyhow!("unknown address family preference: {}", s)),
+        }
+    }
+}
+
+/// which transport the data path to a peer prefers when it has conns over several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransportPreference {
+    #[default]
+    Auto,
+    Udp,
+    Tcp,
+}
+
+impl TransportPreference {
+    /// whether a conn of `tunnel_type` is of the preferred transport, every conn is for auto.
+    pub fn matches(&self, tunnel_type: &str) -> bool {
+        match self {
+            TransportPreference::Auto => true,
+            TransportPreference::Udp => matches!(tunnel_type, "udp" | "wg" | "quic"),
+            // faketcp looks like tcp on the wire, which is what a udp throttle goes by
+            TransportPreference::Tcp => {
+                matches!(tunnel_type, "tcp" | "ws" | "wss") || tunnel_type.starts_with("faketcp")
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for TransportPreference {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TransportPreference::Auto),
+            "udp" => Ok(TransportPreference::Udp),
+            "tcp" => Ok(TransportPreference::Tcp),
+            _ => Err(anyhow::anyhow!("unknown transport preference: {}", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum GlobalCtxEvent {
     TunDeviceReady(String),
@@ -33,8 +183,11 @@ pub enum GlobalCtxEvent {
 
     PeerAdded(PeerId),
     PeerRemoved(PeerId),
+    FirstPeerConnected(PeerId, u64), // (peer, ms since the instance started)
     PeerConnAdded(PeerConnInfo),
     PeerConnRemoved(PeerConnInfo),
+    PeerBlocked(PeerId, u64), // (peer, duration ms)
+    PeerUnblocked(PeerId),
 
     ListenerAdded(url::Url),
     ListenerAddFailed(url::Url, String), // (url, error message)
@@ -44,6 +197,11 @@ pub enum GlobalCtxEvent {
 
     Connecting(url::Url),
     ConnectError(String, String, String), // (dst, ip version, error message)
+    ConnectorGaveUp(String),              // (dst), after max reconnect attempts
+
+    HolePunchStarted(PeerId),
+    HolePunchSucceeded(PeerId),
+    HolePunchFailed(PeerId), // retried after a backoff, unless the task was cancelled
 
     VpnPortalStarted(String),                    // (portal)
     VpnPortalClientConnected(String, String),    // (portal, client ip)
@@ -55,6 +213,8 @@ pub enum GlobalCtxEvent {
     PortForwardAdded(PortForwardConfigPb),
 
     ConfigPatched(InstanceConfigPatch),
+    RelayEnabledChanged(bool),
+    WokenOnDemand(String), // (what woke the instance: traffic or forced)
 
     ProxyCidrsUpdated(Vec<cidr::Ipv4Cidr>, Vec<cidr::Ipv4Cidr>), // (added, removed)
 }
@@ -79,9 +239,13 @@ pub struct GlobalCtx {
 
     hostname: Mutex<String>,
 
+    peer_metadata: Mutex<BTreeMap<String, String>>,
+
     stun_info_collection: Mutex<Arc<dyn StunInfoCollectorTrait>>,
 
     running_listeners: Mutex<Vec<url::Url>>,
+    // outcome of binding each configured listener, by the url it is configured with
+    listener_statuses: Mutex<Vec<ListenerStatus>>,
 
     enable_exit_node: bool,
     proxy_forward_by_system: bool,
@@ -92,6 +256,43 @@ pub struct GlobalCtx {
 
     quic_proxy_port: AtomicCell<Option<u16>>,
 
+    bind_source_ip: AtomicCell<Option<IpAddr>>,
+
+    observer_mode: AtomicCell<bool>,
+
+    relay_enabled: AtomicCell<bool>,
+
+    // started with connect_on_demand and no packet sent yet, the peers are not connected
+    dormant: AtomicCell<bool>,
+    dormant_notifier: tokio::sync::Notify,
+
+    lan_discovery: AtomicCell<bool>,
+    // answering the throughput tests of other peers, see PeerThroughputRpc
+    serve_throughput_tests: AtomicCell<bool>,
+    // peers announced by lan discovery, and when they were seen last
+    lan_peers: Mutex<HashMap<PeerId, std::time::Instant>>,
+
+    preferred_relay: AtomicCell<Option<PeerId>>,
+
+    keepalive_interval: AtomicCell<Option<Duration>>,
+
+    route_failover_policies: Mutex<HashMap<cidr::IpCidr, RouteFailoverPolicy>>,
+
+    outbound_policy: arc_swap::ArcSwapOption<OutboundPolicy>,
+
+    max_peers: AtomicCell<Option<u32>>,
+
+    max_reconnect_attempts: AtomicCell<Option<u32>>,
+
+    transport_profile: AtomicCell<Option<TransportProfile>>,
+
+    address_family_preference: AtomicCell<AddressFamilyPreference>,
+
+    // only peers not on auto
+    peer_transport_preferences: Mutex<HashMap<PeerId, TransportPreference>>,
+
+    dns_resolver: Mutex<Option<Arc<TokioResolver>>>,
+
     token_bucket_manager: TokenBucketManager,
 
     stats_manager: Arc<StatsManager>,
@@ -169,9 +370,12 @@ impl GlobalCtx {
 
             hostname: Mutex::new(hostname),
 
+            peer_metadata: Mutex::new(BTreeMap::new()),
+
             stun_info_collection: Mutex::new(stun_info_collector),
 
             running_listeners: Mutex::new(Vec::new()),
+            listener_statuses: Mutex::new(Vec::new()),
 
             enable_exit_node,
             proxy_forward_by_system,
@@ -181,6 +385,37 @@ impl GlobalCtx {
             feature_flags: AtomicCell::new(feature_flags),
             quic_proxy_port: AtomicCell::new(None),
 
+            bind_source_ip: AtomicCell::new(None),
+
+            observer_mode: AtomicCell::new(false),
+
+            relay_enabled: AtomicCell::new(true),
+
+            dormant: AtomicCell::new(config_fs.get_flags().connect_on_demand),
+            dormant_notifier: tokio::sync::Notify::new(),
+
+            lan_discovery: AtomicCell::new(false),
+            serve_throughput_tests: AtomicCell::new(false),
+            lan_peers: Mutex::new(HashMap::new()),
+
+            preferred_relay: AtomicCell::new(None),
+
+            keepalive_interval: AtomicCell::new(None),
+
+            route_failover_policies: Mutex::new(HashMap::new()),
+
+            outbound_policy: arc_swap::ArcSwapOption::empty(),
+
+            max_peers: AtomicCell::new(None),
+            max_reconnect_attempts: AtomicCell::new(None),
+
+            transport_profile: AtomicCell::new(None),
+
+            address_family_preference: AtomicCell::new(AddressFamilyPreference::default()),
+            peer_transport_preferences: Mutex::new(HashMap::new()),
+
+            dns_resolver: Mutex::new(None),
+
             token_bucket_manager: TokenBucketManager::new(),
 
             stats_manager: Arc::new(StatsManager::new()),
@@ -277,6 +512,25 @@ impl GlobalCtx {
         *self.hostname.lock().unwrap() = hostname;
     }
 
+    pub fn get_peer_metadata(&self) -> BTreeMap<String, String> {
+        self.peer_metadata.lock().unwrap().clone()
+    }
+
+    /// key-values advertised to the other peers with the route info, replaces the previous
+    /// ones. fails if they are larger than `MAX_PEER_METADATA_SIZE`.
+    pub fn set_peer_metadata(&self, metadata: BTreeMap<String, String>) -> anyhow::Result<()> {
+        let size = peer_metadata_size(&metadata);
+        if size > MAX_PEER_METADATA_SIZE {
+            return Err(anyhow::anyhow!(
+                "peer metadata of {} bytes exceeds the limit of {} bytes",
+                size,
+                MAX_PEER_METADATA_SIZE
+            ));
+        }
+        *self.peer_metadata.lock().unwrap() = metadata;
+        Ok(())
+    }
+
     pub fn get_stun_info_collector(&self) -> Arc<dyn StunInfoCollectorTrait> {
         self.stun_info_collection.lock().unwrap().clone()
     }
@@ -303,6 +557,39 @@ impl GlobalCtx {
         }
     }
 
+    /// Records whether the listener configured as `requested` is bound, replacing what was
+    /// recorded for it before, e.g. when a retry succeeds. The internal ring listener is
+    /// left out.
+    pub fn set_listener_status(&self, requested: &url::Url, result: Result<&url::Url, String>) {
+        if requested.scheme() == "ring" {
+            return;
+        }
+        let status = match result {
+            Ok(bound) => ListenerStatus {
+                requested: requested.to_string(),
+                bound: bound.to_string(),
+                error: String::new(),
+            },
+            Err(error) => ListenerStatus {
+                requested: requested.to_string(),
+                bound: String::new(),
+                error,
+            },
+        };
+        let mut statuses = self.listener_statuses.lock().unwrap();
+        match statuses
+            .iter_mut()
+            .find(|x| x.requested == status.requested)
+        {
+            Some(x) => *x = status,
+            None => statuses.push(status),
+        }
+    }
+
+    pub fn get_listener_statuses(&self) -> Vec<ListenerStatus> {
+        self.listener_statuses.lock().unwrap().clone()
+    }
+
     pub fn is_port_in_running_listeners(&self, port: u16, is_udp: bool) -> bool {
         let check_proto = |listener_proto: &str| {
             let listener_is_udp = matches!(listener_proto, "udp" | "wg");
@@ -327,6 +614,17 @@ impl GlobalCtx {
         self.config.set_flags(flags);
     }
 
+    /// The mtu of packets sent into the virtual network. The configured mtu already
+    /// leaves room for the tunnel headers, encryption also needs room for its tail.
+    pub fn get_overlay_mtu(&self) -> u32 {
+        let flags = self.get_flags();
+        if flags.enable_encryption {
+            flags.mtu.saturating_sub(ENCRYPTION_MTU_OVERHEAD)
+        } else {
+            flags.mtu
+        }
+    }
+
     pub fn get_128_key(&self) -> [u8; 16] {
         let mut key = [0u8; 16];
         let secret = self
@@ -396,6 +694,244 @@ impl GlobalCtx {
         self.quic_proxy_port.store(port);
     }
 
+    pub fn get_bind_source_ip(&self) -> Option<IpAddr> {
+        self.bind_source_ip.load()
+    }
+
+    pub fn set_bind_source_ip(&self, ip: Option<IpAddr>) {
+        self.bind_source_ip.store(ip);
+    }
+
+    pub fn is_observer_mode(&self) -> bool {
+        self.observer_mode.load()
+    }
+
+    /// observer mode joins the network but never relays traffic for other peers
+    /// and never advertises proxy cidrs, so other nodes won't route through it.
+    pub fn set_observer_mode(&self, enabled: bool) {
+        self.observer_mode.store(enabled);
+        self.update_avoid_relay_data();
+    }
+
+    pub fn is_relay_enabled(&self) -> bool {
+        self.relay_enabled.load()
+    }
+
+    /// with relay disabled the node stays a leaf, traffic between other peers is never
+    /// forwarded through it. unlike observer mode its proxy cidrs are still advertised.
+    pub fn set_relay_enabled(&self, enabled: bool) {
+        if self.relay_enabled.swap(enabled) == enabled {
+            return;
+        }
+        self.update_avoid_relay_data();
+        self.issue_event(GlobalCtxEvent::RelayEnabledChanged(enabled));
+    }
+
+    /// whether packets from other peers to other peers may be forwarded by this node
+    pub fn can_forward_for_peers(&self) -> bool {
+        self.is_relay_enabled() && !self.is_observer_mode()
+    }
+
+    fn update_avoid_relay_data(&self) {
+        let mut f = self.get_feature_flags();
+        f.avoid_relay_data = !self.can_forward_for_peers()
+            || self
+                .check_network_in_whitelist(&self.get_network_name())
+                .is_err();
+        self.set_feature_flags(f);
+    }
+
+    #[inline]
+    pub fn is_dormant(&self) -> bool {
+        self.dormant.load()
+    }
+
+    /// Ends the dormancy of an instance started with connect_on_demand, the connectors
+    /// connect from now on. `reason` goes into the event, only the first call issues it.
+    pub fn wake_up(&self, reason: &str) {
+        if self.dormant.swap(false) {
+            tracing::info!(reason, "woken on demand, connecting to peers");
+            self.dormant_notifier.notify_waiters();
+            self.issue_event(GlobalCtxEvent::WokenOnDemand(reason.to_string()));
+        }
+    }
+
+    pub async fn wait_until_awake(&self) {
+        loop {
+            let notified = self.dormant_notifier.notified();
+            if !self.is_dormant() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub fn is_lan_discovery_enabled(&self) -> bool {
+        self.lan_discovery.load()
+    }
+
+    /// announces this node on the local network by mdns and connects to the peers of the
+    /// same network announcing themselves there.
+    pub fn set_lan_discovery(&self, enabled: bool) {
+        self.lan_discovery.store(enabled);
+        if !enabled {
+            self.lan_peers.lock().unwrap().clear();
+        }
+    }
+
+    pub fn serves_throughput_tests(&self) -> bool {
+        self.serve_throughput_tests.load()
+    }
+
+    /// lets the other peers run throughput tests against this node, off by default as
+    /// each test makes the node send and receive data for up to a minute.
+    pub fn set_serve_throughput_tests(&self, enabled: bool) {
+        self.serve_throughput_tests.store(enabled);
+    }
+
+    pub fn is_lan_peer(&self, peer_id: PeerId) -> bool {
+        self.lan_peers.lock().unwrap().contains_key(&peer_id)
+    }
+
+    pub fn touch_lan_peer(&self, peer_id: PeerId) {
+        self.lan_peers
+            .lock()
+            .unwrap()
+            .insert(peer_id, std::time::Instant::now());
+    }
+
+    /// forgets the lan peers not seen for `timeout`
+    pub fn expire_lan_peers(&self, timeout: Duration) {
+        self.lan_peers
+            .lock()
+            .unwrap()
+            .retain(|_, seen| seen.elapsed() < timeout);
+    }
+
+    pub fn get_preferred_relay(&self) -> Option<PeerId> {
+        self.preferred_relay.load()
+    }
+
+    pub fn set_preferred_relay(&self, peer_id: Option<PeerId>) {
+        self.preferred_relay.store(peer_id);
+    }
+
+    /// max interval between keepalive pings on an idle peer conn, none for the default backoff.
+    pub fn get_keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval.load()
+    }
+
+    pub fn set_keepalive_interval(&self, interval: Option<Duration>) {
+        self.keepalive_interval.store(interval);
+    }
+
+    pub fn get_route_failover_policies(&self) -> HashMap<cidr::IpCidr, RouteFailoverPolicy> {
+        self.route_failover_policies.lock().unwrap().clone()
+    }
+
+    pub fn set_route_failover_policy(
+        &self,
+        cidr: cidr::IpCidr,
+        policy: Option<RouteFailoverPolicy>,
+    ) {
+        let mut policies = self.route_failover_policies.lock().unwrap();
+        match policy {
+            Some(policy) => policies.insert(cidr, policy),
+            None => policies.remove(&cidr),
+        };
+    }
+
+    pub fn clear_route_failover_policies(&self) {
+        self.route_failover_policies.lock().unwrap().clear();
+    }
+
+    pub fn get_outbound_policy(&self) -> Option<OutboundPolicy> {
+        self.outbound_policy.load().as_deref().cloned()
+    }
+
+    /// an empty policy removes the filter
+    pub fn set_outbound_policy(&self, policy: OutboundPolicy) {
+        self.outbound_policy
+            .store(Some(policy).filter(|x| !x.is_empty()).map(Arc::new));
+    }
+
+    /// checked for every packet sent to the overlay, lock free
+    pub fn is_outbound_allowed(&self, ip: &IpAddr) -> bool {
+        match self.outbound_policy.load().as_ref() {
+            Some(policy) => policy.allows(ip),
+            None => true,
+        }
+    }
+
+    pub fn get_max_peers(&self) -> Option<u32> {
+        self.max_peers.load()
+    }
+
+    /// cap of directly connected peers, the rest are reached through relays.
+    pub fn set_max_peers(&self, max_peers: Option<u32>) {
+        self.max_peers.store(max_peers);
+    }
+
+    pub fn get_max_reconnect_attempts(&self) -> Option<u32> {
+        self.max_reconnect_attempts.load()
+    }
+
+    /// failed reconnects in a row after which a connector is given up instead of retried.
+    pub fn set_max_reconnect_attempts(&self, max: Option<u32>) {
+        self.max_reconnect_attempts.store(max);
+    }
+
+    pub fn get_transport_profile(&self) -> Option<TransportProfile> {
+        self.transport_profile.load()
+    }
+
+    /// also overrides the latency_first flag of the config, only `LowLatency` routes by
+    /// latency. kcp streams opened afterwards use the new tuning.
+    pub fn set_transport_profile(&self, profile: TransportProfile) {
+        let mut flags = self.get_flags();
+        flags.latency_first = profile.latency_first();
+        self.set_flags(flags);
+        self.transport_profile.store(Some(profile));
+    }
+
+    pub fn get_address_family_preference(&self) -> AddressFamilyPreference {
+        self.address_family_preference.load()
+    }
+
+    /// used by connectors on their next attempt, the enable_ipv6 flag still applies.
+    pub fn set_address_family_preference(&self, preference: AddressFamilyPreference) {
+        self.address_family_preference.store(preference);
+    }
+
+    pub fn get_peer_transport_preference(&self, peer_id: PeerId) -> TransportPreference {
+        self.peer_transport_preferences
+            .lock()
+            .unwrap()
+            .get(&peer_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// kept for the peer across reconnects, the peer manager applies it to a connected peer.
+    pub fn set_peer_transport_preference(&self, peer_id: PeerId, preference: TransportPreference) {
+        let mut preferences = self.peer_transport_preferences.lock().unwrap();
+        if preference == TransportPreference::Auto {
+            preferences.remove(&peer_id);
+        } else {
+            preferences.insert(peer_id, preference);
+        }
+    }
+
+    pub fn get_dns_resolver(&self) -> Option<Arc<TokioResolver>> {
+        self.dns_resolver.lock().unwrap().clone()
+    }
+
+    /// name servers peer hostnames are resolved with by connectors, empty to use the system ones.
+    pub fn set_dns_servers(&self, servers: Vec<SocketAddr>) {
+        *self.dns_resolver.lock().unwrap() =
+            (!servers.is_empty()).then(|| super::dns::build_resolver(&servers));
+    }
+
     pub fn token_bucket_manager(&self) -> &TokenBucketManager {
         &self.token_bucket_manager
     }
@@ -488,6 +1024,177 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_listener_statuses() {
+        let global_ctx = GlobalCtx::new(TomlConfigLoader::default());
+        let tcp: url::Url = "tcp://0.0.0.0:0".parse().unwrap();
+        let udp: url::Url = "udp://0.0.0.0:11010".parse().unwrap();
+        global_ctx.set_listener_status(&"ring://x".parse().unwrap(), Ok(&tcp));
+        global_ctx.set_listener_status(&tcp, Err("permission denied".to_string()));
+        global_ctx.set_listener_status(&udp, Ok(&udp));
+        let statuses = global_ctx.get_listener_statuses();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].error, "permission denied");
+        assert!(statuses[0].bound.is_empty());
+
+        // a retry succeeded
+        let bound: url::Url = "tcp://0.0.0.0:40000".parse().unwrap();
+        global_ctx.set_listener_status(&tcp, Ok(&bound));
+        let statuses = global_ctx.get_listener_statuses();
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].bound, bound.to_string());
+        assert!(statuses[0].error.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_relay_enabled() {
+        let global_ctx = GlobalCtx::new(TomlConfigLoader::default());
+        let mut subscriber = global_ctx.subscribe();
+        assert!(global_ctx.can_forward_for_peers());
+
+        global_ctx.set_relay_enabled(false);
+        // unchanged, no second event
+        global_ctx.set_relay_enabled(false);
+        assert!(!global_ctx.can_forward_for_peers());
+        assert!(global_ctx.get_feature_flags().avoid_relay_data);
+
+        // still a leaf while either of them is set
+        global_ctx.set_observer_mode(true);
+        global_ctx.set_relay_enabled(true);
+        assert!(!global_ctx.can_forward_for_peers());
+        global_ctx.set_observer_mode(false);
+        assert!(global_ctx.can_forward_for_peers());
+        assert!(!global_ctx.get_feature_flags().avoid_relay_data);
+
+        assert_eq!(
+            subscriber.recv().await.unwrap(),
+            GlobalCtxEvent::RelayEnabledChanged(false)
+        );
+        assert_eq!(
+            subscriber.recv().await.unwrap(),
+            GlobalCtxEvent::RelayEnabledChanged(true)
+        );
+        assert!(subscriber.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wake_up() {
+        let config = TomlConfigLoader::default();
+        let mut flags = config.get_flags();
+        flags.connect_on_demand = true;
+        config.set_flags(flags);
+        let global_ctx = Arc::new(GlobalCtx::new(config));
+        let mut subscriber = global_ctx.subscribe();
+        assert!(global_ctx.is_dormant());
+
+        let waiter = tokio::spawn({
+            let global_ctx = global_ctx.clone();
+            async move { global_ctx.wait_until_awake().await }
+        });
+        global_ctx.wake_up("forced");
+        global_ctx.wake_up("traffic");
+        waiter.await.unwrap();
+        assert!(!global_ctx.is_dormant());
+        assert_eq!(
+            subscriber.recv().await.unwrap(),
+            GlobalCtxEvent::WokenOnDemand("forced".to_string())
+        );
+        assert!(subscriber.try_recv().is_err());
+
+        assert!(!GlobalCtx::new(TomlConfigLoader::default()).is_dormant());
+    }
+
+    #[test]
+    fn test_outbound_policy() {
+        let global_ctx = GlobalCtx::new(TomlConfigLoader::default());
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let cidrs = |s: &[&str]| s.iter().map(|x| x.parse().unwrap()).collect();
+        assert!(global_ctx.is_outbound_allowed(&ip("8.8.8.8")));
+
+        global_ctx.set_outbound_policy(OutboundPolicy {
+            allowed: cidrs(&["10.0.0.0/8", "fd00::/8"]),
+            denied: cidrs(&["10.1.0.0/16"]),
+        });
+        assert!(global_ctx.is_outbound_allowed(&ip("10.2.3.4")));
+        assert!(global_ctx.is_outbound_allowed(&ip("fd00::1")));
+        assert!(!global_ctx.is_outbound_allowed(&ip("10.1.2.3")));
+        assert!(!global_ctx.is_outbound_allowed(&ip("8.8.8.8")));
+
+        // only denied, everything else goes
+        global_ctx.set_outbound_policy(OutboundPolicy {
+            allowed: vec![],
+            denied: cidrs(&["192.168.0.0/16"]),
+        });
+        assert!(!global_ctx.is_outbound_allowed(&ip("192.168.1.1")));
+        assert!(global_ctx.is_outbound_allowed(&ip("8.8.8.8")));
+
+        global_ctx.set_outbound_policy(OutboundPolicy::default());
+        assert!(global_ctx.get_outbound_policy().is_none());
+        assert!(global_ctx.is_outbound_allowed(&ip("192.168.1.1")));
+    }
+
+    #[test]
+    fn test_transport_profile() {
+        let config = TomlConfigLoader::default();
+        let mut flags = config.get_flags();
+        flags.latency_first = true;
+        config.set_flags(flags);
+        let global_ctx = GlobalCtx::new(config);
+        assert!(global_ctx.get_transport_profile().is_none());
+        assert!(global_ctx.latency_first());
+        assert!("fast".parse::<TransportProfile>().is_err());
+
+        global_ctx.set_transport_profile("low_latency".parse().unwrap());
+        assert!(global_ctx.latency_first());
+        global_ctx.set_transport_profile("throughput".parse().unwrap());
+        assert!(!global_ctx.latency_first());
+        assert_eq!(
+            global_ctx.get_transport_profile(),
+            Some(TransportProfile::Throughput)
+        );
+    }
+
+    #[test]
+    fn test_transport_preference() {
+        let global_ctx = GlobalCtx::new(TomlConfigLoader::default());
+        assert_eq!(
+            global_ctx.get_peer_transport_preference(1),
+            TransportPreference::Auto
+        );
+        assert!("quic".parse::<TransportPreference>().is_err());
+
+        global_ctx.set_peer_transport_preference(1, "tcp".parse().unwrap());
+        let preference = global_ctx.get_peer_transport_preference(1);
+        assert!(preference.matches("tcp"));
+        assert!(preference.matches("faketcp_pnet"));
+        assert!(!preference.matches("udp"));
+        assert_eq!(
+            global_ctx.get_peer_transport_preference(2),
+            TransportPreference::Auto
+        );
+
+        global_ctx.set_peer_transport_preference(1, TransportPreference::Auto);
+        assert!(global_ctx
+            .peer_transport_preferences
+            .lock()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_overlay_mtu() {
+        let global_ctx = GlobalCtx::new(TomlConfigLoader::default());
+        let mut flags = global_ctx.get_flags();
+        flags.mtu = 1400;
+        flags.enable_encryption = true;
+        global_ctx.set_flags(flags.clone());
+        assert_eq!(global_ctx.get_overlay_mtu(), 1380);
+
+        flags.enable_encryption = false;
+        global_ctx.set_flags(flags);
+        assert_eq!(global_ctx.get_overlay_mtu(), 1400);
+    }
+
     pub fn get_mock_global_ctx_with_network(
         network_identy: Option<NetworkIdentity>,
     ) -> ArcGlobalCtx {
@@ -322,3 +322,79 @@ impl IPCollector {
         ret
     }
 }
+
+/// mtu of the ipv4 path to `remote` as the kernel knows it: the mtu of the outgoing
+/// interface, lowered by the fragmentation needed replies seen for the destination so far.
+/// nothing is sent. none for ipv6 and on other systems.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_path_mtu(remote: &std::net::SocketAddr) -> Option<u32> {
+    if !remote.is_ipv4() {
+        return None;
+    }
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect(remote).ok()?;
+    nix::sys::socket::getsockopt(&socket, nix::sys::socket::sockopt::IpMtu)
+        .ok()
+        .map(|x| x as u32)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn get_path_mtu(_remote: &std::net::SocketAddr) -> Option<u32> {
+    None
+}
+
+/// The interface behind a tun fd as the kernel reports it.
+#[derive(Debug, Clone, Default)]
+pub struct TunFdInfo {
+    pub ifname: String,
+    pub mtu: Option<u32>,
+    pub addrs: Vec<cidr::IpInet>,
+}
+
+/// none if `fd` is not an open tun fd, and on other systems. the mtu is none where sysfs
+/// is not readable, e.g. in a sandbox.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn get_tun_fd_info(fd: i32) -> Option<TunFdInfo> {
+    // the kernel names the interface of a tun fd in its fdinfo
+    let fdinfo = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd)).ok()?;
+    let ifname = fdinfo
+        .lines()
+        .find_map(|l| l.strip_prefix("iff:"))?
+        .trim()
+        .to_string();
+    let mtu = std::fs::read_to_string(format!("/sys/class/net/{}/mtu", ifname))
+        .ok()
+        .and_then(|x| x.trim().parse().ok());
+    let addrs = pnet::datalink::interfaces()
+        .into_iter()
+        .filter(|x| x.name == ifname)
+        .flat_map(|x| x.ips)
+        .filter_map(|x| cidr::IpInet::new(x.ip(), x.prefix()).ok())
+        .collect();
+    Some(TunFdInfo { ifname, mtu, addrs })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn get_tun_fd_info(_fd: i32) -> Option<TunFdInfo> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{get_path_mtu, get_tun_fd_info};
+
+    #[test]
+    fn test_get_path_mtu() {
+        let mtu = get_path_mtu(&"127.0.0.1:11010".parse().unwrap()).unwrap();
+        assert!(mtu >= 1280);
+        assert_eq!(get_path_mtu(&"[::1]:11010".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_get_tun_fd_info_of_other_fds() {
+        use std::os::fd::AsRawFd;
+        let file = std::fs::File::open("/proc/self/status").unwrap();
+        assert!(get_tun_fd_info(file.as_raw_fd()).is_none());
+        assert!(get_tun_fd_info(-1).is_none());
+    }
+}
@@ -2,6 +2,7 @@ use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::cell::UnsafeCell;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::time::interval;
@@ -68,6 +69,12 @@ pub enum MetricName {
     CompressionBytesTxAfter,
 
     TcpProxyConnect,
+
+    /// Reconnect attempts of manual connectors
+    ConnectorReconnect,
+
+    /// Outbound packets dropped from the reconnect buffer, full or held too long
+    ReconnectBufferDropped,
 }
 
 impl fmt::Display for MetricName {
@@ -116,6 +123,10 @@ impl fmt::Display for MetricName {
             MetricName::CompressionBytesTxAfter => write!(f, "compression_bytes_tx_after"),
 
             MetricName::TcpProxyConnect => write!(f, "tcp_proxy_connect"),
+
+            MetricName::ConnectorReconnect => write!(f, "connector_reconnect"),
+
+            MetricName::ReconnectBufferDropped => write!(f, "reconnect_buffer_dropped"),
         }
     }
 }
@@ -501,6 +512,9 @@ impl MetricSnapshot {
 pub struct StatsManager {
     counters: Arc<DashMap<MetricKey, Arc<MetricData>>>,
     cleanup_task: ScopedTask<()>,
+    created_at: Instant,
+    // ms after created_at a packet last went through the tun device
+    last_tun_traffic_ms: AtomicU64,
 }
 
 impl StatsManager {
@@ -534,9 +548,23 @@ impl StatsManager {
         Self {
             counters,
             cleanup_task: cleanup_task.into(),
+            created_at: Instant::now(),
+            last_tun_traffic_ms: AtomicU64::new(0),
         }
     }
 
+    /// called for every packet read from or written to the tun device
+    pub fn record_tun_traffic(&self) {
+        let ms = self.created_at.elapsed().as_millis() as u64;
+        self.last_tun_traffic_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    /// time since a packet last went through the tun device, or since creation if none did
+    pub fn tun_idle_duration(&self) -> Duration {
+        let last = Duration::from_millis(self.last_tun_traffic_ms.load(Ordering::Relaxed));
+        self.created_at.elapsed().saturating_sub(last)
+    }
+
     /// Get or create a counter with the given name and labels
     pub fn get_counter(&self, name: MetricName, labels: LabelSet) -> CounterHandle {
         let key = MetricKey::new(name, labels);
@@ -823,7 +851,10 @@ mod tests {
         assert_eq!(request, GetStatsRequest { instance: None });
 
         // Test GetStatsResponse
-        let response = GetStatsResponse { metrics: vec![] };
+        let response = GetStatsResponse {
+            metrics: vec![],
+            tun_idle_ms: 0,
+        };
         assert!(response.metrics.is_empty());
 
         // Test GetPrometheusStatsRequest
@@ -903,4 +934,14 @@ mod tests {
         assert!(prometheus_text.contains("src_peer_id=\"123\""));
         assert!(prometheus_text.contains("service_name=\"test\""));
     }
+
+    #[tokio::test]
+    async fn test_tun_idle_duration() {
+        let stats = StatsManager::new();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(stats.tun_idle_duration() >= Duration::from_millis(50));
+
+        stats.record_tun_traffic();
+        assert!(stats.tun_idle_duration() < Duration::from_millis(50));
+    }
 }
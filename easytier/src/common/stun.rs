@@ -908,6 +908,10 @@ pub trait StunInfoCollectorTrait: Send + Sync {
     fn get_stun_info(&self) -> StunInfo;
     async fn get_udp_port_mapping(&self, local_port: u16) -> Result<SocketAddr, Error>;
     async fn get_tcp_port_mapping(&self, local_port: u16) -> Result<SocketAddr, Error>;
+    // pre-detected stun info, reported until the first detection of this collector finishes.
+    fn set_stun_info_hint(&self, _info: StunInfo) {}
+    // starts a new detection right away instead of waiting for the next periodic one.
+    fn update_stun_info(&self) {}
 }
 
 pub struct StunInfoCollector {
@@ -917,6 +921,7 @@ pub struct StunInfoCollector {
     udp_nat_test_result: Arc<RwLock<Option<StunNatTypeDetectResult>>>,
     tcp_nat_test_result: Arc<RwLock<Option<StunNatTypeDetectResult>>>,
     public_ipv6: Arc<AtomicCell<Option<Ipv6Addr>>>,
+    stun_info_hint: RwLock<Option<StunInfo>>,
     nat_test_result_time: Arc<AtomicCell<chrono::DateTime<Local>>>,
     redetect_notify: Arc<tokio::sync::Notify>,
     tasks: std::sync::Mutex<JoinSet<()>>,
@@ -931,7 +936,12 @@ impl StunInfoCollectorTrait for StunInfoCollector {
         let udp_result = self.udp_nat_test_result.read().unwrap().clone();
         let tcp_result = self.tcp_nat_test_result.read().unwrap().clone();
         if udp_result.is_none() && tcp_result.is_none() {
-            return Default::default();
+            return self
+                .stun_info_hint
+                .read()
+                .unwrap()
+                .clone()
+                .unwrap_or_default();
         }
 
         let mut public_ip = BTreeSet::<String>::new();
@@ -1056,6 +1066,15 @@ impl StunInfoCollectorTrait for StunInfoCollector {
 
         Err(Error::NotFound)
     }
+
+    fn set_stun_info_hint(&self, info: StunInfo) {
+        *self.stun_info_hint.write().unwrap() = Some(info);
+    }
+
+    fn update_stun_info(&self) {
+        // a detection that is running already is not restarted, its result counts
+        self.redetect_notify.notify_waiters();
+    }
 }
 
 impl StunInfoCollector {
@@ -1071,6 +1090,7 @@ impl StunInfoCollector {
             udp_nat_test_result: Arc::new(RwLock::new(None)),
             tcp_nat_test_result: Arc::new(RwLock::new(None)),
             public_ipv6: Arc::new(AtomicCell::new(None)),
+            stun_info_hint: RwLock::new(None),
             nat_test_result_time: Arc::new(AtomicCell::new(Local::now())),
             redetect_notify: Arc::new(tokio::sync::Notify::new()),
             tasks: std::sync::Mutex::new(JoinSet::new()),
@@ -1137,6 +1157,33 @@ impl StunInfoCollector {
             .collect()
     }
 
+    /// Whether `server` is an entry HostResolverIter can resolve: `host[:port]` with an
+    /// ip or domain host, ipv6 in brackets when a port is given, or `txt:domain` whose txt
+    /// record lists servers.
+    pub fn is_valid_server(server: &str) -> bool {
+        fn is_domain(host: &str) -> bool {
+            host.len() <= 253
+                && host.trim_end_matches('.').split('.').all(|label| {
+                    !label.is_empty()
+                        && label.len() <= 63
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                })
+        }
+
+        if let Some(domain) = server.strip_prefix("txt:") {
+            return is_domain(domain);
+        }
+        if server.parse::<SocketAddr>().is_ok() || server.parse::<Ipv4Addr>().is_ok() {
+            return true;
+        }
+        match server.rsplit_once(':') {
+            Some((host, port)) => is_domain(host) && port.parse::<u16>().is_ok_and(|x| x != 0),
+            None => is_domain(server),
+        }
+    }
+
     async fn get_public_ipv6(servers: &[String]) -> Option<Ipv6Addr> {
         let mut ips = HostResolverIter::new(servers.to_vec(), 10, true);
         while let Some(ip) = ips.next().await {
@@ -1281,10 +1328,6 @@ impl StunInfoCollector {
             }
         });
     }
-
-    pub fn update_stun_info(&self) {
-        self.redetect_notify.notify_waiters();
-    }
 }
 
 pub struct MockStunInfoCollector {
@@ -1325,6 +1368,35 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_is_valid_server() {
+        for server in StunInfoCollector::get_default_servers()
+            .iter()
+            .chain(StunInfoCollector::get_default_servers_v6().iter())
+            .map(String::as_str)
+            .chain([
+                "1.2.3.4",
+                "1.2.3.4:3478",
+                "[2001:db8::1]:3478",
+                "stun.l.google.com:19302",
+            ])
+        {
+            assert!(StunInfoCollector::is_valid_server(server), "{}", server);
+        }
+        for server in [
+            "",
+            "2001:db8::1",
+            "stun.example.com:0",
+            "stun.example.com:port",
+            "stun..example.com",
+            "-stun.example.com",
+            "stun://stun.example.com",
+            "txt:",
+        ] {
+            assert!(!StunInfoCollector::is_valid_server(server), "{}", server);
+        }
+    }
+
     #[tokio::test]
     async fn test_udp_nat_type_detector() {
         let collector = StunInfoCollector::new_with_default_servers();
@@ -1342,6 +1414,25 @@ mod tests {
         println!("{:#?}", port_mapping);
     }
 
+    #[tokio::test]
+    async fn test_stun_info_hint() {
+        // no stun server, detection never produces a result
+        let collector = StunInfoCollector::new(vec![], vec![], vec![]);
+        assert_eq!(
+            collector.get_stun_info().udp_nat_type,
+            NatType::Unknown as i32
+        );
+
+        collector.set_stun_info_hint(StunInfo {
+            udp_nat_type: NatType::FullCone as i32,
+            public_ip: vec!["1.2.3.4".to_string()],
+            ..Default::default()
+        });
+        let ret = collector.get_stun_info();
+        assert_eq!(ret.udp_nat_type, NatType::FullCone as i32);
+        assert_eq!(ret.public_ip, vec!["1.2.3.4".to_string()]);
+    }
+
     #[tokio::test]
     async fn test_internal_stun_server() {
         let mut udp_server1 = UdpTunnelListener::new("udp://0.0.0.0:55555".parse().unwrap());
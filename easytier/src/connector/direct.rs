@@ -379,6 +379,7 @@ impl DirectConnectorManagerData {
         ip_list: GetIpListResponse,
     ) -> Result<(), Error> {
         let enable_ipv6 = self.global_ctx.get_flags().enable_ipv6;
+        let family = self.global_ctx.get_address_family_preference();
         let available_listeners = ip_list
             .listeners
             .clone()
@@ -386,7 +387,11 @@ impl DirectConnectorManagerData {
             .map(Into::<url::Url>::into)
             .filter_map(|l| if l.scheme() != "ring" { Some(l) } else { None })
             .filter(|l| l.port().is_some() && l.host().is_some())
-            .filter(|l| enable_ipv6 || !matches!(l.host().unwrap().to_owned(), Host::Ipv6(_)))
+            .filter(|l| match l.host().unwrap() {
+                Host::Ipv4(_) => family.allow_ipv4(),
+                Host::Ipv6(_) => enable_ipv6 && family.allow_ipv6(),
+                Host::Domain(_) => true,
+            })
             .collect::<Vec<_>>();
 
         tracing::debug!(?available_listeners, "got available listeners");
@@ -534,16 +539,17 @@ impl PeerTaskLauncher for DirectConnectorLauncher {
     async fn collect_peers_need_task(&self, data: &Self::Data) -> Vec<Self::CollectPeerItem> {
         data.peer_black_list.cleanup();
         let my_peer_id = data.peer_manager.my_peer_id();
-        data.peer_manager
-            .list_peers()
-            .await
-            .into_iter()
-            .filter(|peer_id| {
-                *peer_id != my_peer_id
-                    && !data.peer_manager.has_directly_connected_conn(*peer_id)
-                    && !data.peer_black_list.contains(peer_id)
-            })
-            .collect()
+        let mut peers = Vec::new();
+        for peer_id in data.peer_manager.list_peers().await {
+            if peer_id != my_peer_id
+                && !data.peer_manager.has_directly_connected_conn(peer_id)
+                && !data.peer_black_list.contains(&peer_id)
+                && !data.peer_manager.is_peer_limit_reached(peer_id).await
+            {
+                peers.push(peer_id);
+            }
+        }
+        peers
     }
 
     async fn launch_task(
@@ -0,0 +1,439 @@
+// announce this node on the local network by mdns and connect to the peers of the same
+// network announcing themselves there, so devices on one lan mesh without a relay.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::{DashMap, DashSet};
+use hickory_proto::{
+    op::{Message, MessageType},
+    rr::{rdata::TXT, Name, RData, Record},
+};
+use sha2::{Digest, Sha256};
+use socket2::{Domain, Protocol, SockAddr, SockRef, Socket, Type};
+use tokio::{net::UdpSocket, task::JoinSet, time::timeout};
+
+use crate::{
+    common::{
+        error::Error,
+        global_ctx::{ArcGlobalCtx, NetworkIdentity},
+        PeerId,
+    },
+    peers::peer_manager::PeerManager,
+    tunnel::IpVersion,
+};
+
+use super::create_connector_by_url;
+
+const MDNS_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+const SERVICE_NAME: &str = "_easytier._udp.local.";
+const ANNOUNCE_VERSION: &str = "1";
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+// a peer not announced for this long is no longer a lan peer
+const LAN_PEER_TIMEOUT: Duration = Duration::from_secs(20);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+// a peer of the same network name we could not connect to, e.g. with another secret, is
+// not tried again before this
+const CONNECT_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_ANNOUNCE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Announce {
+    peer_id: PeerId,
+    // hash of the network name, see network_digest
+    network: String,
+    // the host of these is replaced by the address the announce came from
+    listeners: Vec<url::Url>,
+}
+
+impl Announce {
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        let mut txt = vec![
+            format!("v={}", ANNOUNCE_VERSION),
+            format!("id={}", self.peer_id),
+            format!("net={}", self.network),
+        ];
+        txt.extend(self.listeners.iter().map(|x| format!("url={}", x)));
+
+        let name = Name::from_ascii(SERVICE_NAME).unwrap();
+        let mut msg = Message::new();
+        msg.set_message_type(MessageType::Response)
+            .set_authoritative(true)
+            .add_answer(Record::from_rdata(
+                name,
+                LAN_PEER_TIMEOUT.as_secs() as u32,
+                RData::TXT(TXT::new(txt)),
+            ));
+        Ok(msg.to_vec().map_err(anyhow::Error::from)?)
+    }
+
+    // other mdns traffic on the lan is ignored, only our own txt record is parsed
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let msg = Message::from_vec(buf).ok()?;
+        if msg.message_type() != MessageType::Response {
+            return None;
+        }
+        let name = Name::from_ascii(SERVICE_NAME).unwrap();
+        let txt = msg.answers().iter().find_map(|r| match r.data() {
+            RData::TXT(txt) if *r.name() == name => Some(txt),
+            _ => None,
+        })?;
+
+        let mut version = None;
+        let mut peer_id = None;
+        let mut network = None;
+        let mut listeners = vec![];
+        for entry in txt.txt_data() {
+            let Some((key, value)) = std::str::from_utf8(entry).ok()?.split_once('=') else {
+                continue;
+            };
+            match key {
+                "v" => version = Some(value),
+                "id" => peer_id = value.parse().ok(),
+                "net" => network = Some(value.to_string()),
+                "url" => listeners.extend(value.parse::<url::Url>().ok()),
+                _ => {}
+            }
+        }
+        if version != Some(ANNOUNCE_VERSION) {
+            return None;
+        }
+        Some(Self {
+            peer_id: peer_id?,
+            network: network?,
+            listeners,
+        })
+    }
+}
+
+// nothing derived from the secret is multicast, anyone on the lan could try guesses
+// against it offline. the secret is checked by the handshake of the connect, peers of
+// the same name with another secret are backed off.
+fn network_digest(identity: &NetworkIdentity) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"easytier lan discovery ");
+    hasher.update(identity.network_name.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect()
+}
+
+fn bind_mdns_socket(interface_ips: &[Ipv4Addr]) -> Result<UdpSocket, Error> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_nonblocking(true)?;
+    // the system mdns responder may own the port already
+    socket.set_reuse_address(true)?;
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    {
+        let _ = socket.set_reuse_port(true);
+    }
+    socket.bind(&SockAddr::from(SocketAddrV4::new(
+        Ipv4Addr::UNSPECIFIED,
+        MDNS_ADDR.port(),
+    )))?;
+
+    if interface_ips.is_empty() {
+        socket.join_multicast_v4(MDNS_ADDR.ip(), &Ipv4Addr::UNSPECIFIED)?;
+    }
+    for ip in interface_ips {
+        if let Err(e) = socket.join_multicast_v4(MDNS_ADDR.ip(), ip) {
+            tracing::debug!(?ip, ?e, "lan discovery join multicast group failed");
+        }
+    }
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+struct LanDiscoveryData {
+    global_ctx: ArcGlobalCtx,
+    peer_manager: Arc<PeerManager>,
+    connecting: DashSet<PeerId>,
+    // when connecting to the peer failed the last time
+    failed: DashMap<PeerId, Instant>,
+}
+
+impl LanDiscoveryData {
+    // the ipv4 of the physical interfaces, without the virtual ip of the instance
+    async fn interface_ips(&self) -> Vec<Ipv4Addr> {
+        let virtual_ip = self.global_ctx.get_ipv4().map(|x| x.address());
+        self.global_ctx
+            .get_ip_collector()
+            .collect_ip_addrs()
+            .await
+            .interface_ipv4s
+            .into_iter()
+            .map(Ipv4Addr::from)
+            .filter(|x| Some(*x) != virtual_ip)
+            .collect()
+    }
+
+    fn my_announce(&self) -> Announce {
+        Announce {
+            peer_id: self.peer_manager.my_peer_id(),
+            network: network_digest(&self.global_ctx.get_network_identity()),
+            listeners: self
+                .global_ctx
+                .get_running_listeners()
+                .into_iter()
+                .filter(|x| matches!(x.scheme(), "tcp" | "udp") && x.port().is_some())
+                .collect(),
+        }
+    }
+
+    async fn announce(&self, socket: &UdpSocket, interface_ips: &[Ipv4Addr]) -> Result<(), Error> {
+        let packet = self.my_announce().encode()?;
+        if interface_ips.is_empty() {
+            socket.send_to(&packet, MDNS_ADDR).await?;
+        }
+        for ip in interface_ips {
+            SockRef::from(socket).set_multicast_if_v4(ip)?;
+            if let Err(e) = socket.send_to(&packet, MDNS_ADDR).await {
+                tracing::debug!(?ip, ?e, "lan discovery announce failed");
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_announce(self: &Arc<Self>, announce: Announce, from: SocketAddr) {
+        let peer_id = announce.peer_id;
+        if peer_id == self.peer_manager.my_peer_id()
+            || announce.network != network_digest(&self.global_ctx.get_network_identity())
+        {
+            return;
+        }
+        self.global_ctx.touch_lan_peer(peer_id);
+        self.failed
+            .retain(|_, failed_at| failed_at.elapsed() < CONNECT_RETRY_BACKOFF);
+        if self.peer_manager.has_directly_connected_conn(peer_id)
+            || self.failed.contains_key(&peer_id)
+            || !self.connecting.insert(peer_id)
+        {
+            return;
+        }
+
+        let data = self.clone();
+        tokio::spawn(async move {
+            data.connect_to_announce(announce, from.ip()).await;
+            data.connecting.remove(&peer_id);
+        });
+    }
+
+    async fn connect_to_announce(&self, announce: Announce, ip: IpAddr) {
+        if self
+            .peer_manager
+            .is_peer_limit_reached(announce.peer_id)
+            .await
+        {
+            return;
+        }
+        for mut url in announce.listeners {
+            if url.set_ip_host(ip).is_err() {
+                continue;
+            }
+            match self.try_connect(announce.peer_id, &url).await {
+                Ok(()) => {
+                    tracing::info!(peer_id = announce.peer_id, %url, "lan discovery connected");
+                    return;
+                }
+                Err(e) => {
+                    tracing::debug!(peer_id = announce.peer_id, %url, ?e, "lan discovery connect failed")
+                }
+            }
+        }
+        self.failed.insert(announce.peer_id, Instant::now());
+    }
+
+    async fn try_connect(&self, dst_peer_id: PeerId, url: &url::Url) -> Result<(), Error> {
+        let connector =
+            create_connector_by_url(url.as_str(), &self.global_ctx, IpVersion::Both).await?;
+        let (peer_id, conn_id) = timeout(
+            CONNECT_TIMEOUT,
+            self.peer_manager.try_direct_connect(connector),
+        )
+        .await??;
+        if peer_id != dst_peer_id {
+            // someone else answers on the address, e.g. after a dhcp change
+            self.peer_manager.close_peer_conn(peer_id, &conn_id).await?;
+            return Err(Error::InvalidUrl(url.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn run_socket(self: &Arc<Self>) -> Result<(), Error> {
+        let interface_ips = self.interface_ips().await;
+        let socket = {
+            let _g = self.global_ctx.net_ns.guard();
+            bind_mdns_socket(&interface_ips)?
+        };
+        let mut announce_timer = tokio::time::interval(ANNOUNCE_INTERVAL);
+        let mut buf = vec![0u8; MAX_ANNOUNCE_SIZE];
+        while self.global_ctx.is_lan_discovery_enabled() {
+            tokio::select! {
+                _ = announce_timer.tick() => {
+                    self.global_ctx.expire_lan_peers(LAN_PEER_TIMEOUT);
+                    self.announce(&socket, &interface_ips).await?;
+                }
+                ret = socket.recv_from(&mut buf) => {
+                    let (len, from) = ret?;
+                    if let Some(announce) = Announce::decode(&buf[..len]) {
+                        self.handle_announce(announce, from);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self: Arc<Self>) {
+        // a dormant instance neither announces nor connects, the announce right after the
+        // wake lets the lan peers connect
+        self.global_ctx.wait_until_awake().await;
+        loop {
+            if self.global_ctx.is_lan_discovery_enabled() {
+                if let Err(e) = self.run_socket().await {
+                    tracing::warn!(?e, "lan discovery failed, retry later");
+                }
+            }
+            tokio::time::sleep(ANNOUNCE_INTERVAL).await;
+        }
+    }
+}
+
+pub struct LanDiscovery {
+    data: Arc<LanDiscoveryData>,
+    tasks: JoinSet<()>,
+}
+
+impl LanDiscovery {
+    pub fn new(global_ctx: ArcGlobalCtx, peer_manager: Arc<PeerManager>) -> Self {
+        Self {
+            data: Arc::new(LanDiscoveryData {
+                global_ctx,
+                peer_manager,
+                connecting: DashSet::new(),
+                failed: DashMap::new(),
+            }),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    // idles until lan discovery is enabled in the global ctx
+    pub fn run(&mut self) {
+        self.tasks.spawn(self.data.clone().run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::config::ConfigLoader,
+        common::global_ctx::tests::get_mock_global_ctx_with_network,
+        instance::listeners::ListenerManager,
+        peers::{create_packet_recv_chan, peer_manager::RouteAlgoType},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_announce_encode_decode() {
+        let announce = Announce {
+            peer_id: 12345,
+            network: network_digest(&NetworkIdentity::new("net".to_string(), "sec".to_string())),
+            listeners: vec![
+                "tcp://0.0.0.0:11010".parse().unwrap(),
+                "udp://0.0.0.0:11010".parse().unwrap(),
+            ],
+        };
+        let packet = announce.encode().unwrap();
+        assert_eq!(Announce::decode(&packet), Some(announce));
+
+        assert_eq!(Announce::decode(b"not mdns"), None);
+        let mut query = Message::new();
+        query.set_message_type(MessageType::Query);
+        assert_eq!(Announce::decode(&query.to_vec().unwrap()), None);
+    }
+
+    #[test]
+    fn test_network_digest() {
+        let a = NetworkIdentity::new("net".to_string(), "sec".to_string());
+        // the secret is not part of it
+        let b = NetworkIdentity::new("net".to_string(), "other".to_string());
+        assert_eq!(network_digest(&a), network_digest(&b));
+
+        let c = NetworkIdentity::new("other".to_string(), "sec".to_string());
+        assert_ne!(network_digest(&a), network_digest(&c));
+    }
+
+    fn discovery_data(peer_manager: Arc<PeerManager>) -> Arc<LanDiscoveryData> {
+        Arc::new(LanDiscoveryData {
+            global_ctx: peer_manager.get_global_ctx(),
+            peer_manager,
+            connecting: DashSet::new(),
+            failed: DashMap::new(),
+        })
+    }
+
+    // the listener stops with the returned manager
+    async fn listening_peer(
+        identity: NetworkIdentity,
+        port: u16,
+    ) -> (Arc<LanDiscoveryData>, ListenerManager<PeerManager>) {
+        let (s, _r) = create_packet_recv_chan();
+        let global_ctx = get_mock_global_ctx_with_network(Some(identity));
+        global_ctx
+            .config
+            .set_listeners(vec![format!("tcp://0.0.0.0:{}", port).parse().unwrap()]);
+        let peer_mgr = Arc::new(PeerManager::new(RouteAlgoType::Ospf, global_ctx, s));
+        peer_mgr.run().await.unwrap();
+        let mut listeners = ListenerManager::new(peer_mgr.get_global_ctx(), peer_mgr.clone());
+        listeners.prepare_listeners().await.unwrap();
+        listeners.run().await.unwrap();
+        (discovery_data(peer_mgr), listeners)
+    }
+
+    // until the connect started by handle_announce ended
+    async fn wait_connect_done(data: &LanDiscoveryData, peer_id: PeerId) {
+        timeout(Duration::from_secs(5), async {
+            while data.connecting.contains(&peer_id) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_discover_and_connect() {
+        let identity = NetworkIdentity::new("lan_net".to_string(), "sec".to_string());
+        let from: SocketAddr = "127.0.0.1:5353".parse().unwrap();
+
+        let (a, _a_listeners) = listening_peer(identity.clone(), 11361).await;
+        let (b, _b_listeners) = listening_peer(identity.clone(), 11362).await;
+        let b_id = b.peer_manager.my_peer_id();
+        a.handle_announce(b.my_announce(), from);
+        wait_connect_done(&a, b_id).await;
+        assert!(a.peer_manager.has_directly_connected_conn(b_id));
+        assert!(!a.failed.contains_key(&b_id));
+
+        // same name, other secret: the handshake fails and the peer is backed off
+        let other_secret = NetworkIdentity::new("lan_net".to_string(), "other".to_string());
+        let (c, _c_listeners) = listening_peer(other_secret, 11363).await;
+        let c_id = c.peer_manager.my_peer_id();
+        a.handle_announce(c.my_announce(), from);
+        wait_connect_done(&a, c_id).await;
+        assert!(!a.peer_manager.has_directly_connected_conn(c_id));
+        assert!(a.failed.contains_key(&c_id));
+        a.handle_announce(c.my_announce(), from);
+        assert!(!a.connecting.contains(&c_id));
+
+        // another network is ignored
+        let other_net = NetworkIdentity::new("other_net".to_string(), "sec".to_string());
+        let (d, _d_listeners) = listening_peer(other_net, 11364).await;
+        a.handle_announce(d.my_announce(), from);
+        assert!(!a.connecting.contains(&d.peer_manager.my_peer_id()));
+    }
+}
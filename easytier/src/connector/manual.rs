@@ -3,11 +3,14 @@ use std::{
     sync::{Arc, Weak},
 };
 
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use tokio::{sync::mpsc, task::JoinSet, time::timeout};
 
 use crate::{
-    common::{dns::socket_addrs, join_joinset_background, PeerId},
+    common::{
+        dns::{socket_addrs, with_resolver},
+        join_joinset_background, PeerId,
+    },
     peers::peer_conn::PeerConnId,
     proto::{
         api::instance::{
@@ -25,6 +28,7 @@ use crate::{
         error::Error,
         global_ctx::{ArcGlobalCtx, GlobalCtxEvent},
         netns::NetNS,
+        stats_manager::{LabelSet, LabelType, MetricName},
     },
     peers::peer_manager::PeerManager,
     use_global_var,
@@ -48,6 +52,10 @@ struct ConnectorManagerData {
     alive_conn_urls: Arc<DashSet<url::Url>>,
     // user removed connector urls
     removed_conn_urls: Arc<DashSet<url::Url>>,
+    // failed reconnects in a row of each connector
+    failed_attempts: DashMap<url::Url, u32>,
+    // connectors not retried anymore, see GlobalCtx::set_max_reconnect_attempts
+    gave_up_urls: DashSet<url::Url>,
     net_ns: NetNS,
     global_ctx: ArcGlobalCtx,
 }
@@ -71,6 +79,8 @@ impl ManualConnectorManager {
                 peer_manager: Arc::downgrade(&peer_manager),
                 alive_conn_urls: Arc::new(DashSet::new()),
                 removed_conn_urls: Arc::new(DashSet::new()),
+                failed_attempts: DashMap::new(),
+                gave_up_urls: DashSet::new(),
                 net_ns: global_ctx.net_ns.clone(),
                 global_ctx,
             }),
@@ -92,6 +102,9 @@ impl ManualConnectorManager {
     }
 
     pub async fn add_connector_by_url(&self, url: url::Url) -> Result<(), Error> {
+        // adding it again gives a connector that was given up another round of attempts
+        self.data.gave_up_urls.remove(&url);
+        self.data.failed_attempts.remove(&url);
         self.data.connectors.insert(url);
         Ok(())
     }
@@ -155,11 +168,21 @@ impl ManualConnectorManager {
             );
         }
 
+        for conn_url in self.data.gave_up_urls.iter() {
+            ret.push(Connector {
+                url: Some(conn_url.key().clone().into()),
+                status: ConnectorStatus::GaveUp.into(),
+            });
+        }
+
         ret
     }
 
     async fn conn_mgr_reconn_routine(data: Arc<ConnectorManagerData>) {
         tracing::warn!("conn_mgr_routine started");
+        // connect_on_demand, connected once something is sent
+        data.global_ctx.wait_until_awake().await;
+        // the first tick is at once, the peers are connected right after the wake
         let mut reconn_interval = tokio::time::interval(std::time::Duration::from_millis(
             use_global_var!(MANUAL_CONNECTOR_RECONNECT_INTERVAL_MS),
         ));
@@ -182,11 +205,25 @@ impl ManualConnectorManager {
                         assert!(insert_succ);
 
                         tasks.lock().unwrap().spawn(async move {
-                            let reconn_ret = Self::conn_reconnect(data_clone.clone(), dead_url.clone() ).await;
+                            // peer hostnames go to the name servers set for the instance, if any
+                            let resolver = data_clone.global_ctx.get_dns_resolver();
+                            let reconn_ret = with_resolver(
+                                resolver,
+                                Self::conn_reconnect(data_clone.clone(), dead_url.clone()),
+                            )
+                            .await;
+                            let gave_up = Self::count_attempt(&data_clone, &dead_url, reconn_ret.is_ok());
                             let _ = sender.send(reconn_ret).await;
 
                             data_clone.reconnecting.remove(&dead_url).unwrap();
-                            data_clone.connectors.insert(dead_url.clone());
+                            if gave_up {
+                                data_clone.gave_up_urls.insert(dead_url.clone());
+                                data_clone
+                                    .global_ctx
+                                    .issue_event(GlobalCtxEvent::ConnectorGaveUp(dead_url.to_string()));
+                            } else {
+                                data_clone.connectors.insert(dead_url.clone());
+                            }
                         });
                     }
                     tracing::info!("reconn_interval tick, done");
@@ -199,12 +236,35 @@ impl ManualConnectorManager {
         }
     }
 
+    // returns whether the connector has used up its attempts and is given up
+    fn count_attempt(data: &ConnectorManagerData, url: &url::Url, succ: bool) -> bool {
+        if succ {
+            data.failed_attempts.remove(url);
+            return false;
+        }
+        let failed = {
+            let mut failed = data.failed_attempts.entry(url.clone()).or_insert(0);
+            *failed += 1;
+            *failed
+        };
+        let Some(max) = data.global_ctx.get_max_reconnect_attempts() else {
+            return false;
+        };
+        if failed < max {
+            return false;
+        }
+        tracing::warn!("connector: {}, gave up after {} attempts", url, failed);
+        data.failed_attempts.remove(url);
+        true
+    }
+
     fn handle_remove_connector(data: Arc<ConnectorManagerData>) {
         let remove_later = DashSet::new();
         for it in data.removed_conn_urls.iter() {
             let url = it.key();
-            if data.connectors.remove(url).is_some() {
+            if data.connectors.remove(url).is_some() || data.gave_up_urls.remove(url).is_some() {
                 tracing::warn!("connector: {}, removed", url);
+                data.failed_attempts.remove(url);
                 continue;
             } else if data.reconnecting.contains(url) {
                 tracing::warn!("connector: {}, reconnecting, remove later.", url);
@@ -271,6 +331,14 @@ impl ManualConnectorManager {
         dead_url: url::Url,
     ) -> Result<ReconnResult, Error> {
         tracing::info!("reconnect: {}", dead_url);
+        data.global_ctx
+            .stats_manager()
+            .get_counter(
+                MetricName::ConnectorReconnect,
+                LabelSet::new()
+                    .with_label_type(LabelType::NetworkName(data.global_ctx.get_network_name())),
+            )
+            .inc();
 
         let mut ip_versions = vec![];
         if dead_url.scheme() == "ring" || dead_url.scheme() == "txt" || dead_url.scheme() == "srv" {
@@ -292,19 +360,19 @@ impl ManualConnectorManager {
                 }
             };
             tracing::info!(?addrs, ?dead_url, "get ip from url done");
-            let mut has_ipv4 = false;
-            let mut has_ipv6 = false;
-            for addr in addrs {
-                if addr.is_ipv4() {
-                    if !has_ipv4 {
-                        ip_versions.insert(0, IpVersion::V4);
-                    }
-                    has_ipv4 = true;
-                } else if addr.is_ipv6() {
-                    if !has_ipv6 {
-                        ip_versions.push(IpVersion::V6);
-                    }
-                    has_ipv6 = true;
+            // ipv6 may be disabled on networks where it is broken, only try v4 addrs then
+            let enable_ipv6 = data.global_ctx.get_flags().enable_ipv6;
+            let family = data.global_ctx.get_address_family_preference();
+            let has_ipv4 = family.allow_ipv4() && addrs.iter().any(|x| x.is_ipv4());
+            let has_ipv6 = enable_ipv6 && family.allow_ipv6() && addrs.iter().any(|x| x.is_ipv6());
+            if has_ipv4 {
+                ip_versions.push(IpVersion::V4);
+            }
+            if has_ipv6 {
+                if family.prefer_ipv6() {
+                    ip_versions.insert(0, IpVersion::V6);
+                } else {
+                    ip_versions.push(IpVersion::V6);
                 }
             }
         }
@@ -381,7 +449,7 @@ mod tests {
     use crate::{
         peers::tests::create_mock_peer_manager,
         set_global_var,
-        tunnel::{Tunnel, TunnelError},
+        tunnel::{common::tests::wait_for_condition, Tunnel, TunnelError},
     };
 
     use super::*;
@@ -409,4 +477,79 @@ mod tests {
 
         tokio::time::sleep(std::time::Duration::from_secs(5)).await;
     }
+
+    #[tokio::test]
+    async fn test_reconnect_skip_ipv6_when_disabled() {
+        let peer_mgr = create_mock_peer_manager().await;
+        let global_ctx = peer_mgr.get_global_ctx();
+        let mut flags = global_ctx.get_flags();
+        flags.enable_ipv6 = false;
+        global_ctx.set_flags(flags);
+        let mgr = ManualConnectorManager::new(global_ctx, peer_mgr);
+
+        let ret = ManualConnectorManager::conn_reconnect(
+            mgr.data.clone(),
+            "tcp://[::1]:11010".parse().unwrap(),
+        )
+        .await;
+        assert!(ret.unwrap_err().to_string().contains("cannot get ip"));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_skip_by_address_family() {
+        let peer_mgr = create_mock_peer_manager().await;
+        let global_ctx = peer_mgr.get_global_ctx();
+        global_ctx.set_address_family_preference("ipv6_only".parse().unwrap());
+        let mgr = ManualConnectorManager::new(global_ctx, peer_mgr);
+
+        let ret = ManualConnectorManager::conn_reconnect(
+            mgr.data.clone(),
+            "tcp://127.0.0.1:11010".parse().unwrap(),
+        )
+        .await;
+        assert!(ret.unwrap_err().to_string().contains("cannot get ip"));
+    }
+
+    #[tokio::test]
+    async fn test_give_up_after_max_reconnect_attempts() {
+        set_global_var!(MANUAL_CONNECTOR_RECONNECT_INTERVAL_MS, 1);
+
+        let peer_mgr = create_mock_peer_manager().await;
+        let global_ctx = peer_mgr.get_global_ctx();
+        global_ctx.set_max_reconnect_attempts(Some(2));
+        let mut events = global_ctx.subscribe();
+        let mgr = ManualConnectorManager::new(global_ctx, peer_mgr);
+
+        // nothing listens there
+        let url: url::Url = "tcp://127.0.0.1:1".parse().unwrap();
+        mgr.add_connector_by_url(url.clone()).await.unwrap();
+        wait_for_condition(
+            || async {
+                mgr.list_connectors().await.iter().any(|x| {
+                    x.url == Some(url.clone().into()) && x.status == ConnectorStatus::GaveUp as i32
+                })
+            },
+            std::time::Duration::from_secs(10),
+        )
+        .await;
+
+        let mut attempts = 0;
+        loop {
+            match events.try_recv().unwrap() {
+                GlobalCtxEvent::Connecting(_) => attempts += 1,
+                GlobalCtxEvent::ConnectorGaveUp(dst) => {
+                    assert_eq!(dst, url.to_string());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(attempts, 2);
+
+        // not retried anymore
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event, GlobalCtxEvent::Connecting(_)));
+        }
+    }
 }
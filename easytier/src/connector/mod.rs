@@ -1,7 +1,4 @@
-use std::{
-    net::{SocketAddr, SocketAddrV4, SocketAddrV6},
-    sync::Arc,
-};
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6};
 
 use http_connector::HttpTunnelConnector;
 
@@ -10,7 +7,7 @@ use crate::tunnel::quic::QUICTunnelConnector;
 #[cfg(feature = "wireguard")]
 use crate::tunnel::wireguard::{WgConfig, WgTunnelConnector};
 use crate::{
-    common::{error::Error, global_ctx::ArcGlobalCtx, idn, network::IPCollector},
+    common::{error::Error, global_ctx::ArcGlobalCtx, idn},
     tunnel::{
         check_scheme_and_get_socket_addr, fake_tcp::FakeTcpTunnelConnector,
         ring::RingTunnelConnector, tcp::TcpTunnelConnector, udp::UdpTunnelConnector, IpVersion,
@@ -19,6 +16,7 @@ use crate::{
 };
 
 pub mod direct;
+pub mod lan_discovery;
 pub mod manual;
 pub mod tcp_hole_punch;
 pub mod udp_hole_punch;
@@ -29,13 +27,22 @@ pub mod http_connector;
 async fn set_bind_addr_for_peer_connector(
     connector: &mut (impl TunnelConnector + ?Sized),
     is_ipv4: bool,
-    ip_collector: &Arc<IPCollector>,
+    global_ctx: &ArcGlobalCtx,
 ) {
-    if cfg!(any(target_os = "android", target_env = "ohos")) {
+    if let Some(ip) = global_ctx.get_bind_source_ip() {
+        if ip.is_ipv4() == is_ipv4 {
+            connector.set_bind_addrs(vec![SocketAddr::new(ip, 0)]);
+        }
+        return;
+    }
+
+    if !global_ctx.config.get_flags().bind_device
+        || cfg!(any(target_os = "android", target_env = "ohos"))
+    {
         return;
     }
 
-    let ips = ip_collector.collect_ip_addrs().await;
+    let ips = global_ctx.get_ip_collector().collect_ip_addrs().await;
     if is_ipv4 {
         let mut bind_addrs = vec![];
         for ipv4 in ips.interface_ipv4s {
@@ -66,28 +73,14 @@ pub async fn create_connector_by_url(
             let dst_addr =
                 check_scheme_and_get_socket_addr::<SocketAddr>(&url, "tcp", ip_version).await?;
             let mut connector = TcpTunnelConnector::new(url);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         "udp" => {
             let dst_addr =
                 check_scheme_and_get_socket_addr::<SocketAddr>(&url, "udp", ip_version).await?;
             let mut connector = UdpTunnelConnector::new(url);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         "http" | "https" => {
@@ -104,14 +97,7 @@ pub async fn create_connector_by_url(
             let dst_addr =
                 check_scheme_and_get_socket_addr::<SocketAddr>(&url, "quic", ip_version).await?;
             let mut connector = QUICTunnelConnector::new(url);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         #[cfg(feature = "wireguard")]
@@ -124,14 +110,7 @@ pub async fn create_connector_by_url(
                 &nid.network_secret.unwrap_or_default(),
             );
             let mut connector = WgTunnelConnector::new(url, wg_config);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         #[cfg(feature = "websocket")]
@@ -139,14 +118,7 @@ pub async fn create_connector_by_url(
             use crate::tunnel::FromUrl;
             let dst_addr = SocketAddr::from_url(url.clone(), ip_version).await?;
             let mut connector = crate::tunnel::websocket::WSTunnelConnector::new(url);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         "txt" | "srv" => {
@@ -163,14 +135,7 @@ pub async fn create_connector_by_url(
             let dst_addr =
                 check_scheme_and_get_socket_addr::<SocketAddr>(&url, "faketcp", ip_version).await?;
             let mut connector = FakeTcpTunnelConnector::new(url);
-            if global_ctx.config.get_flags().bind_device {
-                set_bind_addr_for_peer_connector(
-                    &mut connector,
-                    dst_addr.is_ipv4(),
-                    &global_ctx.get_ip_collector(),
-                )
-                .await;
-            }
+            set_bind_addr_for_peer_connector(&mut connector, dst_addr.is_ipv4(), global_ctx).await;
             Box::new(connector)
         }
         _ => {
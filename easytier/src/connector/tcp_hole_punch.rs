@@ -463,6 +463,14 @@ impl PeerTaskLauncher for TcpHolePunchPeerTaskLauncher {
                 continue;
             }
 
+            if data.peer_mgr.is_peer_limit_reached(peer_id).await {
+                tracing::trace!(
+                    peer_id,
+                    "tcp hole punch task collect skip max peers reached"
+                );
+                continue;
+            }
+
             let peer_tcp_nat_type = route
                 .stun_info
                 .as_ref()
@@ -247,8 +247,10 @@ impl PunchConeHoleClient {
 
 #[cfg(test)]
 pub mod tests {
+    use std::time::Duration;
 
     use crate::{
+        common::global_ctx::GlobalCtxEvent,
         connector::udp_hole_punch::{
             tests::create_mock_peer_manager_with_mock_stun, UdpHolePunchConnector,
         },
@@ -268,6 +270,7 @@ pub mod tests {
 
         println!("{:?}", p_a.list_routes().await);
 
+        let mut events = p_a.get_global_ctx().subscribe();
         let mut hole_punching_a = UdpHolePunchConnector::new(p_a.clone());
         let mut hole_punching_c = UdpHolePunchConnector::new(p_c.clone());
 
@@ -280,5 +283,23 @@ pub mod tests {
             .await
             .unwrap();
         println!("{:?}", p_a.list_routes().await);
+
+        let mut started = false;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match events.recv().await.unwrap() {
+                    GlobalCtxEvent::HolePunchStarted(peer_id) if peer_id == p_c.my_peer_id() => {
+                        started = true;
+                    }
+                    GlobalCtxEvent::HolePunchSucceeded(peer_id) if peer_id == p_c.my_peer_id() => {
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert!(started);
     }
 }
@@ -13,7 +13,7 @@ use sym_to_cone::{PunchSymToConeHoleClient, PunchSymToConeHoleServer};
 use tokio::{sync::Mutex, task::JoinHandle};
 
 use crate::{
-    common::{stun::StunInfoCollectorTrait, PeerId},
+    common::{global_ctx::GlobalCtxEvent, stun::StunInfoCollectorTrait, PeerId},
     peers::{
         peer_manager::PeerManager,
         peer_task::{PeerTaskLauncher, PeerTaskManager},
@@ -220,6 +220,20 @@ impl UdpHoePunchConnectorData {
         })
     }
 
+    fn issue_punch_event(&self, event: GlobalCtxEvent) {
+        self.peer_mgr.get_global_ctx().issue_event(event);
+    }
+
+    // issues the started event, the returned round issues the finish event when dropped
+    fn start_punch_round(&self, dst_peer_id: PeerId) -> PunchRound<'_> {
+        self.issue_punch_event(GlobalCtxEvent::HolePunchStarted(dst_peer_id));
+        PunchRound {
+            data: self,
+            dst_peer_id,
+            succeeded: false,
+        }
+    }
+
     #[tracing::instrument(skip(self))]
     async fn handle_punch_result(
         &self,
@@ -271,16 +285,18 @@ impl UdpHoePunchConnectorData {
 
         loop {
             backoff.sleep_for_next_backoff().await;
+            let punch_round = self.start_punch_round(task_info.dst_peer_id);
 
             let ret = self
                 .cone_client
                 .do_hole_punching(task_info.dst_peer_id)
                 .await;
 
-            if self
+            let succeeded = self
                 .handle_punch_result(ret, Some(&mut backoff), None)
-                .await
-            {
+                .await;
+            punch_round.finish(succeeded);
+            if succeeded {
                 break;
             }
         }
@@ -297,6 +313,7 @@ impl UdpHoePunchConnectorData {
 
         loop {
             backoff.sleep_for_next_backoff().await;
+            let punch_round = self.start_punch_round(task_info.dst_peer_id);
 
             // always try cone first
             if !RUN_TESTING.load(std::sync::atomic::Ordering::Relaxed) {
@@ -305,6 +322,7 @@ impl UdpHoePunchConnectorData {
                     .do_hole_punching(task_info.dst_peer_id)
                     .await;
                 if self.handle_punch_result(ret, None, None).await {
+                    punch_round.finish(true);
                     break;
                 }
             }
@@ -323,10 +341,11 @@ impl UdpHoePunchConnectorData {
                     .await
             };
 
-            if self
+            let succeeded = self
                 .handle_punch_result(ret, Some(&mut backoff), Some(&mut round))
-                .await
-            {
+                .await;
+            punch_round.finish(succeeded);
+            if succeeded {
                 break;
             }
         }
@@ -341,6 +360,7 @@ impl UdpHoePunchConnectorData {
 
         loop {
             backoff.sleep_for_next_backoff().await;
+            let punch_round = self.start_punch_round(task_info.dst_peer_id);
 
             // always try cone first
             if !RUN_TESTING.load(std::sync::atomic::Ordering::Relaxed) {
@@ -349,6 +369,7 @@ impl UdpHoePunchConnectorData {
                     .do_hole_punching(task_info.dst_peer_id)
                     .await;
                 if self.handle_punch_result(ret, None, None).await {
+                    punch_round.finish(true);
                     break;
                 }
             }
@@ -371,10 +392,13 @@ impl UdpHoePunchConnectorData {
 
             if is_busy {
                 backoff.rollback();
-            } else if self
+                continue;
+            }
+            let succeeded = self
                 .handle_punch_result(ret, Some(&mut backoff), None)
-                .await
-            {
+                .await;
+            punch_round.finish(succeeded);
+            if succeeded {
                 break;
             }
         }
@@ -383,6 +407,31 @@ impl UdpHoePunchConnectorData {
     }
 }
 
+// one round of a punch task, the task retries after the next backoff on failure. a round
+// left early, e.g. because the punch lock was busy or the task was cancelled, counts as
+// failed, so every started event is followed by a finish event.
+struct PunchRound<'a> {
+    data: &'a UdpHoePunchConnectorData,
+    dst_peer_id: PeerId,
+    succeeded: bool,
+}
+
+impl PunchRound<'_> {
+    fn finish(mut self, succeeded: bool) {
+        self.succeeded = succeeded;
+    }
+}
+
+impl Drop for PunchRound<'_> {
+    fn drop(&mut self) {
+        self.data.issue_punch_event(if self.succeeded {
+            GlobalCtxEvent::HolePunchSucceeded(self.dst_peer_id)
+        } else {
+            GlobalCtxEvent::HolePunchFailed(self.dst_peer_id)
+        });
+    }
+}
+
 #[derive(Clone)]
 struct UdpHolePunchPeerTaskLauncher {}
 
@@ -464,6 +513,11 @@ impl PeerTaskLauncher for UdpHolePunchPeerTaskLauncher {
                 continue;
             }
 
+            if data.peer_mgr.is_peer_limit_reached(peer_id).await {
+                tracing::trace!(?peer_id, "max peers reached, skipping");
+                continue;
+            }
+
             let global_ctx = data.peer_mgr.get_global_ctx();
             if !my_nat_type.can_punch_hole_as_client(peer_nat_type, my_peer_id, peer_id, global_ctx)
             {
@@ -35,7 +35,7 @@ use crate::{
     common::{
         acl_processor::PacketInfo,
         error::Result,
-        global_ctx::{ArcGlobalCtx, GlobalCtx},
+        global_ctx::{ArcGlobalCtx, GlobalCtx, TransportProfile},
     },
     peers::{acl_filter::AclFilter, peer_manager::PeerManager, NicPacketFilter, PeerPacketFilter},
     proto::{
@@ -50,11 +50,15 @@ use crate::{
     tunnel::packet_def::{PacketType, PeerManagerHeader, ZCPacket},
 };
 
-fn create_kcp_endpoint() -> KcpEndpoint {
+fn create_kcp_endpoint(global_ctx: ArcGlobalCtx) -> KcpEndpoint {
     let mut kcp_endpoint = KcpEndpoint::new();
-    kcp_endpoint.set_kcp_config_factory(Box::new(|conv| {
+    kcp_endpoint.set_kcp_config_factory(Box::new(move |conv| {
         let mut cfg = KcpConfig::new_turbo(conv);
-        cfg.interval = Some(5);
+        cfg.interval = Some(match global_ctx.get_transport_profile() {
+            None | Some(TransportProfile::LowLatency) => 5,
+            Some(TransportProfile::Reliable) => 10,
+            Some(TransportProfile::Throughput) => 20,
+        });
         cfg
     }));
     kcp_endpoint
@@ -322,7 +326,7 @@ pub struct KcpProxySrc {
 
 impl KcpProxySrc {
     pub async fn new(peer_manager: Arc<PeerManager>) -> Self {
-        let mut kcp_endpoint = create_kcp_endpoint();
+        let mut kcp_endpoint = create_kcp_endpoint(peer_manager.get_global_ctx());
         kcp_endpoint.run().await;
 
         let output_receiver = kcp_endpoint.output_receiver().unwrap();
@@ -431,7 +435,7 @@ impl ProxyAclHandler {
 
 impl KcpProxyDst {
     pub async fn new(peer_manager: Arc<PeerManager>) -> Self {
-        let mut kcp_endpoint = create_kcp_endpoint();
+        let mut kcp_endpoint = create_kcp_endpoint(peer_manager.get_global_ctx());
         kcp_endpoint.run().await;
 
         let mut tasks = JoinSet::new();
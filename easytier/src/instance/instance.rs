@@ -1,25 +1,30 @@
 use std::any::Any;
 use std::collections::HashSet;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use cidr::{IpCidr, Ipv4Inet};
 
 use futures::FutureExt;
-use tokio::sync::{oneshot, Notify};
+use tokio::sync::{broadcast::error::RecvError, oneshot, Notify};
 use tokio::{sync::Mutex, task::JoinSet};
 use tokio_util::sync::CancellationToken;
 
 use crate::common::acl_processor::AclRuleBuilder;
 use crate::common::config::ConfigLoader;
 use crate::common::error::Error;
-use crate::common::global_ctx::{ArcGlobalCtx, GlobalCtx, GlobalCtxEvent};
+use crate::common::global_ctx::{
+    peer_metadata_size, AddressFamilyPreference, ArcGlobalCtx, GlobalCtx, GlobalCtxEvent,
+    OutboundPolicy, RouteFailoverPolicy, TransportPreference, TransportProfile,
+    MAX_PEER_METADATA_SIZE,
+};
 use crate::common::scoped_task::ScopedTask;
 use crate::common::PeerId;
 use crate::connector::direct::DirectConnectorManager;
+use crate::connector::lan_discovery::LanDiscovery;
 use crate::connector::manual::{ConnectorManagerRpcService, ManualConnectorManager};
 use crate::connector::tcp_hole_punch::TcpHolePunchConnector;
 use crate::connector::udp_hole_punch::UdpHolePunchConnector;
@@ -244,6 +249,79 @@ impl InstanceConfigPatcher {
     ) -> Result<(), anyhow::Error> {
         let patch_for_event = patch.clone();
 
+        // parse the values that can be invalid before anything is applied, so a bad one
+        // does not leave the patch half applied
+        let bind_source_ip = patch
+            .bind_source_ip
+            .map(|x| -> Result<_, anyhow::Error> {
+                if x.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(x.parse::<IpAddr>().with_context(|| {
+                    format!("invalid bind source ip: {}", x)
+                })?))
+            })
+            .transpose()?;
+        let transport_preferences = patch
+            .peer_transport_preferences
+            .into_iter()
+            .map(|x| Ok::<_, anyhow::Error>((x.peer_id, x.prefer.parse()?)))
+            .collect::<Result<Vec<(PeerId, TransportPreference)>, _>>()?;
+        let outbound_policy = patch
+            .outbound_policy
+            .map(|outbound_policy| -> Result<_, anyhow::Error> {
+                let parse_cidrs = |cidrs: Vec<String>| {
+                    cidrs
+                        .iter()
+                        .map(|x| {
+                            x.parse::<IpCidr>()
+                                .with_context(|| format!("invalid outbound policy cidr: {}", x))
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                };
+                Ok(OutboundPolicy {
+                    allowed: parse_cidrs(outbound_policy.allowed)?,
+                    denied: parse_cidrs(outbound_policy.denied)?,
+                })
+            })
+            .transpose()?;
+        let transport_profile = patch
+            .transport_profile
+            .map(|x| x.parse::<TransportProfile>())
+            .transpose()?;
+        let address_family_preference = patch
+            .address_family_preference
+            .map(|x| x.parse::<AddressFamilyPreference>())
+            .transpose()?;
+        let dns_servers = patch
+            .dns_servers
+            .map(|dns_servers| {
+                dns_servers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .map(|x| {
+                        // the port is optional
+                        x.parse::<SocketAddr>()
+                            .or_else(|_| x.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+                            .with_context(|| format!("invalid dns server: {}", x))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        if let Some(peer_metadata) = &patch.peer_metadata {
+            let size = peer_metadata_size(&peer_metadata.entries);
+            if size > MAX_PEER_METADATA_SIZE {
+                anyhow::bail!(
+                    "peer metadata of {} bytes exceeds the limit of {} bytes",
+                    size,
+                    MAX_PEER_METADATA_SIZE
+                );
+            }
+        }
+        let global_ctx = weak_upgrade(&self.global_ctx)?;
+        let peer_manager = weak_upgrade(&self.peer_manager)?;
+
         self.patch_port_forwards(patch.port_forwards).await?;
         self.patch_acl(patch.acl).await?;
         self.patch_proxy_networks(patch.proxy_networks).await?;
@@ -251,12 +329,16 @@ impl InstanceConfigPatcher {
         self.patch_exit_nodes(patch.exit_nodes).await?;
         self.patch_mapped_listeners(patch.mapped_listeners).await?;
         self.patch_connector(patch.connectors).await?;
+        self.patch_route_failover_policies(patch.route_failover_policies)
+            .await?;
 
-        let global_ctx = weak_upgrade(&self.global_ctx)?;
         if let Some(hostname) = patch.hostname {
             global_ctx.set_hostname(hostname.clone());
             global_ctx.config.set_hostname(Some(hostname));
         }
+        if let Some(peer_metadata) = patch.peer_metadata {
+            global_ctx.set_peer_metadata(peer_metadata.entries)?;
+        }
         if let Some(ipv4) = patch.ipv4 {
             if !global_ctx.config.get_dhcp() {
                 global_ctx.set_ipv4(Some(ipv4.into()));
@@ -267,6 +349,73 @@ impl InstanceConfigPatcher {
             global_ctx.set_ipv6(Some(ipv6.into()));
             global_ctx.config.set_ipv6(Some(ipv6.into()));
         }
+        if let Some(bind_source_ip) = bind_source_ip {
+            global_ctx.set_bind_source_ip(bind_source_ip);
+        }
+        if let Some(observer_mode) = patch.observer_mode {
+            global_ctx.set_observer_mode(observer_mode);
+        }
+        if let Some(relay_enabled) = patch.relay_enabled {
+            global_ctx.set_relay_enabled(relay_enabled);
+        }
+        if let Some(lan_discovery) = patch.lan_discovery {
+            global_ctx.set_lan_discovery(lan_discovery);
+        }
+        if let Some(enabled) = patch.serve_throughput_tests {
+            global_ctx.set_serve_throughput_tests(enabled);
+        }
+        if let Some(reconnect_buffer) = patch.reconnect_buffer {
+            peer_manager.get_reconnect_buffer().configure(
+                reconnect_buffer.max_packets,
+                Duration::from_millis(reconnect_buffer.max_ms as u64),
+            );
+        }
+        for (peer_id, preference) in transport_preferences {
+            peer_manager.set_peer_transport_preference(peer_id, preference);
+        }
+        if let Some(outbound_policy) = outbound_policy {
+            global_ctx.set_outbound_policy(outbound_policy);
+        }
+        if let Some(preferred_relay) = patch.preferred_relay {
+            global_ctx.set_preferred_relay(Some(preferred_relay).filter(|x| *x != 0));
+        }
+        if let Some(stun_info_hint) = patch.stun_info_hint {
+            global_ctx
+                .get_stun_info_collector()
+                .set_stun_info_hint(stun_info_hint);
+        }
+        if patch.redetect_nat == Some(true) {
+            global_ctx.get_stun_info_collector().update_stun_info();
+        }
+        if patch.connect_now == Some(true) {
+            global_ctx.wake_up("forced");
+        }
+        if let Some(max_peers) = patch.max_peers {
+            global_ctx.set_max_peers(Some(max_peers).filter(|x| *x != 0));
+        }
+        if let Some(max) = patch.max_reconnect_attempts {
+            global_ctx.set_max_reconnect_attempts(Some(max).filter(|x| *x != 0));
+        }
+        if let Some(transport_profile) = transport_profile {
+            global_ctx.set_transport_profile(transport_profile);
+        }
+        if let Some(address_family_preference) = address_family_preference {
+            global_ctx.set_address_family_preference(address_family_preference);
+        }
+        if let Some(encryption_enabled) = patch.encryption_enabled {
+            if !encryption_enabled {
+                tracing::warn!("encryption disabled, packets to peers are sent in plain text");
+            }
+            peer_manager.set_encryption_enabled(encryption_enabled);
+        }
+        if let Some(dns_servers) = dns_servers {
+            global_ctx.set_dns_servers(dns_servers);
+        }
+        if let Some(keepalive_interval_ms) = patch.keepalive_interval_ms {
+            global_ctx.set_keepalive_interval(
+                Some(Duration::from_millis(keepalive_interval_ms as u64)).filter(|x| !x.is_zero()),
+            );
+        }
 
         global_ctx.issue_event(GlobalCtxEvent::ConfigPatched(patch_for_event));
 
@@ -338,6 +487,9 @@ impl InstanceConfigPatcher {
             return Ok(());
         };
         let global_ctx = weak_upgrade(&self.global_ctx)?;
+        let old_acl = global_ctx.config.get_acl();
+        let old_tcp_whitelist = global_ctx.config.get_tcp_whitelist();
+        let old_udp_whitelist = global_ctx.config.get_udp_whitelist();
         if let Some(acl) = acl_patch.acl {
             global_ctx.config.set_acl(Some(acl));
         }
@@ -363,9 +515,17 @@ impl InstanceConfigPatcher {
             crate::proto::api::config::patch_vec(&mut current_whitelist, patches);
             global_ctx.config.set_udp_whitelist(current_whitelist);
         }
-        global_ctx
-            .get_acl_filter()
-            .reload_rules(AclRuleBuilder::build(&global_ctx)?.as_ref());
+        let acl = match AclRuleBuilder::build(&global_ctx) {
+            Ok(acl) => acl,
+            Err(e) => {
+                // keep the rules that are currently in effect if the new ones are invalid
+                global_ctx.config.set_acl(old_acl);
+                global_ctx.config.set_tcp_whitelist(old_tcp_whitelist);
+                global_ctx.config.set_udp_whitelist(old_udp_whitelist);
+                return Err(e);
+            }
+        };
+        global_ctx.get_acl_filter().reload_rules(acl.as_ref());
         Ok(())
     }
 
@@ -408,6 +568,50 @@ impl InstanceConfigPatcher {
         Ok(())
     }
 
+    async fn patch_route_failover_policies(
+        &self,
+        policies: Vec<crate::proto::api::config::RouteFailoverPolicyPatch>,
+    ) -> Result<(), anyhow::Error> {
+        if policies.is_empty() {
+            return Ok(());
+        }
+        let global_ctx = weak_upgrade(&self.global_ctx)?;
+        for policy_patch in policies {
+            let parse_cidr = || {
+                policy_patch.cidr.parse::<IpCidr>().with_context(|| {
+                    format!("invalid route failover policy cidr: {}", policy_patch.cidr)
+                })
+            };
+            match ConfigPatchAction::try_from(policy_patch.action) {
+                Ok(ConfigPatchAction::Add) => {
+                    let cidr = parse_cidr()?;
+                    let policy = RouteFailoverPolicy {
+                        max_latency_ms: Some(policy_patch.max_latency_ms).filter(|x| *x != 0),
+                        max_loss_percent: Some(policy_patch.max_loss_percent).filter(|x| *x != 0),
+                    };
+                    tracing::info!("Route failover policy added: {} {:?}", cidr, policy);
+                    global_ctx.set_route_failover_policy(cidr, Some(policy));
+                }
+                Ok(ConfigPatchAction::Remove) => {
+                    let cidr = parse_cidr()?;
+                    tracing::info!("Route failover policy removed: {}", cidr);
+                    global_ctx.set_route_failover_policy(cidr, None);
+                }
+                Ok(ConfigPatchAction::Clear) => {
+                    tracing::info!("Route failover policies cleared.");
+                    global_ctx.clear_route_failover_policies();
+                }
+                Err(_) => {
+                    tracing::warn!(
+                        "Invalid route failover policy action: {}",
+                        policy_patch.action
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn patch_routes(
         &self,
         routes: Vec<crate::proto::api::config::RoutePatch>,
@@ -516,6 +720,7 @@ pub struct Instance {
     listener_manager: Arc<Mutex<ListenerManager<PeerManager>>>,
     conn_manager: Arc<ManualConnectorManager>,
     direct_conn_manager: Arc<DirectConnectorManager>,
+    lan_discovery: Arc<LanDiscovery>,
     udp_hole_puncher: Arc<Mutex<UdpHolePunchConnector>>,
     tcp_hole_puncher: Arc<Mutex<TcpHolePunchConnector>>,
 
@@ -574,6 +779,9 @@ impl Instance {
             DirectConnectorManager::new(global_ctx.clone(), peer_manager.clone());
         direct_conn_manager.run();
 
+        let mut lan_discovery = LanDiscovery::new(global_ctx.clone(), peer_manager.clone());
+        lan_discovery.run();
+
         let udp_hole_puncher = UdpHolePunchConnector::new(peer_manager.clone());
         let tcp_hole_puncher = TcpHolePunchConnector::new(peer_manager.clone());
 
@@ -598,6 +806,7 @@ impl Instance {
             listener_manager,
             conn_manager,
             direct_conn_manager: Arc::new(direct_conn_manager),
+            lan_discovery: Arc::new(lan_discovery),
             udp_hole_puncher: Arc::new(Mutex::new(udp_hole_puncher)),
             tcp_hole_puncher: Arc::new(Mutex::new(tcp_hole_puncher)),
 
@@ -635,18 +844,26 @@ impl Instance {
     }
 
     // use a mock nic ctx to consume packets.
+    async fn stop_magic_dns(arc_nic_ctx: &ArcNicCtx) {
+        let magic_dns = arc_nic_ctx
+            .lock()
+            .await
+            .as_mut()
+            .and_then(|x| x.magic_dns.take());
+        if let Some(dns_runner) = magic_dns {
+            dns_runner.dns_runner_cancel_token.cancel();
+            tracing::debug!("cancelling dns runner task");
+            let ret = dns_runner.dns_runner_task.await;
+            tracing::debug!("dns runner task cancelled, ret: {:?}", ret);
+        }
+    }
+
     async fn clear_nic_ctx(
         arc_nic_ctx: ArcNicCtx,
         packet_recv: Arc<Mutex<PacketRecvChanReceiver>>,
     ) {
-        if let Some(old_ctx) = arc_nic_ctx.lock().await.take() {
-            if let Some(dns_runner) = old_ctx.magic_dns {
-                dns_runner.dns_runner_cancel_token.cancel();
-                tracing::debug!("cancelling dns runner task");
-                let ret = dns_runner.dns_runner_task.await;
-                tracing::debug!("dns runner task cancelled, ret: {:?}", ret);
-            }
-        };
+        Self::stop_magic_dns(&arc_nic_ctx).await;
+        arc_nic_ctx.lock().await.take();
 
         let mut tasks = JoinSet::new();
         tasks.spawn(async move {
@@ -701,6 +918,9 @@ impl Instance {
         let _peer_packet_receiver = self.peer_packet_receiver.clone();
         tokio::spawn(async move {
             let default_ipv4_addr = Ipv4Inet::new(Ipv4Addr::new(10, 126, 126, 0), 24).unwrap();
+            // an ip already in the config is taken first if free, e.g. the one used before
+            // the instance was restarted
+            let preferred_ipv4 = global_ctx_c.config.get_ipv4();
             let mut current_dhcp_ip: Option<Ipv4Inet> = None;
             let mut next_sleep_time = 0;
             let nic_closed_notifier = Arc::new(Notify::new());
@@ -744,11 +964,15 @@ impl Instance {
                 }
 
                 // find an available ip in the subnet
-                let candidate_ipv4_addr = dhcp_inet.network().iter().find(|ip| {
-                    ip.address() != dhcp_inet.first_address()
-                        && ip.address() != dhcp_inet.last_address()
-                        && !used_ipv4.contains(ip)
-                });
+                let candidate_ipv4_addr = preferred_ipv4
+                    .filter(|ip| ip.network() == dhcp_inet.network() && !used_ipv4.contains(ip))
+                    .or_else(|| {
+                        dhcp_inet.network().iter().find(|ip| {
+                            ip.address() != dhcp_inet.first_address()
+                                && ip.address() != dhcp_inet.last_address()
+                                && !used_ipv4.contains(ip)
+                        })
+                    });
 
                 if current_dhcp_ip == candidate_ipv4_addr {
                     continue;
@@ -880,6 +1104,66 @@ impl Instance {
         });
     }
 
+    // time to the first peer, for onboarding metrics. only a weak ref is kept, an instance
+    // that never connects closes the event bus when it is dropped.
+    fn watch_first_peer(global_ctx: &ArcGlobalCtx, started_at: Instant) {
+        let mut events = global_ctx.subscribe();
+        let global_ctx = Arc::downgrade(global_ctx);
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(GlobalCtxEvent::PeerAdded(peer_id)) => {
+                        let Some(global_ctx) = global_ctx.upgrade() else {
+                            return;
+                        };
+                        global_ctx.issue_event(GlobalCtxEvent::FirstPeerConnected(
+                            peer_id,
+                            started_at.elapsed().as_millis() as u64,
+                        ));
+                        return;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    // sends the packets held during a reconnect once the routes changed or a peer was
+    // added, sends to a destination that is reachable again flush its packets before that.
+    // the peer manager is only upgraded for a flush, the buffer alone is kept meanwhile.
+    fn run_reconnect_buffer_flush(peer_manager: &Arc<PeerManager>) {
+        let buffer = peer_manager.get_reconnect_buffer();
+        let Some(mut route_changes) = peer_manager.subscribe_route_changes() else {
+            return;
+        };
+        let mut events = peer_manager.get_global_ctx().subscribe();
+        let peer_manager = Arc::downgrade(peer_manager);
+        tokio::spawn(async move {
+            loop {
+                buffer.wait_for_packets().await;
+                while !buffer.is_empty() {
+                    tokio::select! {
+                        changed = route_changes.changed() => {
+                            if changed.is_err() {
+                                return;
+                            }
+                        }
+                        event = events.recv() => match event {
+                            Ok(GlobalCtxEvent::PeerAdded(_)) => {}
+                            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return,
+                        },
+                    }
+                    let Some(peer_manager) = peer_manager.upgrade() else {
+                        return;
+                    };
+                    peer_manager.flush_reconnect_buffer().await;
+                }
+            }
+        });
+    }
+
     async fn run_quic_dst(&mut self) -> Result<(), Error> {
         if self.global_ctx.get_flags().disable_quic_input {
             return Ok(());
@@ -895,6 +1179,9 @@ impl Instance {
     }
 
     pub async fn run(&mut self) -> Result<(), Error> {
+        Self::watch_first_peer(&self.global_ctx, Instant::now());
+        Self::run_reconnect_buffer_flush(&self.peer_manager);
+
         self.listener_manager
             .lock()
             .await
@@ -1157,9 +1444,8 @@ impl Instance {
                 _: BaseController,
                 _request: GetStatsRequest,
             ) -> Result<GetStatsResponse, rpc_types::error::Error> {
-                let snapshots = weak_upgrade(&self.global_ctx)?
-                    .stats_manager()
-                    .get_all_metrics();
+                let stats_manager = weak_upgrade(&self.global_ctx)?.stats_manager().clone();
+                let snapshots = stats_manager.get_all_metrics();
 
                 let metrics = snapshots
                     .into_iter()
@@ -1177,7 +1463,10 @@ impl Instance {
                     })
                     .collect();
 
-                Ok(GetStatsResponse { metrics })
+                Ok(GetStatsResponse {
+                    metrics,
+                    tun_idle_ms: stats_manager.tun_idle_duration().as_millis() as u64,
+                })
             }
 
             async fn get_prometheus_stats(
@@ -1402,6 +1691,9 @@ impl Instance {
         self.peer_packet_receiver.clone()
     }
 
+    /// passed as the tun fd to run without a tun device.
+    pub const NO_TUN_FD: i32 = -1;
+
     #[cfg(any(target_os = "android", target_env = "ohos"))]
     pub async fn setup_nic_ctx_for_android(
         nic_ctx: ArcNicCtx,
@@ -1409,12 +1701,24 @@ impl Instance {
         peer_manager: Arc<PeerManager>,
         peer_packet_receiver: Arc<Mutex<PacketRecvChanReceiver>>,
         fd: i32,
+        ipv6_fd: Option<i32>,
     ) -> Result<(), anyhow::Error> {
-        println!("setup_nic_ctx_for_android, fd: {}", fd);
-        Self::clear_nic_ctx(nic_ctx.clone(), peer_packet_receiver.clone()).await;
-        if fd <= 0 {
+        tracing::debug!(fd, ?ipv6_fd, "setup_nic_ctx_for_android");
+        // -1 detaches the tun device. 0 is stdin, a caller passing it has most likely
+        // lost its real fd, so it is refused rather than taken as no tun.
+        if fd == Self::NO_TUN_FD {
+            Self::clear_nic_ctx(nic_ctx.clone(), peer_packet_receiver.clone()).await;
             return Ok(());
         }
+        if fd <= 0 {
+            anyhow::bail!("invalid tun fd {}", fd);
+        }
+        if let Some(ipv6_fd) = ipv6_fd.filter(|x| *x <= 0 || *x == fd) {
+            anyhow::bail!("invalid ipv6 tun fd {}", ipv6_fd);
+        }
+        // the old device keeps working until the new one is up, and stays if that fails.
+        // packets from peers queue up until the old ctx is dropped and releases the
+        // receiver, peer conns and routes are not touched.
         let close_notifier = Arc::new(Notify::new());
         let mut new_nic_ctx = NicCtx::new(
             global_ctx.clone(),
@@ -1423,9 +1727,10 @@ impl Instance {
             close_notifier.clone(),
         );
         new_nic_ctx
-            .run_for_android(fd)
+            .run_for_android(fd, ipv6_fd)
             .await
             .with_context(|| "add ip failed")?;
+        Self::stop_magic_dns(&nic_ctx).await;
 
         let magic_dns_runner = if let Some(ipv4) = global_ctx.get_ipv4() {
             Self::create_magic_dns_runner(peer_manager.clone(), None, ipv4)
@@ -1476,10 +1781,36 @@ impl Drop for Instance {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
+
     use crate::{
-        instance::instance::InstanceRpcServerHook, proto::rpc_impl::standalone::RpcServerHook,
+        common::global_ctx::{tests::get_mock_global_ctx, GlobalCtxEvent},
+        instance::instance::{Instance, InstanceRpcServerHook},
+        proto::rpc_impl::standalone::RpcServerHook,
     };
 
+    #[tokio::test]
+    async fn test_first_peer_connected() {
+        let global_ctx = get_mock_global_ctx();
+        let mut events = global_ctx.subscribe();
+        Instance::watch_first_peer(&global_ctx, Instant::now() - Duration::from_millis(100));
+
+        global_ctx.issue_event(GlobalCtxEvent::PeerAdded(1));
+        global_ctx.issue_event(GlobalCtxEvent::PeerAdded(2));
+        assert_eq!(events.recv().await.unwrap(), GlobalCtxEvent::PeerAdded(1));
+        assert_eq!(events.recv().await.unwrap(), GlobalCtxEvent::PeerAdded(2));
+        let GlobalCtxEvent::FirstPeerConnected(peer_id, elapsed_ms) = events.recv().await.unwrap()
+        else {
+            panic!("expect first peer connected");
+        };
+        assert_eq!(peer_id, 1);
+        assert!(elapsed_ms >= 100);
+
+        // only the first peer is reported
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(events.try_recv().is_err());
+    }
+
     #[tokio::test]
     async fn test_rpc_portal_whitelist() {
         use cidr::IpCidr;
@@ -127,6 +127,7 @@ impl<H: TunnelHandlerForListener + Send + Sync + 'static + Debug> ListenerManage
             let l = l.clone();
             let Ok(_) = get_listener_by_url(&l, self.global_ctx.clone()) else {
                 let msg = format!("failed to get listener by url: {}, maybe not supported", l);
+                self.global_ctx.set_listener_status(&l, Err(msg.clone()));
                 self.global_ctx
                     .issue_event(GlobalCtxEvent::ListenerAddFailed(l.clone(), msg));
                 continue;
@@ -183,15 +184,19 @@ impl<H: TunnelHandlerForListener + Send + Sync + 'static + Debug> ListenerManage
         let mut err_count = 0;
         loop {
             let mut l = (creator)();
+            // the bound url differs from it for port 0
+            let requested = l.local_url();
             let _g = global_ctx.net_ns.guard();
             match l.listen().await {
                 Ok(_) => {
                     err_count = 0;
+                    global_ctx.set_listener_status(&requested, Ok(&l.local_url()));
                     global_ctx.add_running_listener(l.local_url());
                     global_ctx.issue_event(GlobalCtxEvent::ListenerAdded(l.local_url()));
                 }
                 Err(e) => {
                     tracing::error!(?e, ?l, "listener listen error");
+                    global_ctx.set_listener_status(&requested, Err(e.to_string()));
                     global_ctx.issue_event(GlobalCtxEvent::ListenerAddFailed(
                         l.local_url(),
                         format!("error: {:?}, retry listen later...", e),
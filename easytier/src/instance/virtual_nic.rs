@@ -10,7 +10,7 @@ use std::{
 use crate::{
     common::{
         error::Error,
-        global_ctx::{ArcGlobalCtx, GlobalCtxEvent},
+        global_ctx::{ArcGlobalCtx, GlobalCtx, GlobalCtxEvent},
         ifcfg::{IfConfiger, IfConfiguerTrait},
     },
     instance::proxy_cidrs_monitor::ProxyCidrsMonitor,
@@ -447,7 +447,26 @@ impl VirtualNic {
         &mut self,
         tun_fd: std::os::fd::RawFd,
     ) -> Result<Box<dyn Tunnel>, Error> {
-        println!("tun_fd: {}", tun_fd);
+        let ft = Self::create_tunnel_for_fd(tun_fd)?;
+        self.ifname =
+            Some(Self::ifname_of_fd(tun_fd).unwrap_or_else(|| format!("tunfd_{}", tun_fd)));
+        Ok(ft)
+    }
+
+    /// name the kernel gave the tun device behind `tun_fd`, the vpn service of the system
+    /// creates it and only hands out the fd.
+    #[cfg(any(target_os = "android", target_env = "ohos"))]
+    fn ifname_of_fd(tun_fd: std::os::fd::RawFd) -> Option<String> {
+        let info = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", tun_fd)).ok()?;
+        info.lines()
+            .find_map(|x| x.strip_prefix("iff:"))
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+    }
+
+    #[cfg(any(target_os = "android", target_env = "ohos"))]
+    fn create_tunnel_for_fd(tun_fd: std::os::fd::RawFd) -> Result<Box<dyn Tunnel>, Error> {
+        tracing::debug!(tun_fd, "create tunnel for fd");
         let mut config = Configuration::default();
         config.layer(Layer::L3);
         config.raw_fd(tun_fd);
@@ -465,9 +484,6 @@ impl VirtualNic {
             ),
             None,
         );
-
-        self.ifname = Some(format!("tunfd_{}", tun_fd));
-
         Ok(Box::new(ft))
     }
 
@@ -502,11 +518,7 @@ impl VirtualNic {
 
         let dev = AsyncDevice::new(dev)?;
 
-        let flags = self.global_ctx.config.get_flags();
-        let mut mtu_in_config = flags.mtu;
-        if flags.enable_encryption {
-            mtu_in_config -= 20;
-        }
+        let mtu_in_config = self.global_ctx.get_overlay_mtu();
         {
             // set mtu by ourselves, rust-tun does not handle it correctly on windows
             let _g = self.global_ctx.net_ns.guard();
@@ -682,6 +694,60 @@ impl NicCtx {
         Ok(())
     }
 
+    // whether a packet of the system to `dst` is meant for a peer: unicast to the
+    // virtual network, to the manual routes or, with exit nodes, to anywhere. multicast,
+    // broadcast and link local traffic like neighbor discovery is sent by the system on
+    // its own. before dhcp assigned the ipv4 any unicast ipv4 counts.
+    fn is_peer_traffic(global_ctx: &GlobalCtx, dst: IpAddr) -> bool {
+        let has_exit_nodes = !global_ctx.config.get_exit_nodes().is_empty();
+        match dst {
+            IpAddr::V4(dst) => {
+                if dst.is_multicast()
+                    || dst.is_broadcast()
+                    || dst.is_unspecified()
+                    || dst.is_link_local()
+                    || dst.is_loopback()
+                {
+                    return false;
+                }
+                let in_routes = || {
+                    global_ctx
+                        .config
+                        .get_routes()
+                        .is_some_and(|x| x.iter().any(|x| x.contains(&dst)))
+                };
+                match global_ctx.get_ipv4() {
+                    Some(ipv4) if dst == ipv4.network().last_address() => false,
+                    Some(ipv4) => ipv4.network().contains(&dst) || in_routes() || has_exit_nodes,
+                    None => true,
+                }
+            }
+            IpAddr::V6(dst) => {
+                if dst.is_multicast()
+                    || dst.is_unspecified()
+                    || dst.is_unicast_link_local()
+                    || dst.is_loopback()
+                {
+                    return false;
+                }
+                global_ctx
+                    .get_ipv6()
+                    .is_some_and(|x| x.network().contains(&dst))
+                    || has_exit_nodes
+            }
+        }
+    }
+
+    // the first packet for a peer wakes an instance started with connect_on_demand. no
+    // route is known before the peers connect, the packet itself is dropped.
+    #[inline]
+    fn wake_on_traffic(mgr: &PeerManager, dst: IpAddr) {
+        let global_ctx = mgr.get_global_ctx_ref();
+        if global_ctx.is_dormant() && Self::is_peer_traffic(global_ctx, dst) {
+            global_ctx.wake_up("traffic");
+        }
+    }
+
     async fn do_forward_nic_to_peers_ipv4(ret: ZCPacket, mgr: &PeerManager) {
         if let Some(ipv4) = Ipv4Packet::new(ret.payload()) {
             if ipv4.get_version() != 4 {
@@ -698,6 +764,8 @@ impl NicCtx {
                 "[USER_PACKET] recv new packet from tun device and forward to peers."
             );
 
+            Self::wake_on_traffic(mgr, IpAddr::V4(dst_ipv4));
+
             // Subnet A is proxied as 10.0.0.0/24, and Subnet B is also proxied as 10.0.0.0/24.
             //
             // Subnet A has received a route advertised by Subnet B. As a result, A can reach
@@ -754,6 +822,7 @@ impl NicCtx {
                 return;
             }
 
+            Self::wake_on_traffic(mgr, IpAddr::V6(dst_ipv6));
             // TODO: use zero-copy
             let send_ret = mgr
                 .send_msg_by_ip(ret, IpAddr::V6(dst_ipv6), Some(src_ipv6) == my_ipv6)
@@ -790,12 +859,14 @@ impl NicCtx {
             return Err(anyhow::anyhow!("peer manager not available").into());
         };
         let close_notifier = self.close_notifier.clone();
+        let stats_manager = self.global_ctx.stats_manager().clone();
         self.tasks.spawn(async move {
             while let Some(ret) = stream.next().await {
                 if ret.is_err() {
                     tracing::error!("read from nic failed: {:?}", ret);
                     break;
                 }
+                stats_manager.record_tun_traffic();
                 Self::do_forward_nic_to_peers(ret.unwrap(), mgr.as_ref()).await;
             }
             close_notifier.notify_one();
@@ -805,9 +876,19 @@ impl NicCtx {
         Ok(())
     }
 
-    fn do_forward_peers_to_nic(&mut self, mut sink: Pin<Box<dyn ZCPacketSink>>) {
+    fn do_forward_peers_to_nic(&mut self, sink: Pin<Box<dyn ZCPacketSink>>) {
+        self.do_forward_peers_to_nic_by_family(sink, None);
+    }
+
+    /// ipv6 packets go to `ipv6_sink` if there is one, the rest to `sink`.
+    fn do_forward_peers_to_nic_by_family(
+        &mut self,
+        mut sink: Pin<Box<dyn ZCPacketSink>>,
+        mut ipv6_sink: Option<Pin<Box<dyn ZCPacketSink>>>,
+    ) {
         let channel = self.peer_packet_receiver.clone();
         let close_notifier = self.close_notifier.clone();
+        let stats_manager = self.global_ctx.stats_manager().clone();
         self.tasks.spawn(async move {
             // unlock until coroutine finished
             let mut channel = channel.lock().await;
@@ -816,7 +897,12 @@ impl NicCtx {
                     "[USER_PACKET] forward packet from peers to nic. packet: {:?}",
                     packet
                 );
-                let ret = sink.send(packet).await;
+                stats_manager.record_tun_traffic();
+                let is_ipv6 = packet.payload().first().is_some_and(|x| *x >> 4 == 6);
+                let ret = match ipv6_sink.as_mut() {
+                    Some(ipv6_sink) if is_ipv6 => ipv6_sink.send(packet).await,
+                    _ => sink.send(packet).await,
+                };
                 if ret.is_err() {
                     tracing::error!(?ret, "do_forward_tunnel_to_nic sink error");
                 }
@@ -1008,8 +1094,25 @@ impl NicCtx {
         Ok(())
     }
 
+    /// with `ipv6_tun_fd` ipv6 packets are read from and written to that fd and the rest
+    /// to `tun_fd`, for platforms handing out one fd per address family.
     #[cfg(any(target_os = "android", target_env = "ohos"))]
-    pub async fn run_for_android(&mut self, tun_fd: std::os::fd::RawFd) -> Result<(), Error> {
+    pub async fn run_for_android(
+        &mut self,
+        tun_fd: std::os::fd::RawFd,
+        ipv6_tun_fd: Option<std::os::fd::RawFd>,
+    ) -> Result<(), Error> {
+        let ipv6_tunnel = match ipv6_tun_fd {
+            Some(fd) => match VirtualNic::create_tunnel_for_fd(fd) {
+                Ok(ret) => Some(ret),
+                Err(err) => {
+                    self.global_ctx
+                        .issue_event(GlobalCtxEvent::TunDeviceError(err.to_string()));
+                    return Err(err);
+                }
+            },
+            None => None,
+        };
         let tunnel = {
             let mut nic = self.nic.lock().await;
             match nic.create_dev_for_android(tun_fd).await {
@@ -1029,7 +1132,15 @@ impl NicCtx {
         let (stream, sink) = tunnel.split();
 
         self.do_forward_nic_to_peers_task(stream)?;
-        self.do_forward_peers_to_nic(sink);
+        let ipv6_sink = match ipv6_tunnel {
+            Some(ipv6_tunnel) => {
+                let (ipv6_stream, ipv6_sink) = ipv6_tunnel.split();
+                self.do_forward_nic_to_peers_task(ipv6_stream)?;
+                Some(ipv6_sink)
+            }
+            None => None,
+        };
+        self.do_forward_peers_to_nic_by_family(sink, ipv6_sink);
 
         Ok(())
     }
@@ -1037,9 +1148,48 @@ impl NicCtx {
 
 #[cfg(test)]
 mod tests {
-    use crate::common::{error::Error, global_ctx::tests::get_mock_global_ctx};
+    use crate::common::{
+        config::{ConfigLoader, TomlConfigLoader},
+        error::Error,
+        global_ctx::{tests::get_mock_global_ctx, GlobalCtx},
+    };
+
+    use super::{NicCtx, VirtualNic};
+
+    #[test]
+    fn test_is_peer_traffic() {
+        let config = TomlConfigLoader::default();
+        config.set_ipv4(Some("10.144.144.1/24".parse().unwrap()));
+        config.set_ipv6(Some("fd00::1/64".parse().unwrap()));
+        config.set_routes(Some(vec!["192.168.1.0/24".parse().unwrap()]));
+        let global_ctx = GlobalCtx::new(config);
+        let is_peer_traffic =
+            |dst: &str| NicCtx::is_peer_traffic(&global_ctx, dst.parse().unwrap());
+
+        assert!(is_peer_traffic("10.144.144.2"));
+        assert!(is_peer_traffic("192.168.1.10"));
+        assert!(is_peer_traffic("fd00::2"));
+        for dst in [
+            "10.144.144.255",
+            "255.255.255.255",
+            "224.0.0.251",
+            "169.254.1.1",
+            "8.8.8.8",
+            "ff02::1:ff00:2",
+            "ff02::16",
+            "fe80::2",
+            "2001:db8::1",
+        ] {
+            assert!(!is_peer_traffic(dst), "{}", dst);
+        }
 
-    use super::VirtualNic;
+        global_ctx
+            .config
+            .set_exit_nodes(vec!["10.144.144.2".parse().unwrap()]);
+        assert!(is_peer_traffic("8.8.8.8"));
+        assert!(is_peer_traffic("2001:db8::1"));
+        assert!(!is_peer_traffic("224.0.0.251"));
+    }
 
     async fn run_test_helper() -> Result<VirtualNic, Error> {
         let mut dev = VirtualNic::new(get_mock_global_ctx());
@@ -220,6 +220,15 @@ impl NetworkInstanceManager {
             .and_then(|instance| instance.value().get_api_service())
     }
 
+    pub fn subscribe_route_changes(
+        &self,
+        instance_id: &uuid::Uuid,
+    ) -> Option<tokio::sync::watch::Receiver<()>> {
+        self.instance_map
+            .get(instance_id)?
+            .subscribe_route_changes()
+    }
+
     pub fn set_tun_fd(&self, instance_id: &uuid::Uuid, fd: i32) -> Result<(), anyhow::Error> {
         let mut instance = self
             .instance_map
@@ -229,6 +238,20 @@ impl NetworkInstanceManager {
         Ok(())
     }
 
+    /// the fd last passed to set_tun_fd, it may not be in use yet
+    pub fn get_tun_fd(&self, instance_id: &uuid::Uuid) -> Option<i32> {
+        self.instance_map.get(instance_id)?.get_tun_fd()
+    }
+
+    pub fn set_ipv6_tun_fd(&self, instance_id: &uuid::Uuid, fd: i32) -> Result<(), anyhow::Error> {
+        let mut instance = self
+            .instance_map
+            .get_mut(instance_id)
+            .ok_or_else(|| anyhow::anyhow!("instance not found"))?;
+        instance.set_ipv6_tun_fd(fd);
+        Ok(())
+    }
+
     pub fn get_config_dir(&self) -> Option<&PathBuf> {
         self.config_dir.as_ref()
     }
@@ -361,6 +384,58 @@ fn handle_event(
                         );
                     }
 
+                    GlobalCtxEvent::ConnectorGaveUp(dst) => {
+                        print_event(
+                            instance_id,
+                            format!("gave up reconnecting to peer. dst: {}", dst),
+                        );
+                    }
+
+                    GlobalCtxEvent::PeerBlocked(peer_id, duration_ms) => {
+                        print_event(
+                            instance_id,
+                            format!(
+                                "peer blocked. peer: {}, duration: {}ms",
+                                peer_id, duration_ms
+                            ),
+                        );
+                    }
+
+                    GlobalCtxEvent::FirstPeerConnected(peer_id, elapsed_ms) => {
+                        print_event(
+                            instance_id,
+                            format!(
+                                "first peer connected. peer: {}, after: {}ms",
+                                peer_id, elapsed_ms
+                            ),
+                        );
+                    }
+
+                    GlobalCtxEvent::PeerUnblocked(peer_id) => {
+                        print_event(instance_id, format!("peer unblocked. peer: {}", peer_id));
+                    }
+
+                    GlobalCtxEvent::HolePunchStarted(peer_id) => {
+                        print_event(
+                            instance_id,
+                            format!("hole punching started. peer: {}", peer_id),
+                        );
+                    }
+
+                    GlobalCtxEvent::HolePunchSucceeded(peer_id) => {
+                        print_event(
+                            instance_id,
+                            format!("hole punching succeeded. peer: {}", peer_id),
+                        );
+                    }
+
+                    GlobalCtxEvent::HolePunchFailed(peer_id) => {
+                        print_event(
+                            instance_id,
+                            format!("hole punching failed. peer: {}", peer_id),
+                        );
+                    }
+
                     GlobalCtxEvent::VpnPortalStarted(portal) => {
                         print_event(
                             instance_id,
@@ -415,6 +490,20 @@ fn handle_event(
                         print_event(instance_id, format!("config patched. patch: {:?}", patch));
                     }
 
+                    GlobalCtxEvent::WokenOnDemand(reason) => {
+                        print_event(
+                            instance_id,
+                            format!("woken on demand, connecting to peers. reason: {}", reason),
+                        );
+                    }
+
+                    GlobalCtxEvent::RelayEnabledChanged(enabled) => {
+                        print_event(
+                            instance_id,
+                            format!("relay for other peers changed. enabled: {}", enabled),
+                        );
+                    }
+
                     GlobalCtxEvent::ProxyCidrsUpdated(added, removed) => {
                         print_event(
                             instance_id,
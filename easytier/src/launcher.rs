@@ -21,7 +21,10 @@ use std::{
     collections::VecDeque,
     sync::{atomic::AtomicBool, Arc, RwLock},
 };
-use tokio::{sync::broadcast, task::JoinSet};
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinSet,
+};
 
 pub type MyNodeInfo = crate::proto::api::manage::MyNodeInfo;
 
@@ -36,8 +39,14 @@ pub struct Event {
 struct EasyTierData {
     events: RwLock<VecDeque<Event>>,
     tun_fd: Arc<RwLock<Option<i32>>>,
+    // separate fd for ipv6 packets, none to use tun_fd for both families
+    ipv6_tun_fd: Arc<RwLock<Option<i32>>>,
+    // wakes the tun fd watcher so a new fd is taken right away
+    tun_fd_notifier: Arc<tokio::sync::Notify>,
     event_subscriber: RwLock<broadcast::Sender<GlobalCtxEvent>>,
     instance_stop_notifier: Arc<tokio::sync::Notify>,
+    // fires when the route table of the running instance changed
+    route_changes: RwLock<Option<watch::Receiver<()>>>,
 }
 
 impl Default for EasyTierData {
@@ -47,11 +56,20 @@ impl Default for EasyTierData {
             event_subscriber: RwLock::new(tx),
             events: RwLock::new(VecDeque::new()),
             tun_fd: Arc::new(RwLock::new(None)),
+            ipv6_tun_fd: Arc::new(RwLock::new(None)),
+            tun_fd_notifier: Arc::new(tokio::sync::Notify::new()),
             instance_stop_notifier: Arc::new(tokio::sync::Notify::new()),
+            route_changes: RwLock::new(None),
         }
     }
 }
 
+/// name of all threads running an instance, short enough to fit the 15 byte thread name
+/// limit of linux so the cpu time of an instance can be told apart by it.
+pub fn instance_thread_name(id: &uuid::Uuid) -> String {
+    format!("et-{}", &id.simple().to_string()[..8])
+}
+
 pub struct EasyTierLauncher {
     instance_alive: Arc<AtomicBool>,
     stop_flag: Arc<AtomicBool>,
@@ -99,23 +117,39 @@ impl EasyTierLauncher {
         let nic_ctx = instance.get_nic_ctx();
         let peer_packet_receiver = instance.get_peer_packet_receiver();
         let arc_tun_fd = data.tun_fd.clone();
+        let arc_ipv6_tun_fd = data.ipv6_tun_fd.clone();
+        let tun_fd_notifier = data.tun_fd_notifier.clone();
+        let read_tun_fds = move || {
+            let tun_fd = *arc_tun_fd.read().unwrap();
+            let ipv6_tun_fd = *arc_ipv6_tun_fd.read().unwrap();
+            (tun_fd, ipv6_tun_fd.filter(|x| *x != Instance::NO_TUN_FD))
+        };
 
         tasks.spawn(async move {
-            let mut old_tun_fd = arc_tun_fd.read().unwrap().clone();
+            let mut old_tun_fds = read_tun_fds();
             loop {
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                let tun_fd = arc_tun_fd.read().unwrap().clone();
-                if tun_fd != old_tun_fd && tun_fd.is_some() {
+                // failed setups are retried every second
+                let _ = tokio::time::timeout(
+                    std::time::Duration::from_secs(1),
+                    tun_fd_notifier.notified(),
+                )
+                .await;
+                let tun_fds = read_tun_fds();
+                let (Some(tun_fd), ipv6_tun_fd) = tun_fds else {
+                    continue;
+                };
+                if tun_fds != old_tun_fds {
                     let res = Instance::setup_nic_ctx_for_android(
                         nic_ctx.clone(),
                         global_ctx.clone(),
                         peer_mgr.clone(),
                         peer_packet_receiver.clone(),
-                        tun_fd.unwrap(),
+                        tun_fd,
+                        ipv6_tun_fd,
                     )
                     .await;
                     if res.is_ok() {
-                        old_tun_fd = tun_fd;
+                        old_tun_fds = tun_fds;
                     }
                 }
             }
@@ -157,6 +191,8 @@ impl EasyTierLauncher {
 
         instance.run().await?;
 
+        *data.route_changes.write().unwrap() =
+            instance.get_peer_manager().subscribe_route_changes();
         api_service
             .write()
             .unwrap()
@@ -168,6 +204,7 @@ impl EasyTierLauncher {
         tasks.abort_all();
         drop(tasks);
 
+        data.route_changes.write().unwrap().take();
         instance.clear_resources().await;
         drop(instance);
 
@@ -197,43 +234,51 @@ impl EasyTierLauncher {
         let data = self.data.clone();
         let api_service = self.api_service.clone();
 
-        self.thread_handle = Some(std::thread::spawn(move || {
-            let rt = if cfg.get_flags().multi_thread {
-                let worker_threads = 2.max(cfg.get_flags().multi_thread_count as usize);
-                tokio::runtime::Builder::new_multi_thread()
-                    .worker_threads(worker_threads)
-                    .enable_all()
-                    .build()
-            } else {
-                tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-            }
-            .unwrap();
+        let thread_name = instance_thread_name(&cfg.get_id());
+        let thread = std::thread::Builder::new().name(thread_name.clone());
+        self.thread_handle = Some(
+            thread
+                .spawn(move || {
+                    let rt = if cfg.get_flags().multi_thread {
+                        let worker_threads = 2.max(cfg.get_flags().multi_thread_count as usize);
+                        tokio::runtime::Builder::new_multi_thread()
+                            .worker_threads(worker_threads)
+                            .thread_name(thread_name)
+                            .enable_all()
+                            .build()
+                    } else {
+                        tokio::runtime::Builder::new_current_thread()
+                            .thread_name(thread_name)
+                            .enable_all()
+                            .build()
+                    }
+                    .unwrap();
 
-            let stop_notifier = Arc::new(tokio::sync::Notify::new());
+                    let stop_notifier = Arc::new(tokio::sync::Notify::new());
 
-            let stop_notifier_clone = stop_notifier.clone();
-            rt.spawn(async move {
-                while !stop_flag.load(std::sync::atomic::Ordering::Relaxed) {
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                }
-                stop_notifier_clone.notify_one();
-            });
+                    let stop_notifier_clone = stop_notifier.clone();
+                    rt.spawn(async move {
+                        while !stop_flag.load(std::sync::atomic::Ordering::Relaxed) {
+                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        }
+                        stop_notifier_clone.notify_one();
+                    });
 
-            let notifier = data.instance_stop_notifier.clone();
-            let ret = rt.block_on(Self::easytier_routine(
-                cfg,
-                stop_notifier,
-                api_service,
-                data,
-            ));
-            if let Err(e) = ret {
-                error_msg.write().unwrap().replace(format!("{:?}", e));
-            }
-            instance_alive.store(false, std::sync::atomic::Ordering::Relaxed);
-            notifier.notify_one();
-        }));
+                    let notifier = data.instance_stop_notifier.clone();
+                    let ret = rt.block_on(Self::easytier_routine(
+                        cfg,
+                        stop_notifier,
+                        api_service,
+                        data,
+                    ));
+                    if let Err(e) = ret {
+                        error_msg.write().unwrap().replace(format!("{:?}", e));
+                    }
+                    instance_alive.store(false, std::sync::atomic::Ordering::Relaxed);
+                    notifier.notify_one();
+                })
+                .unwrap(),
+        );
     }
 
     pub fn error_msg(&self) -> Option<String> {
@@ -393,9 +438,29 @@ impl NetworkInstance {
         self.config.get_network_identity().network_name
     }
 
+    pub fn uses_tun(&self) -> bool {
+        !self.config.get_flags().no_tun
+    }
+
     pub fn set_tun_fd(&mut self, tun_fd: i32) {
         if let Some(launcher) = self.launcher.as_ref() {
             launcher.data.tun_fd.write().unwrap().replace(tun_fd);
+            launcher.data.tun_fd_notifier.notify_one();
+        }
+    }
+
+    pub fn get_tun_fd(&self) -> Option<i32> {
+        self.launcher
+            .as_ref()
+            .and_then(|launcher| *launcher.data.tun_fd.read().unwrap())
+    }
+
+    /// ipv6 packets are read from and written to this fd instead of the one of
+    /// set_tun_fd, -1 to go back to that one for both families.
+    pub fn set_ipv6_tun_fd(&mut self, tun_fd: i32) {
+        if let Some(launcher) = self.launcher.as_ref() {
+            launcher.data.ipv6_tun_fd.write().unwrap().replace(tun_fd);
+            launcher.data.tun_fd_notifier.notify_one();
         }
     }
 
@@ -422,6 +487,13 @@ impl NetworkInstance {
             .map(|launcher| launcher.data.event_subscriber.read().unwrap().subscribe())
     }
 
+    /// none until the instance runs, the receiver errors once it stopped
+    pub fn subscribe_route_changes(&self) -> Option<watch::Receiver<()>> {
+        self.launcher
+            .as_ref()
+            .and_then(|launcher| launcher.data.route_changes.read().unwrap().clone())
+    }
+
     pub fn get_stop_notifier(&self) -> Option<Arc<tokio::sync::Notify>> {
         self.launcher
             .as_ref()
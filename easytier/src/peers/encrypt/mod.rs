@@ -1,4 +1,7 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use crate::{common::config::EncryptionAlgorithm, tunnel::packet_def::ZCPacket};
 
@@ -50,6 +53,45 @@ impl Encryptor for NullCipher {
     }
 }
 
+/// Wraps a cipher so that encryption of outgoing packets can be turned on and off at
+/// runtime. Incoming packets are always given to the cipher, which passes unencrypted
+/// ones through, so peers keep talking while they switch one after another.
+pub struct SwitchableCipher {
+    cipher: Arc<dyn Encryptor>,
+    enabled: AtomicBool,
+}
+
+impl SwitchableCipher {
+    pub fn new(cipher: Arc<dyn Encryptor>, enabled: bool) -> Self {
+        Self {
+            cipher,
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+impl Encryptor for SwitchableCipher {
+    fn encrypt(&self, zc_packet: &mut ZCPacket) -> Result<(), Error> {
+        if self.is_enabled() {
+            self.cipher.encrypt(zc_packet)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn decrypt(&self, zc_packet: &mut ZCPacket) -> Result<(), Error> {
+        self.cipher.decrypt(zc_packet)
+    }
+}
+
 /// Create an encryptor based on the algorithm name
 pub fn create_encryptor(
     algorithm: &str,
@@ -116,3 +158,41 @@ pub fn create_encryptor(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        peers::encrypt::{xor_cipher::XorCipher, Encryptor, SwitchableCipher},
+        tunnel::packet_def::ZCPacket,
+    };
+
+    #[test]
+    fn test_switchable_cipher() {
+        let cipher = SwitchableCipher::new(Arc::new(XorCipher::new(b"test_key_123456")), false);
+        let text = b"Hello, World!";
+        let new_packet = || {
+            let mut packet = ZCPacket::new_with_payload(text);
+            packet.fill_peer_manager_hdr(0, 0, 0);
+            packet
+        };
+
+        let mut plain = new_packet();
+        cipher.encrypt(&mut plain).unwrap();
+        assert!(!plain.peer_manager_header().unwrap().is_encrypted());
+        assert_eq!(plain.payload(), text);
+
+        cipher.set_enabled(true);
+        let mut encrypted = new_packet();
+        cipher.encrypt(&mut encrypted).unwrap();
+        assert!(encrypted.peer_manager_header().unwrap().is_encrypted());
+
+        // both kinds are accepted whatever the switch is
+        cipher.set_enabled(false);
+        cipher.decrypt(&mut encrypted).unwrap();
+        assert_eq!(encrypted.payload(), text);
+        cipher.decrypt(&mut plain).unwrap();
+        assert_eq!(plain.payload(), text);
+    }
+}
@@ -1,7 +1,9 @@
 mod graph_algo;
 
 pub mod acl_filter;
+pub mod packet_capture;
 pub mod peer;
+pub mod reconnect_buffer;
 // pub mod peer_conn;
 pub mod peer_conn;
 pub mod peer_conn_ping;
@@ -43,6 +45,19 @@ pub trait NicPacketFilter {
     }
 }
 
+/// Asked whether a peer of the own network that is not connected yet may join through
+/// a direct conn, with the id of the asking instance. Set with PEER_CONN_APPROVER, see
+/// `PeerManager::add_new_peer_conn`. The packets from and to a rejected peer are dropped
+/// on every path, peers never connected directly are not asked.
+pub type PeerConnApprover = std::sync::Arc<
+    dyn Fn(
+            uuid::Uuid,
+            crate::proto::api::instance::PeerConnInfo,
+        ) -> futures::future::BoxFuture<'static, bool>
+        + Send
+        + Sync,
+>;
+
 type BoxPeerPacketFilter = Box<dyn PeerPacketFilter + Send + Sync>;
 type BoxNicPacketFilter = Box<dyn NicPacketFilter + Send + Sync>;
 
@@ -0,0 +1,165 @@
+//! In-memory capture of the ip packets exchanged with the nic, exported as pcap.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// bytes kept of each packet unless full packets are captured, enough for the ip and
+/// tcp headers with options.
+pub const CAPTURE_HEADER_LEN: usize = 128;
+/// upper bound of the memory a capture may use, whatever the caller asks for.
+pub const MAX_CAPTURE_BYTES: u64 = 64 * 1024 * 1024;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_SNAPLEN: u32 = 65535;
+// packets start with the ip header, no link layer
+const LINKTYPE_RAW: u32 = 101;
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+struct CaptureBuffer {
+    max_packets: u32,
+    max_bytes: usize,
+    full_packets: bool,
+    packets: u32,
+    // pcap records without the file header
+    records: Vec<u8>,
+}
+
+/// Records the packets passed to `capture` between `start` and `stop`. Disabled it costs
+/// one relaxed load per packet.
+pub struct PacketCapture {
+    enabled: AtomicBool,
+    buffer: Mutex<Option<CaptureBuffer>>,
+}
+
+impl Default for PacketCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketCapture {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            buffer: Mutex::new(None),
+        }
+    }
+
+    /// Starts a capture that stops recording once `max_packets` packets or `max_bytes`
+    /// bytes of pcap records are reached, `max_bytes` is capped at `MAX_CAPTURE_BYTES`.
+    /// Only the first `CAPTURE_HEADER_LEN` bytes of each packet are kept unless
+    /// `full_packets` is set. Returns false if a capture runs already.
+    pub fn start(&self, max_packets: u32, max_bytes: u64, full_packets: bool) -> bool {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.is_some() {
+            return false;
+        }
+        *buffer = Some(CaptureBuffer {
+            max_packets,
+            max_bytes: max_bytes.min(MAX_CAPTURE_BYTES) as usize,
+            full_packets,
+            packets: 0,
+            records: Vec::new(),
+        });
+        self.enabled.store(true, Ordering::Relaxed);
+        true
+    }
+
+    /// Ends the capture and returns the recorded packets as a pcap file, none if no
+    /// capture runs.
+    pub fn stop(&self) -> Option<Vec<u8>> {
+        let buffer = self.buffer.lock().unwrap().take()?;
+        self.enabled.store(false, Ordering::Relaxed);
+
+        let mut pcap = Vec::with_capacity(24 + buffer.records.len());
+        pcap.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        pcap.extend_from_slice(&2u16.to_le_bytes());
+        pcap.extend_from_slice(&4u16.to_le_bytes());
+        // thiszone and sigfigs
+        pcap.extend_from_slice(&[0u8; 8]);
+        pcap.extend_from_slice(&PCAP_SNAPLEN.to_le_bytes());
+        pcap.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        pcap.extend_from_slice(&buffer.records);
+        Some(pcap)
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn capture(&self, packet: &[u8]) {
+        if self.enabled.load(Ordering::Relaxed) {
+            self.record(packet);
+        }
+    }
+
+    fn record(&self, packet: &[u8]) {
+        let mut guard = self.buffer.lock().unwrap();
+        let Some(buffer) = guard.as_mut() else {
+            return;
+        };
+        let len = if buffer.full_packets {
+            packet.len().min(PCAP_SNAPLEN as usize)
+        } else {
+            packet.len().min(CAPTURE_HEADER_LEN)
+        };
+        if buffer.packets >= buffer.max_packets
+            || buffer.records.len() + PCAP_RECORD_HEADER_LEN + len > buffer.max_bytes
+        {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let records = &mut buffer.records;
+        records.extend_from_slice(&(now.as_secs() as u32).to_le_bytes());
+        records.extend_from_slice(&now.subsec_micros().to_le_bytes());
+        records.extend_from_slice(&(len as u32).to_le_bytes());
+        records.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        records.extend_from_slice(&packet[..len]);
+        buffer.packets += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_capture_bounds() {
+        let capture = PacketCapture::new();
+        capture.capture(&[0u8; 10]);
+        assert!(capture.stop().is_none());
+
+        assert!(capture.start(2, MAX_CAPTURE_BYTES, false));
+        assert!(!capture.start(2, MAX_CAPTURE_BYTES, false));
+        capture.capture(&[1u8; 1000]);
+        capture.capture(&[2u8; 20]);
+        capture.capture(&[3u8; 20]);
+        let pcap = capture.stop().unwrap();
+        assert!(!capture.is_capturing());
+        assert_eq!(&pcap[..4], &PCAP_MAGIC.to_le_bytes());
+        // the header of the first one and the second one fully, the third is over the limit
+        assert_eq!(
+            pcap.len(),
+            24 + 2 * PCAP_RECORD_HEADER_LEN + CAPTURE_HEADER_LEN + 20
+        );
+        let first = &pcap[24..24 + PCAP_RECORD_HEADER_LEN];
+        assert_eq!(&first[8..12], &(CAPTURE_HEADER_LEN as u32).to_le_bytes());
+        assert_eq!(&first[12..16], &1000u32.to_le_bytes());
+
+        assert!(capture.start(100, (PCAP_RECORD_HEADER_LEN + 50) as u64, true));
+        capture.capture(&[1u8; 50]);
+        capture.capture(&[1u8; 1]);
+        assert_eq!(
+            capture.stop().unwrap().len(),
+            24 + PCAP_RECORD_HEADER_LEN + 50
+        );
+    }
+}
@@ -14,7 +14,7 @@ use super::{
 use crate::{
     common::{
         error::Error,
-        global_ctx::{ArcGlobalCtx, GlobalCtxEvent},
+        global_ctx::{ArcGlobalCtx, GlobalCtxEvent, TransportPreference},
         PeerId,
     },
     tunnel::packet_def::ZCPacket,
@@ -41,6 +41,8 @@ pub struct Peer {
 
     default_conn_id: Arc<AtomicCell<PeerConnId>>,
     default_conn_id_clear_task: ScopedTask<()>,
+
+    transport_preference: AtomicCell<TransportPreference>,
 }
 
 impl Peer {
@@ -107,6 +109,9 @@ impl Peer {
             }
         }));
 
+        let transport_preference =
+            AtomicCell::new(global_ctx.get_peer_transport_preference(peer_node_id));
+
         Peer {
             peer_node_id,
             conns,
@@ -119,6 +124,8 @@ impl Peer {
             shutdown_notifier,
             default_conn_id,
             default_conn_id_clear_task,
+
+            transport_preference,
         }
     }
 
@@ -151,12 +158,16 @@ impl Peer {
             return Some(conn.clone());
         }
 
-        // find a conn with the smallest latency
-        let mut min_latency = u64::MAX;
+        // find a conn with the smallest latency, of the preferred transport if there is one
+        let preference = self.transport_preference.load();
+        let mut min_key = (true, u64::MAX);
         for conn in self.conns.iter() {
-            let latency = conn.value().get_stats().latency_us;
-            if latency < min_latency {
-                min_latency = latency;
+            let key = (
+                !preference.matches(conn.get_tunnel_type()),
+                conn.value().get_stats().latency_us,
+            );
+            if key < min_key {
+                min_key = key;
                 self.default_conn_id.store(conn.get_conn_id());
             }
         }
@@ -221,6 +232,12 @@ impl Peer {
     pub fn get_default_conn_id(&self) -> PeerConnId {
         self.default_conn_id.load()
     }
+
+    /// the default conn is selected again for the next packet
+    pub fn set_transport_preference(&self, preference: TransportPreference) {
+        self.transport_preference.store(preference);
+        self.default_conn_id.store(PeerConnId::default());
+    }
 }
 
 // pritn on drop
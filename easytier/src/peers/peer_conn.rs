@@ -6,6 +6,7 @@ use std::{
         atomic::{AtomicU32, Ordering},
         Arc,
     },
+    time::SystemTime,
 };
 
 use arc_swap::ArcSwapOption;
@@ -28,6 +29,7 @@ use crate::{
         defer,
         error::Error,
         global_ctx::ArcGlobalCtx,
+        new_peer_id,
         stats_manager::{CounterHandle, LabelSet, LabelType, MetricName},
         PeerId,
     },
@@ -94,6 +96,14 @@ struct PeerConnCounter {
     traffic_rx_packets: CounterHandle,
 }
 
+fn handshake_clock_diff_ms(rsp: &HandshakeRequest) -> Option<i64> {
+    let remote = SystemTime::try_from(rsp.send_time.clone()?).ok()?;
+    Some(match remote.duration_since(SystemTime::now()) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    })
+}
+
 pub struct PeerConn {
     conn_id: PeerConnId,
 
@@ -109,6 +119,9 @@ pub struct PeerConn {
 
     info: Option<HandshakeRequest>,
     is_client: Option<bool>,
+    // remote handshake timestamp minus our clock when it arrived
+    handshake_clock_diff_ms: Option<i64>,
+    established_at: SystemTime,
 
     // remote or local
     is_hole_punched: bool,
@@ -165,6 +178,8 @@ impl PeerConn {
 
             info: None,
             is_client: None,
+            handshake_clock_diff_ms: None,
+            established_at: SystemTime::now(),
 
             is_hole_punched: true,
 
@@ -192,6 +207,12 @@ impl PeerConn {
         self.is_hole_punched
     }
 
+    pub fn get_tunnel_type(&self) -> &str {
+        self.tunnel_info
+            .as_ref()
+            .map_or("", |x| x.tunnel_type.as_str())
+    }
+
     async fn wait_handshake(&self, need_retry: &mut bool) -> Result<HandshakeRequest, Error> {
         *need_retry = false;
 
@@ -268,6 +289,7 @@ impl PeerConn {
             version: VERSION,
             features: Vec::new(),
             network_name: network.network_name.clone(),
+            send_time: Some(SystemTime::now().into()),
             ..Default::default()
         };
 
@@ -309,6 +331,7 @@ impl PeerConn {
         Fn: FnMut(&mut Self, &HandshakeRequest) -> Result<(), Error> + Send,
     {
         let rsp = self.wait_handshake_loop().await?;
+        self.handshake_clock_diff_ms = handshake_clock_diff_ms(&rsp);
 
         handshake_recved(self, &rsp)?;
 
@@ -329,6 +352,7 @@ impl PeerConn {
     #[tracing::instrument]
     pub async fn do_handshake_as_server(&mut self) -> Result<(), Error> {
         let rsp = self.wait_handshake_loop().await?;
+        self.handshake_clock_diff_ms = handshake_clock_diff_ms(&rsp);
         tracing::info!("handshake request: {:?}", rsp);
         self.info = Some(rsp);
         self.is_client = Some(false);
@@ -350,6 +374,7 @@ impl PeerConn {
         self.send_handshake(true).await?;
         tracing::info!("waiting for handshake request from server");
         let rsp = self.wait_handshake_loop().await?;
+        self.handshake_clock_diff_ms = handshake_clock_diff_ms(&rsp);
         tracing::info!("handshake response: {:?}", rsp);
         self.info = Some(rsp);
         self.is_client = Some(true);
@@ -363,6 +388,41 @@ impl PeerConn {
         }
     }
 
+    /// handshakes as a client with the identity of `global_ctx` without joining the peer.
+    /// a member of the network tells whether the secret matches in its response, a relay
+    /// only drops the conn, so then it waits `wait` for the conn to close.
+    pub async fn verify_network_identity(
+        global_ctx: ArcGlobalCtx,
+        tunnel: Box<dyn Tunnel>,
+        wait: Duration,
+    ) -> Result<(), Error> {
+        let network = global_ctx.get_network_identity();
+        let mut conn = PeerConn::new(new_peer_id(), global_ctx, tunnel);
+        conn.do_handshake_as_client().await?;
+        let remote = conn.get_network_identity();
+        if remote.network_name == network.network_name {
+            return if remote == network {
+                Ok(())
+            } else {
+                Err(Error::SecretKeyError(
+                    "network secret not match".to_string(),
+                ))
+            };
+        }
+
+        // the receiver is closed once the recv loop ends
+        let (packet_send, mut packet_recv) = super::create_packet_recv_chan();
+        conn.start_recv_loop(packet_send).await;
+        let closed = timeout(wait, async { while packet_recv.recv().await.is_some() {} }).await;
+        if closed.is_ok() {
+            return Err(Error::SecretKeyError(
+                "relay closed the conn, network secret not match or network not allowed"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn handshake_done(&self) -> bool {
         self.info.is_some()
     }
@@ -450,6 +510,7 @@ impl PeerConn {
             self.latency_stats.clone(),
             self.loss_rate_stats.clone(),
             self.throughput.clone(),
+            self.global_ctx.clone(),
         );
 
         let close_event_notifier = self.close_event_notifier.clone();
@@ -521,9 +582,29 @@ impl PeerConn {
             is_client: self.is_client.unwrap_or_default(),
             network_name: info.network_name.clone(),
             is_closed: self.close_event_notifier.is_closed(),
+            clock_offset_ms: self.get_clock_offset_ms(),
+            protocol_version: self.get_protocol_version(),
+            established_at: self
+                .established_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or_default(),
         }
     }
 
+    /// Version both ends speak, the lower one of the handshakes.
+    pub fn get_protocol_version(&self) -> u32 {
+        self.info.as_ref().unwrap().version.min(VERSION)
+    }
+
+    /// Estimated clock of the peer minus ours. The handshake timestamp was taken half a
+    /// round trip before it arrived, so the diff seen on arrival is corrected by that.
+    pub fn get_clock_offset_ms(&self) -> Option<i64> {
+        let half_rtt_ms = self.latency_stats.get_latency_us::<u64>() / 2000;
+        self.handshake_clock_diff_ms
+            .map(|diff| diff + half_rtt_ms as i64)
+    }
+
     pub fn set_peer_id(&mut self, peer_id: PeerId) {
         if self.info.is_some() {
             panic!("set_peer_id should only be called before handshake");
@@ -549,7 +630,6 @@ mod tests {
 
     use super::*;
     use crate::common::global_ctx::tests::get_mock_global_ctx;
-    use crate::common::new_peer_id;
     use crate::common::scoped_task::ScopedTask;
     use crate::peers::create_packet_recv_chan;
     use crate::tunnel::filter::tests::DropSendTunnelFilter;
@@ -574,6 +654,29 @@ mod tests {
         assert!(s_ret.is_err());
     }
 
+    #[tokio::test]
+    async fn verify_network_identity_against_member() {
+        use crate::common::global_ctx::tests::get_mock_global_ctx_with_network;
+
+        for (secret, ok) in [("secret", true), ("wrong", false)] {
+            let (c, s) = create_ring_tunnel_pair();
+            let s_ctx = get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+                "net".to_owned(),
+                "secret".to_owned(),
+            )));
+            let c_ctx = get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+                "net".to_owned(),
+                secret.to_owned(),
+            )));
+            let mut s_peer = PeerConn::new(new_peer_id(), s_ctx, Box::new(s));
+            let (c_ret, _) = tokio::join!(
+                PeerConn::verify_network_identity(c_ctx, Box::new(c), Duration::from_millis(100)),
+                s_peer.do_handshake_as_server()
+            );
+            assert_eq!(c_ret.is_ok(), ok, "{:?}", c_ret);
+        }
+    }
+
     #[tokio::test]
     async fn peer_conn_handshake() {
         let (c, s) = create_ring_tunnel_pair();
@@ -607,6 +710,14 @@ mod tests {
 
         assert_eq!(c_peer.get_peer_id(), s_peer_id);
         assert_eq!(s_peer.get_peer_id(), c_peer_id);
+
+        // both ends share the clock
+        assert!(c_peer.get_clock_offset_ms().unwrap().abs() < 1000);
+        assert!(s_peer.get_clock_offset_ms().unwrap().abs() < 1000);
+
+        assert_eq!(c_peer.get_protocol_version(), VERSION);
+        assert_eq!(s_peer.get_conn_info().protocol_version, VERSION);
+
         assert_eq!(c_peer.get_network_identity(), s_peer.get_network_identity());
         assert_eq!(c_peer.get_network_identity(), NetworkIdentity::default());
     }
@@ -15,7 +15,7 @@ use tokio::{
 use tracing::Instrument;
 
 use crate::{
-    common::{error::Error, PeerId},
+    common::{error::Error, global_ctx::ArcGlobalCtx, PeerId},
     tunnel::{
         mpsc::MpscTunnelSender,
         packet_def::{PacketType, ZCPacket},
@@ -35,6 +35,8 @@ struct PingIntervalController {
 
     backoff_idx: i32,
     max_backoff_idx: i32,
+    // max seconds between pings on an idle conn, overrides the default backoff limit
+    keepalive_interval: Option<u64>,
 
     last_throughput: Throughput,
 }
@@ -48,6 +50,7 @@ impl std::fmt::Debug for PingIntervalController {
             .field("last_send_logic_time", &self.last_send_logic_time)
             .field("backoff_idx", &self.backoff_idx)
             .field("max_backoff_idx", &self.max_backoff_idx)
+            .field("keepalive_interval", &self.keepalive_interval)
             .field("last_throughput", &self.last_throughput)
             .finish()
     }
@@ -66,6 +69,7 @@ impl PingIntervalController {
 
             backoff_idx: 0,
             max_backoff_idx: 5,
+            keepalive_interval: None,
 
             last_throughput,
         }
@@ -76,6 +80,18 @@ impl PingIntervalController {
         self.logic_time += 1;
     }
 
+    fn set_keepalive_interval(&mut self, interval: Option<Duration>) {
+        self.keepalive_interval = interval.map(|x| x.as_secs().max(1));
+    }
+
+    fn max_backoff_idx(&self) -> i32 {
+        match self.keepalive_interval {
+            // smallest idx whose backoff reaches the keepalive interval
+            Some(secs) => (u64::BITS - (secs - 1).leading_zeros()) as i32,
+            None => self.max_backoff_idx,
+        }
+    }
+
     fn tx_increase(&self) -> bool {
         self.throughput.tx_packets() > self.last_throughput.tx_packets()
     }
@@ -94,14 +110,22 @@ impl PingIntervalController {
 
         self.last_throughput = (*self.throughput).clone();
 
-        if (self.logic_time - self.last_send_logic_time) < (1 << self.backoff_idx) {
+        let max_backoff_idx = self.max_backoff_idx();
+        self.backoff_idx = std::cmp::min(self.backoff_idx, max_backoff_idx);
+
+        let backoff = 1u64 << self.backoff_idx;
+        let gap = self.keepalive_interval.map_or(backoff, |x| backoff.min(x));
+        if (self.logic_time - self.last_send_logic_time) < gap {
             return false;
         }
 
-        self.backoff_idx = std::cmp::min(self.backoff_idx + 1, self.max_backoff_idx);
+        self.backoff_idx = std::cmp::min(self.backoff_idx + 1, max_backoff_idx);
 
         // use this makes two peers not pingpong at the same time
-        if self.backoff_idx > self.max_backoff_idx - 2 && thread_rng().gen_bool(0.2) {
+        if self.backoff_idx > 0
+            && self.backoff_idx > max_backoff_idx - 2
+            && thread_rng().gen_bool(0.2)
+        {
             self.backoff_idx -= 1;
         }
 
@@ -118,6 +142,7 @@ pub struct PeerConnPinger {
     latency_stats: Arc<WindowLatency>,
     loss_rate_stats: Arc<AtomicU32>,
     throughput_stats: Arc<Throughput>,
+    global_ctx: ArcGlobalCtx,
     tasks: JoinSet<Result<(), TunnelError>>,
 }
 
@@ -139,6 +164,7 @@ impl PeerConnPinger {
         latency_stats: Arc<WindowLatency>,
         loss_rate_stats: Arc<AtomicU32>,
         throughput_stats: Arc<Throughput>,
+        global_ctx: ArcGlobalCtx,
     ) -> Self {
         Self {
             my_peer_id,
@@ -149,6 +175,7 @@ impl PeerConnPinger {
             ctrl_sender,
             loss_rate_stats,
             throughput_stats,
+            global_ctx,
         }
     }
 
@@ -233,11 +260,13 @@ impl PeerConnPinger {
         let stopped_clone = stopped.clone();
         let mut controller =
             PingIntervalController::new(self.throughput_stats.clone(), loss_counter.clone());
+        let global_ctx = self.global_ctx.clone();
         self.tasks.spawn(
             async move {
                 let mut req_seq = 0;
                 loop {
                     controller.tick().await;
+                    controller.set_keepalive_interval(global_ctx.get_keepalive_interval());
 
                     if stopped_clone.load(Ordering::Relaxed) != 0 {
                         return Ok(());
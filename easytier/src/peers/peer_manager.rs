@@ -2,13 +2,16 @@ use std::{
     fmt::Debug,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::{atomic::AtomicBool, Arc, Weak},
-    time::{Instant, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Context;
 use async_trait::async_trait;
 
+use cidr::{Ipv4Cidr, Ipv6Cidr};
 use dashmap::DashMap;
+use futures::{stream::FuturesUnordered, StreamExt};
+use rand::RngCore;
 
 use tokio::{
     sync::{
@@ -21,9 +24,10 @@ use tokio::{
 use crate::{
     common::{
         compressor::{Compressor as _, DefaultCompressor},
+        config::EncryptionAlgorithm,
         constants::EASYTIER_VERSION,
         error::Error,
-        global_ctx::{ArcGlobalCtx, NetworkIdentity},
+        global_ctx::{ArcGlobalCtx, GlobalCtxEvent, NetworkIdentity, TransportPreference},
         shrink_dashmap,
         stats_manager::{CounterHandle, LabelSet, LabelType, MetricName},
         stun::StunInfoCollectorTrait,
@@ -41,26 +45,35 @@ use crate::{
             self, list_global_foreign_network_response::OneForeignNetwork,
             ListGlobalForeignNetworkResponse,
         },
+        common::Void,
         peer_rpc::{
-            ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, RouteForeignNetworkSummary,
+            ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, PeerPingRpc,
+            PeerPingRpcClientFactory, PeerPingRpcServer, PeerThroughputRpc,
+            PeerThroughputRpcClientFactory, PeerThroughputRpcServer, RouteForeignNetworkSummary,
+            RoutePeerInfo, ThroughputDownloadRequest, ThroughputDownloadResponse,
+            ThroughputUploadRequest,
         },
+        rpc_types::{self, controller::BaseController},
     },
     tunnel::{
         self,
         packet_def::{CompressorAlgo, PacketType, ZCPacket},
         Tunnel, TunnelConnector,
     },
+    use_global_var,
 };
 
 use super::{
     create_packet_recv_chan,
-    encrypt::{Encryptor, NullCipher},
+    encrypt::{Encryptor, SwitchableCipher},
     foreign_network_client::ForeignNetworkClient,
     foreign_network_manager::{ForeignNetworkManager, GlobalForeignNetworkAccessor},
+    packet_capture::PacketCapture,
     peer_conn::PeerConnId,
     peer_map::PeerMap,
     peer_ospf_route::PeerRoute,
     peer_rpc::PeerRpcManager,
+    reconnect_buffer::{BufferedPacket, ReconnectBuffer},
     route_trait::{ArcRoute, Route},
     BoxNicPacketFilter, BoxPeerPacketFilter, PacketRecvChan, PacketRecvChanReceiver,
 };
@@ -110,6 +123,128 @@ impl PeerRpcManagerTransport for RpcTransport {
     }
 }
 
+#[derive(Clone)]
+struct PeerPingService;
+
+#[async_trait::async_trait]
+impl PeerPingRpc for PeerPingService {
+    type Controller = BaseController;
+
+    async fn ping(&self, _: BaseController, _: Void) -> Result<Void, rpc_types::error::Error> {
+        Ok(Void::default())
+    }
+}
+
+// each throughput rpc carries one chunk, a few of them are kept in flight so the test
+// fills the link without queueing up the traffic of the peer.
+const THROUGHPUT_CHUNK_SIZE: usize = 32 * 1024;
+const THROUGHPUT_WINDOW: usize = 4;
+const THROUGHPUT_RPC_TIMEOUT_MS: i32 = 5000;
+// how long a peer counts as sending plaintext after its last unencrypted packet
+const PLAINTEXT_PEER_TTL: Duration = Duration::from_secs(60);
+
+// limits of the throughput tests other peers run against this node, upload and download
+// of a test together. the client paces itself too, these hold whatever it sends.
+const THROUGHPUT_SERVE_BYTES_PER_SEC: u64 = 16 * 1024 * 1024;
+// the longest test of the client, plus the time its last chunks take
+const THROUGHPUT_SERVE_MAX_DURATION: Duration = Duration::from_secs(65);
+// a test without a chunk for this long is over, the peer may start the next one
+const THROUGHPUT_SESSION_IDLE: Duration = Duration::from_secs(5);
+// tests are refused while the tun device carried traffic this recently
+const THROUGHPUT_BUSY_TUN_IDLE: Duration = Duration::from_secs(1);
+
+struct ThroughputSession {
+    test_id: u64,
+    started: Instant,
+    last_seen: Instant,
+    // when the next chunk may go through at the paced rate
+    next_at: Instant,
+}
+
+#[derive(Clone)]
+struct PeerThroughputService {
+    global_ctx: ArcGlobalCtx,
+    sessions: Arc<DashMap<PeerId, ThroughputSession>>,
+}
+
+impl PeerThroughputService {
+    fn new(global_ctx: ArcGlobalCtx) -> Self {
+        Self {
+            global_ctx,
+            sessions: Arc::new(DashMap::new()),
+        }
+    }
+
+    // checks a chunk of `bytes` of the test against the limits, returns how long to hold
+    // it so the test of the peer stays within the paced rate.
+    fn admit(
+        &self,
+        peer_id: PeerId,
+        test_id: u64,
+        bytes: usize,
+    ) -> Result<Duration, rpc_types::error::Error> {
+        if !self.global_ctx.serves_throughput_tests() {
+            return Err(anyhow::anyhow!("throughput tests are not enabled on this peer").into());
+        }
+        if self.global_ctx.stats_manager().tun_idle_duration() < THROUGHPUT_BUSY_TUN_IDLE {
+            return Err(anyhow::anyhow!("peer is busy with its own traffic").into());
+        }
+
+        let now = Instant::now();
+        self.sessions
+            .retain(|_, s| now.duration_since(s.last_seen) < THROUGHPUT_SESSION_IDLE);
+        let mut session = self
+            .sessions
+            .entry(peer_id)
+            .or_insert_with(|| ThroughputSession {
+                test_id,
+                started: now,
+                last_seen: now,
+                next_at: now,
+            });
+        if session.test_id != test_id {
+            return Err(anyhow::anyhow!("another throughput test of the peer is running").into());
+        }
+        if now.duration_since(session.started) > THROUGHPUT_SERVE_MAX_DURATION {
+            return Err(anyhow::anyhow!("throughput test exceeds its max duration").into());
+        }
+        session.last_seen = now;
+        let send_at = session.next_at.max(now);
+        session.next_at =
+            send_at + Duration::from_secs_f64(bytes as f64 / THROUGHPUT_SERVE_BYTES_PER_SEC as f64);
+        Ok(send_at - now)
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerThroughputRpc for PeerThroughputService {
+    type Controller = BaseController;
+
+    async fn upload(
+        &self,
+        _: BaseController,
+        request: ThroughputUploadRequest,
+    ) -> Result<Void, rpc_types::error::Error> {
+        let delay = self.admit(request.my_peer_id, request.test_id, request.data.len())?;
+        tokio::time::sleep(delay).await;
+        Ok(Void::default())
+    }
+
+    async fn download(
+        &self,
+        _: BaseController,
+        request: ThroughputDownloadRequest,
+    ) -> Result<ThroughputDownloadResponse, rpc_types::error::Error> {
+        let size = (request.size as usize).min(THROUGHPUT_CHUNK_SIZE);
+        let delay = self.admit(request.my_peer_id, request.test_id, size)?;
+        tokio::time::sleep(delay).await;
+        // random data so the rpc compression does not shrink it
+        let mut data = vec![0u8; size];
+        rand::thread_rng().fill_bytes(&mut data);
+        Ok(ThroughputDownloadResponse { data })
+    }
+}
+
 pub enum RouteAlgoType {
     Ospf,
     None,
@@ -151,12 +286,29 @@ pub struct PeerManager {
     foreign_network_client: Arc<ForeignNetworkClient>,
 
     encryptor: Arc<dyn Encryptor + 'static>,
+    encryption_switch: Arc<SwitchableCipher>,
     data_compress_algo: CompressorAlgo,
 
     exit_nodes: RwLock<Vec<IpAddr>>,
 
     reserved_my_peer_id_map: DashMap<String, PeerId>,
 
+    // answers of PEER_CONN_APPROVER, a peer is only asked about once. nothing is sent
+    // to, received from or relayed for a rejected peer, see is_rejected.
+    peer_approvals: Arc<DashMap<PeerId, bool>>,
+
+    // peers that sent data to this node without encryption, and when they last did
+    plaintext_peers: Arc<DashMap<PeerId, Instant>>,
+
+    // peers whose data is dropped until the instant, see block_peer
+    blocked_peers: Arc<DashMap<PeerId, Instant>>,
+
+    // packets between the nic and the peers, off unless a capture runs
+    packet_capture: Arc<PacketCapture>,
+
+    // packets to unreachable destinations, off unless configured
+    reconnect_buffer: Arc<ReconnectBuffer>,
+
     allow_loopback_tunnel: AtomicBool,
 
     self_tx_counters: SelfTxCounters,
@@ -187,18 +339,17 @@ impl PeerManager {
             my_peer_id,
         ));
 
-        let encryptor = if global_ctx.get_flags().enable_encryption {
-            // 只有在启用加密时才使用工厂函数选择算法
-            let algorithm = &global_ctx.get_flags().encryption_algorithm;
+        // the cipher is always created so encryption can be switched on later
+        let flags = global_ctx.get_flags();
+        let encryption_switch = Arc::new(SwitchableCipher::new(
             super::encrypt::create_encryptor(
-                algorithm,
+                &flags.encryption_algorithm,
                 global_ctx.get_128_key(),
                 global_ctx.get_256_key(),
-            )
-        } else {
-            // disable_encryption = true 时使用 NullCipher
-            Arc::new(NullCipher)
-        };
+            ),
+            flags.enable_encryption,
+        ));
+        let encryptor: Arc<dyn Encryptor> = encryption_switch.clone();
 
         if global_ctx
             .check_network_in_whitelist(&global_ctx.get_network_name())
@@ -278,6 +429,10 @@ impl PeerManager {
                     .with_label_type(LabelType::NetworkName(global_ctx.get_network_name())),
             ),
         };
+        let reconnect_buffer = Arc::new(ReconnectBuffer::new(stats_manager.get_counter(
+            MetricName::ReconnectBufferDropped,
+            LabelSet::new().with_label_type(LabelType::NetworkName(global_ctx.get_network_name())),
+        )));
 
         PeerManager {
             my_peer_id,
@@ -303,18 +458,135 @@ impl PeerManager {
             foreign_network_client,
 
             encryptor,
+            encryption_switch,
             data_compress_algo,
 
             exit_nodes: RwLock::new(exit_nodes),
 
             reserved_my_peer_id_map: DashMap::new(),
 
+            peer_approvals: Arc::new(DashMap::new()),
+
+            plaintext_peers: Arc::new(DashMap::new()),
+
+            blocked_peers: Arc::new(DashMap::new()),
+
+            packet_capture: Arc::new(PacketCapture::new()),
+
+            reconnect_buffer,
+
             allow_loopback_tunnel: AtomicBool::new(true),
 
             self_tx_counters,
         }
     }
 
+    /// Turns encryption of sent packets on or off, received packets are accepted either
+    /// way. Peers must use the same algorithm, and ones without this switch drop
+    /// encrypted packets if they run unencrypted. The mtu of an existing nic is kept.
+    pub fn set_encryption_enabled(&self, enabled: bool) {
+        self.encryption_switch.set_enabled(enabled);
+        let mut flags = self.global_ctx.get_flags();
+        flags.enable_encryption = enabled;
+        self.global_ctx.set_flags(flags);
+    }
+
+    pub fn is_encryption_enabled(&self) -> bool {
+        self.encryption_switch.is_enabled()
+    }
+
+    /// Sends the data to `peer_id` over a conn of the preferred transport while it has
+    /// one, over the conn with the lowest latency otherwise. Kept for reconnects.
+    pub fn set_peer_transport_preference(&self, peer_id: PeerId, preference: TransportPreference) {
+        self.global_ctx
+            .set_peer_transport_preference(peer_id, preference);
+        if let Some(peer) = self.peers.get_peer_by_id(peer_id) {
+            peer.set_transport_preference(preference);
+        }
+    }
+
+    /// peers that sent data to this node without encryption within the last minute,
+    /// because they have it disabled or are switching it.
+    pub fn get_plaintext_peers(&self) -> Vec<PeerId> {
+        self.plaintext_peers
+            .retain(|_, last_seen| last_seen.elapsed() < PLAINTEXT_PEER_TTL);
+        let mut peers: Vec<_> = self.plaintext_peers.iter().map(|x| *x.key()).collect();
+        peers.sort();
+        peers
+    }
+
+    /// capture of the ip packets the nic sends to and receives from the peers.
+    pub fn get_packet_capture(&self) -> &PacketCapture {
+        &self.packet_capture
+    }
+
+    /// packets of the nic held while their destination is unreachable, see
+    /// flush_reconnect_buffer.
+    pub fn get_reconnect_buffer(&self) -> Arc<ReconnectBuffer> {
+        self.reconnect_buffer.clone()
+    }
+
+    fn is_blocked(blocked_peers: &DashMap<PeerId, Instant>, peer_id: PeerId) -> bool {
+        blocked_peers
+            .get(&peer_id)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    // a peer the approver rejected may still connect to other nodes and show up in the
+    // routes they sync, its packets are dropped here whichever way they take.
+    fn is_rejected(peer_approvals: &DashMap<PeerId, bool>, peer_id: PeerId) -> bool {
+        peer_approvals.get(&peer_id).is_some_and(|x| !*x)
+    }
+
+    /// Drops the data sent to, received from and relayed for the peer for `duration`,
+    /// control traffic like route sync still goes through. Blocking again restarts the
+    /// duration.
+    pub fn block_peer(&self, peer_id: PeerId, duration: Duration) {
+        let until = Instant::now() + duration;
+        self.blocked_peers.insert(peer_id, until);
+        self.global_ctx.issue_event(GlobalCtxEvent::PeerBlocked(
+            peer_id,
+            duration.as_millis() as u64,
+        ));
+
+        let blocked_peers = Arc::downgrade(&self.blocked_peers);
+        let global_ctx = Arc::downgrade(&self.global_ctx);
+        tokio::spawn(async move {
+            tokio::time::sleep_until(until.into()).await;
+            let (Some(blocked_peers), Some(global_ctx)) =
+                (blocked_peers.upgrade(), global_ctx.upgrade())
+            else {
+                return;
+            };
+            // not blocked again or unblocked meanwhile
+            if blocked_peers
+                .remove_if(&peer_id, |_, x| *x == until)
+                .is_some()
+            {
+                global_ctx.issue_event(GlobalCtxEvent::PeerUnblocked(peer_id));
+            }
+        });
+    }
+
+    /// returns whether the peer was blocked.
+    pub fn unblock_peer(&self, peer_id: PeerId) -> bool {
+        let Some((_, until)) = self.blocked_peers.remove(&peer_id) else {
+            return false;
+        };
+        if until <= Instant::now() {
+            return false;
+        }
+        self.global_ctx
+            .issue_event(GlobalCtxEvent::PeerUnblocked(peer_id));
+        true
+    }
+
+    /// blocked by block_peer or rejected by the peer approver
+    pub fn is_peer_blocked(&self, peer_id: PeerId) -> bool {
+        Self::is_blocked(&self.blocked_peers, peer_id)
+            || Self::is_rejected(&self.peer_approvals, peer_id)
+    }
+
     pub fn set_allow_loopback_tunnel(&self, allow_loopback_tunnel: bool) {
         self.allow_loopback_tunnel
             .store(allow_loopback_tunnel, std::sync::atomic::Ordering::Relaxed);
@@ -354,6 +626,16 @@ impl PeerManager {
                 "network identity not match".to_string(),
             ));
         }
+        if self.is_peer_limit_reached(peer_conn.get_peer_id()).await {
+            return Err(anyhow::anyhow!(
+                "max peers reached, reject new peer {}",
+                peer_conn.get_peer_id()
+            )
+            .into());
+        }
+        if !self.is_peer_approved(&peer_conn).await {
+            return Err(anyhow::anyhow!("peer {} not approved", peer_conn.get_peer_id()).into());
+        }
         self.peers.add_new_peer_conn(peer_conn).await;
         Ok(())
     }
@@ -378,6 +660,34 @@ impl PeerManager {
         Ok((peer_id, conn_id))
     }
 
+    // whether a conn to a peer not connected yet exceeds the max peers limit.
+    pub async fn is_peer_limit_reached(&self, peer_id: PeerId) -> bool {
+        let Some(max_peers) = self.global_ctx.get_max_peers() else {
+            return false;
+        };
+        !self.peers.has_peer(peer_id)
+            && self.peers.list_peers_with_conn().await.len() >= max_peers as usize
+    }
+
+    // conns to connected peers and all conns while no approver is set are let through.
+    // the conn waits for the answer, which is kept for later conns of the same peer.
+    async fn is_peer_approved(&self, peer_conn: &PeerConn) -> bool {
+        let peer_id = peer_conn.get_peer_id();
+        if self.peers.has_peer(peer_id) {
+            return true;
+        }
+        if let Some(approved) = self.peer_approvals.get(&peer_id) {
+            return *approved;
+        }
+        let Some(approver) = use_global_var!(PEER_CONN_APPROVER) else {
+            return true;
+        };
+        let approved = approver(self.global_ctx.get_id(), peer_conn.get_conn_info()).await;
+        tracing::info!(?peer_id, approved, "new peer approval");
+        self.peer_approvals.insert(peer_id, approved);
+        approved
+    }
+
     pub fn has_directly_connected_conn(&self, peer_id: PeerId) -> bool {
         if let Some(peer) = self.peers.get_peer_by_id(peer_id) {
             peer.has_directly_connected_conn()
@@ -636,6 +946,9 @@ impl PeerManager {
             stats_mgr.get_counter(MetricName::CompressionBytesRxBefore, label_set.clone());
         let compress_rx_bytes_after =
             stats_mgr.get_counter(MetricName::CompressionBytesRxAfter, label_set.clone());
+        let plaintext_peers = self.plaintext_peers.clone();
+        let blocked_peers = self.blocked_peers.clone();
+        let peer_approvals = self.peer_approvals.clone();
 
         self.tasks.lock().await.spawn(async move {
             tracing::trace!("start_peer_recv");
@@ -656,6 +969,26 @@ impl PeerManager {
                 tracing::trace!(?hdr, "peer recv a packet...");
                 let from_peer_id = hdr.from_peer_id.get();
                 let to_peer_id = hdr.to_peer_id.get();
+                // only these are encrypted by the sender, see try_compress_and_encrypt callers
+                let is_data = hdr.packet_type == PacketType::Data as u8
+                    || hdr.packet_type == PacketType::KcpSrc as u8
+                    || hdr.packet_type == PacketType::KcpDst as u8;
+                let is_plaintext_data = is_data && !hdr.is_encrypted();
+                // control packets too, a rejected peer must not sync routes or call rpcs
+                // through this node either
+                if Self::is_rejected(&peer_approvals, from_peer_id)
+                    || Self::is_rejected(&peer_approvals, to_peer_id)
+                {
+                    tracing::trace!(?hdr, "peer is not approved, drop packet");
+                    continue;
+                }
+                if is_data
+                    && (Self::is_blocked(&blocked_peers, from_peer_id)
+                        || Self::is_blocked(&blocked_peers, to_peer_id))
+                {
+                    tracing::trace!(?hdr, "peer is blocked, drop packet");
+                    continue;
+                }
                 if to_peer_id != my_peer_id {
                     if hdr.forward_counter > 7 {
                         tracing::warn!(?hdr, "forward counter exceed, drop packet");
@@ -667,15 +1000,17 @@ impl PeerManager {
                         hdr.set_latency_first(false);
                     }
 
+                    if from_peer_id != my_peer_id && !global_ctx.can_forward_for_peers() {
+                        tracing::trace!(?hdr, "relay disabled, drop packet to be forwarded");
+                        continue;
+                    }
+
                     hdr.forward_counter += 1;
 
                     if from_peer_id == my_peer_id {
                         compress_tx_bytes_before.add(buf_len as u64);
 
-                        if hdr.packet_type == PacketType::Data as u8
-                            || hdr.packet_type == PacketType::KcpSrc as u8
-                            || hdr.packet_type == PacketType::KcpDst as u8
-                        {
+                        if is_data {
                             let _ =
                                 Self::try_compress_and_encrypt(compress_algo, &encryptor, &mut ret)
                                     .await;
@@ -696,6 +1031,9 @@ impl PeerManager {
                         tracing::error!(?ret, ?to_peer_id, ?from_peer_id, "forward packet error");
                     }
                 } else {
+                    if is_plaintext_data && from_peer_id != my_peer_id {
+                        plaintext_peers.insert(from_peer_id, Instant::now());
+                    }
                     if let Err(e) = encryptor.decrypt(&mut ret) {
                         tracing::error!(?e, "decrypt failed");
                         continue;
@@ -764,6 +1102,7 @@ impl PeerManager {
         // for tun/tap ip/eth packet.
         struct NicPacketProcessor {
             nic_channel: PacketRecvChan,
+            packet_capture: Arc<PacketCapture>,
         }
         #[async_trait::async_trait]
         impl PeerPacketFilter for NicPacketProcessor {
@@ -771,6 +1110,7 @@ impl PeerManager {
                 let hdr = packet.peer_manager_header().unwrap();
                 if hdr.packet_type == PacketType::Data as u8 && !hdr.is_not_send_to_tun() {
                     tracing::trace!(?packet, "send packet to nic channel");
+                    self.packet_capture.capture(packet.payload());
                     // TODO: use a function to get the body ref directly for zero copy
                     let _ = self.nic_channel.send(packet).await;
                     None
@@ -781,6 +1121,7 @@ impl PeerManager {
         }
         self.add_packet_process_pipeline(Box::new(NicPacketProcessor {
             nic_channel: self.nic_channel.clone(),
+            packet_capture: self.packet_capture.clone(),
         }))
         .await;
 
@@ -845,6 +1186,16 @@ impl PeerManager {
                 self.my_peer_id
             }
 
+            async fn get_peer_loss_rate(&self, peer_id: PeerId) -> Option<f32> {
+                let peer_map = self.peers.upgrade()?;
+                peer_map
+                    .list_peer_conns(peer_id)
+                    .await?
+                    .iter()
+                    .map(|conn| conn.loss_rate)
+                    .reduce(f32::min)
+            }
+
             async fn list_foreign_networks(&self) -> ForeignNetworkRouteInfoMap {
                 let ret = DashMap::new();
                 let Some(foreign_mgr) = self.foreign_network_manager.upgrade() else {
@@ -900,6 +1251,11 @@ impl PeerManager {
         }
     }
 
+    /// none without a route algorithm
+    pub fn subscribe_route_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        self.get_route().subscribe_route_changes()
+    }
+
     pub async fn list_routes(&self) -> Vec<instance::Route> {
         self.get_route().list_routes().await
     }
@@ -988,6 +1344,9 @@ impl PeerManager {
         dst_peer_id: PeerId,
     ) -> Result<(), Error> {
         self.check_p2p_only_before_send(dst_peer_id)?;
+        if self.is_peer_blocked(dst_peer_id) {
+            return Ok(());
+        }
 
         self.self_tx_counters
             .compress_tx_bytes_before
@@ -1155,6 +1514,11 @@ impl PeerManager {
             msg,
             ip_addr
         );
+        if !self.global_ctx.is_outbound_allowed(&ip_addr) {
+            tracing::trace!(?ip_addr, "denied by outbound policy, drop packet");
+            return Ok(());
+        }
+        self.packet_capture.capture(msg.payload());
 
         msg.fill_peer_manager_hdr(
             self.my_peer_id,
@@ -1163,6 +1527,9 @@ impl PeerManager {
         );
         self.run_nic_packet_process_pipeline(&mut msg).await;
         let cur_to_peer_id = msg.peer_manager_header().unwrap().to_peer_id.into();
+        if cur_to_peer_id != 0 && self.is_peer_blocked(cur_to_peer_id) {
+            return Ok(());
+        }
         if cur_to_peer_id != 0 {
             return Self::send_msg_internal(
                 &self.peers,
@@ -1173,11 +1540,107 @@ impl PeerManager {
             .await;
         }
 
+        self.send_msg_to_dst_peers(msg, ip_addr, not_send_to_self, Instant::now())
+            .await
+    }
+
+    // whether the packet can leave now to any of `dst_peers`
+    async fn has_next_hop_to_any(&self, dst_peers: &[PeerId]) -> bool {
+        let policy = Self::get_next_hop_policy(self.global_ctx.latency_first());
+        for peer_id in dst_peers {
+            let reachable = match self
+                .peers
+                .get_gateway_peer_id(*peer_id, policy.clone())
+                .await
+            {
+                Some(gateway) => {
+                    self.peers.has_peer(gateway)
+                        || self.foreign_network_client.has_next_hop(gateway)
+                }
+                None => self.foreign_network_client.has_next_hop(*peer_id),
+            };
+            if reachable {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Sends the packets held by the reconnect buffer whose destination is reachable
+    /// again, the others stay held until they are too old.
+    pub async fn flush_reconnect_buffer(&self) {
+        for packet in self.reconnect_buffer.take_all() {
+            if let Err(e) = self
+                .send_msg_to_dst_peers(
+                    packet.msg,
+                    packet.ip_addr,
+                    packet.not_send_to_self,
+                    packet.queued_at,
+                )
+                .await
+            {
+                tracing::debug!(?e, "send buffered packet failed");
+            }
+        }
+    }
+
+    // the second half of send_msg_by_ip, `msg` passed the nic pipeline already.
+    // `queued_at` is when the packet came from the nic, it is held in the reconnect
+    // buffer if the peers of its destination are not reachable.
+    async fn send_msg_to_dst_peers(
+        &self,
+        msg: ZCPacket,
+        ip_addr: IpAddr,
+        not_send_to_self: bool,
+        queued_at: Instant,
+    ) -> Result<(), Error> {
         let (dst_peers, is_exit_node) = match ip_addr {
             IpAddr::V4(ipv4_addr) => self.get_msg_dst_peer_ipv4(&ipv4_addr).await,
             IpAddr::V6(ipv6_addr) => self.get_msg_dst_peer_ipv6(&ipv6_addr).await,
         };
 
+        // no peer owns the ip, no reconnect makes it reachable
+        if self.reconnect_buffer.is_enabled() && !dst_peers.is_empty() {
+            if !self.has_next_hop_to_any(&dst_peers).await {
+                tracing::trace!(?ip_addr, ?dst_peers, "no peer reachable, hold packet");
+                self.reconnect_buffer.push(BufferedPacket {
+                    queued_at,
+                    ip_addr,
+                    not_send_to_self,
+                    msg,
+                });
+                return Ok(());
+            }
+            // the packets held for the destination leave first, the flush may not have
+            // seen the route yet
+            for held in self.reconnect_buffer.take_for(&ip_addr) {
+                if let Err(e) = self
+                    .send_msg_to_peers(
+                        held.msg,
+                        ip_addr,
+                        &dst_peers,
+                        is_exit_node,
+                        held.not_send_to_self,
+                    )
+                    .await
+                {
+                    tracing::debug!(?e, "send buffered packet failed");
+                }
+            }
+        }
+
+        self.send_msg_to_peers(msg, ip_addr, &dst_peers, is_exit_node, not_send_to_self)
+            .await
+    }
+
+    async fn send_msg_to_peers(
+        &self,
+        mut msg: ZCPacket,
+        ip_addr: IpAddr,
+        dst_peers: &[PeerId],
+        is_exit_node: bool,
+        not_send_to_self: bool,
+    ) -> Result<(), Error> {
         if dst_peers.is_empty() {
             tracing::info!("no peer id for ip: {}", ip_addr);
             return Ok(());
@@ -1207,6 +1670,9 @@ impl PeerManager {
                 errs.push(e);
                 continue;
             }
+            if self.is_peer_blocked(*peer_id) {
+                continue;
+            }
 
             let mut msg = if i == total_dst_peers - 1 {
                 msg.take().unwrap()
@@ -1259,6 +1725,43 @@ impl PeerManager {
         });
     }
 
+    // drop conns of the worst peers when the limit is lowered below the number of
+    // connected peers. low latency peers are kept first, then the most used ones.
+    async fn run_max_peers_routine(&self) {
+        let peer_map = self.peers.clone();
+        let global_ctx = self.global_ctx.clone();
+        self.tasks.lock().await.spawn(async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+                let Some(max_peers) = global_ctx.get_max_peers() else {
+                    continue;
+                };
+                let peers = peer_map.list_peers_with_conn().await;
+                if peers.len() <= max_peers as usize {
+                    continue;
+                }
+
+                let mut ranked = Vec::with_capacity(peers.len());
+                for peer_id in peers {
+                    let conns = peer_map.list_peer_conns(peer_id).await.unwrap_or_default();
+                    let stats = conns.iter().filter_map(|c| c.stats.as_ref());
+                    let latency_us = stats
+                        .clone()
+                        .map(|s| s.latency_us)
+                        .min()
+                        .unwrap_or(u64::MAX);
+                    let bytes: u64 = stats.map(|s| s.rx_bytes + s.tx_bytes).sum();
+                    ranked.push((latency_us, std::cmp::Reverse(bytes), peer_id));
+                }
+                ranked.sort();
+                for (_, _, peer_id) in ranked.into_iter().skip(max_peers as usize) {
+                    tracing::info!(?peer_id, max_peers, "max peers exceeded, close peer");
+                    let _ = peer_map.close_peer(peer_id).await;
+                }
+            }
+        });
+    }
+
     async fn run_foriegn_network(&self) {
         self.peer_rpc_tspt
             .foreign_peers
@@ -1277,9 +1780,18 @@ impl PeerManager {
 
         self.init_packet_process_pipeline().await;
         self.peer_rpc_mgr.run();
+        self.peer_rpc_mgr.rpc_server().registry().register(
+            PeerPingRpcServer::new(PeerPingService),
+            &self.global_ctx.get_network_name(),
+        );
+        self.peer_rpc_mgr.rpc_server().registry().register(
+            PeerThroughputRpcServer::new(PeerThroughputService::new(self.global_ctx.clone())),
+            &self.global_ctx.get_network_name(),
+        );
 
         self.start_peer_recv().await;
         self.run_clean_peer_without_conn_routine().await;
+        self.run_max_peers_routine().await;
 
         self.run_foriegn_network().await;
 
@@ -1322,6 +1834,93 @@ impl PeerManager {
         self.foreign_network_client.clone()
     }
 
+    // round trip of one rpc to the peer, relayed if it is not directly connected.
+    // peers of versions without the ping service never answer.
+    pub async fn ping_peer(
+        &self,
+        dst_peer_id: PeerId,
+        timeout: Duration,
+    ) -> Result<Duration, rpc_types::error::Error> {
+        let stub = self
+            .peer_rpc_mgr
+            .rpc_client()
+            .scoped_client::<PeerPingRpcClientFactory<BaseController>>(
+                self.my_peer_id,
+                dst_peer_id,
+                self.global_ctx.get_network_name(),
+            );
+        let ctrl = BaseController {
+            timeout_ms: timeout.as_millis() as i32,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        stub.ping(ctrl, Void::default()).await?;
+        Ok(start.elapsed())
+    }
+
+    // transfers chunks to the peer (or from it if `upload` is false) for `duration`,
+    // returns the bytes transferred and the time until the last chunk arrived. stops
+    // early when dropped, e.g. by a cancelled test. the upload and download of one test
+    // share `test_id`, the peer serves one test of this node at a time.
+    pub async fn measure_throughput(
+        &self,
+        dst_peer_id: PeerId,
+        test_id: u64,
+        duration: Duration,
+        upload: bool,
+    ) -> Result<(u64, Duration), rpc_types::error::Error> {
+        let stub = self
+            .peer_rpc_mgr
+            .rpc_client()
+            .scoped_client::<PeerThroughputRpcClientFactory<BaseController>>(
+                self.my_peer_id,
+                dst_peer_id,
+                self.global_ctx.get_network_name(),
+            );
+        let mut chunk = vec![0u8; THROUGHPUT_CHUNK_SIZE];
+        rand::thread_rng().fill_bytes(&mut chunk);
+
+        let start = Instant::now();
+        let mut inflight = FuturesUnordered::new();
+        let mut bytes = 0;
+        loop {
+            while inflight.len() < THROUGHPUT_WINDOW && start.elapsed() < duration {
+                let stub = &stub;
+                let chunk = &chunk;
+                inflight.push(async move {
+                    let ctrl = BaseController {
+                        timeout_ms: THROUGHPUT_RPC_TIMEOUT_MS,
+                        ..Default::default()
+                    };
+                    if upload {
+                        let data = chunk.clone();
+                        let request = ThroughputUploadRequest {
+                            data,
+                            my_peer_id: self.my_peer_id,
+                            test_id,
+                        };
+                        stub.upload(ctrl, request).await?;
+                        Ok(chunk.len())
+                    } else {
+                        let size = chunk.len() as u32;
+                        let request = ThroughputDownloadRequest {
+                            size,
+                            my_peer_id: self.my_peer_id,
+                            test_id,
+                        };
+                        let resp = stub.download(ctrl, request).await?;
+                        Ok::<_, rpc_types::error::Error>(resp.data.len())
+                    }
+                });
+            }
+            let Some(ret) = inflight.next().await else {
+                break;
+            };
+            bytes += ret? as u64;
+        }
+        Ok((bytes, start.elapsed()))
+    }
+
     pub async fn get_my_info(&self) -> instance::NodeInfo {
         instance::NodeInfo {
             peer_id: self.my_peer_id,
@@ -1356,6 +1955,29 @@ impl PeerManager {
             version: EASYTIER_VERSION.to_string(),
             feature_flag: Some(self.global_ctx.get_feature_flags()),
             ip_list: Some(self.global_ctx.get_ip_collector().collect_ip_addrs().await),
+            advertised_cidrs: self
+                .global_ctx
+                .get_ipv4()
+                .map(|x| Ipv4Cidr::new(x.address(), 32).unwrap().to_string())
+                .into_iter()
+                .chain(
+                    self.global_ctx
+                        .get_ipv6()
+                        .map(|x| Ipv6Cidr::new(x.address(), 128).unwrap().to_string()),
+                )
+                .chain(RoutePeerInfo::advertised_proxy_cidrs(&self.global_ctx))
+                .collect(),
+            overlay_mtu: self.global_ctx.get_overlay_mtu(),
+            encryption_enabled: self.is_encryption_enabled(),
+            // create_encryptor falls back to aes-gcm for unknown names
+            encryption_algorithm: EncryptionAlgorithm::try_from(
+                self.global_ctx.get_flags().encryption_algorithm.as_str(),
+            )
+            .unwrap_or(EncryptionAlgorithm::AesGcm)
+            .to_string(),
+            plaintext_peer_ids: self.get_plaintext_peers(),
+            relay_enabled: self.global_ctx.can_forward_for_peers(),
+            listener_statuses: self.global_ctx.get_listener_statuses(),
         }
     }
 
@@ -1458,6 +2080,8 @@ mod tests {
 
     use std::{fmt::Debug, sync::Arc, time::Duration};
 
+    use futures::future::BoxFuture;
+
     use crate::{
         common::{config::Flags, global_ctx::tests::get_mock_global_ctx},
         connector::{
@@ -1474,17 +2098,25 @@ mod tests {
                 connect_peer_manager, create_mock_peer_manager_with_name, wait_route_appear,
                 wait_route_appear_with_cost,
             },
+            PeerConnApprover,
         },
-        proto::common::{CompressionAlgoPb, NatType, PeerFeatureFlag},
+        proto::{
+            api::instance::PeerConnInfo,
+            common::{CompressionAlgoPb, NatType, PeerFeatureFlag},
+        },
+        set_global_var,
         tunnel::{
             common::tests::wait_for_condition,
             filter::{tests::DropSendTunnelFilter, TunnelWithFilter},
+            packet_def::{PacketType, ZCPacket},
             ring::create_ring_tunnel_pair,
             TunnelConnector, TunnelListener,
         },
     };
 
-    use super::PeerManager;
+    use super::{
+        PeerManager, PeerThroughputService, THROUGHPUT_BUSY_TUN_IDLE, THROUGHPUT_CHUNK_SIZE,
+    };
 
     #[tokio::test]
     async fn drop_peer_manager() {
@@ -1650,6 +2282,63 @@ mod tests {
         wait_route_appear(mgr_d, peer_mgr_b).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn toggle_encryption_live() {
+        let create_mgr = || async move {
+            let (s, _r) = create_packet_recv_chan();
+            let peer_mgr = Arc::new(PeerManager::new(
+                RouteAlgoType::Ospf,
+                get_mock_global_ctx(),
+                s,
+            ));
+            peer_mgr.run().await.unwrap();
+            peer_mgr
+        };
+
+        let peer_mgr_a = create_mgr().await;
+        let peer_mgr_b = create_mgr().await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_b.clone())
+            .await
+            .unwrap();
+
+        peer_mgr_a.set_encryption_enabled(false);
+        assert!(!peer_mgr_a.is_encryption_enabled());
+        assert!(!peer_mgr_a.get_global_ctx().get_flags().enable_encryption);
+
+        // a sends plain packets now, b and new peers still talk to it
+        let peer_mgr_c = create_mgr().await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_c.clone()).await;
+        wait_route_appear(peer_mgr_c.clone(), peer_mgr_a.clone())
+            .await
+            .unwrap();
+        wait_route_appear(peer_mgr_c, peer_mgr_b.clone())
+            .await
+            .unwrap();
+
+        // b notices that data of a comes in plain
+        assert!(peer_mgr_b.get_plaintext_peers().is_empty());
+        let mut msg = ZCPacket::new_with_payload(b"plain");
+        msg.fill_peer_manager_hdr(
+            peer_mgr_a.my_peer_id(),
+            peer_mgr_b.my_peer_id(),
+            PacketType::Data as u8,
+        );
+        peer_mgr_a
+            .send_msg_for_proxy(msg, peer_mgr_b.my_peer_id())
+            .await
+            .unwrap();
+        wait_for_condition(
+            || async { !peer_mgr_b.get_plaintext_peers().is_empty() },
+            Duration::from_secs(5),
+        )
+        .await;
+        assert_eq!(
+            peer_mgr_b.get_plaintext_peers(),
+            vec![peer_mgr_a.my_peer_id()]
+        );
+    }
+
     #[tokio::test]
     async fn test_avoid_relay_data() {
         // a->b->c
@@ -1722,6 +2411,332 @@ mod tests {
         assert_eq!(ret, Some(peer_mgr_b.my_peer_id));
     }
 
+    #[tokio::test]
+    async fn test_preferred_relay() {
+        // a->b->c
+        // a->d->c
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_d = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_c.clone()).await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_d.clone()).await;
+        connect_peer_manager(peer_mgr_d.clone(), peer_mgr_c.clone()).await;
+
+        wait_route_appear_with_cost(peer_mgr_a.clone(), peer_mgr_c.my_peer_id, Some(2))
+            .await
+            .unwrap();
+
+        for relay in [peer_mgr_b.my_peer_id, peer_mgr_d.my_peer_id] {
+            peer_mgr_a.get_global_ctx().set_preferred_relay(Some(relay));
+            wait_for_condition(
+                || async {
+                    peer_mgr_a
+                        .get_route()
+                        .get_next_hop_with_policy(peer_mgr_c.my_peer_id, NextHopPolicy::LeastHop)
+                        .await
+                        == Some(relay)
+                },
+                Duration::from_secs(5),
+            )
+            .await;
+        }
+
+        // preferred relay gone, fall back to the other path
+        drop(peer_mgr_d);
+        wait_for_condition(
+            || async {
+                peer_mgr_a
+                    .get_route()
+                    .get_next_hop_with_policy(peer_mgr_c.my_peer_id, NextHopPolicy::LeastHop)
+                    .await
+                    == Some(peer_mgr_b.my_peer_id)
+            },
+            Duration::from_secs(10),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_max_peers() {
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        peer_mgr_a.get_global_ctx().set_max_peers(Some(1));
+
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_a.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_b.clone())
+            .await
+            .unwrap();
+
+        let (c_ring, a_ring) = create_ring_tunnel_pair();
+        let peer_mgr_c_copy = peer_mgr_c.clone();
+        tokio::spawn(async move {
+            let _ = peer_mgr_c_copy.add_client_tunnel(c_ring, false).await;
+        });
+        assert!(peer_mgr_a.add_tunnel_as_server(a_ring, true).await.is_err());
+        assert_eq!(
+            peer_mgr_a.get_peer_map().list_peers_with_conn().await,
+            vec![peer_mgr_b.my_peer_id]
+        );
+
+        // more conns to an already connected peer are still accepted
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_a.clone()).await;
+        wait_for_condition(
+            || async {
+                peer_mgr_a
+                    .get_peer_map()
+                    .list_peer_conns(peer_mgr_b.my_peer_id)
+                    .await
+                    .is_some_and(|conns| conns.len() == 2)
+            },
+            Duration::from_secs(5),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_peer_approval() {
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+
+        // the approver is global, only decide for a so other tests are not affected
+        let inst_a = peer_mgr_a.get_global_ctx().get_id();
+        let rejected = peer_mgr_c.my_peer_id;
+        let approver: PeerConnApprover = Arc::new(
+            move |inst_id: uuid::Uuid, conn: PeerConnInfo| -> BoxFuture<'static, bool> {
+                Box::pin(async move { inst_id != inst_a || conn.peer_id != rejected })
+            },
+        );
+        set_global_var!(PEER_CONN_APPROVER, Some(approver));
+
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_a.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_b.clone())
+            .await
+            .unwrap();
+
+        let (c_ring, a_ring) = create_ring_tunnel_pair();
+        let peer_mgr_c_copy = peer_mgr_c.clone();
+        tokio::spawn(async move {
+            let _ = peer_mgr_c_copy.add_client_tunnel(c_ring, false).await;
+        });
+        assert!(peer_mgr_a.add_tunnel_as_server(a_ring, true).await.is_err());
+        assert!(!peer_mgr_a.get_peer_map().has_peer(rejected));
+
+        // c is let in by b, a still drops everything from and to it relayed through b
+        connect_peer_manager(peer_mgr_c.clone(), peer_mgr_b.clone()).await;
+        wait_route_appear(peer_mgr_b.clone(), peer_mgr_c.clone())
+            .await
+            .unwrap();
+        assert!(peer_mgr_a.is_peer_blocked(rejected));
+        let timeout = Duration::from_secs(1);
+        assert!(peer_mgr_a.ping_peer(rejected, timeout).await.is_err());
+        assert!(peer_mgr_c
+            .ping_peer(peer_mgr_a.my_peer_id, timeout)
+            .await
+            .is_err());
+        assert!(peer_mgr_b
+            .ping_peer(peer_mgr_a.my_peer_id, timeout)
+            .await
+            .is_ok());
+
+        set_global_var!(PEER_CONN_APPROVER, None);
+    }
+
+    #[tokio::test]
+    async fn test_ping_peer() {
+        // a->b->c, ping is relayed by b
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_c.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_c.clone())
+            .await
+            .unwrap();
+
+        let timeout = Duration::from_secs(1);
+        let rtt = peer_mgr_a
+            .ping_peer(peer_mgr_c.my_peer_id, timeout)
+            .await
+            .unwrap();
+        assert!(rtt < timeout);
+        assert!(peer_mgr_a
+            .ping_peer(peer_mgr_c.my_peer_id.wrapping_add(1), timeout)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_block_peer() {
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_b.clone())
+            .await
+            .unwrap();
+
+        let mut events = peer_mgr_a.get_global_ctx().subscribe();
+        let peer_id = peer_mgr_b.my_peer_id;
+        peer_mgr_a.block_peer(peer_id, Duration::from_millis(300));
+        assert!(peer_mgr_a.is_peer_blocked(peer_id));
+        loop {
+            if let GlobalCtxEvent::PeerBlocked(id, duration_ms) = events.recv().await.unwrap() {
+                assert_eq!((id, duration_ms), (peer_id, 300));
+                break;
+            }
+        }
+
+        // control traffic still goes through
+        assert!(peer_mgr_a
+            .ping_peer(peer_id, Duration::from_secs(1))
+            .await
+            .is_ok());
+
+        let event = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                if let GlobalCtxEvent::PeerUnblocked(id) = events.recv().await.unwrap() {
+                    return id;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(event, peer_id);
+        assert!(!peer_mgr_a.is_peer_blocked(peer_id));
+        assert!(!peer_mgr_a.unblock_peer(peer_id));
+
+        peer_mgr_a.block_peer(peer_id, Duration::from_secs(60));
+        assert!(peer_mgr_a.unblock_peer(peer_id));
+        assert!(!peer_mgr_a.is_peer_blocked(peer_id));
+    }
+
+    #[tokio::test]
+    async fn test_measure_throughput() {
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_c.clone()).await;
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_c.clone())
+            .await
+            .unwrap();
+
+        let duration = Duration::from_millis(200);
+        // refused until c enables it
+        assert!(peer_mgr_a
+            .measure_throughput(peer_mgr_c.my_peer_id, 1, duration, true)
+            .await
+            .is_err());
+        peer_mgr_c.get_global_ctx().set_serve_throughput_tests(true);
+        // no tun traffic counts as idle only once the busy window passed
+        tokio::time::sleep(THROUGHPUT_BUSY_TUN_IDLE).await;
+        for upload in [true, false] {
+            let (bytes, elapsed) = peer_mgr_a
+                .measure_throughput(peer_mgr_c.my_peer_id, 2, duration, upload)
+                .await
+                .unwrap();
+            assert!(bytes >= THROUGHPUT_CHUNK_SIZE as u64);
+            assert!(elapsed >= duration);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_throughput_service_limits() {
+        let peer_mgr = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let global_ctx = peer_mgr.get_global_ctx();
+        let service = PeerThroughputService::new(global_ctx.clone());
+        tokio::time::sleep(THROUGHPUT_BUSY_TUN_IDLE).await;
+        assert!(service.admit(1, 1, THROUGHPUT_CHUNK_SIZE).is_err());
+
+        global_ctx.set_serve_throughput_tests(true);
+        assert_eq!(
+            service.admit(1, 1, THROUGHPUT_CHUNK_SIZE).unwrap(),
+            Duration::ZERO
+        );
+        // the next chunk waits for the paced rate
+        assert!(service.admit(1, 1, THROUGHPUT_CHUNK_SIZE).unwrap() > Duration::ZERO);
+        // one test per peer, other peers run their own
+        assert!(service.admit(1, 2, THROUGHPUT_CHUNK_SIZE).is_err());
+        assert!(service.admit(2, 2, THROUGHPUT_CHUNK_SIZE).is_ok());
+
+        // refused while the tun device carries traffic
+        global_ctx.stats_manager().record_tun_traffic();
+        assert!(service.admit(2, 2, THROUGHPUT_CHUNK_SIZE).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_observer_mode_never_forward() {
+        use crate::proto::{
+            rpc_impl::RpcController,
+            tests::{GreetingClientFactory, SayHelloRequest},
+        };
+
+        // a->b->c, b is observer
+        let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_b = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        let peer_mgr_c = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
+        register_service(&peer_mgr_b.peer_rpc_mgr, "", 0, "hello b");
+        register_service(&peer_mgr_c.peer_rpc_mgr, "", 0, "hello c");
+
+        peer_mgr_b.get_global_ctx().set_observer_mode(true);
+        assert!(
+            peer_mgr_b
+                .get_global_ctx()
+                .get_feature_flags()
+                .avoid_relay_data
+        );
+
+        connect_peer_manager(peer_mgr_a.clone(), peer_mgr_b.clone()).await;
+        connect_peer_manager(peer_mgr_b.clone(), peer_mgr_c.clone()).await;
+
+        // routes are still learned through the observer
+        wait_route_appear(peer_mgr_a.clone(), peer_mgr_c.clone())
+            .await
+            .unwrap();
+
+        let say_hello = |to_peer_id| {
+            let stub = peer_mgr_a
+                .peer_rpc_mgr
+                .rpc_client()
+                .scoped_client::<GreetingClientFactory<RpcController>>(
+                    peer_mgr_a.my_peer_id,
+                    to_peer_id,
+                    "".to_string(),
+                );
+            async move {
+                tokio::time::timeout(
+                    Duration::from_secs(3),
+                    stub.say_hello(
+                        RpcController::default(),
+                        SayHelloRequest {
+                            name: "abc".to_string(),
+                        },
+                    ),
+                )
+                .await
+            }
+        };
+
+        // observer itself is reachable, but traffic is never relayed through it
+        let ret = say_hello(peer_mgr_b.my_peer_id).await.unwrap().unwrap();
+        assert_eq!(ret.greeting, "hello b abc!");
+        assert!(!matches!(say_hello(peer_mgr_c.my_peer_id).await, Ok(Ok(_))));
+
+        peer_mgr_b.get_global_ctx().set_observer_mode(false);
+        assert!(
+            !peer_mgr_b
+                .get_global_ctx()
+                .get_feature_flags()
+                .avoid_relay_data
+        );
+        let ret = say_hello(peer_mgr_c.my_peer_id).await.unwrap().unwrap();
+        assert_eq!(ret.greeting, "hello c abc!");
+    }
+
     #[tokio::test]
     async fn test_client_inbound_blackhole() {
         let peer_mgr_a = create_mock_peer_manager_with_mock_stun(NatType::Unknown).await;
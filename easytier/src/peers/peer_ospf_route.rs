@@ -32,8 +32,14 @@ use tokio::{
 
 use crate::{
     common::{
-        config::NetworkIdentity, constants::EASYTIER_VERSION, global_ctx::ArcGlobalCtx,
-        shrink_dashmap, stun::StunInfoCollectorTrait, PeerId,
+        config::NetworkIdentity,
+        constants::EASYTIER_VERSION,
+        global_ctx::{
+            peer_metadata_size, ArcGlobalCtx, RouteFailoverPolicy, MAX_PEER_METADATA_SIZE,
+        },
+        shrink_dashmap,
+        stun::StunInfoCollectorTrait,
+        PeerId,
     },
     peers::route_trait::{Route, RouteInterfaceBox},
     proto::{
@@ -78,6 +84,10 @@ static FORCE_USE_CONN_LIST: AtomicBool = AtomicBool::new(false);
 //    in another zone when two zone restore the conneciton.
 static REMOVE_UNREACHABLE_PEER_INFO_AFTER: Duration = Duration::from_secs(90);
 
+// the loss of a peer is only taken over once it moved this many percent, so a loss
+// jittering around a failover threshold does not rebuild the route table every tick.
+static PEER_LOSS_PERCENT_HYSTERESIS: u32 = 5;
+
 type Version = u32;
 
 #[derive(Debug, Clone)]
@@ -144,7 +154,23 @@ impl RoutePeerInfo {
             quic_port: None,
             ipv6_addr: None,
             groups: Vec::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the proxy cidrs announced to other peers, mapped cidrs replace the real ones.
+    pub fn advertised_proxy_cidrs(global_ctx: &ArcGlobalCtx) -> Vec<String> {
+        if global_ctx.is_observer_mode() {
+            return Vec::new();
         }
+        global_ctx
+            .config
+            .get_proxy_cidrs()
+            .iter()
+            .map(|x| x.mapped_cidr.unwrap_or(x.cidr))
+            .chain(global_ctx.get_vpn_portal_cidr())
+            .map(|x| x.to_string())
+            .collect()
     }
 
     /// Creates a new `RoutePeerInfo` instance with updated information from the given context.
@@ -167,14 +193,7 @@ impl RoutePeerInfo {
             inst_id: Some(global_ctx.get_id().into()),
             cost: 0,
             ipv4_addr: global_ctx.get_ipv4().map(|x| x.address().into()),
-            proxy_cidrs: global_ctx
-                .config
-                .get_proxy_cidrs()
-                .iter()
-                .map(|x| x.mapped_cidr.unwrap_or(x.cidr))
-                .chain(global_ctx.get_vpn_portal_cidr())
-                .map(|x| x.to_string())
-                .collect(),
+            proxy_cidrs: Self::advertised_proxy_cidrs(global_ctx),
             hostname: Some(global_ctx.get_hostname()),
             udp_nat_type: stun_info.udp_nat_type,
             tcp_nat_type: stun_info.tcp_nat_type,
@@ -195,6 +214,7 @@ impl RoutePeerInfo {
             ipv6_addr: global_ctx.get_ipv6().map(|x| x.into()),
 
             groups: global_ctx.get_acl_groups(my_peer_id),
+            metadata: global_ctx.get_peer_metadata(),
         }
     }
 
@@ -268,6 +288,10 @@ impl From<RoutePeerInfo> for crate::proto::api::instance::Route {
             path_latency_latency_first: None,
 
             ipv6_addr: val.ipv6_addr,
+
+            active_proxy_cidrs: Vec::new(),
+
+            metadata: val.metadata,
         }
     }
 }
@@ -482,6 +506,10 @@ impl SyncedRouteInfo {
             // time between peers may not be synchronized, so update last_update to local now.
             // note only last_update with larger version will be updated to local saved peer info.
             route_info.last_update = Some(SystemTime::now().into());
+            // not kept when a peer sends more than ours accept, the raw info is still forwarded
+            if peer_metadata_size(&route_info.metadata) > MAX_PEER_METADATA_SIZE {
+                route_info.metadata.clear();
+            }
             if guard
                 .get_mut(&route_info.peer_id)
                 .is_none_or(|old| route_info.version > old.version)
@@ -822,6 +850,45 @@ impl SyncedRouteInfo {
 
 type PeerGraph = Graph<PeerId, usize, Directed>;
 type PeerIdToNodexIdxMap = DashMap<PeerId, NodeIndex>;
+
+// local preferences applied on top of the synced topology when building route tables.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RouteBuildOptions {
+    preferred_relay: Option<PeerId>,
+    failover_policies: HashMap<IpCidr, RouteFailoverPolicy>,
+    // loss percent of the best conn to each direct peer, only collected with failover policies
+    peer_loss_percents: BTreeMap<PeerId, u32>,
+}
+
+impl RouteBuildOptions {
+    fn within_failover_policy(&self, policy: &RouteFailoverPolicy, next_hop: &NextHopInfo) -> bool {
+        let latency_ok = policy
+            .max_latency_ms
+            .is_none_or(|x| next_hop.path_latency <= x as i32);
+        let loss_ok = policy.max_loss_percent.is_none_or(|x| {
+            self.peer_loss_percents
+                .get(&next_hop.next_hop_peer_id)
+                .is_none_or(|loss| *loss <= x)
+        });
+        latency_ok && loss_ok
+    }
+}
+
+// keeps the previous loss of a peer while the new one is within the hysteresis of it.
+fn settle_peer_loss_percents(
+    previous: &BTreeMap<PeerId, u32>,
+    mut current: BTreeMap<PeerId, u32>,
+) -> BTreeMap<PeerId, u32> {
+    for (peer_id, loss) in current.iter_mut() {
+        if let Some(prev) = previous.get(peer_id) {
+            if prev.abs_diff(*loss) < PEER_LOSS_PERCENT_HYSTERESIS {
+                *loss = *prev;
+            }
+        }
+    }
+    current
+}
+
 #[derive(Debug, Clone, Copy)]
 struct NextHopInfo {
     next_hop_peer_id: PeerId,
@@ -883,6 +950,7 @@ impl RouteTable {
         my_peer_id: PeerId,
         synced_info: &SyncedRouteInfo,
         cost_calc: &T,
+        preferred_relay: Option<PeerId>,
     ) -> (PeerGraph, NodeIndex) {
         let mut graph: PeerGraph = PeerGraph::new();
 
@@ -916,6 +984,10 @@ impl RouteTable {
 
             // if avoid relay, just set all outgoing edges to a large value: AVOID_RELAY_COST.
             let peer_avoid_relay_data = synced_info.get_avoid_relay_data(*src_peer_id);
+            // with a preferred relay, other transit peers are treated like avoid relay ones,
+            // so they are only used when no path through the preferred relay exists.
+            let not_preferred_relay =
+                preferred_relay.is_some_and(|x| x != *src_peer_id && my_peer_id != *src_peer_id);
 
             for dst_peer_id in connected_peers.iter() {
                 let Some(dst_node_idx) = peer_id_to_node_index.get(dst_peer_id) else {
@@ -923,7 +995,7 @@ impl RouteTable {
                 };
 
                 let mut cost = cost_calc.calculate_cost(*src_peer_id, *dst_peer_id) as usize;
-                if peer_avoid_relay_data {
+                if peer_avoid_relay_data || not_preferred_relay {
                     cost += AVOID_RELAY_COST;
                 }
 
@@ -1037,12 +1109,17 @@ impl RouteTable {
         synced_info: &SyncedRouteInfo,
         policy: NextHopPolicy,
         cost_calc: &T,
+        options: &RouteBuildOptions,
     ) {
         let version = synced_info.version.get();
 
         // build next hop map
-        let (graph, start_node) =
-            Self::build_peer_graph_from_synced_info(my_peer_id, synced_info, cost_calc);
+        let (graph, start_node) = Self::build_peer_graph_from_synced_info(
+            my_peer_id,
+            synced_info,
+            cost_calc,
+            options.preferred_relay,
+        );
 
         if graph.node_count() == 0 {
             tracing::warn!("no peer in graph, cannot build next hop map");
@@ -1090,6 +1167,31 @@ impl RouteTable {
                 old_next_hop.is_none() || new_next_hop.path_len < old_next_hop.unwrap().path_len
             };
 
+            // with a failover policy, a path within the thresholds wins over one exceeding
+            // them, and among paths within them the lower latency wins.
+            let is_new_peer_better_for_cidr = |old_peer: &PeerIdVersion, cidr: IpCidr| -> bool {
+                let Some(policy) = options.failover_policies.get(&cidr) else {
+                    return is_new_peer_better(old_peer);
+                };
+                if old_peer.peer_id == my_peer_id || old_peer.peer_id == peer_id_and_version.peer_id
+                {
+                    return false;
+                }
+                let Some(old_next_hop) = self.get_next_hop(old_peer.peer_id) else {
+                    return true;
+                };
+                let new_next_hop = item.value();
+                match (
+                    options.within_failover_policy(policy, &old_next_hop),
+                    options.within_failover_policy(policy, new_next_hop),
+                ) {
+                    (false, true) => true,
+                    (true, false) => false,
+                    (true, true) => new_next_hop.path_latency < old_next_hop.path_latency,
+                    (false, false) => is_new_peer_better(old_peer),
+                }
+            };
+
             if let Some(ipv4_addr) = info.ipv4_addr {
                 self.ipv4_peer_id_map
                     .entry(ipv4_addr.into())
@@ -1120,7 +1222,9 @@ impl RouteTable {
                             .entry(cidr)
                             .and_modify(|e| {
                                 // if ourself has same cidr, ensure here put my peer id, so we can know deadloop may happen.
-                                if *peer_id == my_peer_id || is_new_peer_better(e) {
+                                if *peer_id == my_peer_id
+                                    || is_new_peer_better_for_cidr(e, IpCidr::V4(cidr))
+                                {
                                     *e = peer_id_and_version;
                                 }
                             })
@@ -1132,7 +1236,9 @@ impl RouteTable {
                             .entry(cidr)
                             .and_modify(|e| {
                                 // if ourself has same cidr, ensure here put my peer id, so we can know deadloop may happen.
-                                if *peer_id == my_peer_id || is_new_peer_better(e) {
+                                if *peer_id == my_peer_id
+                                    || is_new_peer_better_for_cidr(e, IpCidr::V6(cidr))
+                                {
                                     *e = peer_id_and_version;
                                 }
                             })
@@ -1163,6 +1269,14 @@ impl RouteTable {
         );
     }
 
+    // peer currently selected for exactly this proxy cidr.
+    fn get_peer_id_for_cidr(&self, cidr: &IpCidr) -> Option<PeerId> {
+        match cidr {
+            IpCidr::V4(cidr) => self.cidr_peer_id_map.load().get(cidr).map(|x| x.peer_id),
+            IpCidr::V6(cidr) => self.cidr_v6_peer_id_map.load().get(cidr).map(|x| x.peer_id),
+        }
+    }
+
     fn get_peer_id_for_proxy(&self, ip: &IpAddr) -> Option<PeerId> {
         match ip {
             IpAddr::V4(ipv4) => self
@@ -1535,6 +1649,11 @@ struct PeerRouteServiceImpl {
     last_update_my_foreign_network: AtomicCell<Option<std::time::Instant>>,
 
     peer_info_last_update: AtomicCell<std::time::Instant>,
+
+    peer_loss_percents: std::sync::Mutex<BTreeMap<PeerId, u32>>,
+    applied_route_build_options: std::sync::Mutex<RouteBuildOptions>,
+
+    route_changed: tokio::sync::watch::Sender<()>,
 }
 
 impl Debug for PeerRouteServiceImpl {
@@ -1592,6 +1711,11 @@ impl PeerRouteServiceImpl {
             last_update_my_foreign_network: AtomicCell::new(None),
 
             peer_info_last_update: AtomicCell::new(std::time::Instant::now()),
+
+            peer_loss_percents: std::sync::Mutex::new(BTreeMap::new()),
+            applied_route_build_options: std::sync::Mutex::new(RouteBuildOptions::default()),
+
+            route_changed: tokio::sync::watch::channel(()).0,
         }
     }
 
@@ -1690,11 +1814,14 @@ impl PeerRouteServiceImpl {
 
         let calc_locked = self.cost_calculator.read().unwrap();
 
+        let options = self.route_build_options();
+
         self.route_table.build_from_synced_info(
             self.my_peer_id,
             &self.synced_route_info,
             NextHopPolicy::LeastHop,
             calc_locked.as_ref().unwrap(),
+            &options,
         );
 
         self.route_table_with_cost.build_from_synced_info(
@@ -1702,8 +1829,11 @@ impl PeerRouteServiceImpl {
             &self.synced_route_info,
             NextHopPolicy::LeastCost,
             calc_locked.as_ref().unwrap(),
+            &options,
         );
 
+        *self.applied_route_build_options.lock().unwrap() = options;
+
         drop(calc_locked);
 
         self.cost_calculator
@@ -1712,6 +1842,8 @@ impl PeerRouteServiceImpl {
             .as_mut()
             .unwrap()
             .end_update();
+
+        self.route_changed.send_replace(());
     }
 
     fn update_foreign_network_owner_map(&self) {
@@ -1758,6 +1890,47 @@ impl PeerRouteServiceImpl {
             .unwrap_or(false)
     }
 
+    fn route_build_options(&self) -> RouteBuildOptions {
+        let failover_policies = self.global_ctx.get_route_failover_policies();
+        let peer_loss_percents = if failover_policies.is_empty() {
+            BTreeMap::new()
+        } else {
+            self.peer_loss_percents.lock().unwrap().clone()
+        };
+        RouteBuildOptions {
+            preferred_relay: self.global_ctx.get_preferred_relay(),
+            failover_policies,
+            peer_loss_percents,
+        }
+    }
+
+    fn route_build_options_changed(&self) -> bool {
+        *self.applied_route_build_options.lock().unwrap() != self.route_build_options()
+    }
+
+    async fn update_peer_loss_percents(&self) {
+        if self.global_ctx.get_route_failover_policies().is_empty() {
+            return;
+        }
+        let peers: Vec<PeerId> = self.list_peers_from_interface().await;
+        let mut loss_percents = BTreeMap::new();
+        for peer_id in peers {
+            let loss_rate = self
+                .interface
+                .lock()
+                .await
+                .as_ref()
+                .unwrap()
+                .get_peer_loss_rate(peer_id)
+                .await;
+            if let Some(loss_rate) = loss_rate {
+                loss_percents.insert(peer_id, (loss_rate * 100.0).round() as u32);
+            }
+        }
+        let mut applied = self.peer_loss_percents.lock().unwrap();
+        *applied = settle_peer_loss_percents(&applied, loss_percents);
+    }
+
     fn update_route_table_and_cached_local_conn_bitmap(&self) {
         self.update_peer_info_last_update();
 
@@ -2756,7 +2929,10 @@ impl PeerRoute {
                 session_mgr.sync_now("update_my_infos");
             }
 
-            if service_impl.cost_calculator_need_update() {
+            service_impl.update_peer_loss_percents().await;
+            if service_impl.cost_calculator_need_update()
+                || service_impl.route_build_options_changed()
+            {
                 tracing::debug!("cost_calculator_need_update");
                 service_impl.synced_route_info.version.inc();
                 service_impl.update_route_table();
@@ -2881,6 +3057,17 @@ impl Route for PeerRoute {
             route.cost_latency_first = next_hop_peer_latency_first.map(|x| x.path_len as i32);
             route.path_latency_latency_first = next_hop_peer_latency_first.map(|x| x.path_latency);
 
+            route.active_proxy_cidrs = route
+                .proxy_cidrs
+                .iter()
+                .filter(|cidr| {
+                    cidr.parse::<IpCidr>().is_ok_and(|cidr| {
+                        route_table.get_peer_id_for_cidr(&cidr) == Some(*item.key())
+                    })
+                })
+                .cloned()
+                .collect();
+
             route.feature_flag = item.feature_flag;
 
             routes.push(route);
@@ -3012,6 +3199,10 @@ impl Route for PeerRoute {
     fn get_peer_groups(&self, peer_id: PeerId) -> Arc<Vec<String>> {
         self.service_impl.get_peer_groups(peer_id)
     }
+
+    fn subscribe_route_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        Some(self.service_impl.route_changed.subscribe())
+    }
 }
 
 impl PeerPacketFilter for Arc<PeerRoute> {}
@@ -3019,7 +3210,7 @@ impl PeerPacketFilter for Arc<PeerRoute> {}
 #[cfg(test)]
 mod tests {
     use std::{
-        collections::BTreeSet,
+        collections::{BTreeMap, BTreeSet},
         sync::{atomic::Ordering, Arc},
         time::Duration,
     };
@@ -3030,12 +3221,18 @@ mod tests {
     use prost_reflect::{DynamicMessage, ReflectMessage};
 
     use crate::{
-        common::{global_ctx::tests::get_mock_global_ctx, PeerId},
+        common::{
+            global_ctx::{tests::get_mock_global_ctx, RouteFailoverPolicy, MAX_PEER_METADATA_SIZE},
+            PeerId,
+        },
         connector::udp_hole_punch::tests::replace_stun_info_collector,
         peers::{
             create_packet_recv_chan,
             peer_manager::{PeerManager, RouteAlgoType},
-            peer_ospf_route::{PeerIdVersion, PeerRouteServiceImpl, FORCE_USE_CONN_LIST},
+            peer_ospf_route::{
+                NextHopInfo, PeerIdVersion, PeerRouteServiceImpl, RouteBuildOptions,
+                FORCE_USE_CONN_LIST,
+            },
             route_trait::{NextHopPolicy, Route, RouteCostCalculatorInterface},
             tests::{connect_peer_manager, create_mock_peer_manager, wait_route_appear},
         },
@@ -3688,4 +3885,124 @@ mod tests {
         connect_peer_manager(p_b.clone(), p_c.clone()).await;
         wait_route_appear(p_a.clone(), p_c.clone()).await.unwrap();
     }
+
+    #[test]
+    fn test_within_failover_policy() {
+        let next_hop = |next_hop_peer_id, path_latency| NextHopInfo {
+            next_hop_peer_id,
+            path_latency,
+            path_len: 2,
+            version: 0,
+        };
+        let mut options = RouteBuildOptions::default();
+        options.peer_loss_percents.insert(1, 20);
+
+        let policy = RouteFailoverPolicy {
+            max_latency_ms: Some(100),
+            max_loss_percent: Some(10),
+        };
+        assert!(!options.within_failover_policy(&policy, &next_hop(1, 50)));
+        // unknown loss counts as within
+        assert!(options.within_failover_policy(&policy, &next_hop(2, 50)));
+        assert!(!options.within_failover_policy(&policy, &next_hop(2, 150)));
+
+        let latency_only = RouteFailoverPolicy {
+            max_latency_ms: Some(100),
+            max_loss_percent: None,
+        };
+        assert!(options.within_failover_policy(&latency_only, &next_hop(1, 50)));
+    }
+
+    #[test]
+    fn test_settle_peer_loss_percents() {
+        let previous = BTreeMap::from([(1, 10), (2, 10)]);
+        let current = BTreeMap::from([(1, 12), (2, 20), (3, 7)]);
+        let settled = settle_peer_loss_percents(&previous, current);
+        // small moves keep the old value, large ones and new peers are taken over
+        assert_eq!(settled, BTreeMap::from([(1, 10), (2, 20), (3, 7)]));
+        // peers gone from the current map are dropped
+        let settled = settle_peer_loss_percents(&settled, BTreeMap::from([(3, 8)]));
+        assert_eq!(settled, BTreeMap::from([(3, 7)]));
+    }
+
+    #[tokio::test]
+    async fn test_active_proxy_cidrs() {
+        // b and c advertise the same cidr, only one of them is selected by a
+        let p_a = create_mock_peer_manager().await;
+        let p_b = create_mock_peer_manager().await;
+        let p_c = create_mock_peer_manager().await;
+        connect_peer_manager(p_a.clone(), p_b.clone()).await;
+        connect_peer_manager(p_a.clone(), p_c.clone()).await;
+
+        let proxy_cidr: Ipv4Cidr = "192.168.100.0/24".parse().unwrap();
+        for p in [&p_b, &p_c] {
+            p.get_global_ctx()
+                .config
+                .add_proxy_cidr(proxy_cidr, None)
+                .unwrap();
+        }
+        p_a.get_global_ctx().set_route_failover_policy(
+            proxy_cidr.into(),
+            Some(RouteFailoverPolicy {
+                max_latency_ms: Some(1000),
+                max_loss_percent: None,
+            }),
+        );
+
+        let route_a = p_a.get_route();
+        wait_for_condition(
+            || async {
+                let routes = route_a.list_routes().await;
+                routes.iter().all(|r| r.proxy_cidrs.len() == 1) && routes.len() == 2
+            },
+            Duration::from_secs(10),
+        )
+        .await;
+
+        let routes = route_a.list_routes().await;
+        let active = routes
+            .iter()
+            .filter(|r| r.active_proxy_cidrs == vec![proxy_cidr.to_string()])
+            .map(|r| r.peer_id)
+            .collect::<Vec<_>>();
+        assert_eq!(active.len(), 1);
+        assert_eq!(
+            route_a
+                .get_peer_id_by_ipv4(&proxy_cidr.first_address())
+                .await,
+            Some(active[0])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_peer_metadata() {
+        let p_a = create_mock_peer_manager().await;
+        let p_b = create_mock_peer_manager().await;
+        connect_peer_manager(p_a.clone(), p_b.clone()).await;
+
+        let metadata = BTreeMap::from([("device".to_string(), "phone".to_string())]);
+        p_b.get_global_ctx()
+            .set_peer_metadata(metadata.clone())
+            .unwrap();
+        assert!(p_b
+            .get_global_ctx()
+            .set_peer_metadata(BTreeMap::from([(
+                "k".to_string(),
+                "v".repeat(MAX_PEER_METADATA_SIZE)
+            )]))
+            .is_err());
+
+        let route_a = p_a.get_route();
+        wait_for_condition(
+            || async {
+                route_a
+                    .list_routes()
+                    .await
+                    .iter()
+                    .any(|r| r.peer_id == p_b.my_peer_id() && r.metadata == metadata)
+            },
+            Duration::from_secs(10),
+        )
+        .await;
+    }
 }
@@ -0,0 +1,270 @@
+//! Outbound packets of the nic held back while no peer of their destination is
+//! reachable, e.g. during the reconnect after a network change, and sent once a route
+//! is back instead of being lost.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::{common::stats_manager::CounterHandle, tunnel::packet_def::ZCPacket};
+
+/// upper bound of the packets held, whatever the caller asks for.
+pub const MAX_BUFFERED_PACKETS: u32 = 4096;
+
+pub struct BufferedPacket {
+    pub queued_at: Instant,
+    pub ip_addr: IpAddr,
+    pub not_send_to_self: bool,
+    // passed the nic pipeline already, not yet compressed or encrypted
+    pub msg: ZCPacket,
+}
+
+#[derive(Clone, Copy)]
+struct BufferLimits {
+    max_packets: usize,
+    max_age: Duration,
+}
+
+#[derive(Default)]
+struct HeldPackets {
+    packets: VecDeque<BufferedPacket>,
+    // packets held per destination, so a send can tell cheaply whether it would
+    // overtake some
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+impl HeldPackets {
+    fn forget(&mut self, ip_addr: &IpAddr) {
+        if let Some(count) = self.per_ip.get_mut(ip_addr) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_ip.remove(ip_addr);
+            }
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<BufferedPacket> {
+        let packet = self.packets.pop_front()?;
+        self.forget(&packet.ip_addr);
+        Some(packet)
+    }
+
+    fn push_back(&mut self, packet: BufferedPacket) {
+        *self.per_ip.entry(packet.ip_addr).or_default() += 1;
+        self.packets.push_back(packet);
+    }
+
+    // the packets for which `f` is true, oldest first
+    fn take_if(&mut self, f: impl Fn(&BufferedPacket) -> bool) -> VecDeque<BufferedPacket> {
+        let (taken, kept): (VecDeque<_>, VecDeque<_>) =
+            std::mem::take(&mut self.packets).into_iter().partition(f);
+        self.packets = kept;
+        for packet in taken.iter() {
+            self.forget(&packet.ip_addr);
+        }
+        taken
+    }
+}
+
+/// Bounded by packet count and age. When full the oldest packet is dropped, as are the
+/// packets older than the max age, both counted in `dropped`. Disabled it costs one
+/// relaxed load per packet.
+pub struct ReconnectBuffer {
+    enabled: AtomicBool,
+    limits: Mutex<Option<BufferLimits>>,
+    packets: Mutex<HeldPackets>,
+    // wakes the flush once the first packet is held
+    packets_notifier: Notify,
+    dropped: CounterHandle,
+}
+
+impl ReconnectBuffer {
+    pub fn new(dropped: CounterHandle) -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            limits: Mutex::new(None),
+            packets: Mutex::new(HeldPackets::default()),
+            packets_notifier: Notify::new(),
+            dropped,
+        }
+    }
+
+    /// Holds up to `max_packets` packets for up to `max_age`, 0 for either disables the
+    /// buffer and drops what it holds. `max_packets` is capped at `MAX_BUFFERED_PACKETS`.
+    pub fn configure(&self, max_packets: u32, max_age: Duration) {
+        let limits = (max_packets > 0 && !max_age.is_zero()).then(|| BufferLimits {
+            max_packets: max_packets.min(MAX_BUFFERED_PACKETS) as usize,
+            max_age,
+        });
+        *self.limits.lock().unwrap() = limits;
+        self.enabled.store(limits.is_some(), Ordering::Relaxed);
+        if limits.is_none() {
+            let dropped = std::mem::take(&mut *self.packets.lock().unwrap())
+                .packets
+                .len();
+            self.dropped.add(dropped as u64);
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.lock().unwrap().packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// packets dropped because the buffer was full or they were held too long
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    fn drop_expired(&self, packets: &mut HeldPackets, max_age: Duration) {
+        let expired = packets.take_if(|x| x.queued_at.elapsed() >= max_age);
+        self.dropped.add(expired.len() as u64);
+    }
+
+    /// Returns false if the buffer is disabled, the packet is dropped then.
+    pub fn push(&self, packet: BufferedPacket) -> bool {
+        let Some(limits) = *self.limits.lock().unwrap() else {
+            return false;
+        };
+        let mut packets = self.packets.lock().unwrap();
+        self.drop_expired(&mut packets, limits.max_age);
+        while packets.packets.len() >= limits.max_packets {
+            packets.pop_front();
+            self.dropped.inc();
+        }
+        packets.push_back(packet);
+        if packets.packets.len() == 1 {
+            self.packets_notifier.notify_one();
+        }
+        true
+    }
+
+    /// all held packets that are not too old, oldest first
+    pub fn take_all(&self) -> VecDeque<BufferedPacket> {
+        let limits = *self.limits.lock().unwrap();
+        let mut packets = std::mem::take(&mut *self.packets.lock().unwrap());
+        if let Some(limits) = limits {
+            self.drop_expired(&mut packets, limits.max_age);
+        }
+        packets.packets
+    }
+
+    /// the held packets for `ip_addr` that are not too old, oldest first. one lookup if
+    /// none are held.
+    pub fn take_for(&self, ip_addr: &IpAddr) -> VecDeque<BufferedPacket> {
+        let mut packets = self.packets.lock().unwrap();
+        if !packets.per_ip.contains_key(ip_addr) {
+            return VecDeque::new();
+        }
+        let limits = *self.limits.lock().unwrap();
+        let mut taken = HeldPackets {
+            packets: packets.take_if(|x| x.ip_addr == *ip_addr),
+            per_ip: HashMap::new(),
+        };
+        if let Some(limits) = limits {
+            self.drop_expired(&mut taken, limits.max_age);
+        }
+        taken.packets
+    }
+
+    /// returns once a packet is held, at once if one was pushed since the last call
+    pub async fn wait_for_packets(&self) {
+        self.packets_notifier.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::stats_manager::{LabelSet, MetricName, StatsManager};
+
+    use super::*;
+
+    fn packet(queued_at: Instant) -> BufferedPacket {
+        BufferedPacket {
+            queued_at,
+            ip_addr: "10.144.144.2".parse().unwrap(),
+            not_send_to_self: false,
+            msg: ZCPacket::new_with_payload(b"data"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_buffer() {
+        let stats = StatsManager::new();
+        let buffer = ReconnectBuffer::new(
+            stats.get_counter(MetricName::ReconnectBufferDropped, LabelSet::new()),
+        );
+        assert!(!buffer.is_enabled());
+        assert!(!buffer.push(packet(Instant::now())));
+
+        buffer.configure(2, Duration::from_secs(1));
+        assert!(buffer.is_enabled());
+        let first = Instant::now();
+        assert!(buffer.push(packet(first)));
+        buffer.wait_for_packets().await;
+        assert!(buffer.push(packet(first + Duration::from_millis(1))));
+        // full, the first one goes
+        assert!(buffer.push(packet(first + Duration::from_millis(2))));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.dropped_packets(), 1);
+        assert!(buffer.take_all().iter().all(|x| x.queued_at > first));
+        assert!(buffer.is_empty());
+
+        // too old
+        assert!(buffer.push(packet(Instant::now() - Duration::from_secs(2))));
+        assert!(buffer.take_all().is_empty());
+        assert_eq!(buffer.dropped_packets(), 2);
+
+        buffer.push(packet(Instant::now()));
+        buffer.configure(0, Duration::from_secs(1));
+        assert!(!buffer.is_enabled());
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.dropped_packets(), 3);
+    }
+
+    #[test]
+    fn test_reconnect_buffer_take_for() {
+        let stats = StatsManager::new();
+        let buffer = ReconnectBuffer::new(
+            stats.get_counter(MetricName::ReconnectBufferDropped, LabelSet::new()),
+        );
+        buffer.configure(3, Duration::from_secs(1));
+        let other: IpAddr = "10.144.144.3".parse().unwrap();
+        let first = Instant::now();
+        buffer.push(packet(first));
+        buffer.push(BufferedPacket {
+            ip_addr: other,
+            ..packet(first + Duration::from_millis(1))
+        });
+        buffer.push(packet(first + Duration::from_millis(2)));
+
+        let taken = buffer.take_for(&"10.144.144.2".parse().unwrap());
+        assert_eq!(taken.len(), 2);
+        assert!(taken[0].queued_at < taken[1].queued_at);
+        assert!(buffer.take_for(&"10.144.144.2".parse().unwrap()).is_empty());
+        assert_eq!(buffer.len(), 1);
+
+        // dropping the oldest when full forgets its destination too
+        buffer.push(packet(first));
+        buffer.push(packet(first));
+        buffer.push(packet(first));
+        assert!(buffer.take_for(&other).is_empty());
+        assert_eq!(buffer.take_all().len(), 3);
+    }
+}
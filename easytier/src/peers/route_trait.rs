@@ -30,6 +30,10 @@ pub trait RouteInterface {
     async fn list_foreign_networks(&self) -> ForeignNetworkRouteInfoMap {
         DashMap::new()
     }
+    // loss rate (0.0 - 1.0) of the best conn to a directly connected peer
+    async fn get_peer_loss_rate(&self, _peer_id: PeerId) -> Option<f32> {
+        None
+    }
 }
 
 pub type RouteInterfaceBox = Box<dyn RouteInterface + Send + Sync>;
@@ -141,6 +145,11 @@ pub trait Route {
     async fn dump(&self) -> String {
         "this route implementation does not support dump".to_string()
     }
+
+    /// Marked changed each time the routes are rebuilt, none if they never change.
+    fn subscribe_route_changes(&self) -> Option<tokio::sync::watch::Receiver<()>> {
+        None
+    }
 }
 
 pub type ArcRoute = Arc<Box<dyn Route + Send + Sync>>;
@@ -1,24 +1,37 @@
 use std::{
     ops::Deref,
     sync::{Arc, Weak},
+    time::Duration,
 };
 
+use anyhow::Context;
+
 use crate::{
     proto::{
         api::instance::{
-            AclManageRpc, DumpRouteRequest, DumpRouteResponse, GetAclStatsRequest,
-            GetAclStatsResponse, GetForeignNetworkSummaryRequest, GetForeignNetworkSummaryResponse,
-            GetWhitelistRequest, GetWhitelistResponse, ListForeignNetworkRequest,
-            ListForeignNetworkResponse, ListGlobalForeignNetworkRequest,
-            ListGlobalForeignNetworkResponse, ListPeerRequest, ListPeerResponse, ListRouteRequest,
-            ListRouteResponse, PeerInfo, PeerManageRpc, ShowNodeInfoRequest, ShowNodeInfoResponse,
+            AclManageRpc, BlockPeerRequest, BlockPeerResponse, ClosePeerConnRequest,
+            ClosePeerConnResponse, DumpRouteRequest, DumpRouteResponse, GetAclRequest,
+            GetAclResponse, GetAclStatsRequest, GetAclStatsResponse,
+            GetForeignNetworkSummaryRequest, GetForeignNetworkSummaryResponse, GetWhitelistRequest,
+            GetWhitelistResponse, ListForeignNetworkRequest, ListForeignNetworkResponse,
+            ListGlobalForeignNetworkRequest, ListGlobalForeignNetworkResponse, ListPeerRequest,
+            ListPeerResponse, ListRouteRequest, ListRouteResponse, PeerInfo, PeerManageRpc,
+            PingPeerRequest, PingPeerResponse, ShowNodeInfoRequest, ShowNodeInfoResponse,
+            StartPacketCaptureRequest, StartPacketCaptureResponse, StopPacketCaptureRequest,
+            StopPacketCaptureResponse, ThroughputTestRequest, ThroughputTestResponse,
         },
         rpc_types::{self, controller::BaseController},
     },
     utils::weak_upgrade,
 };
 
-use super::peer_manager::PeerManager;
+use super::{peer_conn::PeerConnId, peer_manager::PeerManager};
+
+const PING_DEFAULT_COUNT: u32 = 4;
+const PING_MAX_COUNT: u32 = 20;
+const PING_DEFAULT_TIMEOUT_MS: u32 = 1000;
+const THROUGHPUT_DEFAULT_DURATION_MS: u32 = 10_000;
+const THROUGHPUT_MAX_DURATION_MS: u32 = 60_000;
 
 #[derive(Clone)]
 pub struct PeerManagerRpcService {
@@ -56,6 +69,7 @@ impl PeerManagerRpcService {
                     .into_iter()
                     .map(Into::into)
                     .collect(),
+                lan_discovered: peer_manager.get_global_ctx_ref().is_lan_peer(peer),
                 ..Default::default()
             };
 
@@ -70,6 +84,15 @@ impl PeerManagerRpcService {
                 peer_info.conns = conns;
             }
 
+            let default_conn_id = peer_info.default_conn_id.map(|id| id.to_string());
+            peer_info.connection_type = peer_info
+                .conns
+                .iter()
+                .find(|x| Some(&x.conn_id) == default_conn_id.as_ref())
+                .and_then(|x| x.tunnel.as_ref())
+                .map(|x| x.tunnel_type.clone())
+                .unwrap_or_default();
+
             peer_infos.push(peer_info);
         }
 
@@ -163,6 +186,116 @@ impl PeerManageRpc for PeerManagerRpcService {
             node_info: Some(weak_upgrade(&self.peer_manager)?.get_my_info().await),
         })
     }
+
+    async fn ping_peer(
+        &self,
+        _: BaseController,
+        request: PingPeerRequest,
+    ) -> Result<PingPeerResponse, rpc_types::error::Error> {
+        let peer_manager = weak_upgrade(&self.peer_manager)?;
+        let count = match request.count {
+            0 => PING_DEFAULT_COUNT,
+            n => n.min(PING_MAX_COUNT),
+        };
+        let timeout = Duration::from_millis(match request.timeout_ms {
+            0 => PING_DEFAULT_TIMEOUT_MS,
+            n => n,
+        } as u64);
+
+        let mut reply = PingPeerResponse::default();
+        for _ in 0..count {
+            reply.sent += 1;
+            if let Ok(rtt) = peer_manager.ping_peer(request.peer_id, timeout).await {
+                reply.received += 1;
+                reply.rtt_us.push(rtt.as_micros() as u64);
+            }
+        }
+        Ok(reply)
+    }
+
+    async fn throughput_test(
+        &self,
+        _: BaseController,
+        request: ThroughputTestRequest,
+    ) -> Result<ThroughputTestResponse, rpc_types::error::Error> {
+        let peer_manager = weak_upgrade(&self.peer_manager)?;
+        let duration_ms = match request.duration_ms {
+            0 => THROUGHPUT_DEFAULT_DURATION_MS,
+            n => n.min(THROUGHPUT_MAX_DURATION_MS),
+        };
+        let half = Duration::from_millis(duration_ms as u64 / 2);
+        let test_id = rand::random();
+
+        let (upload_bytes, upload_time) = peer_manager
+            .measure_throughput(request.peer_id, test_id, half, true)
+            .await?;
+        let (download_bytes, download_time) = peer_manager
+            .measure_throughput(request.peer_id, test_id, half, false)
+            .await?;
+        Ok(ThroughputTestResponse {
+            upload_bytes,
+            upload_us: upload_time.as_micros() as u64,
+            download_bytes,
+            download_us: download_time.as_micros() as u64,
+        })
+    }
+
+    async fn block_peer(
+        &self,
+        _: BaseController,
+        request: BlockPeerRequest,
+    ) -> Result<BlockPeerResponse, rpc_types::error::Error> {
+        let peer_manager = weak_upgrade(&self.peer_manager)?;
+        let was_blocked = if request.duration_ms == 0 {
+            peer_manager.unblock_peer(request.peer_id)
+        } else {
+            let was_blocked = peer_manager.is_peer_blocked(request.peer_id);
+            peer_manager.block_peer(
+                request.peer_id,
+                Duration::from_millis(request.duration_ms as u64),
+            );
+            was_blocked
+        };
+        Ok(BlockPeerResponse { was_blocked })
+    }
+
+    async fn close_peer_conn(
+        &self,
+        _: BaseController,
+        request: ClosePeerConnRequest,
+    ) -> Result<ClosePeerConnResponse, rpc_types::error::Error> {
+        let conn_id: PeerConnId = request
+            .conn_id
+            .parse()
+            .with_context(|| format!("invalid conn id: {}", request.conn_id))?;
+        weak_upgrade(&self.peer_manager)?
+            .close_peer_conn(request.peer_id, &conn_id)
+            .await
+            .with_context(|| format!("close conn {} failed", conn_id))?;
+        Ok(ClosePeerConnResponse {})
+    }
+
+    async fn start_packet_capture(
+        &self,
+        _: BaseController,
+        request: StartPacketCaptureRequest,
+    ) -> Result<StartPacketCaptureResponse, rpc_types::error::Error> {
+        let started = weak_upgrade(&self.peer_manager)?
+            .get_packet_capture()
+            .start(request.max_packets, request.max_bytes, request.full_packets);
+        Ok(StartPacketCaptureResponse { started })
+    }
+
+    async fn stop_packet_capture(
+        &self,
+        _: BaseController,
+        _request: StopPacketCaptureRequest,
+    ) -> Result<StopPacketCaptureResponse, rpc_types::error::Error> {
+        let pcap = weak_upgrade(&self.peer_manager)?
+            .get_packet_capture()
+            .stop();
+        Ok(StopPacketCaptureResponse { pcap })
+    }
 }
 
 #[async_trait::async_trait]
@@ -201,4 +334,15 @@ impl AclManageRpc for PeerManagerRpcService {
             udp_ports,
         })
     }
+
+    async fn get_acl(
+        &self,
+        _: BaseController,
+        _request: GetAclRequest,
+    ) -> Result<GetAclResponse, rpc_types::error::Error> {
+        let global_ctx = weak_upgrade(&self.peer_manager)?.get_global_ctx();
+        Ok(GetAclResponse {
+            acl: global_ctx.config.get_acl(),
+        })
+    }
 }
@@ -4,8 +4,8 @@ use crate::{
     instance_manager::NetworkInstanceManager,
     proto::{
         api::instance::{
-            AclManageRpc, GetAclStatsRequest, GetAclStatsResponse, GetWhitelistRequest,
-            GetWhitelistResponse,
+            AclManageRpc, GetAclRequest, GetAclResponse, GetAclStatsRequest, GetAclStatsResponse,
+            GetWhitelistRequest, GetWhitelistResponse,
         },
         rpc_types::controller::BaseController,
     },
@@ -47,4 +47,15 @@ impl AclManageRpc for AclManageRpcService {
             .get_whitelist(ctrl, req)
             .await
     }
+
+    async fn get_acl(
+        &self,
+        ctrl: Self::Controller,
+        req: GetAclRequest,
+    ) -> crate::proto::rpc_types::error::Result<GetAclResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_acl_manage_service()
+            .get_acl(ctrl, req)
+            .await
+    }
 }
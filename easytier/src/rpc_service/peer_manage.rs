@@ -99,4 +99,70 @@ impl PeerManageRpc for PeerManageRpcService {
             .show_node_info(ctrl, req)
             .await
     }
+
+    async fn ping_peer(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::PingPeerRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::PingPeerResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .ping_peer(ctrl, req)
+            .await
+    }
+
+    async fn throughput_test(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::ThroughputTestRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::ThroughputTestResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .throughput_test(ctrl, req)
+            .await
+    }
+
+    async fn block_peer(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::BlockPeerRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::BlockPeerResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .block_peer(ctrl, req)
+            .await
+    }
+
+    async fn close_peer_conn(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::ClosePeerConnRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::ClosePeerConnResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .close_peer_conn(ctrl, req)
+            .await
+    }
+
+    async fn start_packet_capture(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::StartPacketCaptureRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::StartPacketCaptureResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .start_packet_capture(ctrl, req)
+            .await
+    }
+
+    async fn stop_packet_capture(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::StopPacketCaptureRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::StopPacketCaptureResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .stop_packet_capture(ctrl, req)
+            .await
+    }
 }
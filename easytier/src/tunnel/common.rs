@@ -12,6 +12,7 @@ use pin_project_lite::pin_project;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crossbeam::atomic::AtomicCell;
 use tokio_stream::StreamExt;
 use tokio_util::io::poll_write_buf;
 use zerocopy::FromBytes as _;
@@ -344,6 +345,60 @@ pub(crate) fn get_interface_name_by_ip(local_ip: &IpAddr) -> Option<String> {
     None
 }
 
+pub const MAX_DSCP: u8 = 63;
+
+static SOCKET_DSCP: AtomicCell<Option<u8>> = AtomicCell::new(None);
+
+/// Marks the packets of the sockets created from now on with `dscp`, none keeps the os
+/// default. Sockets that exist already keep their marking. Shared by all instances of the
+/// process.
+pub fn set_socket_dscp(dscp: Option<u8>) -> Result<(), TunnelError> {
+    if let Some(dscp) = dscp.filter(|x| *x > MAX_DSCP) {
+        return Err(anyhow::anyhow!("dscp {} exceeds {}", dscp, MAX_DSCP).into());
+    }
+    SOCKET_DSCP.store(dscp);
+    Ok(())
+}
+
+pub fn get_socket_dscp() -> Option<u8> {
+    SOCKET_DSCP.load()
+}
+
+pub(crate) fn apply_socket_dscp(socket: socket2::SockRef<'_>, is_ipv6: bool) {
+    if let Some(dscp) = SOCKET_DSCP.load() {
+        mark_socket_dscp(socket, is_ipv6, dscp);
+    }
+}
+
+pub(crate) fn mark_socket_dscp(socket: socket2::SockRef<'_>, is_ipv6: bool, dscp: u8) {
+    // dscp is the upper 6 bits of the ipv4 tos and the ipv6 traffic class
+    let tos = (dscp as u32) << 2;
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd"
+    ))]
+    let ret = if is_ipv6 {
+        socket.set_tclass_v6(tos)
+    } else {
+        socket.set_tos(tos)
+    };
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd"
+    )))]
+    let ret: std::io::Result<()> = {
+        let _ = (socket, is_ipv6);
+        Err(std::io::ErrorKind::Unsupported.into())
+    };
+    if let Err(e) = ret {
+        tracing::warn!(?e, ?tos, "set dscp of socket failed");
+    }
+}
+
 pub(crate) fn setup_sokcet2_ext(
     socket2_socket: &socket2::Socket,
     bind_addr: &SocketAddr,
@@ -361,6 +416,7 @@ pub(crate) fn setup_sokcet2_ext(
 
     socket2_socket.set_nonblocking(true)?;
     socket2_socket.set_reuse_address(true)?;
+    apply_socket_dscp(socket2_socket.into(), bind_addr.is_ipv6());
     if let Err(e) = socket2_socket.bind(&socket2::SockAddr::from(*bind_addr)) {
         if bind_addr.is_ipv4() {
             return Err(e.into());
@@ -5,7 +5,7 @@ use futures::stream::FuturesUnordered;
 use tokio::net::{TcpListener, TcpSocket, TcpStream};
 
 use super::TunnelInfo;
-use crate::tunnel::common::setup_sokcet2;
+use crate::tunnel::common::{apply_socket_dscp, setup_sokcet2};
 
 use super::{
     check_scheme_and_get_socket_addr,
@@ -154,6 +154,7 @@ impl TcpTunnelConnector {
     ) -> Result<Box<dyn Tunnel>, super::TunnelError> {
         tracing::info!(url = ?self.addr, ?addr, "connect tcp start, bind addrs: {:?}", self.bind_addrs);
         let stream = TcpStream::connect(addr).await?;
+        apply_socket_dscp((&stream).into(), addr.is_ipv6());
         tracing::info!(url = ?self.addr, ?addr, "connect tcp succ");
         get_tunnel_with_tcp_stream(stream, self.addr.clone())
     }
@@ -283,6 +284,25 @@ mod tests {
         _tunnel_pingpong(listener, connector).await;
     }
 
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn socket_dscp() {
+        use crate::tunnel::common::{get_socket_dscp, mark_socket_dscp, set_socket_dscp};
+
+        // the dscp set is shared by the process, the test leaves it to the other tests
+        assert!(set_socket_dscp(Some(64)).is_err());
+        assert_eq!(get_socket_dscp(), None);
+
+        let socket2_socket = socket2::Socket::new(
+            socket2::Domain::IPV4,
+            socket2::Type::STREAM,
+            Some(socket2::Protocol::TCP),
+        )
+        .unwrap();
+        mark_socket_dscp((&socket2_socket).into(), false, 46);
+        assert_eq!(socket2_socket.tos().unwrap(), 46 << 2);
+    }
+
     #[tokio::test]
     async fn test_alloc_port() {
         // v4
@@ -33,7 +33,7 @@ use crate::{
 };
 
 use super::{
-    common::{setup_sokcet2, setup_sokcet2_ext, wait_for_connect_futures},
+    common::{apply_socket_dscp, setup_sokcet2, setup_sokcet2_ext, wait_for_connect_futures},
     packet_def::{UDPTunnelHeader, UDP_TUNNEL_HEADER_SIZE},
     ring::{RingSink, RingStream},
     IpVersion, Tunnel, TunnelConnCounter, TunnelError, TunnelListener, TunnelUrl,
@@ -806,6 +806,7 @@ impl UdpTunnelConnector {
         } else {
             UdpSocket::bind("[::]:0").await?
         };
+        apply_socket_dscp((&socket).into(), addr.is_ipv6());
 
         return self.try_connect_with_socket(Arc::new(socket), addr).await;
     }